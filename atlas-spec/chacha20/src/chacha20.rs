@@ -1,7 +1,5 @@
 use std::convert::TryInto;
 
-use hacspec_lib::{bytes_to_le_u32s, Conversions};
-
 type State = [u32; 16];
 type StateIdx = usize;
 
@@ -11,6 +9,19 @@ type Block = Vec<u8>;
 pub type ChaChaIV = [u8; 12];
 pub type ChaChaKey = [u8; 32];
 
+// `hacspec_lib` builds its random number generator on this crate, so the
+// byte conversions are defined here rather than imported from there.
+fn bytes_to_le_u32s(input: &[u8]) -> Vec<u32> {
+    input
+        .chunks(4)
+        .map(|block| u32::from_le_bytes(block.try_into().unwrap()))
+        .collect()
+}
+
+fn state_to_le_bytes(state: State) -> Block {
+    state.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
 fn chacha20_line(a: StateIdx, b: StateIdx, d: StateIdx, s: usize, m: State) -> State {
     let mut state = m;
     state[a] = state[a].wrapping_add(state[b]);
@@ -84,7 +95,7 @@ pub fn chacha20_init(key: ChaChaKey, iv: ChaChaIV, ctr: u32) -> State {
 pub fn chacha20_key_block(state: State) -> Block {
     let state = chacha20_core(0u32, state);
 
-    state.to_le_bytes()
+    state_to_le_bytes(state)
 }
 
 pub fn chacha20_key_block0(key: ChaChaKey, iv: ChaChaIV) -> Block {
@@ -100,7 +111,7 @@ pub fn chacha20_encrypt_block(st0: State, ctr: u32, plain: &[u8]) -> Block {
         st_new[i] = st[i] ^ pl[i];
     }
     let st = st_new;
-    st.to_le_bytes()
+    state_to_le_bytes(st)
 }
 
 pub fn chacha20_encrypt_last(st0: State, ctr: u32, plain: &[u8]) -> Vec<u8> {
@@ -1,27 +1,159 @@
 //! This module provides utilities for providing randomness to cryptographic constructions and protocols.
+//!
+//! Randomness is either drawn from a fixed buffer of bytes, which runs out
+//! once all bytes have been consumed, or from a ChaCha20-based
+//! deterministic random bit generator (DRBG), which never does.
+//!
+//! The DRBG keeps a 256-bit ChaCha20 key as its state. To produce output,
+//! it computes [`DRBG_BLOCKS`] blocks of ChaCha20 keystream under that key
+//! with an all-zero nonce, replaces its key with the first 32 bytes of the
+//! keystream and outputs the rest. The key is overwritten on every refill,
+//! and bytes handed out are wiped from the provider's buffer on its next
+//! call and when it is dropped. Compromising the state therefore reveals at
+//! most the output of the latest call, but no earlier outputs.
+//!
+//! A randomness provider can be forked into independent child providers,
+//! each identified by a label. Every fork draws 32 fresh bytes from the
+//! parent, and the seed of the child is derived from these bytes and the
+//! child's label. Children thus never share output, even if they are
+//! forked with the same label, and a child only depends on its label and
+//! the parent's output up to its fork, not on how its siblings are used.
+
+use hacspec_chacha20::{chacha20, ChaChaIV, ChaChaKey};
+use zeroize::Zeroize;
 
 #[derive(Debug)]
 pub enum Error {
     InsufficientRandomness,
+    EntropyUnavailable,
+}
+
+/// The number of ChaCha20 blocks computed per refill of the DRBG.
+const DRBG_BLOCKS: usize = 16;
+/// The number of output bytes per refill of the DRBG.
+const DRBG_OUTPUT: usize = DRBG_BLOCKS * 64 - 32;
+const DRBG_NONCE: ChaChaIV = [0u8; 12];
+
+/// Nonce prefixes separating key derivation from the DRBG output.
+const DERIVE_LENGTH: u8 = 0x01;
+const DERIVE_CHUNK: u8 = 0x02;
+/// The number of label bytes absorbed per ChaCha20 call.
+const DERIVE_CHUNK_LEN: usize = 11;
+
+/// Derive a key from a key and a label.
+///
+/// The label is absorbed in a cascade of ChaCha20 calls, each keyed by
+/// the output of the previous call, with a chunk of the label as nonce.
+/// The first call absorbs the length of the label, which makes the
+/// encoding of labels prefix-free.
+fn derive_key(key: ChaChaKey, label: &[u8]) -> ChaChaKey {
+    fn absorb(key: ChaChaKey, prefix: u8, chunk: &[u8]) -> ChaChaKey {
+        let mut nonce = [0u8; 12];
+        nonce[0] = prefix;
+        nonce[1..1 + chunk.len()].copy_from_slice(chunk);
+        chacha20(key, nonce, 0, &[0u8; 32]).try_into().unwrap()
+    }
+
+    let mut key = absorb(key, DERIVE_LENGTH, &(label.len() as u64).to_be_bytes());
+    for chunk in label.chunks(DERIVE_CHUNK_LEN) {
+        key = absorb(key, DERIVE_CHUNK, chunk);
+    }
+    key
+}
+
+/// A ChaCha20-based deterministic random bit generator.
+struct Drbg {
+    key: ChaChaKey,
+}
+
+impl Drbg {
+    /// Append the next chunk of output to `out` and ratchet the key
+    /// forward.
+    fn generate(&mut self, out: &mut Vec<u8>) {
+        let mut keystream = chacha20(self.key, DRBG_NONCE, 0, &[0u8; DRBG_BLOCKS * 64]);
+        self.key.copy_from_slice(&keystream[..32]);
+        out.extend_from_slice(&keystream[32..]);
+        keystream.zeroize();
+    }
+}
+
+impl Drop for Drbg {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
 }
 
 /// A utility struct for providing random bytes and bits.
 pub struct Randomness {
     pub(crate) bytes: Vec<u8>,
     pub(crate) pointer: usize,
+    /// All bytes before this index have been wiped.
+    wiped: usize,
+    drbg: Option<Drbg>,
 }
 
 impl Randomness {
     /// Initialize the randomness provider.
     pub fn new(bytes: Vec<u8>) -> Self {
-        Self { bytes, pointer: 0 }
+        Self {
+            bytes,
+            pointer: 0,
+            wiped: 0,
+            drbg: None,
+        }
+    }
+
+    /// Initialize a randomness provider that never runs out, backed by a
+    /// ChaCha20 DRBG with the given seed.
+    ///
+    /// The same seed always produces the same sequence of random bytes.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self {
+            bytes: Vec::new(),
+            pointer: 0,
+            wiped: 0,
+            drbg: Some(Drbg { key: seed }),
+        }
+    }
+
+    /// Initialize a randomness provider that never runs out, backed by a
+    /// ChaCha20 DRBG seeded from the operating system's entropy source.
+    ///
+    /// Errors with `EntropyUnavailable` if the operating system does not
+    /// provide entropy.
+    #[cfg(feature = "getrandom")]
+    pub fn from_entropy() -> Result<Self, Error> {
+        let mut seed = [0u8; 32];
+        getrandom::getrandom(&mut seed).map_err(|_| Error::EntropyUnavailable)?;
+        Ok(Self::from_seed(seed))
     }
 
     /// Output a random bytes, or error, if more bytes are requested than are
     /// available.
+    ///
+    /// The returned bytes are wiped from the provider on its next call.
     pub fn bytes(&mut self, len: usize) -> Result<&[u8], Error> {
+        self.bytes[self.wiped..self.pointer].zeroize();
+        self.wiped = self.pointer;
+
         if self.pointer + len > self.bytes.len() {
-            return Err(Error::InsufficientRandomness);
+            let Some(drbg) = self.drbg.as_mut() else {
+                return Err(Error::InsufficientRandomness);
+            };
+
+            // Allocate the new buffer at its final size up front, since
+            // growing it would leave unwiped copies of the output behind.
+            let rest = self.bytes.len() - self.pointer;
+            let refills = len.saturating_sub(rest).div_ceil(DRBG_OUTPUT);
+            let mut bytes = Vec::with_capacity(rest + refills * DRBG_OUTPUT);
+            bytes.extend_from_slice(&self.bytes[self.pointer..]);
+            for _ in 0..refills {
+                drbg.generate(&mut bytes);
+            }
+            self.bytes.zeroize();
+            self.bytes = bytes;
+            self.pointer = 0;
+            self.wiped = 0;
         }
 
         let out = &self.bytes[self.pointer..self.pointer + len];
@@ -29,15 +161,129 @@ impl Randomness {
         Ok(out)
     }
 
+    /// Fork an independent child randomness provider, identified by
+    /// `label`.
+    ///
+    /// The child never runs out of randomness. Every fork draws 32 bytes
+    /// from the parent, or errors if these are not available, so forking
+    /// twice with the same label yields two different children.
+    pub fn fork(&mut self, label: &[u8]) -> Result<Randomness, Error> {
+        let key: ChaChaKey = self.bytes(32)?.try_into().unwrap();
+        Ok(Self::from_seed(derive_key(key, label)))
+    }
+
     /// Output a random boolean, consuming one byte internally, or error if  no
     /// random byte is available.
     pub fn bit(&mut self) -> Result<bool, Error> {
-        if self.pointer + 1 > self.bytes.len() {
-            return Err(Error::InsufficientRandomness);
+        let out = self.bytes(1)?[0];
+        Ok(out & 0x1 == 0x1)
+    }
+}
+
+impl Drop for Randomness {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_buffer_runs_out() {
+        let mut randomness = Randomness::new(vec![1, 2, 3]);
+        assert_eq!(randomness.bytes(2).unwrap(), &[1, 2]);
+        assert!(randomness.bit().unwrap());
+        assert!(randomness.bit().is_err());
+    }
+
+    #[test]
+    fn drbg_never_runs_out() {
+        let mut randomness = Randomness::from_seed([7u8; 32]);
+        let mut replay = Randomness::from_seed([7u8; 32]);
+        let mut other = Randomness::from_seed([8u8; 32]);
+
+        // Requests of varying sizes, across and beyond refill boundaries.
+        let mut output = Vec::new();
+        for len in [0, 1, 31, 32, 33, 992, 993, 4096, 100_000] {
+            output.extend_from_slice(randomness.bytes(len).unwrap());
         }
+        assert_eq!(replay.bytes(output.len()).unwrap(), output);
+        assert_ne!(other.bytes(output.len()).unwrap(), output);
 
-        let out = &self.bytes[self.pointer];
-        self.pointer += 1;
-        Ok(out & 0x1 == 0x1)
+        // The output does not repeat across refills.
+        let chunk = (DRBG_BLOCKS - 1) * 64 + 32;
+        assert_ne!(output[..chunk], output[chunk..2 * chunk]);
+    }
+
+    #[test]
+    fn fork() {
+        let mut parent = Randomness::from_seed([7u8; 32]);
+        let mut replay = Randomness::from_seed([7u8; 32]);
+
+        // Children only depend on the parent's output up to their fork and
+        // on their label.
+        let mut first = parent.fork(b"first").unwrap();
+        let mut second = parent.fork(b"second").unwrap();
+        let mut first_replay = replay.fork(b"first").unwrap();
+        let mut second_replay = replay.fork(b"second").unwrap();
+
+        let second = second.bytes(64).unwrap().to_vec();
+        assert_eq!(second_replay.bytes(64).unwrap(), second);
+        let first = first.bytes(64).unwrap().to_vec();
+        assert_eq!(first_replay.bytes(64).unwrap(), first);
+        assert_ne!(first, second);
+
+        // The label is mixed into the child's seed.
+        let mut relabeled = Randomness::from_seed([7u8; 32]).fork(b"second").unwrap();
+        assert_ne!(relabeled.bytes(64).unwrap(), first);
+
+        // Labels are encoded prefix-free.
+        let seed: ChaChaKey = Randomness::from_seed([7u8; 32])
+            .bytes(32)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_ne!(derive_key(seed, &[0u8; 12]), derive_key(seed, &[0u8; 11]));
+
+        // Reusing a label yields a fresh child.
+        let mut again = parent.fork(b"first").unwrap();
+        assert_ne!(again.bytes(64).unwrap(), first);
+
+        // Every fork draws 32 bytes from the parent.
+        let mut buffer = Randomness::new(vec![1u8; 63]);
+        assert!(buffer.fork(b"first").is_ok());
+        assert!(buffer.fork(b"second").is_err());
+        assert!(Randomness::new(vec![1u8; 31]).fork(b"first").is_err());
+    }
+
+    #[test]
+    fn consumed_bytes_are_wiped() {
+        let mut randomness = Randomness::new(vec![1, 2, 3, 4]);
+        assert_eq!(randomness.bytes(2).unwrap(), &[1, 2]);
+        randomness.bytes(1).unwrap();
+        assert_eq!(randomness.bytes, [0, 0, 3, 4]);
+
+        let mut randomness = Randomness::from_seed([7u8; 32]);
+        randomness.bytes(10).unwrap();
+        randomness.bytes(1).unwrap();
+        assert_eq!(randomness.bytes[..10], [0; 10]);
+        assert_ne!(randomness.bytes[10], 0);
+
+        // Unconsumed bytes are carried over on refill.
+        let mut replay = Randomness::from_seed([7u8; 32]);
+        let output = replay.bytes(DRBG_OUTPUT + 1).unwrap().to_vec();
+        randomness.bytes(DRBG_OUTPUT - 12).unwrap();
+        assert_eq!(randomness.bytes(2).unwrap(), &output[DRBG_OUTPUT - 1..]);
+        assert_eq!(randomness.bytes.len(), DRBG_OUTPUT + 1);
+    }
+
+    #[cfg(feature = "getrandom")]
+    #[test]
+    fn drbg_from_entropy() {
+        let mut first = Randomness::from_entropy().unwrap();
+        let mut second = Randomness::from_entropy().unwrap();
+        assert_ne!(first.bytes(32).unwrap(), second.bytes(32).unwrap());
     }
 }
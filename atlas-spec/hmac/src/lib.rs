@@ -62,3 +62,42 @@ pub fn hkdf_expand(prk: &[u8], info: &[u8], l: usize) -> Vec<u8> {
     }
     t[0..l].to_vec()
 }
+
+// === PBKDF2 ===
+
+/// PBKDF2 with HMAC-SHA256 as the pseudorandom function, as specified in
+/// [RFC8018], Section 5.2.
+///
+/// ```text
+/// DK = T_1 || T_2 || ... || T_l
+/// T_i = U_1 XOR U_2 XOR ... XOR U_c
+/// U_1 = PRF(P, S || INT(i))
+/// U_j = PRF(P, U_{j-1})
+/// ```
+pub fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32, l: usize) -> Vec<u8> {
+    let n = l.div_ceil(HASH_LEN);
+
+    let mut dk = Vec::with_capacity(n * HASH_LEN);
+    for i in 1..=n {
+        let mut u = hmac(password, &salt.concat(&(i as u32).to_be_bytes()));
+        let mut t = u.clone();
+        for _ in 1..iterations {
+            u = hmac(password, &u);
+            t = xor_slice(t, &u);
+        }
+        dk.extend_from_slice(&t);
+    }
+    dk[0..l].to_vec()
+}
+
+#[test]
+fn pbkdf2_rfc7914_vector() {
+    let expected = [
+        0x55, 0xac, 0x04, 0x6e, 0x56, 0xe3, 0x08, 0x9f, 0xec, 0x16, 0x91, 0xc2, 0x25, 0x44, 0xb6,
+        0x05, 0xf9, 0x41, 0x85, 0x21, 0x6d, 0xde, 0x04, 0x65, 0xe6, 0x8b, 0x9d, 0x57, 0xc2, 0x0d,
+        0xac, 0xbc, 0x49, 0xca, 0x9c, 0xcc, 0xf1, 0x79, 0xb6, 0x45, 0x99, 0x16, 0x64, 0xb3, 0x9d,
+        0x77, 0xef, 0x31, 0x7c, 0x71, 0xb8, 0x45, 0xb1, 0xe3, 0x0b, 0xd5, 0x09, 0x11, 0x20, 0x41,
+        0xd3, 0xa1, 0x97, 0x83,
+    ];
+    assert_eq!(pbkdf2(b"passwd", b"salt", 1, 64), expected.to_vec());
+}
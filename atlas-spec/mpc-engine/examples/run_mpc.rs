@@ -3,8 +3,6 @@ use std::thread;
 use hacspec_lib::Randomness;
 use mpc_engine::circuit::{Circuit, WiredGate};
 
-use rand::RngCore;
-
 fn build_circuit() -> Circuit {
     Circuit {
         input_widths: vec![1, 1, 1, 1],
@@ -37,10 +35,7 @@ fn main() {
             .expect("every party should have a channel configuration");
         let c = circuit.clone();
         let party_join_handle = thread::spawn(move || {
-            let mut rng = rand::thread_rng();
-            let mut bytes = vec![0u8; 100 * usize::from(u16::MAX)];
-            rng.fill_bytes(&mut bytes);
-            let mut rng = Randomness::new(bytes);
+            let mut rng = Randomness::from_entropy().unwrap();
             let log_enabled = channel_config.id == 0;
             let input = rng.bit().unwrap();
             eprintln!("Starting party {} with input: {}", channel_config.id, input);
@@ -0,0 +1,578 @@
+#![warn(missing_docs)]
+//! ## E.5. Distributed Key Generation
+//!
+//! [Dealing](super::coprf_threshold::deal) threshold evaluators requires a
+//! dealer that knows the master secret, and thereby every evaluation key.
+//! Instead, the `n` evaluators can generate their seeds and their common
+//! blinding key share jointly, such that no single party ever knows all
+//! seeds.
+//!
+//! The protocol follows the distributed key generation of [Ped91], using
+//! Feldman commitments [Fel87] to make every dealt value verifiable. Since
+//! the evaluators of [threshold evaluation](super::coprf_threshold) hold
+//! replicated seeds rather than Shamir shares of a scalar, each party acts
+//! as a dealer of one random contribution `rho_{j,T}` per seed `r_T` it
+//! may know, i.e. per set `T` of `t - 1` evaluators it does not belong to,
+//! and of one contribution `x_j` to the blinding key share. The protocol
+//! runs in four rounds:
+//!
+//! 1. Every dealer `j` broadcasts the commitments `rho_{j,T} * G` and
+//!    `x_j * G`, and privately sends every other party `i` the
+//!    contributions `rho_{j,T}` for all `T` not containing `i`, as well as
+//!    `x_j`.
+//! 2. Every party checks the received contributions against the dealer's
+//!    commitments and broadcasts a complaint against every dealer whose
+//!    contributions are missing or do not match.
+//! 3. Every dealer answers each complaint against it by broadcasting the
+//!    contributions it sent to the complaining party.
+//! 4. A dealer is disqualified if its commitments are malformed, or if it
+//!    did not answer a complaint with contributions matching its
+//!    commitments. Answered contributions replace the ones received in
+//!    round 1. Every party derives its seeds from the contributions of the
+//!    qualified dealers:
+//!
+//! ```text
+//! r_T = H(T || (j || rho_{j,T}) for all qualified j not in T)
+//! x = sum_j x_j for all qualified j
+//! ```
+//!
+//! Broadcast messages are assumed to be received identically by all
+//! parties, so all honest parties agree on the set of qualified dealers and
+//! on the contributions they use.
+//!
+//! Contributions to `r_T` are only ever sent to the parties outside of `T`
+//! and only published in answer to a complaint by one of them. Thus, as
+//! long as one party outside of `T` is honest, `r_T` is unknown to the
+//! parties in `T`, and any `t - 1` parties learn nothing about the
+//! evaluation keys. As in threshold evaluation, the blinding key share is
+//! known to every evaluator.
+//!
+//! [Ped91]: https://doi.org/10.1007/3-540-46416-6_47
+//! [Fel87]: https://doi.org/10.1109/SFCS.1987.4
+
+use std::collections::BTreeMap;
+
+use hacspec_lib::{hacspec_helper::NatMod, Randomness};
+use p256::{P256Point, P256Scalar};
+
+use super::coprf_threshold::{check_parameters, subsets, PartyIndex, ThresholdEvaluatorContext};
+use crate::Error;
+
+/// Domain separator for sampling contributions.
+const DST_CONTRIBUTION: &[u8] = b"CoPRF-DKGContribution";
+/// Domain separator for the derivation of seeds from contributions.
+const DST_SEED: &[u8] = b"CoPRF-DKGSeed";
+
+/// The contributions of a dealer towards one party.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contributions {
+    /// The contributions to the seeds known to the party, by the set of
+    /// evaluators which do not know them.
+    pub seeds: BTreeMap<Vec<PartyIndex>, P256Scalar>,
+    /// The contribution to the blinding key share.
+    pub blinding: P256Scalar,
+}
+
+/// The commitments to all contributions of a dealer, broadcast in round 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DealerCommitments {
+    /// The index of the dealer.
+    pub dealer: PartyIndex,
+    /// The commitments to the seed contributions, by the set of evaluators
+    /// which do not know the seed.
+    pub seeds: BTreeMap<Vec<PartyIndex>, P256Point>,
+    /// The commitment to the blinding key share contribution.
+    pub blinding: P256Point,
+}
+
+/// The contributions of a dealer towards one party, sent privately in
+/// round 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DealerShare {
+    /// The index of the dealer.
+    pub dealer: PartyIndex,
+    /// The index of the receiving party.
+    pub recipient: PartyIndex,
+    /// The dealer's contributions towards the receiving party.
+    pub contributions: Contributions,
+}
+
+/// A complaint against a dealer, broadcast in round 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Complaint {
+    /// The index of the dealer.
+    pub dealer: PartyIndex,
+    /// The index of the complaining party.
+    pub complainant: PartyIndex,
+}
+
+/// A dealer's answer to a complaint, broadcast in round 3.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Justification {
+    /// The index of the dealer.
+    pub dealer: PartyIndex,
+    /// The index of the complaining party.
+    pub complainant: PartyIndex,
+    /// The dealer's contributions towards the complaining party.
+    pub contributions: Contributions,
+}
+
+/// A party to the distributed key generation.
+pub struct DkgParticipant {
+    index: PartyIndex,
+    threshold: u32,
+    parties: u32,
+    /// The party's own seed contributions, by the set of evaluators which
+    /// do not know the seed.
+    seeds: BTreeMap<Vec<PartyIndex>, P256Scalar>,
+    blinding: P256Scalar,
+    /// The contributions received from other dealers in round 1.
+    received: BTreeMap<PartyIndex, Contributions>,
+}
+
+impl DkgParticipant {
+    /// ### E.5.1. Participant Setup
+    /// Sets up party `index` of `parties` parties, any `threshold` of which
+    /// will be able to evaluate, and samples its contributions.
+    ///
+    /// Raises:
+    /// - `InvalidInputError`: If `threshold` and `parties` are not
+    ///   supported for [threshold
+    ///   evaluation](super::coprf_threshold::deal), or `index` is not
+    ///   between `1` and `parties`.
+    pub fn new(
+        index: PartyIndex,
+        threshold: u32,
+        parties: u32,
+        randomness: &mut Randomness,
+    ) -> Result<Self, Error> {
+        check_parameters(threshold, parties)?;
+        if index == 0 || index > parties {
+            return Err(Error::InvalidInputError);
+        }
+
+        let mut seeds = BTreeMap::new();
+        for excluded in subsets(parties, threshold - 1) {
+            if !excluded.contains(&index) {
+                seeds.insert(excluded, p256::random_scalar(randomness, DST_CONTRIBUTION)?);
+            }
+        }
+
+        Ok(DkgParticipant {
+            index,
+            threshold,
+            parties,
+            seeds,
+            blinding: p256::random_scalar(randomness, DST_CONTRIBUTION)?,
+            received: BTreeMap::new(),
+        })
+    }
+
+    /// The index of the party.
+    pub fn index(&self) -> PartyIndex {
+        self.index
+    }
+
+    /// ### E.5.2. Dealing
+    /// The commitments to the party's contributions, to be broadcast.
+    pub fn commitments(&self) -> Result<DealerCommitments, Error> {
+        let mut seeds = BTreeMap::new();
+        for (excluded, contribution) in &self.seeds {
+            seeds.insert(excluded.clone(), p256::p256_point_mul_base(*contribution)?);
+        }
+        Ok(DealerCommitments {
+            dealer: self.index,
+            seeds,
+            blinding: p256::p256_point_mul_base(self.blinding)?,
+        })
+    }
+
+    /// The party's contributions towards every other party, to be sent
+    /// privately to their recipients.
+    pub fn shares(&self) -> Vec<DealerShare> {
+        (1..=self.parties)
+            .filter(|recipient| *recipient != self.index)
+            .map(|recipient| DealerShare {
+                dealer: self.index,
+                recipient,
+                contributions: self.contributions_for(recipient),
+            })
+            .collect()
+    }
+
+    /// ### E.5.3. Verification
+    /// Checks the contributions received from every other dealer against
+    /// its commitments and returns the complaints to be broadcast.
+    ///
+    /// Dealers with malformed commitments are disqualified by every party
+    /// and need no complaint.
+    pub fn receive(
+        &mut self,
+        commitments: &[DealerCommitments],
+        shares: &[DealerShare],
+    ) -> Vec<Complaint> {
+        let mut complaints = Vec::new();
+        for dealer in (1..=self.parties).filter(|dealer| *dealer != self.index) {
+            let Some(dealer_commitments) = self.well_formed(commitments, dealer) else {
+                continue;
+            };
+            let contributions = shares
+                .iter()
+                .find(|share| share.dealer == dealer && share.recipient == self.index)
+                .map(|share| &share.contributions)
+                .filter(|contributions| {
+                    verify_contributions(dealer_commitments, self.index, contributions)
+                });
+            match contributions {
+                Some(contributions) => {
+                    self.received.insert(dealer, contributions.clone());
+                }
+                None => complaints.push(Complaint {
+                    dealer,
+                    complainant: self.index,
+                }),
+            }
+        }
+        complaints
+    }
+
+    /// ### E.5.4. Justification
+    /// Answers every complaint against the party by revealing the
+    /// contributions it sent to the complaining party.
+    pub fn justify(&self, complaints: &[Complaint]) -> Vec<Justification> {
+        complaints
+            .iter()
+            .filter(|complaint| {
+                complaint.dealer == self.index
+                    && complaint.complainant != self.index
+                    && (1..=self.parties).contains(&complaint.complainant)
+            })
+            .map(|complaint| Justification {
+                dealer: self.index,
+                complainant: complaint.complainant,
+                contributions: self.contributions_for(complaint.complainant),
+            })
+            .collect()
+    }
+
+    /// ### E.5.5. Finalization
+    /// Determines the qualified dealers and derives the party's threshold
+    /// evaluator context from their contributions.
+    ///
+    /// All arguments are the messages broadcast in rounds 1 to 3.
+    ///
+    /// Raises:
+    /// - `InvalidInputError`: If the party itself is disqualified, or a
+    ///   qualified dealer's contributions towards the party are unknown.
+    pub fn finalize(
+        self,
+        commitments: &[DealerCommitments],
+        complaints: &[Complaint],
+        justifications: &[Justification],
+    ) -> Result<ThresholdEvaluatorContext, Error> {
+        let qualified = self.qualified(commitments, complaints, justifications);
+        if !qualified.contains(&self.index) {
+            return Err(Error::InvalidInputError);
+        }
+
+        // Collect the contributions of all qualified dealers, preferring
+        // publicly justified ones over those received privately.
+        let mut contributions = BTreeMap::new();
+        for dealer in &qualified {
+            let dealer_contributions = if *dealer == self.index {
+                self.contributions_for(self.index)
+            } else {
+                justifications
+                    .iter()
+                    .find(|j| j.dealer == *dealer && j.complainant == self.index)
+                    .map(|j| &j.contributions)
+                    .or_else(|| self.received.get(dealer))
+                    .cloned()
+                    .ok_or(Error::InvalidInputError)?
+            };
+            contributions.insert(*dealer, dealer_contributions);
+        }
+
+        let mut seeds = BTreeMap::new();
+        for excluded in self.seeds.keys() {
+            let mut seed_material = DST_SEED.to_vec();
+            for j in excluded {
+                seed_material.extend_from_slice(&j.to_be_bytes());
+            }
+            for (dealer, dealer_contributions) in &contributions {
+                if let Some(contribution) = dealer_contributions.seeds.get(excluded) {
+                    seed_material.extend_from_slice(&dealer.to_be_bytes());
+                    seed_material.extend_from_slice(&contribution.to_be_bytes());
+                }
+            }
+            seeds.insert(excluded.clone(), sha256::hash(&seed_material));
+        }
+
+        let mut blinding_key_share = P256Scalar::zero();
+        for dealer_contributions in contributions.values() {
+            blinding_key_share = blinding_key_share + dealer_contributions.blinding;
+        }
+
+        Ok(ThresholdEvaluatorContext {
+            index: self.index,
+            threshold: self.threshold,
+            parties: self.parties,
+            seeds,
+            blinding_key_share,
+        })
+    }
+
+    /// The party's contributions towards `recipient`.
+    fn contributions_for(&self, recipient: PartyIndex) -> Contributions {
+        Contributions {
+            seeds: self
+                .seeds
+                .iter()
+                .filter(|(excluded, _)| !excluded.contains(&recipient))
+                .map(|(excluded, contribution)| (excluded.clone(), *contribution))
+                .collect(),
+            blinding: self.blinding,
+        }
+    }
+
+    /// The commitments of `dealer`, if it broadcast exactly one set of
+    /// commitments, covering exactly the seeds it may know.
+    fn well_formed<'a>(
+        &self,
+        commitments: &'a [DealerCommitments],
+        dealer: PartyIndex,
+    ) -> Option<&'a DealerCommitments> {
+        let mut candidates = commitments.iter().filter(|c| c.dealer == dealer);
+        let dealer_commitments = candidates.next()?;
+        if candidates.next().is_some() {
+            return None;
+        }
+        let expected = subsets(self.parties, self.threshold - 1)
+            .into_iter()
+            .filter(|excluded| !excluded.contains(&dealer));
+        if !dealer_commitments.seeds.keys().cloned().eq(expected) {
+            return None;
+        }
+        Some(dealer_commitments)
+    }
+
+    /// The dealers with well-formed commitments which answered every
+    /// complaint against them correctly.
+    fn qualified(
+        &self,
+        commitments: &[DealerCommitments],
+        complaints: &[Complaint],
+        justifications: &[Justification],
+    ) -> Vec<PartyIndex> {
+        (1..=self.parties)
+            .filter(|dealer| {
+                let Some(dealer_commitments) = self.well_formed(commitments, *dealer) else {
+                    return false;
+                };
+                complaints
+                    .iter()
+                    .filter(|complaint| {
+                        complaint.dealer == *dealer
+                            && complaint.complainant != *dealer
+                            && (1..=self.parties).contains(&complaint.complainant)
+                    })
+                    .all(|complaint| {
+                        justifications.iter().any(|justification| {
+                            justification.dealer == *dealer
+                                && justification.complainant == complaint.complainant
+                                && verify_contributions(
+                                    dealer_commitments,
+                                    complaint.complainant,
+                                    &justification.contributions,
+                                )
+                        })
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Checks that `contributions` are exactly the contributions towards
+/// `recipient` committed to in `commitments`.
+fn verify_contributions(
+    commitments: &DealerCommitments,
+    recipient: PartyIndex,
+    contributions: &Contributions,
+) -> bool {
+    let expected = commitments
+        .seeds
+        .iter()
+        .filter(|(excluded, _)| !excluded.contains(&recipient))
+        .collect::<Vec<_>>();
+    expected.len() == contributions.seeds.len()
+        && expected.into_iter().all(|(excluded, commitment)| {
+            contributions
+                .seeds
+                .get(excluded)
+                .is_some_and(|contribution| commits_to(commitment, *contribution))
+        })
+        && commits_to(&commitments.blinding, contributions.blinding)
+}
+
+/// Checks that `commitment = contribution * G`.
+fn commits_to(commitment: &P256Point, contribution: P256Scalar) -> bool {
+    p256::p256_point_mul_base(contribution).is_ok_and(|point| point == *commitment)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coprf::{
+        coprf_online::{blind, finalize},
+        coprf_setup::CoPRFReceiverContext,
+        coprf_threshold::{
+            blind_evaluate_partial, combine_evaluations, combine_public_keys,
+            derive_public_key_share,
+        },
+    };
+
+    use super::*;
+
+    fn generate_randomness() -> Randomness {
+        use rand::prelude::*;
+
+        let mut randomness = [0u8; 100000];
+        rand::thread_rng().fill_bytes(&mut randomness);
+        Randomness::new(randomness.to_vec())
+    }
+
+    /// Runs the protocol between the given parties, letting `tamper`
+    /// modify the privately sent shares and `withhold` drop justifications
+    /// of the given dealer.
+    fn run(
+        mut participants: Vec<DkgParticipant>,
+        tamper: impl Fn(&mut DealerShare),
+        withhold: Option<PartyIndex>,
+    ) -> (
+        Vec<Complaint>,
+        Vec<Result<ThresholdEvaluatorContext, Error>>,
+    ) {
+        let commitments = participants
+            .iter()
+            .map(|p| p.commitments().unwrap())
+            .collect::<Vec<_>>();
+        let mut shares = participants
+            .iter()
+            .flat_map(|p| p.shares())
+            .collect::<Vec<_>>();
+        shares.iter_mut().for_each(tamper);
+
+        let complaints = participants
+            .iter_mut()
+            .flat_map(|p| p.receive(&commitments, &shares))
+            .collect::<Vec<_>>();
+        let justifications = participants
+            .iter()
+            .filter(|p| Some(p.index()) != withhold)
+            .flat_map(|p| p.justify(&complaints))
+            .collect::<Vec<_>>();
+
+        let contexts = participants
+            .into_iter()
+            .map(|p| p.finalize(&commitments, &complaints, &justifications))
+            .collect();
+        (complaints, contexts)
+    }
+
+    fn participants(
+        threshold: u32,
+        parties: u32,
+        randomness: &mut Randomness,
+    ) -> Vec<DkgParticipant> {
+        (1..=parties)
+            .map(|index| DkgParticipant::new(index, threshold, parties, randomness).unwrap())
+            .collect()
+    }
+
+    /// Checks that all quorums of the given evaluators interpolate to the
+    /// same public key.
+    fn assert_consistent(evaluators: &[&ThresholdEvaluatorContext]) {
+        let public_keys = evaluators
+            .iter()
+            .map(|e| (e.index(), derive_public_key_share(e, b"1").unwrap()))
+            .collect::<Vec<_>>();
+        let threshold = evaluators[0].threshold() as usize;
+        let public_key = combine_public_keys(&public_keys[..threshold]).unwrap();
+        for quorum in public_keys.windows(threshold) {
+            assert_eq!(combine_public_keys(quorum).unwrap(), public_key);
+        }
+        assert!(evaluators
+            .iter()
+            .all(|e| e.blinding_key_share == evaluators[0].blinding_key_share));
+    }
+
+    #[test]
+    fn distributed_key_generation() {
+        let mut randomness = generate_randomness();
+        let (complaints, contexts) = run(participants(3, 5, &mut randomness), |_| {}, None);
+        assert!(complaints.is_empty());
+        let evaluators = contexts.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_consistent(&evaluators.iter().collect::<Vec<_>>());
+
+        // No party knows the seeds of the sets it belongs to.
+        for evaluator in &evaluators {
+            assert_eq!(evaluator.seeds.len(), 6);
+            assert!(evaluator
+                .seeds
+                .keys()
+                .all(|excluded| !excluded.contains(&evaluator.index)));
+        }
+
+        // Different quorums evaluate to the same PRF output.
+        let receiver_context = CoPRFReceiverContext::new(&mut randomness);
+        let bpk = receiver_context.get_bpk();
+        let blind_input = blind(bpk, b"TestInput", b"Test".to_vec(), &mut randomness).unwrap();
+        let evaluate = |quorum: &[ThresholdEvaluatorContext], randomness: &mut Randomness| {
+            let partials = quorum
+                .iter()
+                .map(|e| blind_evaluate_partial(e, b"1", bpk, blind_input, randomness).unwrap())
+                .collect::<Vec<_>>();
+            finalize(&receiver_context, combine_evaluations(&partials).unwrap()).unwrap()
+        };
+        assert_eq!(
+            evaluate(&evaluators[..3], &mut randomness),
+            evaluate(&evaluators[2..], &mut randomness)
+        );
+
+        assert!(DkgParticipant::new(0, 3, 5, &mut randomness).is_err());
+        assert!(DkgParticipant::new(6, 3, 5, &mut randomness).is_err());
+        assert!(DkgParticipant::new(1, 6, 5, &mut randomness).is_err());
+    }
+
+    #[test]
+    fn complaints() {
+        let mut randomness = generate_randomness();
+        let tamper = |share: &mut DealerShare| {
+            if share.dealer == 1 && share.recipient == 2 {
+                share.contributions.blinding = share.contributions.blinding + P256Scalar::one();
+            }
+        };
+
+        // A justified complaint leaves the dealer qualified.
+        let (complaints, contexts) = run(participants(2, 4, &mut randomness), tamper, None);
+        assert_eq!(
+            complaints,
+            vec![Complaint {
+                dealer: 1,
+                complainant: 2
+            }]
+        );
+        let evaluators = contexts.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_consistent(&evaluators.iter().collect::<Vec<_>>());
+
+        // An unanswered complaint disqualifies the dealer, which can no
+        // longer finalize, while the other parties agree on the remaining
+        // contributions.
+        let (_, contexts) = run(participants(2, 4, &mut randomness), tamper, Some(1));
+        assert_eq!(contexts[0].as_ref().err(), Some(&Error::InvalidInputError));
+        let evaluators = contexts[1..]
+            .iter()
+            .map(|c| c.as_ref().unwrap())
+            .collect::<Vec<_>>();
+        assert_consistent(&evaluators);
+    }
+}
@@ -5,7 +5,7 @@
 //! keys are derived from a master secret.
 
 use hacspec_lib::Randomness;
-use p256::P256Scalar;
+use p256::{P256Point, P256Scalar};
 
 use crate::{
     protocol::configuration::{create_context_string, ModeID},
@@ -14,6 +14,9 @@ use crate::{
 
 /// Domain separator for scalar sampling during CoPRF key generation.
 const DST_KEYGEN: &[u8] = b"CoPRF-KeyGeneration";
+/// Domain separator for scalar sampling during generation of the
+/// evaluator's blinding key share.
+const DST_BLINDING_SHARE: &[u8] = b"CoPRF-BlindingKeyShare";
 
 /// As blinding is performed by Elgamal encryption, the blinding public
 /// key is an Elgamal encryption key.
@@ -86,6 +89,27 @@ impl CoPRFEvaluatorContext {
         let msk = randomness.bytes(COPRF_MSK_BYTES)?.to_vec();
         Ok(CoPRFEvaluatorContext { msk })
     }
+
+    /// Restore an evaluator context from a previously exported master
+    /// secret. The master secret must be exactly `COPRF_MSK_BYTES` long.
+    pub fn from_master_secret(msk: &[u8]) -> Result<Self, Error> {
+        if msk.len() != COPRF_MSK_BYTES {
+            return Err(Error::InvalidInputError);
+        }
+        Ok(CoPRFEvaluatorContext { msk: msk.to_vec() })
+    }
+
+    /// Retrieves the master secret, e.g. for persistent storage.
+    pub fn master_secret(&self) -> &[u8] {
+        &self.msk
+    }
+
+    /// Retrieves the public part of the evaluator's blinding key share. For
+    /// verifiable evaluation, requesters blind towards the
+    /// [joint blinding key](joint_blinding_key) of receiver and evaluator.
+    pub fn blinding_key_share(&self) -> Result<BlindingPublicKey, Error> {
+        Ok(p256::p256_point_mul_base(derive_blinding_key_share(self)?)?)
+    }
 }
 
 impl CoPRFReceiverContext {
@@ -96,6 +120,19 @@ impl CoPRFReceiverContext {
         let (bsk, bpk) = generate_blinding_key_pair(randomness).unwrap();
         CoPRFReceiverContext { bsk, bpk }
     }
+
+    /// Restore a receiver context from a previously exported unblinding
+    /// private key. The blinding public key is recomputed from it.
+    pub fn from_private_key(bsk: BlindingPrivateKey) -> Result<Self, Error> {
+        let bpk = p256::p256_point_mul_base(bsk)?;
+        Ok(CoPRFReceiverContext { bsk, bpk })
+    }
+
+    /// Retrieves the receivers unblinding private key, e.g. for persistent
+    /// storage.
+    pub fn get_bsk(&self) -> BlindingPrivateKey {
+        self.bsk
+    }
 }
 
 /// ### E.1.4. Blinding Key Generation
@@ -114,7 +151,31 @@ fn generate_blinding_key_pair(
     Ok((bsk, bpk))
 }
 
-/// ### E.1.5. Evaluation Key Derivation
+/// ### E.1.5. Joint Blinding Keys
+/// For [verifiable evaluation](super::coprf_verifiable), the evaluator
+/// holds a share of the blinding private key, which it derives from its
+/// master secret. Inputs are blinded towards the joint blinding key, such
+/// that neither the receiver nor the evaluator alone can unblind them.
+pub fn joint_blinding_key(
+    bpk: BlindingPublicKey,
+    blinding_key_share: BlindingPublicKey,
+) -> Result<BlindingPublicKey, Error> {
+    Ok(p256::point_add(bpk, blinding_key_share)?)
+}
+
+/// Derives the evaluator's share of the joint blinding private key.
+pub fn derive_blinding_key_share(
+    context: &CoPRFEvaluatorContext,
+) -> Result<BlindingPrivateKey, Error> {
+    let random_bytes = sha256::hash(&context.msk);
+    p256::random_scalar(
+        &mut Randomness::new(random_bytes.to_vec()),
+        DST_BLINDING_SHARE,
+    )
+    .map_err(|e| e.into())
+}
+
+/// ### E.1.6. Evaluation Key Derivation
 /// [Lehman] recommends a key derivation procedure using an underlying PRF
 /// which maps from bitstrings to a finite field, such that the field is
 /// compatible with the homomorphism afforded by the encryption scheme.
@@ -130,3 +191,12 @@ pub fn derive_key(context: &CoPRFEvaluatorContext, key_id: &[u8]) -> Result<CoPR
     p256::random_scalar(&mut Randomness::new(random_bytes.to_vec()), DST_KEYGEN)
         .map_err(|e| e.into())
 }
+
+/// The public key of a coPRF evaluation key, against which verifiable
+/// evaluation results are checked.
+pub fn derive_public_key(
+    context: &CoPRFEvaluatorContext,
+    key_id: &[u8],
+) -> Result<P256Point, Error> {
+    Ok(p256::p256_point_mul_base(derive_key(context, key_id)?)?)
+}
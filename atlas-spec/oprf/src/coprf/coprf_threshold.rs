@@ -0,0 +1,1129 @@
+#![warn(missing_docs)]
+//! ## E.4. Threshold Evaluation
+//!
+//! A single evaluator holding the master secret can evaluate the PRF on
+//! any input at will. In threshold mode, the evaluator is instead
+//! replaced by `n` evaluators, each of which holds a Shamir share of every
+//! evaluation key, such that any `t` of them can jointly evaluate, while
+//! any `t - 1` of them learn nothing about the evaluation keys.
+//!
+//! ### Key Shares
+//! Evaluators derive their shares of an evaluation key from the key
+//! identifier without interaction, using pseudo-random secret sharing
+//! [CDI05]. For every set `T` of `t - 1` evaluators there is a seed `r_T`,
+//! which is known to all evaluators outside of `T`. The evaluation key
+//! with identifier `id` is
+//!
+//! ```text
+//! k = sum_T F(r_T, id)
+//! ```
+//!
+//! where `F` is a PRF mapping to scalars, and evaluator `i` holds the share
+//!
+//! ```text
+//! k_i = sum_{T, i not in T} F(r_T, id) * f_T(i)
+//! f_T(z) = prod_{j in T} (j - z) / j
+//! ```
+//!
+//! Since `f_T` has degree `t - 1`, `f_T(0) = 1` and `f_T(j) = 0` for all
+//! `j` in `T`, the shares `k_i` lie on a polynomial of degree `t - 1` with
+//! constant term `k`. For any `t - 1` evaluators, the seed of the set they
+//! form is unknown to them, so `k` is pseudo-random from their view.
+//!
+//! The same seeds yield shares of random zeros on polynomials of degree
+//! `2t - 2`, which are used below to mask products of shares.
+//!
+//! ### Blind Evaluation
+//! Each evaluator `i` of a quorum `Q` of `t` evaluators returns the
+//! partial result `k_i * c` on the blinded input `c`, rerandomized
+//! towards the receiver. The receiver, or any other combiner, obtains the
+//! blind evaluation result by Lagrange interpolation in the exponent:
+//!
+//! ```text
+//! c' = sum_{i in Q} lambda_i * (k_i * c)
+//! lambda_i = prod_{j in Q, j != i} j / (j - i)
+//! ```
+//!
+//! This is a blinded `k * c`, since the rerandomizations of all partial
+//! results add up to a rerandomization of the result.
+//!
+//! ### Blind Conversion
+//! Conversion from `k_from` to `k_to` requires the scalar `k_to / k_from`,
+//! which is not linear in the shares. Evaluators therefore derive shares
+//! of a random mask `r` and each evaluator `i` returns the partial result
+//! `d_i * c` together with the mask share `w_i`, where
+//!
+//! ```text
+//! d_i = k_to_i * r_i + z_i
+//! w_i = k_from_i * r_i + z'_i
+//! ```
+//!
+//! and `z_i, z'_i` are shares of zero. Both lie on polynomials of degree
+//! `2t - 2`, so conversion requires a quorum of `2t - 1` evaluators. The
+//! combiner interpolates `d * c = k_to * r * c` and `w = k_from * r` and
+//! outputs `w^-1 * (d * c)`. Since `r` is random, `w` reveals nothing
+//! about `k_from`.
+//!
+//! ### Verifiable Threshold Evaluation
+//! In verifiable mode, the evaluators hold a common blinding key share,
+//! and each partial result is computed and proven as in
+//! [verifiable evaluation](super::coprf_verifiable), using the key share
+//! `k_i` for evaluation and the scalar `d_i` for conversion. The public
+//! keys of the partial proofs are `K_i = k_i * G` and `T_i = d_i * K_from`,
+//! respectively, and interpolate to the public key `K` of the evaluation
+//! key, and to `w * K_to`.
+//!
+//! ### Key Rotation
+//! Evaluators moving to fresh seeds, e.g. after a compromise, convert
+//! existing PRF outputs from the keys derived from their old seeds to the
+//! keys with the same identifiers derived from the new seeds. If every
+//! evaluator holds its old and new context under the same index, it
+//! returns the partial result for conversion as above, with
+//!
+//! ```text
+//! d_i = k_to_i * r_i + z_i
+//! ```
+//!
+//! where `k_to_i` is its share of the new key and the mask share `r_i` and
+//! zero share `z_i` are derived from the old seeds. The shares of the new
+//! keys lie on polynomials of degree `t - 1` as well, so the partial
+//! results combine as for regular conversion, and are proven using the
+//! old blinding key share.
+//!
+//! ### Migration from a Single Evaluator
+//! The evaluation keys derived from the seeds differ from the keys the
+//! dealer derives from its master secret. PRF outputs under the dealer's
+//! keys are moved to the evaluators' keys by a single conversion with the
+//! scalar `k_to / k_from`, where `k_from` is the dealer's key and `k_to` is
+//! reconstructed from the shares of `t` evaluators, see [`reconstruct_key`].
+//! Since this reveals `k_to` to whoever holds the evaluators' contexts, the
+//! migration is done by the dealer, before handing out the contexts.
+//!
+//! Threshold evaluation protects the evaluation keys. The blinding key
+//! share only protects blinded inputs against the receiver, and is held by
+//! every evaluator.
+//!
+//! [CDI05]: https://doi.org/10.1007/978-3-540-30576-7_19
+
+use std::collections::BTreeMap;
+
+use hacspec_lib::{hacspec_helper::NatMod, Randomness};
+use p256::{P256Point, P256Scalar};
+
+use super::{
+    coprf_online::{blind_evaluate, BlindInput, BlindOutput},
+    coprf_setup::{
+        derive_blinding_key_share, BlindingPrivateKey, BlindingPublicKey, CoPRFEvaluatorContext,
+        CoPRFKey,
+    },
+    coprf_verifiable::{
+        blind_evaluate_verifiable, evaluate_precomputed_with_proof, verify_conversion,
+        verify_evaluation, ConversionScalars, Proof,
+    },
+};
+use crate::{p256_sha256, Error};
+
+/// Domain separator for the derivation of seeds from a master secret.
+const DST_SEED: &[u8] = b"CoPRF-ThresholdSeed";
+/// Context string for the PRF used to derive shares from seeds.
+const CONTEXT_SHARE: &[u8] = b"CoPRF-ThresholdShare";
+
+/// Marks the derivation of a share of an evaluation key.
+const LABEL_KEY: u8 = 0;
+/// Marks the derivation of a share of a conversion mask.
+const LABEL_MASK: u8 = 1;
+/// Marks the derivation of a share of zero, followed by its purpose and
+/// the degree of the monomial it contributes to.
+const LABEL_ZERO: u8 = 2;
+/// Marks the derivation of a share of a key rotation mask.
+const LABEL_UPDATE_MASK: u8 = 3;
+/// Purpose of the zero share masking the mask share `w_i`.
+const ZERO_MASK: u8 = 0;
+/// Purpose of the zero share masking the conversion scalar `d_i`.
+const ZERO_CONVERSION: u8 = 1;
+/// Purpose of the zero share masking the mask share `w_i` of a key
+/// rotation.
+const ZERO_UPDATE_MASK: u8 = 2;
+/// Purpose of the zero share masking the conversion scalar `d_i` of a key
+/// rotation.
+const ZERO_UPDATE: u8 = 3;
+
+/// The maximum number of evaluators. Every evaluator holds up to
+/// `binomial(n - 1, t - 1)` seeds, which are all used for every share.
+pub const MAX_PARTIES: u32 = 10;
+
+/// The index of an evaluator, ranging from `1` to the number of
+/// evaluators.
+pub type PartyIndex = u32;
+
+/// A threshold evaluator holds the seeds of all sets of `t - 1` evaluators
+/// it does not belong to.
+pub struct ThresholdEvaluatorContext {
+    pub(crate) index: PartyIndex,
+    pub(crate) threshold: u32,
+    pub(crate) parties: u32,
+    /// The seeds, by the set of evaluators which do not know them.
+    pub(crate) seeds: BTreeMap<Vec<PartyIndex>, [u8; 32]>,
+    pub(crate) blinding_key_share: BlindingPrivateKey,
+}
+
+/// An evaluator's partial blind evaluation result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PartialEvaluation {
+    /// The index of the evaluator.
+    pub index: PartyIndex,
+    /// The blinded input, multiplied by the evaluator's key share.
+    pub output: BlindOutput,
+}
+
+/// An evaluator's partial blind conversion result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PartialConversion {
+    /// The index of the evaluator.
+    pub index: PartyIndex,
+    /// The evaluator's share `w_i` of the masked source key.
+    pub mask_share: P256Scalar,
+    /// The blinded input, multiplied by the evaluator's share `d_i` of the
+    /// masked target key.
+    pub output: BlindOutput,
+}
+
+/// An evaluator's scalars for verifiable partial blind conversion, see
+/// [`blind_convert_partial_precomputed_verifiable`].
+#[derive(Clone, Copy)]
+pub struct PartialConversionScalars {
+    /// The index of the evaluator.
+    pub index: PartyIndex,
+    /// The evaluator's share `w_i` of the masked source key.
+    pub mask_share: P256Scalar,
+    /// The scalars for converting using `d_i`.
+    pub scalars: ConversionScalars,
+}
+
+/// An evaluator's proof for a partial result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PartialProof {
+    /// The public key of the scalar the partial result was computed with,
+    /// `K_i` for evaluation and `T_i` for conversion.
+    pub public_key: P256Point,
+    /// The proof of correct evaluation or conversion.
+    pub proof: Proof,
+}
+
+impl ThresholdEvaluatorContext {
+    /// The index of this evaluator.
+    pub fn index(&self) -> PartyIndex {
+        self.index
+    }
+
+    /// The number of evaluators required for blind evaluation.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// The total number of evaluators.
+    pub fn parties(&self) -> u32 {
+        self.parties
+    }
+
+    /// The number of evaluators required for blind conversion, `2t - 1`.
+    pub fn conversion_threshold(&self) -> u32 {
+        2 * self.threshold - 1
+    }
+
+    /// Retrieves the public part of the evaluators' common blinding key
+    /// share.
+    pub fn blinding_key_share(&self) -> Result<BlindingPublicKey, Error> {
+        Ok(p256::p256_point_mul_base(self.blinding_key_share)?)
+    }
+
+    /// Retrieves the evaluator's seeds, by the set of evaluators which do
+    /// not know them, e.g. for persistent storage.
+    pub fn seeds(&self) -> &BTreeMap<Vec<PartyIndex>, [u8; 32]> {
+        &self.seeds
+    }
+
+    /// Retrieves the evaluators' common blinding key share, e.g. for
+    /// persistent storage.
+    pub fn blinding_private_key(&self) -> BlindingPrivateKey {
+        self.blinding_key_share
+    }
+
+    /// Restore an evaluator context from previously exported seeds and
+    /// blinding key share.
+    ///
+    /// Raises:
+    /// - `InvalidInputError`: If the parameters are not supported, or the
+    ///   seeds are not exactly those of the sets of `threshold - 1`
+    ///   evaluators not containing `index`.
+    pub fn from_seeds(
+        index: PartyIndex,
+        threshold: u32,
+        parties: u32,
+        seeds: BTreeMap<Vec<PartyIndex>, [u8; 32]>,
+        blinding_key_share: BlindingPrivateKey,
+    ) -> Result<Self, Error> {
+        check_parameters(threshold, parties)?;
+        if index == 0
+            || index > parties
+            || !seeds.keys().cloned().eq(subsets(parties, threshold - 1)
+                .into_iter()
+                .filter(|excluded| !excluded.contains(&index)))
+        {
+            return Err(Error::InvalidInputError);
+        }
+        Ok(Self {
+            index,
+            threshold,
+            parties,
+            seeds,
+            blinding_key_share,
+        })
+    }
+}
+
+/// ### E.4.1. Dealing
+/// A dealer holding a coPRF master secret sets up `parties` threshold
+/// evaluators, any `threshold` of which can evaluate. The seeds are
+/// derived from the master secret, which must then be deleted. See
+/// [distributed key generation](super::coprf_dkg) for a setup without a
+/// dealer.
+///
+/// Raises:
+/// - `InvalidInputError`: If `threshold` is zero or exceeds `parties`, or
+///   `parties` exceeds [`MAX_PARTIES`].
+pub fn deal(
+    context: &CoPRFEvaluatorContext,
+    threshold: u32,
+    parties: u32,
+) -> Result<Vec<ThresholdEvaluatorContext>, Error> {
+    check_parameters(threshold, parties)?;
+    let blinding_key_share = derive_blinding_key_share(context)?;
+
+    let seeds = subsets(parties, threshold - 1)
+        .into_iter()
+        .map(|excluded| {
+            let mut seed_material = DST_SEED.to_vec();
+            seed_material.extend_from_slice(&context.msk);
+            for j in &excluded {
+                seed_material.extend_from_slice(&j.to_be_bytes());
+            }
+            (excluded, sha256::hash(&seed_material))
+        })
+        .collect::<Vec<_>>();
+
+    Ok((1..=parties)
+        .map(|index| ThresholdEvaluatorContext {
+            index,
+            threshold,
+            parties,
+            seeds: seeds
+                .iter()
+                .filter(|(excluded, _)| !excluded.contains(&index))
+                .cloned()
+                .collect(),
+            blinding_key_share,
+        })
+        .collect())
+}
+
+/// Checks that `threshold` out of `parties` evaluators is a supported
+/// configuration.
+pub(crate) fn check_parameters(threshold: u32, parties: u32) -> Result<(), Error> {
+    if threshold == 0 || threshold > parties || parties > MAX_PARTIES {
+        return Err(Error::InvalidInputError);
+    }
+    Ok(())
+}
+
+/// All subsets of `{1, ..., parties}` of the given size, in ascending
+/// lexicographic order.
+pub(crate) fn subsets(parties: u32, size: u32) -> Vec<Vec<PartyIndex>> {
+    let mut result = Vec::new();
+    let mut current = Vec::new();
+    fn extend(
+        next: PartyIndex,
+        parties: u32,
+        size: u32,
+        current: &mut Vec<PartyIndex>,
+        result: &mut Vec<Vec<PartyIndex>>,
+    ) {
+        if current.len() == size as usize {
+            result.push(current.clone());
+            return;
+        }
+        for j in next..=parties {
+            current.push(j);
+            extend(j + 1, parties, size, current, result);
+            current.pop();
+        }
+    }
+    extend(1, parties, size, &mut current, &mut result);
+    result
+}
+
+fn scalar(value: u32) -> P256Scalar {
+    P256Scalar::from_u128(value as u128)
+}
+
+/// The polynomial `f_T` evaluated at `z`.
+fn vanishing_polynomial(excluded: &[PartyIndex], z: PartyIndex) -> P256Scalar {
+    let mut result = P256Scalar::one();
+    for j in excluded {
+        result = result * (scalar(*j) - scalar(z)) * scalar(*j).inv();
+    }
+    result
+}
+
+/// The PRF `F(seed, label || input)`.
+fn prf(seed: &[u8; 32], label: &[u8], input: &[u8]) -> Result<P256Scalar, Error> {
+    let mut material = seed.to_vec();
+    material.extend_from_slice(label);
+    material.extend_from_slice(input);
+    p256_sha256::hash_to_scalar(&material, CONTEXT_SHARE)
+}
+
+/// The evaluator's share of a pseudo-random value on a polynomial of
+/// degree `t - 1`.
+fn random_share(
+    context: &ThresholdEvaluatorContext,
+    label: u8,
+    input: &[u8],
+) -> Result<P256Scalar, Error> {
+    let mut share = P256Scalar::zero();
+    for (excluded, seed) in &context.seeds {
+        share = share + prf(seed, &[label], input)? * vanishing_polynomial(excluded, context.index);
+    }
+    Ok(share)
+}
+
+/// The evaluator's share of zero on a polynomial of degree `2t - 2`.
+fn zero_share(
+    context: &ThresholdEvaluatorContext,
+    purpose: u8,
+    input: &[u8],
+) -> Result<P256Scalar, Error> {
+    let mut share = P256Scalar::zero();
+    for (excluded, seed) in &context.seeds {
+        let base = vanishing_polynomial(excluded, context.index);
+        let mut monomial = base;
+        for degree in 1..context.threshold {
+            monomial = monomial * scalar(context.index);
+            share = share + prf(seed, &[LABEL_ZERO, purpose, degree as u8], input)? * monomial;
+        }
+    }
+    Ok(share)
+}
+
+/// ### E.4.2. Key Share Derivation
+/// The evaluator's share of the evaluation key with the given identifier.
+pub fn derive_key_share(
+    context: &ThresholdEvaluatorContext,
+    key_id: &[u8],
+) -> Result<CoPRFKey, Error> {
+    random_share(context, LABEL_KEY, key_id)
+}
+
+/// The public key `K_i` of the evaluator's share of an evaluation key.
+/// The public keys of the shares of `t` evaluators interpolate to the
+/// public key of the evaluation key, see [`combine_public_keys`].
+pub fn derive_public_key_share(
+    context: &ThresholdEvaluatorContext,
+    key_id: &[u8],
+) -> Result<P256Point, Error> {
+    Ok(p256::p256_point_mul_base(derive_key_share(
+        context, key_id,
+    )?)?)
+}
+
+/// The evaluator's shares `(d_i, w_i)` for conversion from `key_id_from`
+/// to `key_id_to`.
+fn conversion_shares(
+    context: &ThresholdEvaluatorContext,
+    key_id_from: &[u8],
+    key_id_to: &[u8],
+) -> Result<(P256Scalar, P256Scalar), Error> {
+    let mut conversion_id = (key_id_from.len() as u32).to_be_bytes().to_vec();
+    conversion_id.extend_from_slice(key_id_from);
+    conversion_id.extend_from_slice(key_id_to);
+
+    let mask = random_share(context, LABEL_MASK, &conversion_id)?;
+    let scalar = derive_key_share(context, key_id_to)? * mask
+        + zero_share(context, ZERO_CONVERSION, &conversion_id)?;
+    let mask_share = derive_key_share(context, key_id_from)? * mask
+        + zero_share(context, ZERO_MASK, &conversion_id)?;
+    Ok((scalar, mask_share))
+}
+
+/// ### E.4.3. Partial Blind Evaluation
+/// The evaluator's partial result for blind evaluation using the
+/// evaluation key with the given identifier.
+pub fn blind_evaluate_partial(
+    context: &ThresholdEvaluatorContext,
+    key_id: &[u8],
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<PartialEvaluation, Error> {
+    let key_share = derive_key_share(context, key_id)?;
+    Ok(PartialEvaluation {
+        index: context.index,
+        output: blind_evaluate(key_share, bpk, blind_input, randomness)?,
+    })
+}
+
+/// The evaluator's partial result for verifiable blind evaluation of an
+/// input blinded towards the joint blinding key of receiver and
+/// evaluators.
+pub fn blind_evaluate_partial_verifiable(
+    context: &ThresholdEvaluatorContext,
+    key_id: &[u8],
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<(PartialEvaluation, PartialProof), Error> {
+    let key_share = derive_key_share(context, key_id)?;
+    let (output, proof) = blind_evaluate_verifiable(
+        key_share,
+        context.blinding_key_share,
+        bpk,
+        blind_input,
+        randomness,
+    )?;
+    Ok((
+        PartialEvaluation {
+            index: context.index,
+            output,
+        },
+        PartialProof {
+            public_key: p256::p256_point_mul_base(key_share)?,
+            proof,
+        },
+    ))
+}
+
+/// Verify that a partial blind evaluation result was computed from the
+/// blinded input using the key share with the public key in the proof.
+///
+/// Raises:
+/// - `InvalidProofError`: If the proof does not verify.
+pub fn verify_partial_evaluation(
+    blinding_key_share: BlindingPublicKey,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    partial: &PartialEvaluation,
+    proof: &PartialProof,
+) -> Result<(), Error> {
+    verify_evaluation(
+        proof.public_key,
+        blinding_key_share,
+        bpk,
+        blind_input,
+        partial.output,
+        &proof.proof,
+    )
+}
+
+/// ### E.4.4. Partial Blind Conversion
+/// The evaluator's partial result for blind conversion from the
+/// evaluation key with identifier `key_id_from` to the one with identifier
+/// `key_id_to`.
+pub fn blind_convert_partial(
+    context: &ThresholdEvaluatorContext,
+    key_id_from: &[u8],
+    key_id_to: &[u8],
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<PartialConversion, Error> {
+    let (scalar, mask_share) = conversion_shares(context, key_id_from, key_id_to)?;
+    Ok(PartialConversion {
+        index: context.index,
+        mask_share,
+        output: blind_evaluate(scalar, bpk, blind_input, randomness)?,
+    })
+}
+
+/// The evaluator's partial result for verifiable blind conversion of a PRF
+/// output blinded towards the joint blinding key of receiver and
+/// evaluators. `public_key_from` is the public key of the evaluation key
+/// with identifier `key_id_from`.
+pub fn blind_convert_partial_verifiable(
+    context: &ThresholdEvaluatorContext,
+    public_key_from: P256Point,
+    key_id_from: &[u8],
+    key_id_to: &[u8],
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<(PartialConversion, PartialProof), Error> {
+    let (scalar, mask_share) = conversion_shares(context, key_id_from, key_id_to)?;
+    let scalars = partial_conversion_scalars(context, public_key_from, scalar, mask_share)?;
+    blind_convert_partial_precomputed_verifiable(&scalars, bpk, blind_input, randomness)
+}
+
+/// The evaluator's scalars for verifiable partial blind conversion using
+/// the conversion scalar `d_i` and mask share `w_i`.
+fn partial_conversion_scalars(
+    context: &ThresholdEvaluatorContext,
+    public_key_from: P256Point,
+    scalar: P256Scalar,
+    mask_share: P256Scalar,
+) -> Result<PartialConversionScalars, Error> {
+    Ok(PartialConversionScalars {
+        index: context.index,
+        mask_share,
+        scalars: ConversionScalars {
+            public_key_from,
+            scalar,
+            key_share: scalar * context.blinding_key_share,
+            blinding_key_share: context.blinding_key_share()?,
+        },
+    })
+}
+
+/// The evaluator's partial result for verifiable blind conversion using
+/// precomputed scalars. The result is the same as that of
+/// [`blind_convert_partial_verifiable`] for the scalars' conversion, and
+/// verifies in the same way.
+pub fn blind_convert_partial_precomputed_verifiable(
+    scalars: &PartialConversionScalars,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<(PartialConversion, PartialProof), Error> {
+    let (output, proof) =
+        evaluate_precomputed_with_proof(&scalars.scalars, bpk, blind_input, randomness)?;
+    Ok((
+        PartialConversion {
+            index: scalars.index,
+            mask_share: scalars.mask_share,
+            output,
+        },
+        PartialProof {
+            public_key: p256::p256_point_mul(
+                scalars.scalars.scalar,
+                scalars.scalars.public_key_from,
+            )?,
+            proof,
+        },
+    ))
+}
+
+/// ### E.4.5. Key Rotation
+/// The evaluator's scalars for converting PRF outputs from the evaluation
+/// key with the given identifier under its old context `context_from` to
+/// the one under its new context `context_to`. `public_key_from` is the
+/// public key of the evaluation key under the old context. Once computed,
+/// the old context is no longer required for conversion, see
+/// [`blind_convert_partial_precomputed_verifiable`].
+///
+/// Raises:
+/// - `InvalidInputError`: If the contexts differ in index or threshold.
+pub fn update_scalars(
+    context_from: &ThresholdEvaluatorContext,
+    context_to: &ThresholdEvaluatorContext,
+    public_key_from: P256Point,
+    key_id: &[u8],
+) -> Result<PartialConversionScalars, Error> {
+    if context_from.index != context_to.index || context_from.threshold != context_to.threshold {
+        return Err(Error::InvalidInputError);
+    }
+    let mask = random_share(context_from, LABEL_UPDATE_MASK, key_id)?;
+    let scalar = derive_key_share(context_to, key_id)? * mask
+        + zero_share(context_from, ZERO_UPDATE, key_id)?;
+    let mask_share = derive_key_share(context_from, key_id)? * mask
+        + zero_share(context_from, ZERO_UPDATE_MASK, key_id)?;
+    partial_conversion_scalars(context_from, public_key_from, scalar, mask_share)
+}
+
+/// Verify that a partial blind conversion result was computed from the
+/// blinded input using the scalar `d_i` with `T_i = d_i * K_from`, where
+/// `T_i` is the public key in the proof.
+///
+/// Raises:
+/// - `InvalidProofError`: If the proof does not verify.
+pub fn verify_partial_conversion(
+    public_key_from: P256Point,
+    blinding_key_share: BlindingPublicKey,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    partial: &PartialConversion,
+    proof: &PartialProof,
+) -> Result<(), Error> {
+    verify_conversion(
+        public_key_from,
+        proof.public_key,
+        blinding_key_share,
+        bpk,
+        blind_input,
+        partial.output,
+        &proof.proof,
+    )
+}
+
+/// ### E.4.6. Combination
+/// The Lagrange coefficients for interpolating the value at `0` of a
+/// polynomial from its values at the given indices.
+///
+/// Raises:
+/// - `InvalidInputError`: If no index is given, or an index is zero or
+///   given twice.
+pub fn lagrange_coefficients(indices: &[PartyIndex]) -> Result<Vec<P256Scalar>, Error> {
+    if indices.is_empty() {
+        return Err(Error::InvalidInputError);
+    }
+    for (position, i) in indices.iter().enumerate() {
+        if *i == 0 || indices[..position].contains(i) {
+            return Err(Error::InvalidInputError);
+        }
+    }
+
+    Ok(indices
+        .iter()
+        .map(|i| {
+            let mut coefficient = P256Scalar::one();
+            for j in indices.iter().filter(|j| *j != i) {
+                coefficient = coefficient * scalar(*j) * (scalar(*j) - scalar(*i)).inv();
+            }
+            coefficient
+        })
+        .collect())
+}
+
+/// Point addition, where `p256::point_add` does not handle the point at
+/// infinity as the right hand summand.
+fn add(p: P256Point, q: P256Point) -> Result<P256Point, Error> {
+    match q {
+        P256Point::AtInfinity => Ok(p),
+        _ => Ok(p256::point_add(p, q)?),
+    }
+}
+
+/// Interpolate points in the exponent.
+fn interpolate(indices: &[PartyIndex], points: &[P256Point]) -> Result<P256Point, Error> {
+    let mut result = P256Point::AtInfinity;
+    for (coefficient, point) in lagrange_coefficients(indices)?.into_iter().zip(points) {
+        result = add(result, p256::p256_point_mul(coefficient, *point)?)?;
+    }
+    Ok(result)
+}
+
+/// Interpolate ciphertexts componentwise in the exponent.
+fn interpolate_ciphertexts(
+    indices: &[PartyIndex],
+    ciphertexts: &[BlindOutput],
+) -> Result<BlindOutput, Error> {
+    let (c0, c1): (Vec<_>, Vec<_>) = ciphertexts.iter().copied().unzip();
+    Ok((interpolate(indices, &c0)?, interpolate(indices, &c1)?))
+}
+
+/// Combine the public keys of the key shares of at least `t` evaluators to
+/// the public key of the evaluation key.
+pub fn combine_public_keys(shares: &[(PartyIndex, P256Point)]) -> Result<P256Point, Error> {
+    let (indices, points): (Vec<_>, Vec<_>) = shares.iter().copied().unzip();
+    interpolate(&indices, &points)
+}
+
+/// Reconstruct the evaluation key with the given identifier from the key
+/// shares of at least `t` evaluators. Whoever holds the evaluators'
+/// contexts learns the key, so this is only meant for migrating PRF outputs
+/// of a single evaluator to the evaluators' keys, see the
+/// [module documentation](self).
+///
+/// Raises:
+/// - `InvalidInputError`: If fewer than `t` evaluators are given, the
+///   evaluators differ in threshold or an evaluator is given twice.
+pub fn reconstruct_key(
+    evaluators: &[&ThresholdEvaluatorContext],
+    key_id: &[u8],
+) -> Result<CoPRFKey, Error> {
+    let threshold = evaluators
+        .first()
+        .ok_or(Error::InvalidInputError)?
+        .threshold;
+    if evaluators.len() < threshold as usize || evaluators.iter().any(|e| e.threshold != threshold)
+    {
+        return Err(Error::InvalidInputError);
+    }
+    let indices = evaluators.iter().map(|e| e.index).collect::<Vec<_>>();
+    let mut key = P256Scalar::zero();
+    for (coefficient, evaluator) in lagrange_coefficients(&indices)?.into_iter().zip(evaluators) {
+        key = key + coefficient * derive_key_share(evaluator, key_id)?;
+    }
+    Ok(key)
+}
+
+/// Combine the partial results of at least `t` evaluators to the blind
+/// evaluation result. It can be unblinded by the receiver using
+/// [`finalize`](super::coprf_online::finalize).
+pub fn combine_evaluations(partials: &[PartialEvaluation]) -> Result<BlindOutput, Error> {
+    let indices = partials.iter().map(|p| p.index).collect::<Vec<_>>();
+    let outputs = partials.iter().map(|p| p.output).collect::<Vec<_>>();
+    interpolate_ciphertexts(&indices, &outputs)
+}
+
+/// The inverse of the interpolated mask `w = k_from * r`.
+fn combine_masks(partials: &[PartialConversion]) -> Result<P256Scalar, Error> {
+    let indices = partials.iter().map(|p| p.index).collect::<Vec<_>>();
+    let mut mask = P256Scalar::zero();
+    for (coefficient, partial) in lagrange_coefficients(&indices)?.into_iter().zip(partials) {
+        mask = mask + coefficient * partial.mask_share;
+    }
+    if mask == P256Scalar::zero() {
+        return Err(Error::InverseError);
+    }
+    Ok(mask.inv())
+}
+
+/// Combine the partial results of at least `2t - 1` evaluators to the
+/// blind conversion result. It can be unblinded by the receiver using
+/// [`finalize`](super::coprf_online::finalize).
+///
+/// Raises:
+/// - `InverseError`: If the mask shares interpolate to zero.
+pub fn combine_conversions(partials: &[PartialConversion]) -> Result<BlindOutput, Error> {
+    let indices = partials.iter().map(|p| p.index).collect::<Vec<_>>();
+    let outputs = partials.iter().map(|p| p.output).collect::<Vec<_>>();
+    Ok(elgamal::scalar_mul_ciphertext(
+        combine_masks(partials)?,
+        interpolate_ciphertexts(&indices, &outputs)?,
+    )?)
+}
+
+/// Combine the public keys of the proofs for partial blind conversion
+/// results of at least `2t - 1` evaluators to the public key of the target
+/// evaluation key.
+///
+/// Raises:
+/// - `InverseError`: If the mask shares interpolate to zero.
+pub fn combine_conversion_public_keys(
+    partials: &[(PartialConversion, PartialProof)],
+) -> Result<P256Point, Error> {
+    let (partials, proofs): (Vec<_>, Vec<_>) = partials.iter().copied().unzip();
+    let indices = partials.iter().map(|p| p.index).collect::<Vec<_>>();
+    let public_keys = proofs.iter().map(|p| p.public_key).collect::<Vec<_>>();
+    Ok(p256::p256_point_mul(
+        combine_masks(&partials)?,
+        interpolate(&indices, &public_keys)?,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coprf::{
+        coprf_online::{blind, finalize, prepare_blind_convert},
+        coprf_setup::{derive_key, joint_blinding_key, CoPRFReceiverContext},
+    };
+
+    use super::*;
+
+    fn generate_randomness() -> Randomness {
+        use rand::prelude::*;
+
+        let mut randomness = [0u8; 100000];
+        rand::thread_rng().fill_bytes(&mut randomness);
+        Randomness::new(randomness.to_vec())
+    }
+
+    /// The evaluation key with the given identifier, interpolated from the
+    /// key shares of the given evaluators.
+    fn joint_key(evaluators: &[&ThresholdEvaluatorContext], key_id: &[u8]) -> CoPRFKey {
+        reconstruct_key(evaluators, key_id).unwrap()
+    }
+
+    #[test]
+    fn key_shares() {
+        let mut randomness = generate_randomness();
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let evaluators = deal(&dealer, 3, 5).unwrap();
+        assert_eq!(evaluators.len(), 5);
+        assert!(evaluators.iter().all(|e| e.seeds.len() == 6));
+
+        // Any quorum interpolates to the same key.
+        let key = joint_key(&[&evaluators[0], &evaluators[1], &evaluators[2]], b"1");
+        assert_eq!(
+            joint_key(&[&evaluators[4], &evaluators[1], &evaluators[3]], b"1"),
+            key
+        );
+        assert_eq!(joint_key(&evaluators.iter().collect::<Vec<_>>(), b"1"), key);
+        assert_ne!(
+            joint_key(&[&evaluators[0], &evaluators[1], &evaluators[2]], b"2"),
+            key
+        );
+
+        // Fewer evaluators cannot.
+        assert_eq!(
+            reconstruct_key(&[&evaluators[0], &evaluators[1]], b"1"),
+            Err(Error::InvalidInputError)
+        );
+        assert_eq!(
+            reconstruct_key(&[&evaluators[0], &evaluators[1], &evaluators[0]], b"1"),
+            Err(Error::InvalidInputError)
+        );
+
+        // The public keys of the shares interpolate to the public key.
+        let public_key_shares = evaluators[1..4]
+            .iter()
+            .map(|e| (e.index, derive_public_key_share(e, b"1").unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            combine_public_keys(&public_key_shares).unwrap(),
+            p256::p256_point_mul_base(key).unwrap()
+        );
+
+        assert!(deal(&dealer, 0, 5).is_err());
+        assert!(deal(&dealer, 6, 5).is_err());
+        assert!(deal(&dealer, 2, MAX_PARTIES + 1).is_err());
+        assert_eq!(
+            lagrange_coefficients(&[1, 2, 1]),
+            Err(Error::InvalidInputError)
+        );
+    }
+
+    #[test]
+    fn restore_from_seeds() {
+        let mut randomness = generate_randomness();
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let evaluators = deal(&dealer, 2, 3).unwrap();
+        let restore = |index, seeds: &BTreeMap<_, _>| {
+            ThresholdEvaluatorContext::from_seeds(
+                index,
+                2,
+                3,
+                seeds.clone(),
+                evaluators[0].blinding_private_key(),
+            )
+        };
+
+        let restored = restore(2, evaluators[1].seeds()).unwrap();
+        assert_eq!(
+            derive_key_share(&restored, b"1").unwrap(),
+            derive_key_share(&evaluators[1], b"1").unwrap()
+        );
+        assert_eq!(
+            restored.blinding_key_share().unwrap(),
+            evaluators[1].blinding_key_share().unwrap()
+        );
+
+        // The seeds must be exactly those of the evaluator's index.
+        assert!(restore(1, evaluators[1].seeds()).is_err());
+        assert!(restore(4, evaluators[1].seeds()).is_err());
+        let mut missing = evaluators[1].seeds().clone();
+        missing.pop_first();
+        assert!(restore(2, &missing).is_err());
+    }
+
+    #[test]
+    fn threshold_evaluation() {
+        let mut randomness = generate_randomness();
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let receiver_context = CoPRFReceiverContext::new(&mut randomness);
+        let bpk = receiver_context.get_bpk();
+        let evaluators = deal(&dealer, 2, 3).unwrap();
+
+        let key = joint_key(&[&evaluators[0], &evaluators[1]], b"1");
+        let input_element = p256_sha256::hash_to_group(b"TestInput", b"Test").unwrap();
+
+        let blind_input = blind(bpk, b"TestInput", b"Test".to_vec(), &mut randomness).unwrap();
+        let partials = [&evaluators[2], &evaluators[0]]
+            .iter()
+            .map(|e| blind_evaluate_partial(e, b"1", bpk, blind_input, &mut randomness).unwrap())
+            .collect::<Vec<_>>();
+        let blind_output = combine_evaluations(&partials).unwrap();
+
+        assert_eq!(
+            finalize(&receiver_context, blind_output).unwrap(),
+            p256::p256_point_mul(key, input_element).unwrap()
+        );
+
+        // Conversion requires three evaluators.
+        let y = p256::p256_point_mul(key, input_element).unwrap();
+        let blind_input = prepare_blind_convert(bpk, y, &mut randomness).unwrap();
+        let partials = evaluators
+            .iter()
+            .map(|e| {
+                blind_convert_partial(e, b"1", b"2", bpk, blind_input, &mut randomness).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let blind_output = combine_conversions(&partials).unwrap();
+
+        let key_to = joint_key(&[&evaluators[0], &evaluators[1]], b"2");
+        assert_eq!(
+            finalize(&receiver_context, blind_output).unwrap(),
+            p256::p256_point_mul(key_to, input_element).unwrap()
+        );
+        assert_ne!(
+            finalize(
+                &receiver_context,
+                combine_conversions(&partials[..2]).unwrap()
+            )
+            .unwrap(),
+            p256::p256_point_mul(key_to, input_element).unwrap()
+        );
+
+        // Threshold keys are independent of the dealer's keys.
+        assert_ne!(key, derive_key(&dealer, b"1").unwrap());
+    }
+
+    #[test]
+    fn verifiable_threshold_evaluation() {
+        let mut randomness = generate_randomness();
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let receiver_context = CoPRFReceiverContext::new(&mut randomness);
+        let bpk = receiver_context.get_bpk();
+        let evaluators = deal(&dealer, 2, 4).unwrap();
+        let epk = evaluators[0].blinding_key_share().unwrap();
+        assert_eq!(epk, dealer.blinding_key_share().unwrap());
+
+        let key = joint_key(&[&evaluators[0], &evaluators[1]], b"1");
+        let public_key = p256::p256_point_mul_base(key).unwrap();
+        let input_element = p256_sha256::hash_to_group(b"TestInput", b"Test").unwrap();
+
+        // Evaluation
+        let blind_input = blind(
+            joint_blinding_key(bpk, epk).unwrap(),
+            b"TestInput",
+            b"Test".to_vec(),
+            &mut randomness,
+        )
+        .unwrap();
+        let partials = evaluators[1..3]
+            .iter()
+            .map(|e| {
+                blind_evaluate_partial_verifiable(e, b"1", bpk, blind_input, &mut randomness)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        for (partial, proof) in &partials {
+            verify_partial_evaluation(epk, bpk, blind_input, partial, proof).unwrap();
+        }
+        assert_eq!(
+            combine_public_keys(
+                &partials
+                    .iter()
+                    .map(|(partial, proof)| (partial.index, proof.public_key))
+                    .collect::<Vec<_>>()
+            )
+            .unwrap(),
+            public_key
+        );
+        let blind_output =
+            combine_evaluations(&partials.iter().map(|(p, _)| *p).collect::<Vec<_>>()).unwrap();
+        let y = finalize(&receiver_context, blind_output).unwrap();
+        assert_eq!(y, p256::p256_point_mul(key, input_element).unwrap());
+
+        // A partial result computed with another key share does not verify.
+        let (_, other_proof) = blind_evaluate_partial_verifiable(
+            &evaluators[1],
+            b"2",
+            bpk,
+            blind_input,
+            &mut randomness,
+        )
+        .unwrap();
+        assert_eq!(
+            verify_partial_evaluation(epk, bpk, blind_input, &partials[0].0, &other_proof),
+            Err(Error::InvalidProofError)
+        );
+
+        // Conversion
+        let blind_input =
+            prepare_blind_convert(joint_blinding_key(bpk, epk).unwrap(), y, &mut randomness)
+                .unwrap();
+        let partials = evaluators[..3]
+            .iter()
+            .map(|e| {
+                blind_convert_partial_verifiable(
+                    e,
+                    public_key,
+                    b"1",
+                    b"2",
+                    bpk,
+                    blind_input,
+                    &mut randomness,
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        for (partial, proof) in &partials {
+            verify_partial_conversion(public_key, epk, bpk, blind_input, partial, proof).unwrap();
+        }
+        let key_to = joint_key(&[&evaluators[0], &evaluators[1]], b"2");
+        assert_eq!(
+            combine_conversion_public_keys(&partials).unwrap(),
+            p256::p256_point_mul_base(key_to).unwrap()
+        );
+        let blind_output =
+            combine_conversions(&partials.iter().map(|(p, _)| *p).collect::<Vec<_>>()).unwrap();
+        assert_eq!(
+            finalize(&receiver_context, blind_output).unwrap(),
+            p256::p256_point_mul(key_to, input_element).unwrap()
+        );
+    }
+
+    #[test]
+    fn threshold_key_rotation() {
+        let mut randomness = generate_randomness();
+        let receiver_context = CoPRFReceiverContext::new(&mut randomness);
+        let bpk = receiver_context.get_bpk();
+        let old_evaluators =
+            deal(&CoPRFEvaluatorContext::new(&mut randomness).unwrap(), 2, 3).unwrap();
+        let new_evaluators =
+            deal(&CoPRFEvaluatorContext::new(&mut randomness).unwrap(), 2, 3).unwrap();
+        let epk = old_evaluators[0].blinding_key_share().unwrap();
+
+        let key_from = joint_key(&[&old_evaluators[0], &old_evaluators[1]], b"1");
+        let key_to = joint_key(&[&new_evaluators[0], &new_evaluators[1]], b"1");
+        let public_key_from = p256::p256_point_mul_base(key_from).unwrap();
+        let input_element = p256_sha256::hash_to_group(b"TestInput", b"Test").unwrap();
+
+        // The evaluators' old and new contexts must match.
+        assert!(update_scalars(
+            &old_evaluators[0],
+            &new_evaluators[1],
+            public_key_from,
+            b"1"
+        )
+        .is_err());
+
+        let scalars = old_evaluators
+            .iter()
+            .zip(&new_evaluators)
+            .map(|(from, to)| update_scalars(from, to, public_key_from, b"1").unwrap())
+            .collect::<Vec<_>>();
+        drop(old_evaluators);
+
+        let y = p256::p256_point_mul(key_from, input_element).unwrap();
+        let blind_input =
+            prepare_blind_convert(joint_blinding_key(bpk, epk).unwrap(), y, &mut randomness)
+                .unwrap();
+        let partials = scalars
+            .iter()
+            .map(|scalars| {
+                blind_convert_partial_precomputed_verifiable(
+                    scalars,
+                    bpk,
+                    blind_input,
+                    &mut randomness,
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        for (partial, proof) in &partials {
+            verify_partial_conversion(public_key_from, epk, bpk, blind_input, partial, proof)
+                .unwrap();
+        }
+        assert_eq!(
+            combine_conversion_public_keys(&partials).unwrap(),
+            p256::p256_point_mul_base(key_to).unwrap()
+        );
+
+        let blind_output =
+            combine_conversions(&partials.iter().map(|(p, _)| *p).collect::<Vec<_>>()).unwrap();
+        assert_eq!(
+            finalize(&receiver_context, blind_output).unwrap(),
+            p256::p256_point_mul(key_to, input_element).unwrap()
+        );
+    }
+}
@@ -0,0 +1,552 @@
+#![warn(missing_docs)]
+//! ## E.3. Verifiable Blind Evaluation and Conversion
+//!
+//! A faulty or malicious evaluator could blindly evaluate or convert using
+//! a key other than the one requested, e.g. a fresh key per input, without
+//! the receiver being able to tell. In verifiable mode, the evaluator
+//! publishes the public key `K = k * G` of every evaluation key `k` (see
+//! [`derive_public_key`](super::coprf_setup::derive_public_key)) and
+//! attaches to each blind evaluation result a non-interactive
+//! zero-knowledge proof that it was computed from the blinded input using
+//! `k`.
+//!
+//! To check such a proof, the receiver needs to know the blinded input.
+//! Since the receiver could unblind an input blinded only towards itself,
+//! inputs are instead blinded towards the [joint blinding
+//! key](super::coprf_setup::joint_blinding_key) `bpk + epk` of receiver and
+//! evaluator, where `epk = x * G` is the evaluator's blinding key share.
+//! Given a blinded input `(c0, c1)`, the evaluator removes its share of the
+//! blinding during evaluation and rerandomizes towards the receiver using a
+//! fresh scalar `s`:
+//!
+//! ```text
+//! u = k * x
+//! U = u * G
+//! out0 = k * c0 + s * G
+//! out1 = k * c1 - u * c0 + s * bpk
+//! ```
+//!
+//! The result `(out0, out1)` is unblinded using `bsk` like a regular blind
+//! evaluation result. The proof is a Chaum-Pedersen style proof of
+//! knowledge of `(k, u, s)` such that
+//!
+//! ```text
+//! K = k * G
+//! U = k * epk
+//! U = u * G
+//! out0 = k * c0 + s * G
+//! out1 = k * c1 - u * c0 + s * bpk
+//! ```
+//!
+//! made non-interactive using the Fiat-Shamir transform. Blind conversion
+//! from key `k_from` to key `k_to` is proven in the same way for the scalar
+//! `delta = k_to / k_from`, with the first equation replaced by
+//! `K_to = delta * K_from`.
+//!
+//! Conversion only depends on `K_from`, `delta` and `u = delta * x`. Once
+//! these are computed using [`conversion_scalars`], neither evaluation key
+//! nor the blinding key share is required to convert.
+
+use hacspec_lib::{hacspec_helper::NatMod, Randomness};
+use p256::{P256Point, P256Scalar};
+
+use super::{
+    coprf_online::{BlindInput, BlindOutput},
+    coprf_setup::{BlindingPrivateKey, BlindingPublicKey, CoPRFKey},
+};
+use crate::{p256_sha256, Error};
+
+/// Domain separator for scalar sampling during proof generation.
+const DST_PROOF: &[u8] = b"CoPRF-Proof";
+/// Context string for hashing proof transcripts to challenges.
+const CONTEXT_PROOF: &[u8] = b"CoPRF-ProofChallenge";
+
+/// The number of secret scalars a proof is about.
+const WITNESSES: usize = 3;
+
+/// A proof that a blind evaluation or conversion result was computed
+/// correctly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Proof {
+    /// The evaluator's blinding key share multiplied by the evaluation key,
+    /// `U` above.
+    pub key_share: P256Point,
+    /// The Fiat-Shamir challenge.
+    pub challenge: P256Scalar,
+    /// The responses for the witnesses `(k, u, s)`.
+    pub responses: [P256Scalar; WITNESSES],
+}
+
+/// The scalars for verifiable blind conversion from one evaluation key to
+/// another. They suffice to convert without knowing either evaluation key
+/// or the evaluator's blinding key share, see
+/// [`blind_convert_precomputed_verifiable`].
+#[derive(Clone, Copy)]
+pub struct ConversionScalars {
+    /// The public key `K_from` of the source evaluation key.
+    pub public_key_from: P256Point,
+    /// The conversion scalar `delta = k_to / k_from`.
+    pub scalar: P256Scalar,
+    /// The conversion scalar multiplied by the evaluator's blinding key
+    /// share, `u` above.
+    pub key_share: P256Scalar,
+    /// The public part `epk` of the evaluator's blinding key share.
+    pub blinding_key_share: BlindingPublicKey,
+}
+
+/// A statement about the witnesses `w`, consisting of equations
+/// `image = w[0] * bases[0] + w[1] * bases[1] + w[2] * bases[2]`, where
+/// missing bases are omitted.
+type Statement = Vec<([Option<P256Point>; WITNESSES], P256Point)>;
+
+fn generator() -> Result<P256Point, Error> {
+    Ok(p256::p256_point_mul_base(P256Scalar::one())?)
+}
+
+/// Point addition, where `p256::point_add` does not handle the point at
+/// infinity as the right hand summand.
+fn add(p: P256Point, q: P256Point) -> Result<P256Point, Error> {
+    match q {
+        P256Point::AtInfinity => Ok(p),
+        _ => Ok(p256::point_add(p, q)?),
+    }
+}
+
+fn combine(
+    bases: &[Option<P256Point>; WITNESSES],
+    scalars: &[P256Scalar; WITNESSES],
+) -> Result<P256Point, Error> {
+    let mut result = P256Point::AtInfinity;
+    for (base, scalar) in bases.iter().zip(scalars) {
+        if let Some(base) = base {
+            result = add(result, p256::p256_point_mul(*scalar, *base)?)?;
+        }
+    }
+    Ok(result)
+}
+
+/// The statement proven about a blind evaluation (`base = G`) or a blind
+/// conversion (`base = K_from`).
+fn statement(
+    base: P256Point,
+    public_key: P256Point,
+    key_share: P256Point,
+    blinding_key_share: BlindingPublicKey,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    blind_output: BlindOutput,
+) -> Result<Statement, Error> {
+    let g = generator()?;
+    let (c0, c1) = blind_input;
+    let (out0, out1) = blind_output;
+
+    for point in [
+        public_key,
+        key_share,
+        blinding_key_share,
+        bpk,
+        c0,
+        c1,
+        out0,
+        out1,
+    ] {
+        if point == P256Point::AtInfinity {
+            return Err(Error::InvalidProofError);
+        }
+    }
+
+    Ok(vec![
+        ([Some(base), None, None], public_key),
+        ([Some(blinding_key_share), None, None], key_share),
+        ([None, Some(g), None], key_share),
+        ([Some(c0), None, Some(g)], out0),
+        ([Some(c1), Some(-c0), Some(bpk)], out1),
+    ])
+}
+
+/// Hash the statement and the prover's commitments to a challenge.
+fn challenge(statement: &Statement, commitments: &[P256Point]) -> Result<P256Scalar, Error> {
+    let mut transcript = Vec::new();
+    for ((bases, image), commitment) in statement.iter().zip(commitments) {
+        for base in bases {
+            transcript.extend_from_slice(&p256_sha256::serialize_element(
+                &base.unwrap_or(P256Point::AtInfinity),
+            ));
+        }
+        transcript.extend_from_slice(&p256_sha256::serialize_element(image));
+        transcript.extend_from_slice(&p256_sha256::serialize_element(commitment));
+    }
+    p256_sha256::hash_to_scalar(&transcript, CONTEXT_PROOF)
+}
+
+fn prove(
+    statement: &Statement,
+    witnesses: [P256Scalar; WITNESSES],
+    key_share: P256Point,
+    randomness: &mut Randomness,
+) -> Result<Proof, Error> {
+    let nonces = [
+        p256::random_scalar(randomness, DST_PROOF)?,
+        p256::random_scalar(randomness, DST_PROOF)?,
+        p256::random_scalar(randomness, DST_PROOF)?,
+    ];
+    let commitments = statement
+        .iter()
+        .map(|(bases, _)| combine(bases, &nonces))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let challenge = challenge(statement, &commitments)?;
+
+    let mut responses = nonces;
+    for (response, witness) in responses.iter_mut().zip(witnesses) {
+        *response = *response + challenge * witness;
+    }
+
+    Ok(Proof {
+        key_share,
+        challenge,
+        responses,
+    })
+}
+
+fn verify(statement: &Statement, proof: &Proof) -> Result<(), Error> {
+    let commitments = statement
+        .iter()
+        .map(|(bases, image)| {
+            add(
+                combine(bases, &proof.responses)?,
+                -p256::p256_point_mul(proof.challenge, *image)?,
+            )
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    if challenge(statement, &commitments)? != proof.challenge {
+        return Err(Error::InvalidProofError);
+    }
+    Ok(())
+}
+
+/// Evaluate on a blinded input using `scalar`, remove the evaluator's share
+/// of the blinding and rerandomize towards the receiver, and prove this.
+fn evaluate_with_proof(
+    base: P256Point,
+    scalar: P256Scalar,
+    blinding_key_share: BlindingPrivateKey,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<(BlindOutput, Proof), Error> {
+    let scalars = ConversionScalars {
+        public_key_from: base,
+        scalar,
+        key_share: scalar * blinding_key_share,
+        blinding_key_share: p256::p256_point_mul_base(blinding_key_share)?,
+    };
+    evaluate_precomputed_with_proof(&scalars, bpk, blind_input, randomness)
+}
+
+/// [`evaluate_with_proof`] using precomputed scalars.
+pub(super) fn evaluate_precomputed_with_proof(
+    scalars: &ConversionScalars,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<(BlindOutput, Proof), Error> {
+    let ConversionScalars {
+        public_key_from: base,
+        scalar,
+        key_share: share,
+        blinding_key_share,
+    } = *scalars;
+    let public_key = p256::p256_point_mul(scalar, base)?;
+    let key_share = p256::p256_point_mul_base(share)?;
+    let rerandomizer = p256::random_scalar(randomness, DST_PROOF)?;
+
+    let (c0, c1) = blind_input;
+    let out0 = add(
+        p256::p256_point_mul(scalar, c0)?,
+        p256::p256_point_mul_base(rerandomizer)?,
+    )?;
+    let out1 = add(
+        add(
+            p256::p256_point_mul(scalar, c1)?,
+            p256::p256_point_mul(share, -c0)?,
+        )?,
+        p256::p256_point_mul(rerandomizer, bpk)?,
+    )?;
+    let blind_output = (out0, out1);
+
+    let statement = statement(
+        base,
+        public_key,
+        key_share,
+        blinding_key_share,
+        bpk,
+        blind_input,
+        blind_output,
+    )?;
+    let proof = prove(
+        &statement,
+        [scalar, share, rerandomizer],
+        key_share,
+        randomness,
+    )?;
+
+    Ok((blind_output, proof))
+}
+
+/// Verifiable blind evaluation of an input blinded towards the joint
+/// blinding key of receiver and evaluator.
+///
+/// The result can be unblinded by the receiver using
+/// [`finalize`](super::coprf_online::finalize) and is accompanied by a
+/// proof that it was computed using `key`.
+pub fn blind_evaluate_verifiable(
+    key: CoPRFKey,
+    blinding_key_share: BlindingPrivateKey,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<(BlindOutput, Proof), Error> {
+    evaluate_with_proof(
+        generator()?,
+        key,
+        blinding_key_share,
+        bpk,
+        blind_input,
+        randomness,
+    )
+}
+
+/// Verify that a blind evaluation result was computed from the blinded
+/// input using the evaluation key with public key `public_key`.
+///
+/// Raises:
+/// - `InvalidProofError`: If the proof does not verify.
+pub fn verify_evaluation(
+    public_key: P256Point,
+    blinding_key_share: BlindingPublicKey,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    blind_output: BlindOutput,
+    proof: &Proof,
+) -> Result<(), Error> {
+    let statement = statement(
+        generator()?,
+        public_key,
+        proof.key_share,
+        blinding_key_share,
+        bpk,
+        blind_input,
+        blind_output,
+    )?;
+    verify(&statement, proof)
+}
+
+/// Verifiable blind conversion of a PRF output blinded towards the joint
+/// blinding key of receiver and evaluator.
+///
+/// The result can be unblinded by the receiver using
+/// [`finalize`](super::coprf_online::finalize) and is accompanied by a
+/// proof that it was converted from `key_from` to `key_to`.
+pub fn blind_convert_verifiable(
+    bpk: BlindingPublicKey,
+    key_from: CoPRFKey,
+    key_to: CoPRFKey,
+    blinding_key_share: BlindingPrivateKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<(BlindOutput, Proof), Error> {
+    let scalars = conversion_scalars(key_from, key_to, blinding_key_share)?;
+    blind_convert_precomputed_verifiable(&scalars, bpk, blind_input, randomness)
+}
+
+/// The scalars for verifiable blind conversion from `key_from` to
+/// `key_to`. Once computed, conversion no longer requires either key, see
+/// [`blind_convert_precomputed_verifiable`].
+pub fn conversion_scalars(
+    key_from: CoPRFKey,
+    key_to: CoPRFKey,
+    blinding_key_share: BlindingPrivateKey,
+) -> Result<ConversionScalars, Error> {
+    let delta = key_to * key_from.inv();
+    Ok(ConversionScalars {
+        public_key_from: p256::p256_point_mul_base(key_from)?,
+        scalar: delta,
+        key_share: delta * blinding_key_share,
+        blinding_key_share: p256::p256_point_mul_base(blinding_key_share)?,
+    })
+}
+
+/// Verifiable blind conversion using precomputed scalars. The result is
+/// the same as that of [`blind_convert_verifiable`] using the keys the
+/// scalars were computed from, and verifies in the same way.
+pub fn blind_convert_precomputed_verifiable(
+    scalars: &ConversionScalars,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    randomness: &mut Randomness,
+) -> Result<(BlindOutput, Proof), Error> {
+    evaluate_precomputed_with_proof(scalars, bpk, blind_input, randomness)
+}
+
+/// Verify that a blind conversion result was converted from the
+/// evaluation key with public key `public_key_from` to the evaluation key
+/// with public key `public_key_to`.
+///
+/// Raises:
+/// - `InvalidProofError`: If the proof does not verify.
+pub fn verify_conversion(
+    public_key_from: P256Point,
+    public_key_to: P256Point,
+    blinding_key_share: BlindingPublicKey,
+    bpk: BlindingPublicKey,
+    blind_input: BlindInput,
+    blind_output: BlindOutput,
+    proof: &Proof,
+) -> Result<(), Error> {
+    if public_key_from == P256Point::AtInfinity {
+        return Err(Error::InvalidProofError);
+    }
+    let statement = statement(
+        public_key_from,
+        public_key_to,
+        proof.key_share,
+        blinding_key_share,
+        bpk,
+        blind_input,
+        blind_output,
+    )?;
+    verify(&statement, proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coprf::{
+        coprf_online::{blind, finalize, prepare_blind_convert},
+        coprf_setup::{
+            derive_blinding_key_share, derive_key, derive_public_key, joint_blinding_key,
+            CoPRFEvaluatorContext, CoPRFReceiverContext,
+        },
+    };
+
+    use super::*;
+
+    fn generate_randomness() -> Randomness {
+        use rand::prelude::*;
+
+        let mut randomness = [0u8; 100000];
+        rand::thread_rng().fill_bytes(&mut randomness);
+        Randomness::new(randomness.to_vec())
+    }
+
+    #[test]
+    fn verifiable_evaluation() {
+        let mut randomness = generate_randomness();
+        let evaluator_context = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let receiver_context = CoPRFReceiverContext::new(&mut randomness);
+        let bpk = receiver_context.get_bpk();
+        let epk = evaluator_context.blinding_key_share().unwrap();
+        let x = derive_blinding_key_share(&evaluator_context).unwrap();
+
+        let key = derive_key(&evaluator_context, b"1").unwrap();
+        let public_key = derive_public_key(&evaluator_context, b"1").unwrap();
+
+        let blind_input = blind(
+            joint_blinding_key(bpk, epk).unwrap(),
+            b"TestInput",
+            b"Test".to_vec(),
+            &mut randomness,
+        )
+        .unwrap();
+        let (blind_output, proof) =
+            blind_evaluate_verifiable(key, x, bpk, blind_input, &mut randomness).unwrap();
+
+        verify_evaluation(public_key, epk, bpk, blind_input, blind_output, &proof).unwrap();
+
+        // The result is the regular PRF output.
+        let expected = p256::p256_point_mul(
+            key,
+            p256_sha256::hash_to_group(b"TestInput", b"Test").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(finalize(&receiver_context, blind_output).unwrap(), expected);
+
+        // Proofs do not verify for other keys, inputs or outputs.
+        let other_key = derive_public_key(&evaluator_context, b"2").unwrap();
+        assert_eq!(
+            verify_evaluation(other_key, epk, bpk, blind_input, blind_output, &proof),
+            Err(Error::InvalidProofError)
+        );
+        let other_output = (blind_output.0, blind_input.1);
+        assert_eq!(
+            verify_evaluation(public_key, epk, bpk, blind_input, other_output, &proof),
+            Err(Error::InvalidProofError)
+        );
+        let other_input = (blind_input.0, blind_output.1);
+        assert_eq!(
+            verify_evaluation(public_key, epk, bpk, other_input, blind_output, &proof),
+            Err(Error::InvalidProofError)
+        );
+
+        // Evaluating with a different key is detected.
+        let other = derive_key(&evaluator_context, b"2").unwrap();
+        let (blind_output, proof) =
+            blind_evaluate_verifiable(other, x, bpk, blind_input, &mut randomness).unwrap();
+        assert_eq!(
+            verify_evaluation(public_key, epk, bpk, blind_input, blind_output, &proof),
+            Err(Error::InvalidProofError)
+        );
+    }
+
+    #[test]
+    fn verifiable_conversion() {
+        let mut randomness = generate_randomness();
+        let evaluator_context = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let receiver_context = CoPRFReceiverContext::new(&mut randomness);
+        let bpk = receiver_context.get_bpk();
+        let epk = evaluator_context.blinding_key_share().unwrap();
+        let x = derive_blinding_key_share(&evaluator_context).unwrap();
+
+        let key_from = derive_key(&evaluator_context, b"1").unwrap();
+        let key_to = derive_key(&evaluator_context, b"2").unwrap();
+        let public_key_from = derive_public_key(&evaluator_context, b"1").unwrap();
+        let public_key_to = derive_public_key(&evaluator_context, b"2").unwrap();
+
+        let input_element = p256_sha256::hash_to_group(b"TestInput", b"Test").unwrap();
+        let y = p256::p256_point_mul(key_from, input_element).unwrap();
+        let blind_input =
+            prepare_blind_convert(joint_blinding_key(bpk, epk).unwrap(), y, &mut randomness)
+                .unwrap();
+        let (blind_output, proof) =
+            blind_convert_verifiable(bpk, key_from, key_to, x, blind_input, &mut randomness)
+                .unwrap();
+
+        verify_conversion(
+            public_key_from,
+            public_key_to,
+            epk,
+            bpk,
+            blind_input,
+            blind_output,
+            &proof,
+        )
+        .unwrap();
+        assert_eq!(
+            finalize(&receiver_context, blind_output).unwrap(),
+            p256::p256_point_mul(key_to, input_element).unwrap()
+        );
+
+        assert_eq!(
+            verify_conversion(
+                public_key_to,
+                public_key_from,
+                epk,
+                bpk,
+                blind_input,
+                blind_output,
+                &proof,
+            ),
+            Err(Error::InvalidProofError)
+        );
+    }
+}
@@ -14,5 +14,8 @@
 //!
 //! CoPRFs further provide the possiblity of converting PRF outputs, both
 //! in blinded and unblinded form, from one PRF key to another.
+pub mod coprf_dkg;
 pub mod coprf_online;
 pub mod coprf_setup;
+pub mod coprf_threshold;
+pub mod coprf_verifiable;
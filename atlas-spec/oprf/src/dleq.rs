@@ -0,0 +1,325 @@
+//! ## 2.2. Discrete Logarithm Equivalence Proofs
+//!
+//! A proof of knowledge allows a prover to convince a verifier that some
+//! statement is true.  If the prover can generate a proof without
+//! interaction with the verifier, the proof is noninteractive.  If the
+//! verifier learns nothing other than whether the statement claimed by
+//! the prover is true or false, the proof is zero-knowledge.
+//!
+//! This section describes a noninteractive zero-knowledge proof for
+//! discrete logarithm equivalence (DLEQ), which is used in the
+//! construction of VOPRF and POPRF.  A DLEQ proof demonstrates that two
+//! pairs of group elements have the same discrete logarithm without
+//! revealing the discrete logarithm.
+//!
+//! The DLEQ proof resembles the Chaum-Pedersen [ChaumPedersen] proof,
+//! which is shown to be zero-knowledge by Jarecki, et al.  [JKK14] and
+//! is noninteractive after applying the Fiat-Shamir transform [FS00].
+//! Furthermore, Davidson, et al.  [DGSTV18] showed a proof system for
+//! batching DLEQ proofs that has constant-size proofs with respect to
+//! the number of inputs.  The specific DLEQ proof system presented
+//! below follows this latter construction with two modifications: (1)
+//! the transcript used to generate the seed includes more context
+//! information and (2) the individual values of the composite result
+//! are also included in the transcript.
+
+use hacspec_lib::{i2osp, Randomness};
+use p256::{P256Point, P256Scalar};
+
+use crate::{p256_sha256, Error};
+
+const DST_NONCE: &[u8] = b"OPRF-ProofNonce";
+
+/// A DLEQ proof is a pair of scalars `[c, s]`, serialized as the
+/// concatenation of both scalars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Proof {
+    /// The challenge `c`.
+    pub c: P256Scalar,
+    /// The response `s`.
+    pub s: P256Scalar,
+}
+
+/// The serialized size of a [`Proof`], `2 * Ns`.
+pub const PROOF_LEN: usize = 64;
+
+impl Proof {
+    /// `SerializeScalar(c) || SerializeScalar(s)`
+    pub fn serialize(&self) -> [u8; PROOF_LEN] {
+        use hacspec_lib::hacspec_helper::NatMod;
+
+        let mut out = [0u8; PROOF_LEN];
+        out[..32].copy_from_slice(&self.c.to_be_bytes());
+        out[32..].copy_from_slice(&self.s.to_be_bytes());
+        out
+    }
+
+    /// Deserialize a proof, failing with `InvalidInputError` if either
+    /// scalar is not reduced modulo `Group.Order()`.
+    pub fn deserialize(bytes: &[u8; PROOF_LEN]) -> Result<Self, Error> {
+        let scalar = |bytes: &[u8]| {
+            use hacspec_lib::hacspec_helper::NatMod;
+
+            let scalar = P256Scalar::from_be_bytes(bytes);
+            if scalar.to_be_bytes() != bytes {
+                return Err(Error::InvalidInputError);
+            }
+            Ok(scalar)
+        };
+        Ok(Self {
+            c: scalar(&bytes[..32])?,
+            s: scalar(&bytes[32..])?,
+        })
+    }
+}
+
+fn length_prefixed(transcript: &mut Vec<u8>, bytes: &[u8]) {
+    transcript.extend_from_slice(&i2osp(bytes.len(), 2));
+    transcript.extend_from_slice(bytes);
+}
+
+/// ### 2.2.1. Proof Generation
+///
+/// Generating a proof is done with the GenerateProof function, defined
+/// below.  Given elements A and B, two non-empty lists of elements C and
+/// D of length m, and a scalar k, this function produces a proof that
+/// k*A == B and k*C[i] == D[i] for each i in [0, ..., m - 1].  The
+/// output is a value of type Proof, which is a tuple of two Scalar
+/// values.  We use the notation proof[0] and proof[1] to denote the
+/// first and second elements in this tuple, respectively.
+///
+/// ```text
+/// Input:
+///
+///   Scalar k
+///   Element A
+///   Element B
+///   Element C[m]
+///   Element D[m]
+///
+/// Output:
+///
+///   Proof proof
+///
+/// Parameters:
+///
+///   Group G
+///
+/// def GenerateProof(k, A, B, C, D)
+///   (M, Z) = ComputeCompositesFast(k, B, C, D)
+///
+///   r = G.RandomScalar()
+///   t2 = r * A
+///   t3 = r * M
+///
+///   Bm = G.SerializeElement(B)
+///   a0 = G.SerializeElement(M)
+///   a1 = G.SerializeElement(Z)
+///   a2 = G.SerializeElement(t2)
+///   a3 = G.SerializeElement(t3)
+///
+///   challengeTranscript =
+///     I2OSP(len(Bm), 2) || Bm ||
+///     I2OSP(len(a0), 2) || a0 ||
+///     I2OSP(len(a1), 2) || a1 ||
+///     I2OSP(len(a2), 2) || a2 ||
+///     I2OSP(len(a3), 2) || a3 ||
+///     "Challenge"
+///
+///   c = G.HashToScalar(challengeTranscript)
+///   s = r - c * k
+///
+///   return [c, s]
+/// ```
+pub fn generate_proof(
+    k: P256Scalar,
+    A: P256Point,
+    B: P256Point,
+    C: &[P256Point],
+    D: &[P256Point],
+    context_string: &[u8],
+    randomness: &mut Randomness,
+) -> Result<Proof, Error> {
+    let r = p256::random_scalar(randomness, DST_NONCE)?;
+    generate_proof_with_nonce(k, A, B, C, D, r, context_string)
+}
+
+/// [`generate_proof`] with a given nonce `r`.
+pub(crate) fn generate_proof_with_nonce(
+    k: P256Scalar,
+    A: P256Point,
+    B: P256Point,
+    C: &[P256Point],
+    D: &[P256Point],
+    r: P256Scalar,
+    context_string: &[u8],
+) -> Result<Proof, Error> {
+    let (M, Z) = compute_composites(Some(k), B, C, D, context_string)?;
+
+    let t2 = p256::p256_point_mul(r, A)?;
+    let t3 = p256::p256_point_mul(r, M)?;
+
+    let c = challenge(B, M, Z, t2, t3, context_string)?;
+    let s = r - c * k;
+
+    Ok(Proof { c, s })
+}
+
+fn challenge(
+    B: P256Point,
+    M: P256Point,
+    Z: P256Point,
+    t2: P256Point,
+    t3: P256Point,
+    context_string: &[u8],
+) -> Result<P256Scalar, Error> {
+    let mut challenge_transcript = Vec::new();
+    for element in [B, M, Z, t2, t3] {
+        length_prefixed(
+            &mut challenge_transcript,
+            &p256_sha256::serialize_element(&element),
+        );
+    }
+    challenge_transcript.extend_from_slice(b"Challenge");
+
+    p256_sha256::hash_to_scalar(&challenge_transcript, context_string)
+}
+
+/// #### 2.2.1.1. Batching Inputs
+///
+/// The ComputeCompositesFast function takes lists of inputs and produces
+/// a single composite element `M = Σ di * C[i]`.  If the private key `k`
+/// is known, the composite `Z = k * M` is computed directly, as described
+/// for ComputeCompositesFast.  Otherwise, `Z = Σ di * D[i]` is computed
+/// as in ComputeComposites, which is used by the verifier.
+///
+/// ```text
+/// def ComputeComposites(B, C, D):
+///   Bm = G.SerializeElement(B)
+///   seedDST = "Seed-" || contextString
+///   seedTranscript =
+///     I2OSP(len(Bm), 2) || Bm ||
+///     I2OSP(len(seedDST), 2) || seedDST
+///   seed = Hash(seedTranscript)
+///
+///   M = G.Identity()
+///   Z = G.Identity()
+///   for i in range(m):
+///     Ci = G.SerializeElement(C[i])
+///     Di = G.SerializeElement(D[i])
+///     compositeTranscript =
+///       I2OSP(len(seed), 2) || seed || I2OSP(i, 2) ||
+///       I2OSP(len(Ci), 2) || Ci ||
+///       I2OSP(len(Di), 2) || Di ||
+///       "Composite"
+///
+///     di = G.HashToScalar(compositeTranscript)
+///     M = di * C[i] + M
+///     Z = di * D[i] + Z
+///
+///   return (M, Z)
+/// ```
+fn compute_composites(
+    k: Option<P256Scalar>,
+    B: P256Point,
+    C: &[P256Point],
+    D: &[P256Point],
+    context_string: &[u8],
+) -> Result<(P256Point, P256Point), Error> {
+    if C.is_empty() || C.len() != D.len() || C.len() > u16::MAX as usize {
+        return Err(Error::InvalidInputError);
+    }
+
+    let mut seed_dst = b"Seed-".to_vec();
+    seed_dst.extend_from_slice(context_string);
+    let mut seed_transcript = Vec::new();
+    length_prefixed(&mut seed_transcript, &p256_sha256::serialize_element(&B));
+    length_prefixed(&mut seed_transcript, &seed_dst);
+    let seed = sha256::hash(&seed_transcript);
+
+    // `p256::point_add` only handles the identity as left hand summand.
+    let mut M = p256_sha256::identity();
+    let mut Z = p256_sha256::identity();
+    for (i, (Ci, Di)) in C.iter().zip(D).enumerate() {
+        let mut composite_transcript = Vec::new();
+        length_prefixed(&mut composite_transcript, &seed);
+        composite_transcript.extend_from_slice(&i2osp(i, 2));
+        length_prefixed(
+            &mut composite_transcript,
+            &p256_sha256::serialize_element(Ci),
+        );
+        length_prefixed(
+            &mut composite_transcript,
+            &p256_sha256::serialize_element(Di),
+        );
+        composite_transcript.extend_from_slice(b"Composite");
+
+        let di = p256_sha256::hash_to_scalar(&composite_transcript, context_string)?;
+        M = p256::point_add(M, p256::p256_point_mul(di, *Ci)?)?;
+        if k.is_none() {
+            Z = p256::point_add(Z, p256::p256_point_mul(di, *Di)?)?;
+        }
+    }
+
+    if let Some(k) = k {
+        Z = p256::p256_point_mul(k, M)?;
+    }
+
+    Ok((M, Z))
+}
+
+/// ### 2.2.2. Proof Verification
+///
+/// Verifying a proof is done with the VerifyProof function, defined
+/// below.  This function takes elements A and B, two non-empty lists of
+/// elements C and D of length m, and a Proof value output from
+/// GenerateProof.  It outputs a single boolean value indicating whether
+/// or not the proof is valid for the given DLEQ inputs.  Note this
+/// function can verify proofs on lists of inputs whenever the proof was
+/// generated as a batched DLEQ proof with the same inputs.
+///
+/// ```text
+/// def VerifyProof(A, B, C, D, proof):
+///   (M, Z) = ComputeComposites(B, C, D)
+///   c = proof[0]
+///   s = proof[1]
+///
+///   t2 = ((s * A) + (c * B))
+///   t3 = ((s * M) + (c * Z))
+///
+///   ...
+///
+///   expectedC = G.HashToScalar(challengeTranscript)
+///   verified = (expectedC == c)
+///
+///   return verified
+/// ```
+///
+/// Raises:
+/// - `InvalidProofError`: If the proof does not verify.
+pub fn verify_proof(
+    A: P256Point,
+    B: P256Point,
+    C: &[P256Point],
+    D: &[P256Point],
+    proof: &Proof,
+    context_string: &[u8],
+) -> Result<(), Error> {
+    let (M, Z) = compute_composites(None, B, C, D, context_string)?;
+
+    let t2 = p256::point_add(
+        p256::p256_point_mul(proof.s, A)?,
+        p256::p256_point_mul(proof.c, B)?,
+    )?;
+    let t3 = p256::point_add(
+        p256::p256_point_mul(proof.s, M)?,
+        p256::p256_point_mul(proof.c, Z)?,
+    )?;
+
+    let expected_c = challenge(B, M, Z, t2, t3, context_string)?;
+    if expected_c != proof.c {
+        return Err(Error::InvalidProofError);
+    }
+
+    Ok(())
+}
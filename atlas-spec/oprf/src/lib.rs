@@ -8,6 +8,8 @@ pub enum Error {
     HashToCurveError,
     ElgamalError,
     RandomnessError,
+    InvalidProofError,
+    InverseError,
 }
 
 impl From<p256::Error> for Error {
@@ -34,6 +36,9 @@ impl From<hacspec_lib::Error> for Error {
     }
 }
 
+// 2.2 Discrete Logarithm Equivalence Proofs
+pub mod dleq;
+
 // 3. Protocol
 pub mod protocol;
 
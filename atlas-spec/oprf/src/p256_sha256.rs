@@ -9,8 +9,8 @@ use crate::Error;
 
 use p256::{P256Point, P256Scalar};
 
-#[allow(non_upper_case_globals, unused)]
-const identifier: &[u8] = b"P256-SHA256";
+#[allow(non_upper_case_globals)]
+pub const identifier: &[u8] = b"P256-SHA256";
 
 pub type P256SerializedPoint = [u8; 33];
 
@@ -39,9 +39,7 @@ pub fn scalar_inverse(s: P256Scalar) -> P256Scalar {
 /// using L = 48, expand_message_xmd with SHA-256, DST = "HashToScalar-"
 /// || contextString, and prime modulus equal to Group.Order().
 pub fn hash_to_scalar(bytes: &[u8], context_string: &[u8]) -> Result<P256Scalar, Error> {
-    let mut dst: Vec<u8> = "HashToScalar-".into(); // DST = "HashToScalar-" || contextString
-    dst.extend_from_slice(context_string);
-    hash_to_scalar_dst(bytes, &dst, context_string)
+    hash_to_scalar_dst(bytes, b"HashToScalar-", context_string) // DST = "HashToScalar-" || contextString
 }
 
 pub fn hash_to_scalar_dst(
@@ -94,3 +94,10 @@
 //! Section 3.1.
 
 pub mod configuration;
+pub mod oprf;
+pub mod poprf;
+pub mod setup;
+pub mod voprf;
+
+#[cfg(test)]
+mod test_vectors;
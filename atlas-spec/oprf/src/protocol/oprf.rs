@@ -0,0 +1,251 @@
+//! ### 3.3.1. OPRF Protocol
+//!
+//! The OPRF protocol begins with the client blinding its input, as
+//! described by the Blind function below.  Note that this function can
+//! fail with an InvalidInputError error for certain inputs that map to
+//! the group identity element.  Dealing with this failure is an
+//! application-specific decision; see Section 5.3.
+//!
+//! Clients store blind locally and send blindedElement to the server for
+//! evaluation.  Upon receipt, servers process blindedElement using the
+//! BlindEvaluate function.  Servers send the output evaluatedElement to
+//! clients for processing.  Upon receipt of evaluatedElement, clients
+//! process it to complete the OPRF evaluation with the Finalize
+//! function.
+//!
+//! An entity that knows both the private key and the input can compute
+//! the PRF result using the Evaluate function.
+
+use hacspec_lib::{i2osp, Randomness};
+use p256::{P256Point, P256Scalar};
+
+use super::{
+    configuration::{create_context_string, ModeID},
+    setup::PrivateKey,
+};
+use crate::{p256_sha256, Error};
+
+/// The output of the PRF, `Nh = 32` bytes.
+pub type Output = [u8; 32];
+
+const DST_BLIND: &[u8] = b"OPRF-Blind";
+
+fn context_string() -> Vec<u8> {
+    create_context_string(ModeID::modeOPRF, p256_sha256::identifier)
+}
+
+/// Elements received from the other party must not be the group
+/// identity.
+pub(super) fn check_element(element: P256Point) -> Result<(), Error> {
+    if element == p256_sha256::identity() {
+        return Err(Error::InvalidInputError);
+    }
+    Ok(())
+}
+
+/// Map `input` to the group and multiply the result by `blind`.
+pub(super) fn blind_with(
+    input: &[u8],
+    blind: P256Scalar,
+    context_string: &[u8],
+) -> Result<P256Point, Error> {
+    let inputElement = p256_sha256::hash_to_group(input, context_string)?;
+    check_element(inputElement)?;
+
+    Ok(p256::p256_point_mul(blind, inputElement)?)
+}
+
+/// Sample a random blind.
+pub(super) fn random_blind(randomness: &mut Randomness) -> Result<P256Scalar, Error> {
+    Ok(p256::random_scalar(randomness, DST_BLIND)?)
+}
+
+/// Remove the blind from an evaluated element.
+pub(super) fn unblind(blind: P256Scalar, evaluatedElement: P256Point) -> Result<P256Point, Error> {
+    check_element(evaluatedElement)?;
+    Ok(p256::p256_point_mul(
+        p256_sha256::scalar_inverse(blind),
+        evaluatedElement,
+    )?)
+}
+
+/// Hash the input, the public `info` (POPRF mode only) and the unblinded
+/// element to the PRF output.
+pub(super) fn finalize_hash(input: &[u8], info: Option<&[u8]>, element: P256Point) -> Output {
+    let unblindedElement = p256_sha256::serialize_element(&element);
+
+    let mut hashInput = i2osp(input.len(), 2);
+    hashInput.extend_from_slice(input);
+    if let Some(info) = info {
+        hashInput.extend_from_slice(&i2osp(info.len(), 2));
+        hashInput.extend_from_slice(info);
+    }
+    hashInput.extend_from_slice(&i2osp(unblindedElement.len(), 2));
+    hashInput.extend_from_slice(&unblindedElement);
+    hashInput.extend_from_slice(b"Finalize");
+
+    sha256::hash(&hashInput)
+}
+
+/// ```text
+/// Input:
+///
+///   PrivateInput input
+///
+/// Output:
+///
+///   Scalar blind
+///   Element blindedElement
+///
+/// Parameters:
+///
+///   Group G
+///
+/// Errors: InvalidInputError
+///
+/// def Blind(input):
+///   blind = G.RandomScalar()
+///   inputElement = G.HashToGroup(input)
+///   if inputElement == G.Identity():
+///     raise InvalidInputError
+///   blindedElement = blind * inputElement
+///
+///   return blind, blindedElement
+/// ```
+pub fn blind(input: &[u8], randomness: &mut Randomness) -> Result<(P256Scalar, P256Point), Error> {
+    let blind = random_blind(randomness)?;
+    let blindedElement = blind_with(input, blind, &context_string())?;
+
+    Ok((blind, blindedElement))
+}
+
+/// ```text
+/// Input:
+///
+///   Scalar skS
+///   Element blindedElement
+///
+/// Output:
+///
+///   Element evaluatedElement
+///
+/// def BlindEvaluate(skS, blindedElement):
+///   evaluatedElement = skS * blindedElement
+///   return evaluatedElement
+/// ```
+pub fn blind_evaluate(skS: PrivateKey, blindedElement: P256Point) -> Result<P256Point, Error> {
+    check_element(blindedElement)?;
+    Ok(p256::p256_point_mul(skS, blindedElement)?)
+}
+
+/// ```text
+/// Input:
+///
+///   PrivateInput input
+///   Scalar blind
+///   Element evaluatedElement
+///
+/// Output:
+///
+///   opaque output[Nh]
+///
+/// Parameters:
+///
+///   Group G
+///
+/// def Finalize(input, blind, evaluatedElement):
+///   N = G.ScalarInverse(blind) * evaluatedElement
+///   unblindedElement = G.SerializeElement(N)
+///
+///   hashInput = I2OSP(len(input), 2) || input ||
+///               I2OSP(len(unblindedElement), 2) || unblindedElement ||
+///               "Finalize"
+///   return Hash(hashInput)
+/// ```
+pub fn finalize(
+    input: &[u8],
+    blind: P256Scalar,
+    evaluatedElement: P256Point,
+) -> Result<Output, Error> {
+    let N = unblind(blind, evaluatedElement)?;
+    Ok(finalize_hash(input, None, N))
+}
+
+/// ```text
+/// Input:
+///
+///   Scalar skS
+///   PrivateInput input
+///
+/// Output:
+///
+///   opaque output[Nh]
+///
+/// Parameters:
+///
+///   Group G
+///
+/// Errors: InvalidInputError
+///
+/// def Evaluate(skS, input):
+///   inputElement = G.HashToGroup(input)
+///   if inputElement == G.Identity():
+///     raise InvalidInputError
+///   evaluatedElement = skS * inputElement
+///   issuedElement = G.SerializeElement(evaluatedElement)
+///
+///   hashInput = I2OSP(len(input), 2) || input ||
+///               I2OSP(len(issuedElement), 2) || issuedElement ||
+///               "Finalize"
+///   return Hash(hashInput)
+/// ```
+pub fn evaluate(skS: PrivateKey, input: &[u8]) -> Result<Output, Error> {
+    let evaluatedElement = blind_with(input, skS, &context_string())?;
+    Ok(finalize_hash(input, None, evaluatedElement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{setup::derive_key_pair, test_vectors};
+
+    #[test]
+    fn test_vectors() {
+        let suite = test_vectors::load(ModeID::modeOPRF);
+        let (skS, _) = derive_key_pair(&suite.seed, &suite.key_info, ModeID::modeOPRF).unwrap();
+        assert_eq!(skS, suite.skS);
+
+        for vector in suite.vectors {
+            for (i, input) in vector.inputs.iter().enumerate() {
+                let blindedElement =
+                    blind_with(input, vector.blinds[i], &context_string()).unwrap();
+                assert_eq!(blindedElement, vector.blinded_elements[i]);
+
+                let evaluatedElement = blind_evaluate(skS, blindedElement).unwrap();
+                assert_eq!(evaluatedElement, vector.evaluation_elements[i]);
+
+                let output = finalize(input, vector.blinds[i], evaluatedElement).unwrap();
+                assert_eq!(output.to_vec(), vector.outputs[i]);
+                assert_eq!(evaluate(skS, input).unwrap().to_vec(), vector.outputs[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn blind_evaluate_finalize() {
+        use rand::prelude::*;
+
+        let mut bytes = vec![0u8; 64];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let mut randomness = Randomness::new(bytes);
+        let (skS, _) = crate::protocol::setup::generate_key_pair(&mut randomness).unwrap();
+
+        let input = b"input";
+        let (blind, blindedElement) = blind(input, &mut randomness).unwrap();
+        let evaluatedElement = blind_evaluate(skS, blindedElement).unwrap();
+        let output = finalize(input, blind, evaluatedElement).unwrap();
+
+        assert_eq!(output, evaluate(skS, input).unwrap());
+        assert_ne!(output, evaluate(skS, b"other input").unwrap());
+    }
+}
@@ -0,0 +1,419 @@
+//! ### 3.3.3. POPRF Protocol
+//!
+//! The POPRF protocol begins with the client blinding its input, using
+//! the following modified Blind function.  In this step, the client also
+//! binds a public info value, which produces an additional tweakedKey to
+//! be used later in the protocol.  Note that this function can fail with
+//! an InvalidInputError error for certain private inputs that map to the
+//! group identity element, as well as certain public inputs that, if not
+//! detected at this point, will cause server evaluation to fail.
+//!
+//! Clients store the outputs blind and tweakedKey locally and send
+//! blindedElement to the server for evaluation.  Upon receipt, servers
+//! process blindedElement to compute an evaluated element and a DLEQ
+//! proof using the following BlindEvaluate function.
+//!
+//! As in the VOPRF mode, the functions below are stated for batches of
+//! blinded elements with a single batched DLEQ proof.
+//!
+//! The server sends both evaluatedElement and proof back to the client.
+//! Upon receipt, the client processes both values to complete the POPRF
+//! computation using the Finalize function below.
+
+use hacspec_lib::{i2osp, Randomness};
+use p256::{P256Point, P256Scalar};
+
+use super::{
+    configuration::{create_context_string, ModeID},
+    oprf::{blind_with, check_element, finalize_hash, random_blind, unblind, Output},
+    setup::{PrivateKey, PublicKey},
+};
+use crate::{
+    dleq::{self, Proof},
+    p256_sha256, Error,
+};
+
+fn context_string() -> Vec<u8> {
+    create_context_string(ModeID::modePOPRF, p256_sha256::identifier)
+}
+
+fn generator() -> Result<P256Point, Error> {
+    use hacspec_lib::hacspec_helper::NatMod;
+    Ok(p256::p256_point_mul_base(P256Scalar::one())?)
+}
+
+/// `m = G.HashToScalar("Info" || I2OSP(len(info), 2) || info)`
+fn tweak(info: &[u8]) -> Result<P256Scalar, Error> {
+    let mut framedInfo = b"Info".to_vec();
+    framedInfo.extend_from_slice(&i2osp(info.len(), 2));
+    framedInfo.extend_from_slice(info);
+
+    p256_sha256::hash_to_scalar(&framedInfo, &context_string())
+}
+
+/// `t = skS + m`, failing with `InverseError` if `t` is not invertible.
+fn tweaked_private_key(skS: PrivateKey, info: &[u8]) -> Result<P256Scalar, Error> {
+    use hacspec_lib::hacspec_helper::NatMod;
+
+    let t = skS + tweak(info)?;
+    if t == P256Scalar::zero() {
+        return Err(Error::InverseError);
+    }
+    Ok(t)
+}
+
+/// ```text
+/// Input:
+///
+///   PrivateInput input
+///   PublicInput info
+///   Element pkS
+///
+/// Output:
+///
+///   Scalar blind
+///   Element blindedElement
+///   Element tweakedKey
+///
+/// Parameters:
+///
+///   Group G
+///
+/// Errors: InvalidInputError
+///
+/// def Blind(input, info, pkS):
+///   framedInfo = "Info" || I2OSP(len(info), 2) || info
+///   m = G.HashToScalar(framedInfo)
+///   T = G.ScalarMultGen(m)
+///   tweakedKey = T + pkS
+///   if tweakedKey == G.Identity():
+///     raise InvalidInputError
+///
+///   blind = G.RandomScalar()
+///   inputElement = G.HashToGroup(input)
+///   if inputElement == G.Identity():
+///     raise InvalidInputError
+///
+///   blindedElement = blind * inputElement
+///
+///   return blind, blindedElement, tweakedKey
+/// ```
+pub fn blind(
+    input: &[u8],
+    info: &[u8],
+    pkS: PublicKey,
+    randomness: &mut Randomness,
+) -> Result<(P256Scalar, P256Point, P256Point), Error> {
+    let T = p256::p256_point_mul_base(tweak(info)?)?;
+    if T == -pkS {
+        return Err(Error::InvalidInputError);
+    }
+    let tweakedKey = p256::point_add(T, pkS)?;
+
+    let blind = random_blind(randomness)?;
+    let blindedElement = blind_with(input, blind, &context_string())?;
+
+    Ok((blind, blindedElement, tweakedKey))
+}
+
+/// ```text
+/// Input:
+///
+///   Scalar skS
+///   Element blindedElement
+///   PublicInput info
+///
+/// Output:
+///
+///   Element evaluatedElement
+///   Proof proof
+///
+/// Parameters:
+///
+///   Group G
+///
+/// Errors: InverseError
+///
+/// def BlindEvaluate(skS, blindedElement, info):
+///   framedInfo = "Info" || I2OSP(len(info), 2) || info
+///   m = G.HashToScalar(framedInfo)
+///   t = skS + m
+///   if t == 0:
+///       raise InverseError
+///
+///   evaluatedElement = G.ScalarInverse(t) * blindedElement
+///
+///   tweakedKey = G.ScalarMultGen(t)
+///   evaluatedElements = [evaluatedElement] // list of length 1
+///   blindedElements = [blindedElement]     // list of length 1
+///   proof = GenerateProof(t, G.Generator(), tweakedKey,
+///                         evaluatedElements, blindedElements)
+///
+///   return evaluatedElement, proof
+/// ```
+pub fn blind_evaluate(
+    skS: PrivateKey,
+    blindedElements: &[P256Point],
+    info: &[u8],
+    randomness: &mut Randomness,
+) -> Result<(Vec<P256Point>, Proof), Error> {
+    let t = tweaked_private_key(skS, info)?;
+    let evaluatedElements = evaluate_elements(t, blindedElements)?;
+
+    let tweakedKey = p256::p256_point_mul_base(t)?;
+    let proof = dleq::generate_proof(
+        t,
+        generator()?,
+        tweakedKey,
+        &evaluatedElements,
+        blindedElements,
+        &context_string(),
+        randomness,
+    )?;
+
+    Ok((evaluatedElements, proof))
+}
+
+fn evaluate_elements(
+    t: P256Scalar,
+    blindedElements: &[P256Point],
+) -> Result<Vec<P256Point>, Error> {
+    let t_inverse = p256_sha256::scalar_inverse(t);
+    blindedElements
+        .iter()
+        .map(|blindedElement| {
+            check_element(*blindedElement)?;
+            Ok(p256::p256_point_mul(t_inverse, *blindedElement)?)
+        })
+        .collect()
+}
+
+/// ```text
+/// Input:
+///
+///   PrivateInput input
+///   Scalar blind
+///   Element evaluatedElement
+///   Element blindedElement
+///   Proof proof
+///   PublicInput info
+///   Element tweakedKey
+///
+/// Output:
+///
+///   opaque output[Nh]
+///
+/// Parameters:
+///
+///   Group G
+///
+/// Errors: VerifyError
+///
+/// def Finalize(input, blind, evaluatedElement, blindedElement,
+///              proof, info, tweakedKey):
+///   evaluatedElements = [evaluatedElement] // list of length 1
+///   blindedElements = [blindedElement]     // list of length 1
+///   if VerifyProof(G.Generator(), tweakedKey, evaluatedElements,
+///                  blindedElements, proof) == false:
+///     raise VerifyError
+///
+///   N = G.ScalarInverse(blind) * evaluatedElement
+///   unblindedElement = G.SerializeElement(N)
+///
+///   hashInput = I2OSP(len(input), 2) || input ||
+///               I2OSP(len(info), 2) || info ||
+///               I2OSP(len(unblindedElement), 2) || unblindedElement ||
+///               "Finalize"
+///   return Hash(hashInput)
+/// ```
+///
+/// Raises:
+/// - `InvalidInputError`: If the batch sizes don't match.
+/// - `InvalidProofError`: If the proof does not verify.
+pub fn finalize(
+    inputs: &[&[u8]],
+    blinds: &[P256Scalar],
+    evaluatedElements: &[P256Point],
+    blindedElements: &[P256Point],
+    proof: &Proof,
+    info: &[u8],
+    tweakedKey: P256Point,
+) -> Result<Vec<Output>, Error> {
+    if inputs.len() != blinds.len() || inputs.len() != evaluatedElements.len() {
+        return Err(Error::InvalidInputError);
+    }
+
+    dleq::verify_proof(
+        generator()?,
+        tweakedKey,
+        evaluatedElements,
+        blindedElements,
+        proof,
+        &context_string(),
+    )?;
+
+    inputs
+        .iter()
+        .zip(blinds)
+        .zip(evaluatedElements)
+        .map(|((input, blind), evaluatedElement)| {
+            let N = unblind(*blind, *evaluatedElement)?;
+            Ok(finalize_hash(input, Some(info), N))
+        })
+        .collect()
+}
+
+/// ```text
+/// Input:
+///
+///   Scalar skS
+///   PrivateInput input
+///   PublicInput info
+///
+/// Output:
+///
+///   opaque output[Nh]
+///
+/// Parameters:
+///
+///   Group G
+///
+/// Errors: InvalidInputError, InverseError
+///
+/// def Evaluate(skS, input, info):
+///   inputElement = G.HashToGroup(input)
+///   if inputElement == G.Identity():
+///     raise InvalidInputError
+///
+///   framedInfo = "Info" || I2OSP(len(info), 2) || info
+///   m = G.HashToScalar(framedInfo)
+///   t = skS + m
+///   if t == 0:
+///       raise InverseError
+///   evaluatedElement = G.ScalarInverse(t) * inputElement
+///   issuedElement = G.SerializeElement(evaluatedElement)
+///
+///   hashInput = I2OSP(len(input), 2) || input ||
+///               I2OSP(len(info), 2) || info ||
+///               I2OSP(len(issuedElement), 2) || issuedElement ||
+///               "Finalize"
+///   return Hash(hashInput)
+/// ```
+pub fn evaluate(skS: PrivateKey, input: &[u8], info: &[u8]) -> Result<Output, Error> {
+    let t = tweaked_private_key(skS, info)?;
+    let evaluatedElement = blind_with(input, p256_sha256::scalar_inverse(t), &context_string())?;
+    Ok(finalize_hash(input, Some(info), evaluatedElement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        setup::{derive_key_pair, generate_key_pair},
+        test_vectors,
+    };
+
+    #[test]
+    fn test_vectors() {
+        let suite = test_vectors::load(ModeID::modePOPRF);
+        let (skS, pkS) = derive_key_pair(&suite.seed, &suite.key_info, ModeID::modePOPRF).unwrap();
+        assert_eq!(skS, suite.skS);
+        assert_eq!(Some(pkS), suite.pkS);
+
+        for vector in suite.vectors {
+            let info = vector.info.unwrap();
+            let t = tweaked_private_key(skS, &info).unwrap();
+            let tweakedKey = p256::point_add(
+                p256::p256_point_mul_base(tweak(&info).unwrap()).unwrap(),
+                pkS,
+            )
+            .unwrap();
+            assert_eq!(tweakedKey, p256::p256_point_mul_base(t).unwrap());
+
+            let blindedElements = vector
+                .inputs
+                .iter()
+                .zip(&vector.blinds)
+                .map(|(input, blind)| blind_with(input, *blind, &context_string()).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(blindedElements, vector.blinded_elements);
+
+            let evaluatedElements = evaluate_elements(t, &blindedElements).unwrap();
+            assert_eq!(evaluatedElements, vector.evaluation_elements);
+
+            let (expected_proof, r) = vector.proof.unwrap();
+            let proof = dleq::generate_proof_with_nonce(
+                t,
+                generator().unwrap(),
+                tweakedKey,
+                &evaluatedElements,
+                &blindedElements,
+                r,
+                &context_string(),
+            )
+            .unwrap();
+            assert_eq!(proof.serialize().to_vec(), expected_proof);
+
+            let inputs = vector.inputs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            let outputs = finalize(
+                &inputs,
+                &vector.blinds,
+                &evaluatedElements,
+                &blindedElements,
+                &proof,
+                &info,
+                tweakedKey,
+            )
+            .unwrap();
+            for ((input, output), expected) in inputs.iter().zip(outputs).zip(&vector.outputs) {
+                assert_eq!(&output.to_vec(), expected);
+                assert_eq!(evaluate(skS, input, &info).unwrap(), output);
+            }
+        }
+    }
+
+    #[test]
+    fn finalize_rejects_other_info() {
+        use rand::prelude::*;
+
+        let mut bytes = vec![0u8; 128];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let mut randomness = Randomness::new(bytes);
+        let (skS, pkS) = generate_key_pair(&mut randomness).unwrap();
+
+        let input: &[u8] = b"input";
+        let (blind, blindedElement, tweakedKey) =
+            super::blind(input, b"info", pkS, &mut randomness).unwrap();
+
+        let (evaluatedElements, proof) =
+            blind_evaluate(skS, &[blindedElement], b"info", &mut randomness).unwrap();
+        let outputs = finalize(
+            &[input],
+            &[blind],
+            &evaluatedElements,
+            &[blindedElement],
+            &proof,
+            b"info",
+            tweakedKey,
+        )
+        .unwrap();
+        assert_eq!(outputs[0], evaluate(skS, input, b"info").unwrap());
+        assert_ne!(outputs[0], evaluate(skS, input, b"other info").unwrap());
+
+        // The server evaluated with a different public input.
+        let (evaluatedElements, proof) =
+            blind_evaluate(skS, &[blindedElement], b"other info", &mut randomness).unwrap();
+        assert_eq!(
+            finalize(
+                &[input],
+                &[blind],
+                &evaluatedElements,
+                &[blindedElement],
+                &proof,
+                b"info",
+                tweakedKey,
+            ),
+            Err(Error::InvalidProofError)
+        );
+    }
+}
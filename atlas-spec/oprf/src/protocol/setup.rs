@@ -0,0 +1,103 @@
+//! ## 3.2. Key Generation and Context Setup
+//!
+//! In the offline setup phase, the server generates a fresh, random key
+//! pair (skS, pkS).  There are two ways to generate this key pair.  The
+//! first of which is using the GenerateKeyPair function described in
+//! Section 2.1.  The second of which is using the DeriveKeyPair
+//! function described in Section 3.2.1.  The key pair generated by
+//! either of these functions is the server's key pair for all
+//! subsequent protocol executions.  In the VOPRF and POPRF modes, the
+//! public key pkS is distributed to clients.
+
+use hacspec_lib::{i2osp, Randomness};
+use p256::{P256Point, P256Scalar};
+
+use super::configuration::{create_context_string, ModeID};
+use crate::{p256_sha256, Error};
+
+/// A server's private key `skS`.
+pub type PrivateKey = P256Scalar;
+/// A server's public key `pkS = skS * G`.
+pub type PublicKey = P256Point;
+
+const DST_KEY_GENERATION: &[u8] = b"OPRF-GenerateKeyPair";
+
+/// GenerateKeyPair(): Creates a random private key skS and its
+/// corresponding public key pkS.
+///
+/// ```text
+/// def GenerateKeyPair():
+///   skS = G.RandomScalar()
+///   pkS = G.ScalarMultGen(skS)
+///   return skS, pkS
+/// ```
+pub fn generate_key_pair(randomness: &mut Randomness) -> Result<(PrivateKey, PublicKey), Error> {
+    let skS = p256::random_scalar(randomness, DST_KEY_GENERATION)?;
+    let pkS = p256::p256_point_mul_base(skS)?;
+
+    Ok((skS, pkS))
+}
+
+/// ### 3.2.1. Deterministic Key Generation
+///
+/// This section describes a deterministic key generation function,
+/// DeriveKeyPair, which is used to deterministically derive a key pair
+/// from a seed and info string.
+///
+/// ```text
+/// Input:
+///
+///   opaque seed[Ns]
+///   PublicInput info
+///
+/// Output:
+///
+///   Scalar skS
+///   Element pkS
+///
+/// Parameters:
+///
+///   Group G
+///   PublicInput contextString
+///
+/// Errors: DeriveKeyPairError
+///
+/// def DeriveKeyPair(seed, info):
+///   deriveInput = seed || I2OSP(len(info), 2) || info
+///   counter = 0
+///   skS = 0
+///   while skS == 0:
+///     if counter > 255:
+///       raise DeriveKeyPairError
+///     skS = G.HashToScalar(deriveInput || I2OSP(counter, 1),
+///                           DST = "DeriveKeyPair" || contextString)
+///     counter = counter + 1
+///   pkS = G.ScalarMultGen(skS)
+///   return skS, pkS
+/// ```
+pub fn derive_key_pair(
+    seed: &[u8; 32],
+    info: &[u8],
+    mode: ModeID,
+) -> Result<(PrivateKey, PublicKey), Error> {
+    use hacspec_lib::hacspec_helper::NatMod;
+
+    let context_string = create_context_string(mode, p256_sha256::identifier);
+
+    let mut derive_input = seed.to_vec();
+    derive_input.extend_from_slice(&i2osp(info.len(), 2));
+    derive_input.extend_from_slice(info);
+
+    for counter in 0..=u8::MAX {
+        let mut counter_input = derive_input.clone();
+        counter_input.push(counter);
+        let skS =
+            p256_sha256::hash_to_scalar_dst(&counter_input, b"DeriveKeyPair", &context_string)?;
+        if skS != P256Scalar::zero() {
+            let pkS = p256::p256_point_mul_base(skS)?;
+            return Ok((skS, pkS));
+        }
+    }
+
+    Err(Error::DeriveKeyPairError)
+}
@@ -0,0 +1,91 @@
+//! Parsing of the RFC 9497 test vectors for P256-SHA256.
+
+use hacspec_lib::hacspec_helper::NatMod;
+use p256::{P256Point, P256Scalar};
+use serde_json::Value;
+
+use super::configuration::ModeID;
+use crate::p256_sha256;
+
+pub(crate) struct Suite {
+    pub(crate) seed: [u8; 32],
+    pub(crate) key_info: Vec<u8>,
+    pub(crate) skS: P256Scalar,
+    pub(crate) pkS: Option<P256Point>,
+    pub(crate) vectors: Vec<Vector>,
+}
+
+pub(crate) struct Vector {
+    pub(crate) inputs: Vec<Vec<u8>>,
+    pub(crate) info: Option<Vec<u8>>,
+    pub(crate) blinds: Vec<P256Scalar>,
+    pub(crate) blinded_elements: Vec<P256Point>,
+    pub(crate) evaluation_elements: Vec<P256Point>,
+    pub(crate) outputs: Vec<Vec<u8>>,
+    /// The serialized proof and the nonce used to generate it.
+    pub(crate) proof: Option<(Vec<u8>, P256Scalar)>,
+}
+
+fn bytes(value: &Value) -> Vec<u8> {
+    hex::decode(value.as_str().unwrap()).unwrap()
+}
+
+fn list(value: &Value) -> Vec<Vec<u8>> {
+    value
+        .as_str()
+        .unwrap()
+        .split(',')
+        .map(|item| hex::decode(item).unwrap())
+        .collect()
+}
+
+fn element(bytes: &[u8]) -> P256Point {
+    p256_sha256::deserialize_element(bytes.try_into().unwrap()).unwrap()
+}
+
+fn scalar(bytes: &[u8]) -> P256Scalar {
+    P256Scalar::from_be_bytes(bytes)
+}
+
+/// Load the P256-SHA256 test vectors for the given mode.
+pub(crate) fn load(mode: ModeID) -> Suite {
+    let mode = mode as u64;
+    let suites: Value = serde_json::from_str(include_str!("../../allVectors.json")).unwrap();
+    let suite = suites
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|suite| suite["identifier"] == "P256-SHA256" && suite["mode"] == mode)
+        .unwrap();
+
+    let vectors = suite["vectors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|vector| Vector {
+            inputs: list(&vector["Input"]),
+            info: vector.get("Info").map(bytes),
+            blinds: list(&vector["Blind"]).iter().map(|b| scalar(b)).collect(),
+            blinded_elements: list(&vector["BlindedElement"])
+                .iter()
+                .map(|e| element(e))
+                .collect(),
+            evaluation_elements: list(&vector["EvaluationElement"])
+                .iter()
+                .map(|e| element(e))
+                .collect(),
+            outputs: list(&vector["Output"]),
+            proof: vector
+                .get("Proof")
+                .map(|proof| (bytes(&proof["proof"]), scalar(&bytes(&proof["r"])))),
+        })
+        .collect();
+
+    Suite {
+        seed: bytes(&suite["seed"]).try_into().unwrap(),
+        key_info: bytes(&suite["keyInfo"]),
+        skS: scalar(&bytes(&suite["skSm"])),
+        pkS: suite.get("pkSm").map(|pkS| element(&bytes(pkS))),
+        vectors,
+    }
+}
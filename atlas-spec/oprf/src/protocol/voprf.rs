@@ -0,0 +1,289 @@
+//! ### 3.3.2. VOPRF Protocol
+//!
+//! The VOPRF protocol begins with the client blinding its input, using
+//! the same Blind function as in Section 3.3.1.  Clients store the
+//! output blind locally and send blindedElement to the server for
+//! evaluation.  Upon receipt, servers process blindedElement to compute
+//! an evaluated element and a DLEQ proof using the BlindEvaluate
+//! function.
+//!
+//! In the description above, inputs to GenerateProof are one-item lists.
+//! Using larger lists allows servers to batch the evaluation of multiple
+//! elements while producing a single batched DLEQ proof for them.  The
+//! functions below are stated for such batches; a single evaluation is a
+//! batch of size one.
+//!
+//! The server sends both evaluatedElement and proof back to the client.
+//! Upon receipt, the client processes both values to complete the VOPRF
+//! computation using the Finalize function below.
+
+use hacspec_lib::Randomness;
+use p256::{P256Point, P256Scalar};
+
+use super::{
+    configuration::{create_context_string, ModeID},
+    oprf::{blind_with, check_element, finalize_hash, random_blind, unblind, Output},
+    setup::{PrivateKey, PublicKey},
+};
+use crate::{
+    dleq::{self, Proof},
+    p256_sha256, Error,
+};
+
+fn context_string() -> Vec<u8> {
+    create_context_string(ModeID::modeVOPRF, p256_sha256::identifier)
+}
+
+fn generator() -> Result<P256Point, Error> {
+    use hacspec_lib::hacspec_helper::NatMod;
+    Ok(p256::p256_point_mul_base(P256Scalar::one())?)
+}
+
+/// The Blind function of the VOPRF mode is the same as in the OPRF mode,
+/// but uses the VOPRF context string.
+pub fn blind(input: &[u8], randomness: &mut Randomness) -> Result<(P256Scalar, P256Point), Error> {
+    let blind = random_blind(randomness)?;
+    let blindedElement = blind_with(input, blind, &context_string())?;
+
+    Ok((blind, blindedElement))
+}
+
+/// ```text
+/// Input:
+///
+///   Scalar skS
+///   Element pkS
+///   Element blindedElement
+///
+/// Output:
+///
+///   Element evaluatedElement
+///   Proof proof
+///
+/// Parameters:
+///
+///   Group G
+///
+/// def BlindEvaluate(skS, pkS, blindedElement):
+///   evaluatedElement = skS * blindedElement
+///   blindedElements = [blindedElement]     // list of length 1
+///   evaluatedElements = [evaluatedElement] // list of length 1
+///   proof = GenerateProof(skS, G.Generator(), pkS,
+///                         blindedElements, evaluatedElements)
+///   return evaluatedElement, proof
+/// ```
+pub fn blind_evaluate(
+    skS: PrivateKey,
+    pkS: PublicKey,
+    blindedElements: &[P256Point],
+    randomness: &mut Randomness,
+) -> Result<(Vec<P256Point>, Proof), Error> {
+    let evaluatedElements = evaluate_elements(skS, blindedElements)?;
+    let proof = dleq::generate_proof(
+        skS,
+        generator()?,
+        pkS,
+        blindedElements,
+        &evaluatedElements,
+        &context_string(),
+        randomness,
+    )?;
+
+    Ok((evaluatedElements, proof))
+}
+
+fn evaluate_elements(
+    skS: PrivateKey,
+    blindedElements: &[P256Point],
+) -> Result<Vec<P256Point>, Error> {
+    blindedElements
+        .iter()
+        .map(|blindedElement| {
+            check_element(*blindedElement)?;
+            Ok(p256::p256_point_mul(skS, *blindedElement)?)
+        })
+        .collect()
+}
+
+/// ```text
+/// Input:
+///
+///   PrivateInput input
+///   Scalar blind
+///   Element evaluatedElement
+///   Element blindedElement
+///   Element pkS
+///   Proof proof
+///
+/// Output:
+///
+///   opaque output[Nh]
+///
+/// Parameters:
+///
+///   Group G
+///
+/// Errors: VerifyError
+///
+/// def Finalize(input, blind, evaluatedElement,
+///              blindedElement, pkS, proof):
+///   blindedElements = [blindedElement]     // list of length 1
+///   evaluatedElements = [evaluatedElement] // list of length 1
+///   if VerifyProof(G.Generator(), pkS, blindedElements,
+///                  evaluatedElements, proof) == false:
+///     raise VerifyError
+///
+///   N = G.ScalarInverse(blind) * evaluatedElement
+///   unblindedElement = G.SerializeElement(N)
+///
+///   hashInput = I2OSP(len(input), 2) || input ||
+///               I2OSP(len(unblindedElement), 2) || unblindedElement ||
+///               "Finalize"
+///   return Hash(hashInput)
+/// ```
+///
+/// Raises:
+/// - `InvalidInputError`: If the batch sizes don't match.
+/// - `InvalidProofError`: If the proof does not verify.
+pub fn finalize(
+    inputs: &[&[u8]],
+    blinds: &[P256Scalar],
+    evaluatedElements: &[P256Point],
+    blindedElements: &[P256Point],
+    pkS: PublicKey,
+    proof: &Proof,
+) -> Result<Vec<Output>, Error> {
+    if inputs.len() != blinds.len() || inputs.len() != evaluatedElements.len() {
+        return Err(Error::InvalidInputError);
+    }
+
+    dleq::verify_proof(
+        generator()?,
+        pkS,
+        blindedElements,
+        evaluatedElements,
+        proof,
+        &context_string(),
+    )?;
+
+    inputs
+        .iter()
+        .zip(blinds)
+        .zip(evaluatedElements)
+        .map(|((input, blind), evaluatedElement)| {
+            let N = unblind(*blind, *evaluatedElement)?;
+            Ok(finalize_hash(input, None, N))
+        })
+        .collect()
+}
+
+/// The Evaluate function of the VOPRF mode is the same as in the OPRF
+/// mode, but uses the VOPRF context string.
+pub fn evaluate(skS: PrivateKey, input: &[u8]) -> Result<Output, Error> {
+    let evaluatedElement = blind_with(input, skS, &context_string())?;
+    Ok(finalize_hash(input, None, evaluatedElement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        setup::{derive_key_pair, generate_key_pair},
+        test_vectors,
+    };
+
+    #[test]
+    fn test_vectors() {
+        let suite = test_vectors::load(ModeID::modeVOPRF);
+        let (skS, pkS) = derive_key_pair(&suite.seed, &suite.key_info, ModeID::modeVOPRF).unwrap();
+        assert_eq!(skS, suite.skS);
+        assert_eq!(Some(pkS), suite.pkS);
+
+        for vector in suite.vectors {
+            let blindedElements = vector
+                .inputs
+                .iter()
+                .zip(&vector.blinds)
+                .map(|(input, blind)| blind_with(input, *blind, &context_string()).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(blindedElements, vector.blinded_elements);
+
+            let evaluatedElements = evaluate_elements(skS, &blindedElements).unwrap();
+            assert_eq!(evaluatedElements, vector.evaluation_elements);
+
+            let (expected_proof, r) = vector.proof.unwrap();
+            let proof = dleq::generate_proof_with_nonce(
+                skS,
+                generator().unwrap(),
+                pkS,
+                &blindedElements,
+                &evaluatedElements,
+                r,
+                &context_string(),
+            )
+            .unwrap();
+            assert_eq!(proof.serialize().to_vec(), expected_proof);
+
+            let inputs = vector.inputs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            let outputs = finalize(
+                &inputs,
+                &vector.blinds,
+                &evaluatedElements,
+                &blindedElements,
+                pkS,
+                &proof,
+            )
+            .unwrap();
+            for ((input, output), expected) in inputs.iter().zip(outputs).zip(&vector.outputs) {
+                assert_eq!(&output.to_vec(), expected);
+                assert_eq!(evaluate(skS, input).unwrap(), output);
+            }
+        }
+    }
+
+    #[test]
+    fn finalize_rejects_invalid_proof() {
+        use rand::prelude::*;
+
+        let mut bytes = vec![0u8; 256];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let mut randomness = Randomness::new(bytes);
+        let (skS, pkS) = generate_key_pair(&mut randomness).unwrap();
+        let (other_skS, _) = generate_key_pair(&mut randomness).unwrap();
+
+        let inputs: [&[u8]; 2] = [b"first input", b"second input"];
+        let (blinds, blindedElements): (Vec<_>, Vec<_>) = inputs
+            .iter()
+            .map(|input| blind(input, &mut randomness).unwrap())
+            .unzip();
+
+        let (evaluatedElements, proof) =
+            blind_evaluate(skS, pkS, &blindedElements, &mut randomness).unwrap();
+        let outputs = finalize(
+            &inputs,
+            &blinds,
+            &evaluatedElements,
+            &blindedElements,
+            pkS,
+            &proof,
+        )
+        .unwrap();
+        assert_eq!(outputs[0], evaluate(skS, inputs[0]).unwrap());
+        assert_eq!(outputs[1], evaluate(skS, inputs[1]).unwrap());
+
+        // Evaluation under a different key than pkS.
+        let (evaluatedElements, proof) =
+            blind_evaluate(other_skS, pkS, &blindedElements, &mut randomness).unwrap();
+        assert_eq!(
+            finalize(
+                &inputs,
+                &blinds,
+                &evaluatedElements,
+                &blindedElements,
+                pkS,
+                &proof
+            ),
+            Err(Error::InvalidProofError)
+        );
+    }
+}
@@ -21,6 +21,7 @@ const BITS: u128 = 256;
 #[nat_mod("ffffffff00000001000000000000000000000000ffffffffffffffffffffffff", 32)]
 pub struct P256FieldElement {}
 
+#[derive(Hash, PartialOrd, Ord)]
 #[nat_mod("ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551", 32)]
 pub struct P256Scalar {}
 
@@ -0,0 +1,633 @@
+//! # Converter Audit Log
+//!
+//! The converter records every conversion it performs in an append-only,
+//! hash-chained audit log. Each [`AuditRecord`] holds
+//! - the time of the conversion,
+//! - the requesting party,
+//! - whether it was a split or a join conversion,
+//! - the attribute names and the number of rows per attribute,
+//! - fingerprints of the receiver's blinding and encryption keys, and
+//! - for join conversions, an identifier of the conversion target.
+//!
+//! The log contains no pseudonyms, blinded or not, and no ciphertexts.
+//! Fingerprints are SHA-256 hashes of the canonical
+//! [wire format](crate::serialization) encodings of the keys, with the
+//! storage format version in their header, such that fingerprints remain
+//! comparable across wire format versions. The conversion target is
+//! identified by the fingerprint of the target key of the converter's
+//! [proofs](crate::data_types::ConversionProof), which the receiver of a
+//! join can compute as well.
+//!
+//! Every entry carries the link of the hash chain up to and including it:
+//!
+//! ``` text
+//! link_0 = 0^32
+//! link_i = SHA256(link_{i-1} || i || record_i)
+//! ```
+//!
+//! Anyone holding a copy of the log can check the chain offline using
+//! [`verify_audit_log`]. Since the converter writes the log, it can also
+//! rewrite or truncate it. The converter therefore hands out
+//! [checkpoints](Checkpoint) of the chain, i.e. the number of entries and
+//! the link of the last entry, to auditors, who keep their own copy of
+//! every checkpoint they receive. A log verifies against a checkpoint only
+//! if it extends the chain the checkpoint was taken of, so a rewritten or
+//! truncated log is detected by every auditor holding a checkpoint taken
+//! before the rewrite. Checkpoints are not authenticated: a checkpoint
+//! presented by the converter later proves nothing, only those stored by
+//! the auditors themselves do.
+//!
+//! On disk, each entry is stored as its wire format encoding prefixed by
+//! its length as a big-endian `u32`.
+
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use oprf::coprf::{
+    coprf_setup::BlindingPublicKey, coprf_threshold::combine_conversion_public_keys,
+};
+
+use crate::{
+    data_types::{BlindedIdentifiableData, BlindedPseudonymizedData, ConversionProof},
+    error::Error,
+    serialization::{
+        put_byte_string, put_point, put_u32, put_u64, Reader, WireFormat, STORAGE_FORMAT_VERSION,
+    },
+    setup::StoreEncryptionKey,
+    table::Table,
+};
+
+/// The length of fingerprints and chain links.
+pub const DIGEST_BYTES: usize = sha256::HASH_SIZE;
+
+/// The link preceding the first entry of an audit log.
+const GENESIS_LINK: [u8; DIGEST_BYTES] = [0; DIGEST_BYTES];
+
+const CONVERSION_SPLIT: u8 = 1;
+const CONVERSION_JOIN: u8 = 2;
+
+/// The kind of an audited conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionKind {
+    /// Pseudonymization as part of a split conversion.
+    Split,
+    /// Conversion as part of a join conversion.
+    Join,
+}
+
+/// The record of a single conversion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Seconds since the Unix epoch at the time of the conversion
+    pub timestamp: u64,
+    /// The party requesting the conversion
+    pub requester: String,
+    /// The kind of conversion
+    pub kind: ConversionKind,
+    /// The number of converted rows per attribute
+    pub attributes: BTreeMap<String, u64>,
+    /// Fingerprint of the receiver's public blinding key
+    pub receiver_blinding_key: [u8; DIGEST_BYTES],
+    /// Fingerprint of the receiver's public encryption key
+    pub receiver_encryption_key: [u8; DIGEST_BYTES],
+    /// Fingerprint of the conversion target key of a join conversion
+    pub conversion_target: Option<[u8; DIGEST_BYTES]>,
+}
+
+impl AuditRecord {
+    /// The record of a split conversion of the given blinded table.
+    pub fn split(
+        timestamp: u64,
+        requester: &str,
+        bpk_receiver: &BlindingPublicKey,
+        ek_receiver: &StoreEncryptionKey,
+        table: &Table<BlindedIdentifiableData>,
+    ) -> Self {
+        Self::new(
+            timestamp,
+            requester,
+            ConversionKind::Split,
+            bpk_receiver,
+            ek_receiver,
+            count_attributes(
+                table
+                    .data()
+                    .iter()
+                    .map(|entry| entry.encrypted_data_value.attribute_name.as_str()),
+            ),
+            None,
+        )
+    }
+
+    /// The record of a join conversion resulting in the given converted
+    /// table.
+    pub fn join(
+        timestamp: u64,
+        requester: &str,
+        bpk_receiver: &BlindingPublicKey,
+        ek_receiver: &StoreEncryptionKey,
+        converted_table: &Table<BlindedPseudonymizedData>,
+    ) -> Self {
+        Self::new(
+            timestamp,
+            requester,
+            ConversionKind::Join,
+            bpk_receiver,
+            ek_receiver,
+            count_attributes(
+                converted_table
+                    .data()
+                    .iter()
+                    .map(|entry| entry.encrypted_data_value.attribute_name.as_str()),
+            ),
+            converted_table.data().iter().find_map(conversion_target),
+        )
+    }
+
+    pub(crate) fn new(
+        timestamp: u64,
+        requester: &str,
+        kind: ConversionKind,
+        bpk_receiver: &BlindingPublicKey,
+        ek_receiver: &StoreEncryptionKey,
+        attributes: BTreeMap<String, u64>,
+        conversion_target: Option<[u8; DIGEST_BYTES]>,
+    ) -> Self {
+        let mut encoded_bpk = Vec::new();
+        put_point(&mut encoded_bpk, bpk_receiver);
+        let mut encoded_ek = vec![STORAGE_FORMAT_VERSION, StoreEncryptionKey::TAG];
+        ek_receiver.encode_body(&mut encoded_ek);
+        Self {
+            timestamp,
+            requester: requester.into(),
+            kind,
+            attributes,
+            receiver_blinding_key: sha256::hash(&encoded_bpk),
+            receiver_encryption_key: sha256::hash(&encoded_ek),
+            conversion_target,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        put_u64(out, self.timestamp);
+        put_byte_string(out, self.requester.as_bytes());
+        out.push(match self.kind {
+            ConversionKind::Split => CONVERSION_SPLIT,
+            ConversionKind::Join => CONVERSION_JOIN,
+        });
+        put_u32(out, self.attributes.len() as u32);
+        for (attribute, rows) in &self.attributes {
+            put_byte_string(out, attribute.as_bytes());
+            put_u64(out, *rows);
+        }
+        out.extend_from_slice(&self.receiver_blinding_key);
+        out.extend_from_slice(&self.receiver_encryption_key);
+        match &self.conversion_target {
+            Some(target) => {
+                out.push(1);
+                out.extend_from_slice(target);
+            }
+            None => out.push(0),
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let timestamp = reader.u64()?;
+        let requester = reader.string()?;
+        let kind = match reader.u8()? {
+            CONVERSION_SPLIT => ConversionKind::Split,
+            CONVERSION_JOIN => ConversionKind::Join,
+            _ => return Err(Error::CorruptedData),
+        };
+        let count = reader.count(12)?;
+        let mut attributes = BTreeMap::new();
+        for _ in 0..count {
+            if attributes.insert(reader.string()?, reader.u64()?).is_some() {
+                return Err(Error::CorruptedData);
+            }
+        }
+        let receiver_blinding_key = reader.take(DIGEST_BYTES)?.try_into()?;
+        let receiver_encryption_key = reader.take(DIGEST_BYTES)?.try_into()?;
+        let conversion_target = match reader.u8()? {
+            0 => None,
+            1 => Some(reader.take(DIGEST_BYTES)?.try_into()?),
+            _ => return Err(Error::CorruptedData),
+        };
+        Ok(Self {
+            timestamp,
+            requester,
+            kind,
+            attributes,
+            receiver_blinding_key,
+            receiver_encryption_key,
+            conversion_target,
+        })
+    }
+}
+
+/// Count the rows per attribute.
+pub(crate) fn count_attributes<'a>(
+    attribute_names: impl Iterator<Item = &'a str>,
+) -> BTreeMap<String, u64> {
+    let mut attributes = BTreeMap::new();
+    for attribute in attribute_names {
+        *attributes.entry(attribute.to_string()).or_insert(0) += 1;
+    }
+    attributes
+}
+
+/// The fingerprint of the conversion target key of a converted entry.
+pub(crate) fn conversion_target(entry: &BlindedPseudonymizedData) -> Option<[u8; DIGEST_BYTES]> {
+    let target_key = match &entry.conversion_proof {
+        Some(ConversionProof::Conversion { target_key, .. }) => *target_key,
+        Some(ConversionProof::ThresholdConversion { partials, .. }) => {
+            combine_conversion_public_keys(partials).ok()?
+        }
+        _ => return None,
+    };
+    let mut encoded = Vec::new();
+    put_point(&mut encoded, &target_key);
+    Some(sha256::hash(&encoded))
+}
+
+/// An entry of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// The position of the entry in the log, starting at 1
+    pub sequence: u64,
+    /// The audited conversion
+    pub record: AuditRecord,
+    /// The link of the hash chain up to and including this entry
+    pub link: [u8; DIGEST_BYTES],
+}
+
+impl WireFormat for AuditEntry {
+    const TAG: u8 = 0x0c;
+    const VERSION: u8 = STORAGE_FORMAT_VERSION;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_u64(out, self.sequence);
+        self.record.encode(out);
+        out.extend_from_slice(&self.link);
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            sequence: reader.u64()?,
+            record: AuditRecord::decode(reader)?,
+            link: reader.take(DIGEST_BYTES)?.try_into()?,
+        })
+    }
+}
+
+/// Compute the link of the entry holding `record` at position `sequence`.
+fn chain_link(
+    previous: &[u8; DIGEST_BYTES],
+    sequence: u64,
+    record: &AuditRecord,
+) -> [u8; DIGEST_BYTES] {
+    let mut input = previous.to_vec();
+    put_u64(&mut input, sequence);
+    record.encode(&mut input);
+    sha256::hash(&input)
+}
+
+/// The state of an audit log at some point in time, as stored by an
+/// auditor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// The number of entries in the log
+    pub sequence: u64,
+    /// The link of the last entry
+    pub link: [u8; DIGEST_BYTES],
+}
+
+/// An append-only audit log, written to a file.
+pub struct AuditLog {
+    file: File,
+    file_len: u64,
+    sequence: u64,
+    link: [u8; DIGEST_BYTES],
+}
+
+impl AuditLog {
+    /// Open the audit log at the given path, creating it if it does not
+    /// exist, and verify the existing entries.
+    ///
+    /// An entry at the end of the log that was not written completely, e.g.
+    /// because of a crash, is discarded.
+    ///
+    /// Raises:
+    /// - `VerificationError`: If the hash chain of the existing entries is
+    ///   broken.
+    /// - `CorruptedData`: If an entry is malformed.
+    /// - `StorageError`: If accessing the file fails.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        let (entries, len) = decode_entries(&contents)?;
+        verify_audit_log(&entries)?;
+        if len < contents.len() {
+            file.set_len(len as u64)?;
+            file.sync_all()?;
+        }
+
+        let (sequence, link) = entries
+            .last()
+            .map_or((0, GENESIS_LINK), |entry| (entry.sequence, entry.link));
+        Ok(Self {
+            file,
+            file_len: len as u64,
+            sequence,
+            link,
+        })
+    }
+
+    /// Append a record to the log. The entry is durable once this returns.
+    ///
+    /// Raises:
+    /// - `StorageError`: If writing the file fails.
+    pub fn append(&mut self, record: AuditRecord) -> Result<AuditEntry, Error> {
+        let sequence = self.sequence + 1;
+        let entry = AuditEntry {
+            sequence,
+            link: chain_link(&self.link, sequence, &record),
+            record,
+        };
+        let encoded = entry.to_bytes();
+        let mut frame = Vec::with_capacity(4 + encoded.len());
+        put_byte_string(&mut frame, &encoded);
+
+        let written = self
+            .file
+            .seek(SeekFrom::Start(self.file_len))
+            .and_then(|_| self.file.write_all(&frame))
+            .and_then(|_| self.file.sync_data());
+        if let Err(e) = written {
+            let _ = self.file.set_len(self.file_len);
+            return Err(e.into());
+        }
+
+        self.file_len += frame.len() as u64;
+        self.sequence = sequence;
+        self.link = entry.link;
+        Ok(entry)
+    }
+
+    /// The number of entries in the log.
+    pub fn len(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Whether the log has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.sequence == 0
+    }
+
+    /// Take a checkpoint of the current state of the log, to be handed to
+    /// auditors.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            sequence: self.sequence,
+            link: self.link,
+        }
+    }
+}
+
+/// Decode the entries of a log file, returning them together with the
+/// length of the complete entries.
+fn decode_entries(contents: &[u8]) -> Result<(Vec<AuditEntry>, usize), Error> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while contents.len() - offset >= 4 {
+        let len = u32::from_be_bytes(contents[offset..offset + 4].try_into()?) as usize;
+        if contents.len() - offset - 4 < len {
+            break;
+        }
+        entries.push(AuditEntry::from_bytes(
+            &contents[offset + 4..offset + 4 + len],
+        )?);
+        offset += 4 + len;
+    }
+    Ok((entries, offset))
+}
+
+/// Read all entries of an audit log, e.g. a copy handed to an auditor.
+///
+/// Raises:
+/// - `CorruptedData`: If an entry is malformed or incomplete.
+/// - `StorageError`: If reading fails.
+pub fn read_audit_log(mut reader: impl Read) -> Result<Vec<AuditEntry>, Error> {
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents)?;
+    let (entries, len) = decode_entries(&contents)?;
+    if len != contents.len() {
+        return Err(Error::CorruptedData);
+    }
+    Ok(entries)
+}
+
+/// ## Audit Log Verification
+///
+/// Verify the hash chain of a complete audit log.
+///
+/// Inputs:
+/// - `entries`: The entries of the log, starting with the first
+///
+/// Output:
+/// The link of the last entry.
+///
+/// Raises:
+/// - `VerificationError`: If the entries are not numbered consecutively or
+///   a link does not match.
+pub fn verify_audit_log(entries: &[AuditEntry]) -> Result<[u8; DIGEST_BYTES], Error> {
+    let mut link = GENESIS_LINK;
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.sequence != index as u64 + 1
+            || entry.link != chain_link(&link, entry.sequence, &entry.record)
+        {
+            return Err(Error::VerificationError);
+        }
+        link = entry.link;
+    }
+    Ok(link)
+}
+
+/// Verify a complete audit log against a checkpoint stored by an auditor,
+/// i.e. check that the log's hash chain is intact and that the log extends
+/// the state of the checkpoint.
+///
+/// Raises:
+/// - `VerificationError`: If any of the checks fails.
+pub fn verify_checkpoint(entries: &[AuditEntry], checkpoint: &Checkpoint) -> Result<(), Error> {
+    verify_audit_log(entries)?;
+    let link = match checkpoint.sequence {
+        0 => GENESIS_LINK,
+        sequence => {
+            entries
+                .get(sequence as usize - 1)
+                .ok_or(Error::VerificationError)?
+                .link
+        }
+    };
+    if link != checkpoint.link {
+        return Err(Error::VerificationError);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use hacspec_lib::Randomness;
+
+    use crate::{
+        join::{blind_pseudonymous_table, convert_blinded_table},
+        setup::{ConverterContext, StoreContext},
+        split::{blind_orthonymous_table, pseudonymize_blinded_table},
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    use super::*;
+
+    /// An audit log file that is removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let path = TempFile(std::env::temp_dir().join(format!(
+            "scrambledb-audit-{}",
+            hex::encode(randomness.bytes(16).unwrap())
+        )));
+
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+        let (processor_ek, processor_bpk) =
+            StoreContext::setup(&mut randomness).unwrap().public_keys();
+
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+        let split_record = AuditRecord::split(1, "source", &lake_bpk, &lake_ek, &blind_table);
+        assert_eq!(split_record.attributes["Address"], 2);
+        assert_eq!(split_record.conversion_target, None);
+
+        let lake_table = crate::finalize::finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            pseudonymize_blinded_table(
+                &converter_context,
+                lake_bpk,
+                &lake_ek,
+                blind_table,
+                &mut randomness,
+            )
+            .unwrap(),
+            &mut randomness,
+        )
+        .unwrap();
+        let joined_table = convert_blinded_table(
+            &converter_context,
+            processor_bpk,
+            &processor_ek,
+            blind_pseudonymous_table(
+                &lake_context,
+                &converter_keys,
+                processor_bpk,
+                &processor_ek,
+                lake_table,
+                &mut randomness,
+            )
+            .unwrap(),
+            &mut randomness,
+        )
+        .unwrap();
+        let join_record =
+            AuditRecord::join(2, "lake", &processor_bpk, &processor_ek, &joined_table);
+        assert!(join_record.conversion_target.is_some());
+        assert_ne!(
+            join_record.receiver_blinding_key,
+            split_record.receiver_blinding_key
+        );
+
+        let mut log = AuditLog::open(&path.0).unwrap();
+        log.append(split_record.clone()).unwrap();
+        let checkpoint = log.checkpoint();
+        drop(log);
+
+        // The log is resumed after reopening it.
+        let mut log = AuditLog::open(&path.0).unwrap();
+        assert_eq!(log.len(), 1);
+        log.append(join_record.clone()).unwrap();
+        drop(log);
+
+        let contents = std::fs::read(&path.0).unwrap();
+        let entries = read_audit_log(contents.as_slice()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].record, split_record);
+        assert_eq!(entries[1].record, join_record);
+        assert!(verify_checkpoint(&entries, &checkpoint).is_ok());
+        let mut other_checkpoint = checkpoint;
+        other_checkpoint.link[0] ^= 1;
+        assert_eq!(
+            verify_checkpoint(&entries, &other_checkpoint),
+            Err(Error::VerificationError)
+        );
+
+        // Modified, reordered and truncated logs are detected.
+        let mut modified = entries.clone();
+        modified[0].record.attributes.insert("Address".into(), 1);
+        assert_eq!(verify_audit_log(&modified), Err(Error::VerificationError));
+        assert_eq!(
+            verify_audit_log(&[entries[1].clone(), entries[0].clone()]),
+            Err(Error::VerificationError)
+        );
+        assert_eq!(
+            verify_checkpoint(&[], &checkpoint),
+            Err(Error::VerificationError)
+        );
+        let mut rewritten = entries.clone();
+        rewritten[0].record.requester = "other".into();
+        rewritten[0].link = chain_link(&GENESIS_LINK, 1, &rewritten[0].record);
+        rewritten.truncate(1);
+        assert!(verify_audit_log(&rewritten).is_ok());
+        assert_eq!(
+            verify_checkpoint(&rewritten, &checkpoint),
+            Err(Error::VerificationError)
+        );
+
+        // A torn entry at the end is discarded when reopening the log.
+        std::fs::write(&path.0, &contents[..contents.len() - 3]).unwrap();
+        assert_eq!(
+            read_audit_log(&contents[..contents.len() - 3]).unwrap_err(),
+            Error::CorruptedData
+        );
+        assert_eq!(AuditLog::open(&path.0).unwrap().len(), 1);
+    }
+}
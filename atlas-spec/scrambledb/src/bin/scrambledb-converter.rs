@@ -0,0 +1,416 @@
+//! # ScrambleDB Converter Service
+//!
+//! Runs the converter as a standalone service, answering split and join
+//! requests as described in [`scrambledb::converter_service`].
+//!
+//! ``` text
+//! scrambledb-converter (--keys <path> [--generate-keys] | --evaluators <address,...>)
+//!     [--public-keys <path> --attributes <name,...>] [--audit-log <path>]
+//!     [--policy <path>] [--sessions <path>] --listen <address>
+//! scrambledb-converter --evaluator-keys <path> --listen <address>
+//! scrambledb-converter --evaluator-keys <path> --deal <t>,<n>
+//! ```
+//!
+//! The converter's key material is read from (or, using
+//! `--generate-keys`, written to) the file at `<path>`. If the environment
+//! variable `SCRAMBLEDB_KEY_PASSWORD` is set, the key material is protected
+//! under the given password.
+//!
+//! Instead of holding key material, the converter can combine the partial
+//! results of [threshold evaluators](scrambledb::threshold) listening on
+//! the given addresses, any `t` of which must be given for split
+//! conversions and `2t - 1` for join conversions. Each evaluator is run by
+//! the same binary using `--evaluator-keys`, reading its key material from
+//! the file at `<path>` and answering requests as described in
+//! [`scrambledb::evaluator_service`]. Using `--deal`, fresh key material
+//! for `n` evaluators, any `t` of which can evaluate, is written to the
+//! files `<path>.1` to `<path>.n` and the dealer exits. The key files are
+//! protected in the same way as the converter's.
+//!
+//! Using `--public-keys`, the converter's public keys for the given
+//! attributes are written to a file in the
+//! [wire format](scrambledb::serialization) for distribution to data
+//! sources and data stores.
+//!
+//! Using `--audit-log`, every conversion is recorded in the
+//! [audit log](scrambledb::audit) at `<path>`, on behalf of the peer
+//! address of the connection it was requested on.
+//!
+//! Using `--policy`, join requests are checked against the
+//! [join policy](scrambledb::policy) stored in the wire format at `<path>`.
+//! Without a policy, every join is allowed.
+//!
+//! Using `--sessions`, the [join sessions](scrambledb::session) opened by
+//! processors are kept in the file at `<path>`, so that they survive a
+//! restart. Without it, all sessions are closed when the service exits.
+//!
+//! The service listens on `<address>`, which is either `tcp:<host>:<port>`
+//! or, on Unix platforms, `unix:<path>`. Evaluator addresses have the same
+//! form.
+
+use std::{io, process::ExitCode, sync::Arc, thread};
+
+use hacspec_lib::Randomness;
+use oprf::coprf::{coprf_setup::CoPRFEvaluatorContext, coprf_threshold::deal};
+use scrambledb::{
+    audit::AuditLog,
+    converter_service::ConverterService,
+    error::Error,
+    evaluator_service::{EvaluatorClient, EvaluatorService},
+    policy::JoinPolicy,
+    serialization::WireFormat,
+    setup::ConverterContext,
+    threshold::{export_evaluator, import_evaluator},
+};
+
+const PASSWORD_VARIABLE: &str = "SCRAMBLEDB_KEY_PASSWORD";
+
+struct Options {
+    keys: Option<String>,
+    generate_keys: bool,
+    evaluators: Vec<String>,
+    evaluator_keys: Option<String>,
+    deal: Option<(u32, u32)>,
+    public_keys: Option<String>,
+    attributes: Vec<String>,
+    audit_log: Option<String>,
+    policy: Option<String>,
+    sessions: Option<String>,
+    listen: Option<String>,
+}
+
+fn usage() -> String {
+    "usage: scrambledb-converter (--keys <path> [--generate-keys] | \
+     --evaluators <address,...>) [--public-keys <path> --attributes <name,...>] \
+     [--audit-log <path>] [--policy <path>] [--sessions <path>] --listen <tcp:host:port|unix:path>\n       \
+     scrambledb-converter --evaluator-keys <path> \
+     (--listen <tcp:host:port|unix:path> | --deal <t>,<n>)"
+        .to_string()
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut keys = None;
+    let mut generate_keys = false;
+    let mut evaluators = Vec::new();
+    let mut evaluator_keys = None;
+    let mut deal = None;
+    let mut public_keys = None;
+    let mut attributes = Vec::new();
+    let mut audit_log = None;
+    let mut policy = None;
+    let mut sessions = None;
+    let mut listen = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keys" => keys = args.next(),
+            "--generate-keys" => generate_keys = true,
+            "--evaluators" => {
+                evaluators = args
+                    .next()
+                    .ok_or_else(usage)?
+                    .split(',')
+                    .map(str::to_string)
+                    .collect()
+            }
+            "--evaluator-keys" => evaluator_keys = args.next(),
+            "--deal" => {
+                let parameters = args.next().ok_or_else(usage)?;
+                let (threshold, parties) = parameters.split_once(',').ok_or_else(usage)?;
+                deal = Some((
+                    threshold.parse().map_err(|_| usage())?,
+                    parties.parse().map_err(|_| usage())?,
+                ));
+            }
+            "--public-keys" => public_keys = args.next(),
+            "--attributes" => {
+                attributes = args
+                    .next()
+                    .ok_or_else(usage)?
+                    .split(',')
+                    .map(str::to_string)
+                    .collect()
+            }
+            "--audit-log" => audit_log = args.next(),
+            "--policy" => policy = args.next(),
+            "--sessions" => sessions = args.next(),
+            "--listen" => listen = args.next(),
+            _ => return Err(usage()),
+        }
+    }
+
+    // Exactly one source of key material, and a dealer does not listen.
+    let sources =
+        keys.is_some() as u8 + !evaluators.is_empty() as u8 + evaluator_keys.is_some() as u8;
+    if sources != 1
+        || (deal.is_some() && evaluator_keys.is_none())
+        || (deal.is_some() == listen.is_some())
+        || (generate_keys && keys.is_none())
+        || (evaluator_keys.is_some()
+            && (public_keys.is_some()
+                || audit_log.is_some()
+                || policy.is_some()
+                || sessions.is_some()))
+    {
+        return Err(usage());
+    }
+
+    Ok(Options {
+        keys,
+        generate_keys,
+        evaluators,
+        evaluator_keys,
+        deal,
+        public_keys,
+        attributes,
+        audit_log,
+        policy,
+        sessions,
+        listen,
+    })
+}
+
+fn fresh_randomness() -> Result<Randomness, String> {
+    Randomness::from_entropy().map_err(|e| format!("failed to seed randomness: {e:?}"))
+}
+
+fn load_context(options: &Options) -> Result<ConverterContext, String> {
+    if !options.evaluators.is_empty() {
+        let evaluators = options
+            .evaluators
+            .iter()
+            .map(|address| {
+                let connect_to = address.clone();
+                EvaluatorClient::connect(move || connect(&connect_to))
+                    .map_err(|e| format!("failed to connect to evaluator {address}: {e:?}"))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        return ConverterContext::threshold(evaluators)
+            .map_err(|e| format!("evaluators do not form a quorum: {e:?}"));
+    }
+
+    let password = std::env::var(PASSWORD_VARIABLE).ok();
+    let keys = options.keys.as_ref().ok_or_else(usage)?;
+
+    if options.generate_keys {
+        let mut randomness = fresh_randomness()?;
+        let context = ConverterContext::setup(&mut randomness)
+            .map_err(|e| format!("failed to generate keys: {e:?}"))?;
+        let exported = context
+            .export(password.as_ref().map(|p| (p.as_bytes(), &mut randomness)))
+            .map_err(|e| format!("failed to export keys: {e:?}"))?;
+        std::fs::write(keys, exported).map_err(|e| format!("failed to write {keys}: {e}"))?;
+        Ok(context)
+    } else {
+        let exported = std::fs::read(keys).map_err(|e| format!("failed to read {keys}: {e}"))?;
+        ConverterContext::import(&exported, password.as_ref().map(|p| p.as_bytes()))
+            .map_err(|e| format!("failed to import keys: {e:?}"))
+    }
+}
+
+/// Write fresh key material for `parties` threshold evaluators to the files
+/// `<path>.1` to `<path>.<parties>`.
+fn deal_evaluators(path: &str, threshold: u32, parties: u32) -> Result<(), String> {
+    let password = std::env::var(PASSWORD_VARIABLE).ok();
+    let mut randomness = fresh_randomness()?;
+    let dealer = CoPRFEvaluatorContext::new(&mut randomness)
+        .map_err(|e| format!("failed to generate keys: {e:?}"))?;
+    let evaluators =
+        deal(&dealer, threshold, parties).map_err(|e| format!("failed to deal keys: {e:?}"))?;
+    for evaluator in &evaluators {
+        let exported = export_evaluator(
+            evaluator,
+            password.as_ref().map(|p| (p.as_bytes(), &mut randomness)),
+        )
+        .map_err(|e| format!("failed to export keys: {e:?}"))?;
+        let path = format!("{path}.{}", evaluator.index());
+        std::fs::write(&path, exported).map_err(|e| format!("failed to write {path}: {e}"))?;
+    }
+    Ok(())
+}
+
+fn load_evaluator(path: &str) -> Result<EvaluatorService, String> {
+    let password = std::env::var(PASSWORD_VARIABLE).ok();
+    let exported = std::fs::read(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+    let context = import_evaluator(&exported, password.as_ref().map(|p| p.as_bytes()))
+        .map_err(|e| format!("failed to import keys: {e:?}"))?;
+    Ok(EvaluatorService::new(context))
+}
+
+/// A connection to a client or an evaluator.
+trait Connection: io::Read + io::Write + Send {}
+
+impl<S: io::Read + io::Write + Send> Connection for S {}
+
+/// Connect to an evaluator listening on `address`.
+fn connect(address: &str) -> Result<Box<dyn Connection>, Error> {
+    if let Some(address) = address.strip_prefix("tcp:") {
+        let stream =
+            std::net::TcpStream::connect(address).map_err(|_| Error::CommunicationError)?;
+        return Ok(Box::new(stream));
+    }
+    #[cfg(unix)]
+    if let Some(path) = address.strip_prefix("unix:") {
+        let stream =
+            std::os::unix::net::UnixStream::connect(path).map_err(|_| Error::CommunicationError)?;
+        return Ok(Box::new(stream));
+    }
+    Err(Error::InvalidInput)
+}
+
+/// A service answering requests on connections.
+trait Service: Send + Sync + 'static {
+    fn serve_connection(
+        &self,
+        stream: &mut dyn Connection,
+        requester: &str,
+        randomness: impl FnMut() -> Randomness,
+    ) -> Result<(), Error>;
+}
+
+impl Service for ConverterService {
+    fn serve_connection(
+        &self,
+        mut stream: &mut dyn Connection,
+        requester: &str,
+        mut randomness: impl FnMut() -> Randomness,
+    ) -> Result<(), Error> {
+        self.serve_as(&mut stream, requester, |_| randomness())
+    }
+}
+
+impl Service for EvaluatorService {
+    fn serve_connection(
+        &self,
+        mut stream: &mut dyn Connection,
+        _requester: &str,
+        mut randomness: impl FnMut() -> Randomness,
+    ) -> Result<(), Error> {
+        self.serve(&mut stream, |_| randomness())
+    }
+}
+
+fn serve<S: io::Read + io::Write + Send + 'static>(
+    service: &Arc<impl Service>,
+    stream: io::Result<S>,
+    peer: impl FnOnce(&S) -> String,
+) {
+    let mut stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("failed to accept connection: {e}");
+            return;
+        }
+    };
+    let requester = peer(&stream);
+    let service = Arc::clone(service);
+    thread::spawn(move || {
+        let mut randomness = match fresh_randomness() {
+            Ok(randomness) => randomness,
+            Err(e) => {
+                eprintln!("{e}");
+                return;
+            }
+        };
+        // Every request gets its own child of the connection's randomness.
+        let mut requests = 0u64;
+        if let Err(e) = service.serve_connection(&mut stream, &requester, || {
+            requests += 1;
+            randomness
+                .fork(&requests.to_be_bytes())
+                .expect("a DRBG never runs out")
+        }) {
+            eprintln!("connection closed: {e:?}");
+        }
+    });
+}
+
+fn write_public_keys(context: &ConverterContext, options: &Options) -> Result<(), String> {
+    let Some(path) = &options.public_keys else {
+        return Ok(());
+    };
+    let attributes: Vec<&str> = options.attributes.iter().map(String::as_str).collect();
+    let public_keys = context
+        .public_keys(&attributes)
+        .map_err(|e| format!("failed to derive public keys: {e:?}"))?;
+    std::fs::write(path, public_keys.to_bytes()).map_err(|e| format!("failed to write {path}: {e}"))
+}
+
+fn run(options: Options) -> Result<(), String> {
+    if let Some(path) = &options.evaluator_keys {
+        if let Some((threshold, parties)) = options.deal {
+            return deal_evaluators(path, threshold, parties);
+        }
+        let service = Arc::new(load_evaluator(path)?);
+        return listen(&service, options.listen.as_deref().ok_or_else(usage)?);
+    }
+
+    let context = load_context(&options)?;
+    write_public_keys(&context, &options)?;
+    let mut service = ConverterService::new(context);
+    if let Some(path) = &options.audit_log {
+        let audit_log =
+            AuditLog::open(path).map_err(|e| format!("failed to open {path}: {e:?}"))?;
+        service = service.with_audit_log(audit_log);
+    }
+    if let Some(path) = &options.policy {
+        let policy = std::fs::read(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+        let policy = JoinPolicy::from_bytes(&policy)
+            .map_err(|e| format!("failed to load policy {path}: {e:?}"))?;
+        service = service.with_join_policy(policy);
+    }
+    if let Some(path) = &options.sessions {
+        service = service
+            .with_join_sessions_file(path)
+            .map_err(|e| format!("failed to load sessions {path}: {e:?}"))?;
+    }
+    let service = Arc::new(service);
+    listen(&service, options.listen.as_deref().ok_or_else(usage)?)
+}
+
+/// Answer requests on connections accepted on `address`.
+fn listen(service: &Arc<impl Service>, address: &str) -> Result<(), String> {
+    if let Some(address) = address.strip_prefix("tcp:") {
+        let listener = std::net::TcpListener::bind(address)
+            .map_err(|e| format!("failed to listen on {address}: {e}"))?;
+        for stream in listener.incoming() {
+            serve(service, stream, |stream| {
+                stream
+                    .peer_addr()
+                    .map_or_else(|_| "tcp".to_string(), |address| format!("tcp:{address}"))
+            });
+        }
+        return Ok(());
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = address.strip_prefix("unix:") {
+        let listener = std::os::unix::net::UnixListener::bind(path)
+            .map_err(|e| format!("failed to listen on {path}: {e}"))?;
+        for stream in listener.incoming() {
+            serve(service, stream, |stream| {
+                stream
+                    .peer_addr()
+                    .ok()
+                    .and_then(|address| {
+                        address.as_pathname().map(|path| path.display().to_string())
+                    })
+                    .map_or_else(|| "unix".to_string(), |path| format!("unix:{path}"))
+            });
+        }
+        return Ok(());
+    }
+
+    Err(format!("unsupported address: {address}"))
+}
+
+fn main() -> ExitCode {
+    match parse_options().and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,1648 @@
+//! # Converter Service
+//!
+//! The converter is run as a separate service holding the
+//! [`ConverterContext`], such that data sources and data stores never
+//! have access to the converter's master secret. Parties talk to the
+//! converter using a simple request/response protocol over a reliable
+//! byte stream, e.g. a TCP connection or a Unix domain socket.
+//!
+//! Each message is framed by its length as a big-endian `u32`, followed by
+//! the message in the [wire format](crate::serialization):
+//!
+//! ``` text
+//! Request = VERSION || TAG_REQUEST || kind || bpk_receiver || ek_receiver || table
+//! Response = VERSION || TAG_RESPONSE || status || table
+//! ```
+//!
+//! A split request (`kind = REQUEST_SPLIT`) carries a table of blinded
+//! identifiable data to be pseudonymized using
+//! [`pseudonymize_blinded_table`], a join request (`kind = REQUEST_JOIN`)
+//! carries a table of blinded pseudonymized data to be converted using
+//! [`convert_blinded_table`]. In both cases the receiver's public keys are
+//! part of the request. The parts of a multi-lake join are sent as a single
+//! join request holding the entries of all parts, see
+//! [`ConverterClient::convert_blinded_tables`].
+//!
+//! The response carries the converted table if `status` is
+//! `STATUS_OK`, otherwise `status` encodes the error that occurred and no
+//! table is included. Every entry of a converted table carries the
+//! converter's proof of correct conversion, which the receiver checks in
+//! [`finalize_blinded_table`](crate::finalize::finalize_blinded_table)
+//! against the converter's [public keys](ConverterContext::public_keys).
+//!
+//! ## Streamed Requests
+//!
+//! Tables that don't fit into a single message, or into memory, are
+//! converted using streamed requests, which are answered using the
+//! [streaming transformations](crate::stream):
+//!
+//! ``` text
+//! StreamRequest = VERSION || TAG_REQUEST || kind || bpk_receiver || ek_receiver
+//! ```
+//!
+//! A streamed split request (`kind = REQUEST_SPLIT_STREAM`) or join
+//! request (`kind = REQUEST_JOIN_STREAM`) is followed by messages holding
+//! the entries of the table in chunks, encoded as tables, and terminated by
+//! an empty table. Once the converter has received all chunks, it answers
+//! with a sequence of responses, each carrying a chunk of the converted
+//! table, terminated by a response carrying an empty table. If an error
+//! occurs, the converter answers with an error response instead, which
+//! ends the sequence.
+//!
+//! The converter only starts answering after it has received the entire
+//! table, since its output is shuffled across all chunks. The identifiers
+//! of chunks are ignored.
+//!
+//! ## Join Sessions
+//!
+//! A join request in a [join session](crate::session) (`kind =
+//! REQUEST_JOIN_SESSION`) additionally carries the session identifier
+//! after the receiver's public keys:
+//!
+//! ``` text
+//! SessionRequest = VERSION || TAG_REQUEST || kind || bpk_receiver || ek_receiver || session || table
+//! ```
+//!
+//! The converter converts it to the session's conversion target if the
+//! session was opened for the receiver and has not expired, and answers
+//! with `STATUS_POLICY_VIOLATION` otherwise.
+//!
+//! Sessions are opened by the operator using
+//! [`ConverterService::open_join_session`], or by the processor using an
+//! open request (`kind = REQUEST_OPEN_SESSION`), and closed using a close
+//! request (`kind = REQUEST_CLOSE_SESSION`):
+//!
+//! ``` text
+//! OpenSession = VERSION || TAG_REQUEST || kind || bpk_processor || study || expires_at
+//! CloseSession = VERSION || TAG_REQUEST || kind || bpk_processor || session
+//! SessionResponse = VERSION || TAG_SESSION_RESPONSE || status || session
+//! ```
+//!
+//! where `expires_at` is a `u64` in seconds since the Unix epoch. The
+//! response carries the identifier of the opened or closed session if
+//! `status` is `STATUS_OK`. A session can only be closed using the
+//! blinding public key it was opened for. Open sessions are kept in
+//! memory, or in a file given to [`ConverterService::with_join_sessions_file`]
+//! such that they survive restarts.
+//!
+//! ## Join Authorization
+//!
+//! A converter service set up with a [`JoinPolicy`] only converts join
+//! requests, streamed, in a session or not, whose attributes the receiver
+//! may join
+//! according to the policy. For a join in a session, this includes the
+//! attributes joined in the session before, see [`JoinSessions::join`].
+//! Other join requests are answered with `STATUS_POLICY_VIOLATION`, as are
+//! open requests of processors without any grant. Split requests are not
+//! restricted.
+//!
+//! ## Auditing
+//!
+//! A converter service set up with an [`AuditLog`] records every
+//! conversion before releasing its result. Requests are recorded on behalf
+//! of the requesting party given to [`ConverterService::serve_as`], which
+//! the operator determines from the connection, e.g. from the peer address
+//! or an authenticated channel.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Read, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use hacspec_lib::Randomness;
+use oprf::coprf::coprf_setup::BlindingPublicKey;
+use p256::P256Point;
+
+use crate::{
+    audit::{conversion_target, AuditLog, AuditRecord, ConversionKind},
+    data_types::{BlindedIdentifiableData, BlindedPseudonymizedData},
+    error::Error,
+    join::{convert_blinded_table, convert_blinded_tables_to},
+    policy::{authorized_convert_blinded_table, JoinPolicy},
+    serialization::{put_byte_string, put_point, put_u64, Reader, WireFormat},
+    session::{JoinSessionId, JoinSessions},
+    setup::{ConverterContext, StoreEncryptionKey},
+    split::pseudonymize_blinded_table,
+    stream::{convert_blinded_stream, pseudonymize_blinded_stream, StreamConfig},
+    table::Table,
+    SECPAR_BYTES,
+};
+
+/// Maximum accepted size of a single message. Tables exceeding it are
+/// converted using streamed requests.
+pub const MAX_MESSAGE_BYTES: usize = 1 << 26;
+
+const REQUEST_SPLIT: u8 = 1;
+const REQUEST_JOIN: u8 = 2;
+const REQUEST_SPLIT_STREAM: u8 = 3;
+const REQUEST_JOIN_STREAM: u8 = 4;
+const REQUEST_JOIN_SESSION: u8 = 5;
+const REQUEST_OPEN_SESSION: u8 = 6;
+const REQUEST_CLOSE_SESSION: u8 = 7;
+
+pub(crate) const STATUS_OK: u8 = 0;
+const STATUS_RANDOMNESS_ERROR: u8 = 1;
+const STATUS_CORRUPTED_DATA: u8 = 2;
+const STATUS_INVALID_INPUT: u8 = 3;
+const STATUS_COMMUNICATION_ERROR: u8 = 4;
+const STATUS_VERIFICATION_ERROR: u8 = 5;
+const STATUS_POLICY_VIOLATION: u8 = 6;
+const STATUS_STORAGE_ERROR: u8 = 7;
+const STATUS_INTERNAL_ERROR: u8 = 8;
+
+/// A request to the converter.
+pub enum Request {
+    /// Pseudonymize a blinded table as part of a split conversion.
+    Split {
+        /// The receiver's public blinding key
+        bpk_receiver: BlindingPublicKey,
+        /// The receiver's public encryption key
+        ek_receiver: StoreEncryptionKey,
+        /// A table of blinded identifiable data values
+        table: Table<BlindedIdentifiableData>,
+    },
+    /// Convert a blinded table as part of a join conversion.
+    Join {
+        /// The receiver's public blinding key
+        bpk_receiver: BlindingPublicKey,
+        /// The receiver's public encryption key
+        ek_receiver: StoreEncryptionKey,
+        /// A table of blinded pseudonymous data values
+        table: Table<BlindedPseudonymizedData>,
+    },
+    /// Convert a blinded table as part of a join conversion in a join
+    /// session.
+    JoinSession {
+        /// The receiver's public blinding key
+        bpk_receiver: BlindingPublicKey,
+        /// The receiver's public encryption key
+        ek_receiver: StoreEncryptionKey,
+        /// The identifier of the join session
+        session: JoinSessionId,
+        /// A table of blinded pseudonymous data values
+        table: Table<BlindedPseudonymizedData>,
+    },
+    /// Pseudonymize a streamed table of blinded identifiable data values
+    /// as part of a split conversion.
+    SplitStream {
+        /// The receiver's public blinding key
+        bpk_receiver: BlindingPublicKey,
+        /// The receiver's public encryption key
+        ek_receiver: StoreEncryptionKey,
+    },
+    /// Convert a streamed table of blinded pseudonymous data values as
+    /// part of a join conversion.
+    JoinStream {
+        /// The receiver's public blinding key
+        bpk_receiver: BlindingPublicKey,
+        /// The receiver's public encryption key
+        ek_receiver: StoreEncryptionKey,
+    },
+    /// Open a join session for a processor and a study.
+    OpenSession {
+        /// The processor's public blinding key
+        bpk_processor: BlindingPublicKey,
+        /// The study the session belongs to
+        study: String,
+        /// The time at which the session expires, in seconds since the
+        /// Unix epoch
+        expires_at: u64,
+    },
+    /// Close a join session of a processor.
+    CloseSession {
+        /// The processor's public blinding key
+        bpk_processor: BlindingPublicKey,
+        /// The identifier of the join session
+        session: JoinSessionId,
+    },
+}
+
+/// The converter's response to a [`Request`].
+pub type Response = Result<Table<BlindedPseudonymizedData>, Error>;
+
+/// The converter's response to a request to open or close a join session,
+/// carrying the session identifier.
+pub type SessionResponse = Result<JoinSessionId, Error>;
+
+impl WireFormat for Request {
+    const TAG: u8 = 0x20;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        match self {
+            Request::Split {
+                bpk_receiver,
+                ek_receiver,
+                table,
+            } => {
+                out.push(REQUEST_SPLIT);
+                put_point(out, bpk_receiver);
+                ek_receiver.encode_body(out);
+                table.encode_body(out);
+            }
+            Request::Join {
+                bpk_receiver,
+                ek_receiver,
+                table,
+            } => {
+                out.push(REQUEST_JOIN);
+                put_point(out, bpk_receiver);
+                ek_receiver.encode_body(out);
+                table.encode_body(out);
+            }
+            Request::JoinSession {
+                bpk_receiver,
+                ek_receiver,
+                session,
+                table,
+            } => {
+                out.push(REQUEST_JOIN_SESSION);
+                put_point(out, bpk_receiver);
+                ek_receiver.encode_body(out);
+                out.extend_from_slice(session);
+                table.encode_body(out);
+            }
+            Request::SplitStream {
+                bpk_receiver,
+                ek_receiver,
+            } => {
+                out.push(REQUEST_SPLIT_STREAM);
+                put_point(out, bpk_receiver);
+                ek_receiver.encode_body(out);
+            }
+            Request::JoinStream {
+                bpk_receiver,
+                ek_receiver,
+            } => {
+                out.push(REQUEST_JOIN_STREAM);
+                put_point(out, bpk_receiver);
+                ek_receiver.encode_body(out);
+            }
+            Request::OpenSession {
+                bpk_processor,
+                study,
+                expires_at,
+            } => {
+                out.push(REQUEST_OPEN_SESSION);
+                put_point(out, bpk_processor);
+                put_byte_string(out, study.as_bytes());
+                put_u64(out, *expires_at);
+            }
+            Request::CloseSession {
+                bpk_processor,
+                session,
+            } => {
+                out.push(REQUEST_CLOSE_SESSION);
+                put_point(out, bpk_processor);
+                out.extend_from_slice(session);
+            }
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.u8()? {
+            REQUEST_SPLIT => Ok(Request::Split {
+                bpk_receiver: reader.point()?,
+                ek_receiver: StoreEncryptionKey::decode_body(reader)?,
+                table: Table::decode_body(reader)?,
+            }),
+            REQUEST_JOIN => Ok(Request::Join {
+                bpk_receiver: reader.point()?,
+                ek_receiver: StoreEncryptionKey::decode_body(reader)?,
+                table: Table::decode_body(reader)?,
+            }),
+            REQUEST_JOIN_SESSION => Ok(Request::JoinSession {
+                bpk_receiver: reader.point()?,
+                ek_receiver: StoreEncryptionKey::decode_body(reader)?,
+                session: reader.take(SECPAR_BYTES)?.try_into()?,
+                table: Table::decode_body(reader)?,
+            }),
+            REQUEST_SPLIT_STREAM => Ok(Request::SplitStream {
+                bpk_receiver: reader.point()?,
+                ek_receiver: StoreEncryptionKey::decode_body(reader)?,
+            }),
+            REQUEST_JOIN_STREAM => Ok(Request::JoinStream {
+                bpk_receiver: reader.point()?,
+                ek_receiver: StoreEncryptionKey::decode_body(reader)?,
+            }),
+            REQUEST_OPEN_SESSION => Ok(Request::OpenSession {
+                bpk_processor: reader.point()?,
+                study: reader.string()?,
+                expires_at: reader.u64()?,
+            }),
+            REQUEST_CLOSE_SESSION => Ok(Request::CloseSession {
+                bpk_processor: reader.point()?,
+                session: reader.take(SECPAR_BYTES)?.try_into()?,
+            }),
+            _ => Err(Error::CorruptedData),
+        }
+    }
+}
+
+impl WireFormat for Response {
+    const TAG: u8 = 0x21;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        match self {
+            Ok(table) => {
+                out.push(STATUS_OK);
+                table.encode_body(out);
+            }
+            Err(error) => out.push(error_status(error)),
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.u8()? {
+            STATUS_OK => Ok(Ok(Table::decode_body(reader)?)),
+            status => Ok(Err(status_error(status)?)),
+        }
+    }
+}
+
+impl WireFormat for SessionResponse {
+    const TAG: u8 = 0x24;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        match self {
+            Ok(session) => {
+                out.push(STATUS_OK);
+                out.extend_from_slice(session);
+            }
+            Err(error) => out.push(error_status(error)),
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.u8()? {
+            STATUS_OK => Ok(Ok(reader.take(SECPAR_BYTES)?.try_into()?)),
+            status => Ok(Err(status_error(status)?)),
+        }
+    }
+}
+
+/// The status of a response carrying the given error.
+pub(crate) fn error_status(error: &Error) -> u8 {
+    match error {
+        Error::RandomnessError => STATUS_RANDOMNESS_ERROR,
+        Error::CorruptedData => STATUS_CORRUPTED_DATA,
+        Error::InvalidInput => STATUS_INVALID_INPUT,
+        Error::CommunicationError => STATUS_COMMUNICATION_ERROR,
+        Error::VerificationError => STATUS_VERIFICATION_ERROR,
+        Error::PolicyViolation => STATUS_POLICY_VIOLATION,
+        Error::StorageError => STATUS_STORAGE_ERROR,
+        Error::InternalError => STATUS_INTERNAL_ERROR,
+    }
+}
+
+/// The error carried by a response with the given status, other than
+/// `STATUS_OK`.
+///
+/// Raises:
+/// - `CorruptedData`: If the status is unknown.
+pub(crate) fn status_error(status: u8) -> Result<Error, Error> {
+    match status {
+        STATUS_RANDOMNESS_ERROR => Ok(Error::RandomnessError),
+        STATUS_CORRUPTED_DATA => Ok(Error::CorruptedData),
+        STATUS_INVALID_INPUT => Ok(Error::InvalidInput),
+        STATUS_COMMUNICATION_ERROR => Ok(Error::CommunicationError),
+        STATUS_VERIFICATION_ERROR => Ok(Error::VerificationError),
+        STATUS_POLICY_VIOLATION => Ok(Error::PolicyViolation),
+        STATUS_STORAGE_ERROR => Ok(Error::StorageError),
+        STATUS_INTERNAL_ERROR => Ok(Error::InternalError),
+        _ => Err(Error::CorruptedData),
+    }
+}
+
+/// Write a length framed message to a stream.
+pub fn write_message(stream: &mut impl Write, message: &[u8]) -> Result<(), Error> {
+    if message.len() > MAX_MESSAGE_BYTES {
+        return Err(Error::InvalidInput);
+    }
+    stream
+        .write_all(&(message.len() as u32).to_be_bytes())
+        .and_then(|()| stream.write_all(message))
+        .and_then(|()| stream.flush())
+        .map_err(|_| Error::CommunicationError)
+}
+
+/// Read a length framed message from a stream. Returns `None` if the stream
+/// was closed before the start of a new message.
+///
+/// The message buffer grows with the bytes actually received, so a peer
+/// announcing a large message does not make us allocate it up front.
+pub fn read_message(stream: &mut impl Read) -> Result<Option<Vec<u8>>, Error> {
+    let mut length = [0u8; 4];
+    match stream.read_exact(&mut length) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(_) => return Err(Error::CommunicationError),
+    }
+
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_MESSAGE_BYTES {
+        return Err(Error::CorruptedData);
+    }
+    let mut message = Vec::new();
+    stream
+        .take(length as u64)
+        .read_to_end(&mut message)
+        .map_err(|_| Error::CommunicationError)?;
+    if message.len() != length {
+        return Err(Error::CommunicationError);
+    }
+    Ok(Some(message))
+}
+
+/// Reads the chunks of a streamed request as a stream of entries.
+struct ChunkReader<'a, S, T> {
+    stream: &'a mut S,
+    entries: std::vec::IntoIter<T>,
+    done: bool,
+    broken: bool,
+}
+
+impl<'a, S: Read, T: WireFormat> ChunkReader<'a, S, T> {
+    fn new(stream: &'a mut S) -> Self {
+        Self {
+            stream,
+            entries: Vec::new().into_iter(),
+            done: false,
+            broken: false,
+        }
+    }
+
+    /// Skip the remaining chunks up to the terminating empty table.
+    ///
+    /// Raises:
+    /// - `CommunicationError`: If the connection failed.
+    fn drain(&mut self) -> Result<(), Error> {
+        for _ in self.by_ref() {}
+        if self.broken {
+            return Err(Error::CommunicationError);
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read, T: WireFormat> Iterator for ChunkReader<'_, S, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            if self.done {
+                return None;
+            }
+
+            let Ok(Some(message)) = read_message(self.stream) else {
+                self.done = true;
+                self.broken = true;
+                return Some(Err(Error::CommunicationError));
+            };
+            match Table::<T>::from_bytes(&message) {
+                Ok(chunk) if chunk.data().is_empty() => self.done = true,
+                Ok(chunk) => self.entries = chunk.into_data().into_iter(),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+/// Send a stream of entries in chunks, terminated by an empty chunk.
+///
+/// If an error occurs in the stream of entries, the chunks sent so far are
+/// terminated and the error is returned.
+fn write_chunks<T: WireFormat>(
+    stream: &mut impl Write,
+    entries: impl IntoIterator<Item = Result<T, Error>>,
+    chunk_entries: usize,
+) -> Result<(), Error> {
+    let mut entries = entries.into_iter();
+    loop {
+        let (chunk, result) = match entries
+            .by_ref()
+            .take(chunk_entries.max(1))
+            .collect::<Result<Vec<T>, Error>>()
+        {
+            Ok(chunk) => (chunk, Ok(())),
+            Err(error) => (Vec::new(), Err(error)),
+        };
+        let last = chunk.is_empty();
+        write_message(stream, &Table::new(String::new(), chunk).to_bytes())?;
+        if last {
+            return result;
+        }
+    }
+}
+
+/// Answer a streamed request with the chunks of its converted table.
+fn write_responses(
+    stream: &mut impl Write,
+    output: Result<impl Iterator<Item = Result<BlindedPseudonymizedData, Error>>, Error>,
+    chunk_entries: usize,
+) -> Result<(), Error> {
+    let mut output = match output {
+        Ok(output) => output,
+        Err(error) => return write_message(stream, &Response::Err(error).to_bytes()),
+    };
+    loop {
+        let response: Response = output
+            .by_ref()
+            .take(chunk_entries.max(1))
+            .collect::<Result<Vec<_>, Error>>()
+            .map(|chunk| Table::new(String::new(), chunk));
+        write_message(stream, &response.to_bytes())?;
+        match response {
+            Ok(chunk) if !chunk.data().is_empty() => continue,
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// The converter service, answering requests using the converter's context.
+pub struct ConverterService {
+    context: ConverterContext,
+    config: StreamConfig,
+    policy: Option<JoinPolicy>,
+    sessions: Mutex<JoinSessions>,
+    sessions_path: Option<PathBuf>,
+    audit_log: Option<Mutex<AuditLog>>,
+}
+
+impl ConverterService {
+    /// Start a converter service using the given converter context.
+    pub fn new(context: ConverterContext) -> Self {
+        Self {
+            context,
+            config: StreamConfig::default(),
+            policy: None,
+            sessions: Mutex::new(JoinSessions::default()),
+            sessions_path: None,
+            audit_log: None,
+        }
+    }
+
+    /// Set the resource limits used for streamed requests.
+    pub fn with_stream_config(mut self, config: StreamConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Restrict join requests to those allowed by the given policy.
+    pub fn with_join_policy(mut self, policy: JoinPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Resume the given join sessions, e.g. as persisted before a restart.
+    pub fn with_join_sessions(mut self, sessions: JoinSessions) -> Self {
+        self.sessions = Mutex::new(sessions);
+        self
+    }
+
+    /// Keep the join sessions in the file at `path`, resuming the sessions
+    /// kept there before a restart. Every change to the sessions is saved
+    /// to the file before it takes effect, see [`JoinSessions::save`].
+    ///
+    /// Raises:
+    /// - `StorageError`: If reading the file fails.
+    /// - `CorruptedData`: If the file does not hold join sessions.
+    pub fn with_join_sessions_file(mut self, path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        self.sessions = Mutex::new(JoinSessions::load(&path)?);
+        self.sessions_path = Some(path);
+        Ok(self)
+    }
+
+    /// Apply a change to the join sessions, saving them first if the
+    /// service keeps them in a file. Expired sessions are closed on saving.
+    ///
+    /// Raises:
+    /// - `StorageError`: If saving the sessions fails. The change is
+    ///   discarded in this case.
+    fn update_sessions<T>(
+        &self,
+        change: impl FnOnce(&mut JoinSessions) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut sessions = self.sessions.lock().map_err(|_| Error::InternalError)?;
+        let Some(path) = &self.sessions_path else {
+            return change(&mut sessions);
+        };
+        let mut updated = sessions.clone();
+        let result = change(&mut updated)?;
+        updated.purge_expired(timestamp());
+        updated.save(path)?;
+        *sessions = updated;
+        Ok(result)
+    }
+
+    /// Open a join session for a processor and a study, see
+    /// [`JoinSessions::open`].
+    pub fn open_join_session(
+        &self,
+        processor: BlindingPublicKey,
+        study: &str,
+        expires_at: u64,
+        randomness: &mut Randomness,
+    ) -> Result<JoinSessionId, Error> {
+        self.update_sessions(|sessions| sessions.open(processor, study, expires_at, randomness))
+    }
+
+    /// Close a join session, see [`JoinSessions::close`].
+    pub fn close_join_session(&self, session: &JoinSessionId) -> Result<(), Error> {
+        self.update_sessions(|sessions| {
+            sessions.close(session);
+            Ok(())
+        })
+    }
+
+    /// The currently open join sessions, e.g. to persist them across
+    /// restarts. Expired sessions are closed first.
+    pub fn join_sessions(&self) -> Result<JoinSessions, Error> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .map_err(|_| Error::InternalError)?;
+        sessions.purge_expired(timestamp());
+        Ok(sessions.clone())
+    }
+
+    /// Record conversions in the given audit log.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(Mutex::new(audit_log));
+        self
+    }
+
+    /// Append a record to the audit log, if the service keeps one.
+    ///
+    /// Raises:
+    /// - `StorageError`: If writing the audit log fails.
+    fn audit(&self, record: impl FnOnce() -> AuditRecord) -> Result<(), Error> {
+        if let Some(audit_log) = &self.audit_log {
+            audit_log
+                .lock()
+                .map_err(|_| Error::InternalError)?
+                .append(record())?;
+        }
+        Ok(())
+    }
+
+    /// Handle a single request.
+    ///
+    /// Inputs:
+    /// - `request`: A request for split or join conversion
+    /// - `randomness`: Random bytes
+    ///
+    /// Output:
+    /// The converted table or the error that occurred during conversion.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the request is streamed, or opens or closes a
+    ///   join session. Streamed requests are only answered by
+    ///   [`ConverterService::serve`], session requests by
+    ///   [`ConverterService::handle_session`].
+    /// - `PolicyViolation`: If the service has a join policy which does not
+    ///   allow the receiver to join the attributes of a join request,
+    ///   together with those joined in its session before, or the join
+    ///   session of a request is unknown, belongs to another receiver or has
+    ///   expired.
+    /// - `StorageError`: If the service keeps an audit log or its join
+    ///   sessions in a file and writing it fails. The converted table is
+    ///   withheld in this case.
+    pub fn handle(&self, request: Request, randomness: &mut Randomness) -> Response {
+        self.handle_as("", request, randomness)
+    }
+
+    /// Handle a single request on behalf of the given requesting party, as
+    /// recorded in the audit log. See [`ConverterService::handle`].
+    pub fn handle_as(
+        &self,
+        requester: &str,
+        request: Request,
+        randomness: &mut Randomness,
+    ) -> Response {
+        match request {
+            Request::Split {
+                bpk_receiver,
+                ek_receiver,
+                table,
+            } => {
+                let record = self.audit_log.as_ref().map(|_| {
+                    AuditRecord::split(timestamp(), requester, &bpk_receiver, &ek_receiver, &table)
+                });
+                let output = pseudonymize_blinded_table(
+                    &self.context,
+                    bpk_receiver,
+                    &ek_receiver,
+                    table,
+                    randomness,
+                )?;
+                if let Some(record) = record {
+                    self.audit(|| record)?;
+                }
+                Ok(output)
+            }
+            Request::Join {
+                bpk_receiver,
+                ek_receiver,
+                table,
+            } => {
+                let output = match &self.policy {
+                    Some(policy) => authorized_convert_blinded_table(
+                        policy,
+                        &self.context,
+                        bpk_receiver,
+                        &ek_receiver,
+                        table,
+                        randomness,
+                    ),
+                    None => convert_blinded_table(
+                        &self.context,
+                        bpk_receiver,
+                        &ek_receiver,
+                        table,
+                        randomness,
+                    ),
+                }?;
+                self.audit(|| {
+                    AuditRecord::join(timestamp(), requester, &bpk_receiver, &ek_receiver, &output)
+                })?;
+                Ok(output)
+            }
+            Request::JoinSession {
+                bpk_receiver,
+                ek_receiver,
+                session,
+                table,
+            } => {
+                let attributes = table
+                    .data()
+                    .iter()
+                    .map(|entry| entry.encrypted_data_value.attribute_name.clone())
+                    .collect::<BTreeSet<_>>();
+                // The attributes are only recorded in the session once the
+                // conversion succeeded.
+                let conversion_target = self
+                    .sessions
+                    .lock()
+                    .map_err(|_| Error::InternalError)?
+                    .authorize_join(
+                        &session,
+                        &bpk_receiver,
+                        attributes.iter().map(String::as_str),
+                        self.policy.as_ref(),
+                        timestamp(),
+                    )?;
+                let output = convert_blinded_tables_to(
+                    &self.context,
+                    &conversion_target,
+                    bpk_receiver,
+                    &ek_receiver,
+                    vec![table],
+                    randomness,
+                )?;
+                self.update_sessions(|sessions| {
+                    sessions.join(
+                        &session,
+                        &bpk_receiver,
+                        attributes.iter().map(String::as_str),
+                        self.policy.as_ref(),
+                        timestamp(),
+                    )
+                })?;
+                self.audit(|| {
+                    AuditRecord::join(timestamp(), requester, &bpk_receiver, &ek_receiver, &output)
+                })?;
+                Ok(output)
+            }
+            Request::SplitStream { .. }
+            | Request::JoinStream { .. }
+            | Request::OpenSession { .. }
+            | Request::CloseSession { .. } => Err(Error::InvalidInput),
+        }
+    }
+
+    /// Handle a request to open or close a join session.
+    ///
+    /// Inputs:
+    /// - `request`: A request to open or close a join session
+    /// - `randomness`: Random bytes
+    ///
+    /// Output:
+    /// The identifier of the opened or closed session or the error that
+    /// occurred.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the request does not open or close a session.
+    /// - `PolicyViolation`: If the service has a join policy without any
+    ///   grant for the processor of an open request, or the session of a
+    ///   close request is unknown or belongs to another processor.
+    /// - `StorageError`: If the service keeps its join sessions in a file
+    ///   and writing it fails.
+    pub fn handle_session(&self, request: Request, randomness: &mut Randomness) -> SessionResponse {
+        match request {
+            Request::OpenSession {
+                bpk_processor,
+                study,
+                expires_at,
+            } => {
+                if let Some(policy) = &self.policy {
+                    policy.authorize(&bpk_processor, std::iter::empty())?;
+                }
+                self.open_join_session(bpk_processor, &study, expires_at, randomness)
+            }
+            Request::CloseSession {
+                bpk_processor,
+                session,
+            } => self.update_sessions(|sessions| match sessions.get(&session) {
+                Some(open) if open.processor == bpk_processor => {
+                    sessions.close(&session);
+                    Ok(session)
+                }
+                _ => Err(Error::PolicyViolation),
+            }),
+            _ => Err(Error::InvalidInput),
+        }
+    }
+
+    /// Handle a streamed request, reading its chunks from and writing the
+    /// response chunks to the connection.
+    fn handle_stream<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        requester: &str,
+        request: Request,
+        randomness: &mut Randomness,
+    ) -> Result<(), Error> {
+        let mut attributes = BTreeMap::new();
+        let (kind, bpk_receiver, ek_receiver, output) = match request {
+            Request::SplitStream {
+                bpk_receiver,
+                ek_receiver,
+            } => {
+                let mut chunks = ChunkReader::new(stream);
+                let counted = chunks.by_ref().map(|entry| {
+                    let entry: BlindedIdentifiableData = entry?;
+                    *attributes
+                        .entry(entry.encrypted_data_value.attribute_name.clone())
+                        .or_insert(0) += 1;
+                    Ok(entry)
+                });
+                let output = pseudonymize_blinded_stream(
+                    &self.context,
+                    bpk_receiver,
+                    &ek_receiver,
+                    counted,
+                    &self.config,
+                    randomness,
+                );
+                chunks.drain()?;
+                (ConversionKind::Split, bpk_receiver, ek_receiver, output)
+            }
+            Request::JoinStream {
+                bpk_receiver,
+                ek_receiver,
+            } => {
+                let mut chunks = ChunkReader::new(stream);
+                let authorized = chunks.by_ref().map(|entry| {
+                    let entry: BlindedPseudonymizedData = entry?;
+                    let attribute = &entry.encrypted_data_value.attribute_name;
+                    if !attributes.contains_key(attribute) {
+                        attributes.insert(attribute.clone(), 0);
+                        if let Some(policy) = &self.policy {
+                            policy
+                                .authorize(&bpk_receiver, attributes.keys().map(String::as_str))?;
+                        }
+                    }
+                    *attributes.get_mut(attribute).unwrap() += 1;
+                    Ok(entry)
+                });
+                let output = convert_blinded_stream(
+                    &self.context,
+                    bpk_receiver,
+                    &ek_receiver,
+                    authorized,
+                    &self.config,
+                    randomness,
+                );
+                chunks.drain()?;
+                (ConversionKind::Join, bpk_receiver, ek_receiver, output)
+            }
+            _ => return write_message(stream, &Response::Err(Error::InvalidInput).to_bytes()),
+        };
+
+        // The converted entries are only released once recorded.
+        let output = output.and_then(|mut output| {
+            let first = output.next().transpose()?;
+            self.audit(|| {
+                AuditRecord::new(
+                    timestamp(),
+                    requester,
+                    kind,
+                    &bpk_receiver,
+                    &ek_receiver,
+                    attributes,
+                    first.as_ref().and_then(conversion_target),
+                )
+            })?;
+            Ok(first.map(Ok).into_iter().chain(output))
+        });
+        write_responses(stream, output, self.config.chunk_entries)
+    }
+
+    /// Answer requests on a connection until it is closed by the client.
+    ///
+    /// Inputs:
+    /// - `stream`: A connection to a client
+    /// - `randomness`: Provides fresh randomness for each received request
+    ///
+    /// Raises:
+    /// - `CommunicationError`: If reading from or writing to the connection
+    ///   fails.
+    /// - `CorruptedData`: If a message exceeds [`MAX_MESSAGE_BYTES`].
+    pub fn serve<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        randomness: impl FnMut(&Request) -> Randomness,
+    ) -> Result<(), Error> {
+        self.serve_as(stream, "", randomness)
+    }
+
+    /// Answer requests on a connection on behalf of the given requesting
+    /// party, as recorded in the audit log. See [`ConverterService::serve`].
+    pub fn serve_as<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        requester: &str,
+        mut randomness: impl FnMut(&Request) -> Randomness,
+    ) -> Result<(), Error> {
+        while let Some(message) = read_message(stream)? {
+            let response = match Request::from_bytes(&message) {
+                Ok(request @ (Request::SplitStream { .. } | Request::JoinStream { .. })) => {
+                    let mut randomness = randomness(&request);
+                    self.handle_stream(stream, requester, request, &mut randomness)?;
+                    continue;
+                }
+                Ok(request @ (Request::OpenSession { .. } | Request::CloseSession { .. })) => {
+                    let mut randomness = randomness(&request);
+                    let response = self.handle_session(request, &mut randomness);
+                    write_message(stream, &response.to_bytes())?;
+                    continue;
+                }
+                Ok(request) => {
+                    let mut randomness = randomness(&request);
+                    self.handle_as(requester, request, &mut randomness)
+                }
+                Err(error) => Err(error),
+            };
+            write_message(stream, &response.to_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Seconds since the Unix epoch.
+fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The converted entries of a streamed request, as received from the
+/// converter.
+///
+/// Dropping the stream before it ends skips the remaining responses, such
+/// that the connection can be used for further requests.
+pub struct ResponseStream<'a, S: Read> {
+    stream: &'a mut S,
+    entries: std::vec::IntoIter<BlindedPseudonymizedData>,
+    done: bool,
+}
+
+impl<S: Read> Iterator for ResponseStream<'_, S> {
+    type Item = Result<BlindedPseudonymizedData, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            if self.done {
+                return None;
+            }
+
+            let response = read_message(self.stream)
+                .and_then(|message| message.ok_or(Error::CommunicationError))
+                .and_then(|message| Response::from_bytes(&message));
+            match response {
+                Ok(Ok(chunk)) if chunk.data().is_empty() => self.done = true,
+                Ok(Ok(chunk)) => self.entries = chunk.into_data().into_iter(),
+                Ok(Err(error)) | Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+impl<S: Read> Drop for ResponseStream<'_, S> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+/// A client for the converter service.
+pub struct ConverterClient<S> {
+    stream: S,
+}
+
+impl<S: Read + Write> ConverterClient<S> {
+    /// Use an established connection to the converter service.
+    pub fn new(stream: S) -> Self {
+        Self { stream }
+    }
+
+    /// Send a request to the converter and wait for the response. Requests
+    /// to open or close a join session are answered with a
+    /// [`SessionResponse`] instead, see
+    /// [`ConverterClient::open_join_session`].
+    pub fn request(&mut self, request: &Request) -> Response {
+        write_message(&mut self.stream, &request.to_bytes())?;
+        let response = read_message(&mut self.stream)?.ok_or(Error::CommunicationError)?;
+        Response::from_bytes(&response)?
+    }
+
+    /// Send a streamed request, followed by the given entries in chunks of
+    /// `chunk_entries` entries, and start receiving the response.
+    ///
+    /// Raises:
+    /// - Any error of the stream of entries. The entries read so far are
+    ///   still sent to the converter, but its response is discarded.
+    /// - `CommunicationError`: If writing to the connection fails.
+    fn request_stream<T: WireFormat>(
+        &mut self,
+        request: &Request,
+        entries: impl IntoIterator<Item = Result<T, Error>>,
+        chunk_entries: usize,
+    ) -> Result<ResponseStream<'_, S>, Error> {
+        write_message(&mut self.stream, &request.to_bytes())?;
+        let result = write_chunks(&mut self.stream, entries, chunk_entries);
+        let responses = ResponseStream {
+            stream: &mut self.stream,
+            entries: Vec::new().into_iter(),
+            done: false,
+        };
+        result.map(|()| responses)
+    }
+
+    /// Request oblivious pseudonymization of a blinded table, as in
+    /// [`pseudonymize_blinded_table`].
+    pub fn pseudonymize_blinded_table(
+        &mut self,
+        bpk_receiver: BlindingPublicKey,
+        ek_receiver: &StoreEncryptionKey,
+        blinded_table: Table<BlindedIdentifiableData>,
+    ) -> Response {
+        self.request(&Request::Split {
+            bpk_receiver,
+            ek_receiver: ek_receiver.clone(),
+            table: blinded_table,
+        })
+    }
+
+    /// Request oblivious conversion of a blinded table, as in
+    /// [`convert_blinded_table`].
+    pub fn convert_blinded_table(
+        &mut self,
+        bpk_receiver: P256Point,
+        ek_receiver: &StoreEncryptionKey,
+        table: Table<BlindedPseudonymizedData>,
+    ) -> Response {
+        self.request(&Request::Join {
+            bpk_receiver,
+            ek_receiver: ek_receiver.clone(),
+            table,
+        })
+    }
+
+    /// Request oblivious conversion of a blinded table in a join session, as
+    /// in [`convert_blinded_tables_in_session`](crate::session::convert_blinded_tables_in_session).
+    pub fn convert_blinded_table_in_session(
+        &mut self,
+        session: &JoinSessionId,
+        bpk_receiver: BlindingPublicKey,
+        ek_receiver: &StoreEncryptionKey,
+        table: Table<BlindedPseudonymizedData>,
+    ) -> Response {
+        self.request(&Request::JoinSession {
+            bpk_receiver,
+            ek_receiver: ek_receiver.clone(),
+            session: *session,
+            table,
+        })
+    }
+
+    /// Send a request to open or close a join session and wait for the
+    /// response.
+    fn request_session(&mut self, request: &Request) -> SessionResponse {
+        write_message(&mut self.stream, &request.to_bytes())?;
+        let response = read_message(&mut self.stream)?.ok_or(Error::CommunicationError)?;
+        SessionResponse::from_bytes(&response)?
+    }
+
+    /// Request a join session for a processor and a study, which expires
+    /// at the given time, see [`JoinSessions::open`].
+    pub fn open_join_session(
+        &mut self,
+        bpk_processor: BlindingPublicKey,
+        study: &str,
+        expires_at: u64,
+    ) -> SessionResponse {
+        self.request_session(&Request::OpenSession {
+            bpk_processor,
+            study: study.to_string(),
+            expires_at,
+        })
+    }
+
+    /// Close a join session of a processor, see [`JoinSessions::close`].
+    pub fn close_join_session(
+        &mut self,
+        bpk_processor: BlindingPublicKey,
+        session: &JoinSessionId,
+    ) -> Result<(), Error> {
+        self.request_session(&Request::CloseSession {
+            bpk_processor,
+            session: *session,
+        })
+        .map(|_| ())
+    }
+
+    /// Request oblivious conversion of the parts of a multi-lake join, as in
+    /// [`convert_blinded_tables`](crate::join::convert_blinded_tables). The
+    /// parts are merged into a single join request.
+    pub fn convert_blinded_tables(
+        &mut self,
+        bpk_receiver: P256Point,
+        ek_receiver: &StoreEncryptionKey,
+        parts: Vec<Table<BlindedPseudonymizedData>>,
+    ) -> Response {
+        self.convert_blinded_table(bpk_receiver, ek_receiver, Table::merge(parts)?)
+    }
+
+    /// Request oblivious pseudonymization of a stream of blinded entries, as
+    /// in [`pseudonymize_blinded_stream`]. The entries are sent in chunks of
+    /// `chunk_entries` entries.
+    pub fn pseudonymize_blinded_stream(
+        &mut self,
+        bpk_receiver: BlindingPublicKey,
+        ek_receiver: &StoreEncryptionKey,
+        entries: impl IntoIterator<Item = Result<BlindedIdentifiableData, Error>>,
+        chunk_entries: usize,
+    ) -> Result<ResponseStream<'_, S>, Error> {
+        let request = Request::SplitStream {
+            bpk_receiver,
+            ek_receiver: ek_receiver.clone(),
+        };
+        self.request_stream(&request, entries, chunk_entries)
+    }
+
+    /// Request oblivious conversion of a stream of blinded entries, as in
+    /// [`convert_blinded_stream`]. The entries are sent in chunks of
+    /// `chunk_entries` entries.
+    pub fn convert_blinded_stream(
+        &mut self,
+        bpk_receiver: BlindingPublicKey,
+        ek_receiver: &StoreEncryptionKey,
+        entries: impl IntoIterator<Item = Result<BlindedPseudonymizedData, Error>>,
+        chunk_entries: usize,
+    ) -> Result<ResponseStream<'_, S>, Error> {
+        let request = Request::JoinStream {
+            bpk_receiver,
+            ek_receiver: ek_receiver.clone(),
+        };
+        self.request_stream(&request, entries, chunk_entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use crate::{
+        setup::StoreContext,
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    use super::*;
+
+    fn generate_randomness() -> Randomness {
+        Randomness::from_entropy().unwrap()
+    }
+
+    #[test]
+    fn test_converter_service() {
+        let mut randomness = generate_randomness();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let converter_export = converter_context.export(None).unwrap();
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let audit_path = std::env::temp_dir().join(format!(
+            "scrambledb-service-audit-{}",
+            hex::encode(randomness.bytes(16).unwrap())
+        ));
+        let service = ConverterService::new(converter_context)
+            .with_stream_config(StreamConfig {
+                chunk_entries: 2,
+                memory_entries: 3,
+                temp_dir: std::env::temp_dir(),
+            })
+            .with_audit_log(AuditLog::open(&audit_path).unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            service
+                .serve_as(&mut stream, "lake", |_| generate_randomness())
+                .unwrap();
+        });
+
+        let mut client = ConverterClient::new(TcpStream::connect(address).unwrap());
+
+        let blind_table = crate::split::blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+
+        let remote_result = client
+            .pseudonymize_blinded_table(lake_bpk, &lake_ek, blind_table.clone())
+            .unwrap();
+
+        // The remote converter computes the same pseudonyms as a local one.
+        let local_context = ConverterContext::import(&converter_export, None).unwrap();
+        let local_result = pseudonymize_blinded_table(
+            &local_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table.clone(),
+            &mut randomness,
+        )
+        .unwrap();
+        let pseudonyms = |table: &Table<BlindedPseudonymizedData>| {
+            table
+                .data()
+                .iter()
+                .map(|entry| {
+                    lake_context
+                        .finalize_pseudonym(entry.blinded_handle)
+                        .unwrap()
+                })
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(pseudonyms(&remote_result), pseudonyms(&local_result));
+
+        // Streamed requests are converted the same way.
+        let streamed = client
+            .pseudonymize_blinded_stream(
+                lake_bpk,
+                &lake_ek,
+                blind_table.data().iter().cloned().map(Ok),
+                2,
+            )
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            pseudonyms(&Table::new("Test".into(), streamed)),
+            pseudonyms(&local_result)
+        );
+
+        // Errors in a stream end it, and leave the connection usable.
+        let entries = remote_result.data().iter().cloned().map(Ok);
+        let mut responses = client
+            .convert_blinded_stream(lake_bpk, &lake_ek, entries, 2)
+            .unwrap();
+        assert!(responses.next().unwrap().is_ok());
+        drop(responses);
+        let request = Request::SplitStream {
+            bpk_receiver: lake_bpk,
+            ek_receiver: lake_ek.clone(),
+        };
+        write_message(&mut client.stream, &request.to_bytes()).unwrap();
+        write_message(&mut client.stream, &[0xff; 8]).unwrap();
+        write_chunks::<BlindedIdentifiableData>(&mut client.stream, [], 2).unwrap();
+        let response = read_message(&mut client.stream).unwrap().unwrap();
+        assert_eq!(
+            Response::from_bytes(&response).unwrap().unwrap_err(),
+            Error::CorruptedData
+        );
+
+        // Errors are reported back to the client.
+        let mut invalid_request = Request::Join {
+            bpk_receiver: lake_bpk,
+            ek_receiver: lake_ek,
+            table: remote_result,
+        }
+        .to_bytes();
+        invalid_request[2] = 0xff;
+        write_message(&mut client.stream, &invalid_request).unwrap();
+        let response = read_message(&mut client.stream).unwrap().unwrap();
+        assert_eq!(
+            Response::from_bytes(&response).unwrap().unwrap_err(),
+            Error::CorruptedData
+        );
+
+        drop(client);
+        server.join().unwrap();
+
+        // Successful conversions are recorded, streamed or not.
+        let entries =
+            crate::audit::read_audit_log(std::fs::File::open(&audit_path).unwrap()).unwrap();
+        std::fs::remove_file(&audit_path).unwrap();
+        assert!(crate::audit::verify_audit_log(&entries).is_ok());
+        let kinds: Vec<_> = entries.iter().map(|entry| entry.record.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ConversionKind::Split,
+                ConversionKind::Split,
+                ConversionKind::Join
+            ]
+        );
+        for entry in &entries {
+            assert_eq!(entry.record.requester, "lake");
+            assert_eq!(entry.record.attributes.values().sum::<u64>(), 6);
+        }
+        assert!(entries[2].record.conversion_target.is_some());
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let mut framed = Vec::new();
+        write_message(&mut framed, b"message").unwrap();
+        assert_eq!(
+            read_message(&mut framed.as_slice()).unwrap().unwrap(),
+            b"message"
+        );
+        assert_eq!(read_message(&mut [].as_slice()).unwrap(), None);
+
+        // A message announcing more bytes than it carries is not buffered
+        // up front, and fails once the stream ends.
+        let mut truncated = (MAX_MESSAGE_BYTES as u32).to_be_bytes().to_vec();
+        truncated.extend_from_slice(b"message");
+        assert_eq!(
+            read_message(&mut truncated.as_slice()).err(),
+            Some(Error::CommunicationError)
+        );
+
+        let oversized = (MAX_MESSAGE_BYTES as u32 + 1).to_be_bytes();
+        assert_eq!(
+            read_message(&mut oversized.as_slice()).err(),
+            Some(Error::CorruptedData)
+        );
+
+        // Every error keeps its identity across the wire.
+        for error in [
+            Error::RandomnessError,
+            Error::CorruptedData,
+            Error::InvalidInput,
+            Error::CommunicationError,
+            Error::VerificationError,
+            Error::PolicyViolation,
+            Error::StorageError,
+            Error::InternalError,
+        ] {
+            let response = Response::from_bytes(&Response::Err(error).to_bytes());
+            assert_eq!(response.unwrap().unwrap_err(), error);
+            let response = SessionResponse::from_bytes(&SessionResponse::Err(error).to_bytes());
+            assert_eq!(response.unwrap().unwrap_err(), error);
+        }
+        let response =
+            SessionResponse::from_bytes(&SessionResponse::Ok([7; SECPAR_BYTES]).to_bytes());
+        assert_eq!(response.unwrap().unwrap(), [7; SECPAR_BYTES]);
+    }
+
+    #[test]
+    fn test_converter_service_join_policy() {
+        let mut randomness = generate_randomness();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+        let processor_context = StoreContext::setup(&mut randomness).unwrap();
+        let (processor_ek, processor_bpk) = processor_context.public_keys();
+
+        let lake_table = crate::finalize::finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            pseudonymize_blinded_table(
+                &converter_context,
+                lake_bpk,
+                &lake_ek,
+                crate::split::blind_orthonymous_table(
+                    &converter_keys,
+                    &lake_ek,
+                    lake_bpk,
+                    generate_plain_table(),
+                    &mut randomness,
+                )
+                .unwrap(),
+                &mut randomness,
+            )
+            .unwrap(),
+            &mut randomness,
+        )
+        .unwrap();
+        let blinded_table = crate::join::blind_pseudonymous_table(
+            &lake_context,
+            &converter_keys,
+            processor_bpk,
+            &processor_ek,
+            lake_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let address_table = Table::new(
+            "Join".into(),
+            blinded_table
+                .data()
+                .iter()
+                .filter(|entry| entry.encrypted_data_value.attribute_name == "Address")
+                .cloned()
+                .collect(),
+        );
+        let birth_date_table = Table::new(
+            "Join".into(),
+            blinded_table
+                .data()
+                .iter()
+                .filter(|entry| entry.encrypted_data_value.attribute_name == "Date of Birth")
+                .cloned()
+                .collect(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let service = ConverterService::new(converter_context).with_join_policy(
+            JoinPolicy::default()
+                .allow(processor_bpk, &["Address"])
+                .allow(processor_bpk, &["Date of Birth"]),
+        );
+        let session = service
+            .open_join_session(processor_bpk, "Study", u64::MAX, &mut randomness)
+            .unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            service
+                .serve(&mut stream, |_| generate_randomness())
+                .unwrap();
+        });
+        let mut client = ConverterClient::new(TcpStream::connect(address).unwrap());
+
+        assert_eq!(
+            client
+                .convert_blinded_table(processor_bpk, &processor_ek, blinded_table.clone())
+                .unwrap_err(),
+            Error::PolicyViolation
+        );
+        let streamed = client
+            .convert_blinded_stream(
+                processor_bpk,
+                &processor_ek,
+                blinded_table.data().iter().cloned().map(Ok),
+                1,
+            )
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(streamed.unwrap_err(), Error::PolicyViolation);
+
+        // Only the receiver named in the policy may join.
+        assert_eq!(
+            client
+                .convert_blinded_table(lake_bpk, &lake_ek, address_table.clone())
+                .unwrap_err(),
+            Error::PolicyViolation
+        );
+        assert!(client
+            .convert_blinded_table(processor_bpk, &processor_ek, address_table.clone())
+            .is_ok());
+        let streamed = client
+            .convert_blinded_stream(
+                processor_bpk,
+                &processor_ek,
+                address_table.data().iter().cloned().map(Ok),
+                1,
+            )
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(streamed.len(), address_table.data().len());
+
+        // Join sessions are subject to the policy and scoped to their
+        // processor.
+        assert_eq!(
+            client
+                .convert_blinded_table_in_session(
+                    &session,
+                    processor_bpk,
+                    &processor_ek,
+                    blinded_table.clone()
+                )
+                .unwrap_err(),
+            Error::PolicyViolation
+        );
+        assert_eq!(
+            client
+                .convert_blinded_table_in_session(
+                    &[0; SECPAR_BYTES],
+                    processor_bpk,
+                    &processor_ek,
+                    address_table.clone()
+                )
+                .unwrap_err(),
+            Error::PolicyViolation
+        );
+        assert!(client
+            .convert_blinded_table_in_session(
+                &session,
+                processor_bpk,
+                &processor_ek,
+                address_table.clone()
+            )
+            .is_ok());
+
+        // Each grant allows one of the attributes, but no grant allows
+        // linking both of them in one session.
+        assert_eq!(
+            client
+                .convert_blinded_table_in_session(
+                    &session,
+                    processor_bpk,
+                    &processor_ek,
+                    birth_date_table.clone()
+                )
+                .unwrap_err(),
+            Error::PolicyViolation
+        );
+
+        // Processors open and close their own sessions, if the policy has
+        // a grant for them.
+        assert_eq!(
+            client
+                .open_join_session(lake_bpk, "Study", u64::MAX)
+                .unwrap_err(),
+            Error::PolicyViolation
+        );
+        let session = client
+            .open_join_session(processor_bpk, "Other Study", u64::MAX)
+            .unwrap();
+        assert!(client
+            .convert_blinded_table_in_session(
+                &session,
+                processor_bpk,
+                &processor_ek,
+                birth_date_table.clone()
+            )
+            .is_ok());
+        assert_eq!(
+            client.close_join_session(lake_bpk, &session).unwrap_err(),
+            Error::PolicyViolation
+        );
+        client.close_join_session(processor_bpk, &session).unwrap();
+        assert_eq!(
+            client
+                .convert_blinded_table_in_session(
+                    &session,
+                    processor_bpk,
+                    &processor_ek,
+                    birth_date_table
+                )
+                .unwrap_err(),
+            Error::PolicyViolation
+        );
+        assert_eq!(
+            client
+                .close_join_session(processor_bpk, &session)
+                .unwrap_err(),
+            Error::PolicyViolation
+        );
+
+        drop(client);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,821 @@
+//! # CSV Import and Export
+//!
+//! Data sources usually hold their data in tabular files. This module
+//! reads such files into [`PlainTable`]s and per-attribute
+//! [`Table<IdentifiableData>`]s that can be used as input to a split
+//! conversion. Conversely, pseudonymized tables held by a data lake or a
+//! processor can be written as CSV for further analysis with standard
+//! tools.
+//!
+//! On import, one column of the file is chosen as the identifier column and
+//! a set of further columns is chosen as attribute columns. All other
+//! columns are ignored. Values are imported as text. Files that don't fit
+//! into memory can be read entry by entry, as input to a
+//! [streaming](crate::stream) split conversion.
+//!
+//! On export, one row is written per pseudonym, with the pseudonym in the
+//! first column, followed by one column per attribute occurring in the
+//! table. Cells for which the table holds no value under a pseudonym are
+//! left empty. Since empty values are written as empty cells as well, they
+//! cannot be told apart from missing values, and are dropped when an
+//! exported table is read back. Pseudonyms are written as the decimal epoch of the PRP key
+//! they were finalized under, a colon, and the hexadecimal or Base64
+//! encoding of their value, e.g. `3:8f1c...`, such that exported tables
+//! can be read back and matched or rekeyed under the right key after the
+//! data store rotated its PRP key. Values that are not printable text,
+//! such as encoded integers, are written in the same encoding as the
+//! pseudonyms.
+
+use std::{collections::BTreeMap, fs::File, io, path::Path};
+
+use base64::Engine;
+
+use crate::{
+    data_types::{DataValue, FinalizedPseudonym, IdentifiableData, PseudonymizedData},
+    error::Error,
+    table::{Column, ColumnType, PlainTable, Schema, Table, Value},
+};
+
+/// The encoding of pseudonyms in exported CSV files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PseudonymEncoding {
+    /// Lower case hexadecimal encoding.
+    Hex,
+    /// Standard Base64 encoding with padding.
+    Base64,
+}
+
+impl PseudonymEncoding {
+    /// All supported encodings.
+    const ALL: [PseudonymEncoding; 2] = [PseudonymEncoding::Hex, PseudonymEncoding::Base64];
+
+    /// Encode a pseudonym as a string, prefixed by its epoch.
+    pub fn encode(&self, pseudonym: &FinalizedPseudonym) -> String {
+        let value = self.encode_bytes(&pseudonym.value);
+        format!("{}:{value}", pseudonym.epoch)
+    }
+
+    /// Decode a pseudonym encoded using [`PseudonymEncoding::encode`].
+    ///
+    /// Raises:
+    /// - `CorruptedData`: If the string is not a pseudonym in this encoding.
+    pub fn decode(&self, encoded: &str) -> Result<FinalizedPseudonym, Error> {
+        let (epoch, value) = encoded.split_once(':').ok_or(Error::CorruptedData)?;
+        Ok(FinalizedPseudonym {
+            epoch: epoch.parse().map_err(|_| Error::CorruptedData)?,
+            value: self
+                .decode_bytes(value)?
+                .try_into()
+                .map_err(|_| Error::CorruptedData)?,
+        })
+    }
+
+    fn encode_bytes(&self, bytes: &[u8]) -> String {
+        match self {
+            PseudonymEncoding::Hex => hex::encode(bytes),
+            PseudonymEncoding::Base64 => base64::engine::general_purpose::STANDARD.encode(bytes),
+        }
+    }
+
+    fn decode_bytes(&self, encoded: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            PseudonymEncoding::Hex => hex::decode(encoded).map_err(|_| Error::CorruptedData),
+            PseudonymEncoding::Base64 => base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|_| Error::CorruptedData),
+        }
+    }
+
+    /// The suffix marking the header of an attribute column whose values are
+    /// written in this encoding.
+    fn header_suffix(&self) -> &'static str {
+        match self {
+            PseudonymEncoding::Hex => ":hex",
+            PseudonymEncoding::Base64 => ":base64",
+        }
+    }
+}
+
+/// Whether a value can be written to a CSV file as is.
+///
+/// Control characters other than white space are excluded, since they are
+/// not preserved by many tools, and integers encoded as in
+/// [`Value::encode`] usually contain zero bytes.
+fn is_text(value: &[u8]) -> bool {
+    std::str::from_utf8(value)
+        .is_ok_and(|text| !text.chars().any(|c| c.is_control() && !c.is_whitespace()))
+}
+
+/// Read a plain table from CSV data with a header row.
+///
+/// Inputs:
+/// - `reader`: The CSV data
+/// - `name`: The name of the resulting table
+/// - `identifier_column`: The header of the column holding identifiers
+/// - `attribute_columns`: The headers of the columns to import
+///
+/// Output:
+/// A plain table with a text column per attribute column.
+///
+/// Raises:
+/// - `InvalidInput`: If one of the given columns is missing from the header
+///   or given twice.
+/// - `CorruptedData`: If the data is not well-formed CSV.
+/// - `StorageError`: If reading the data fails.
+pub fn read_plain_table(
+    reader: impl io::Read,
+    name: &str,
+    identifier_column: &str,
+    attribute_columns: &[&str],
+) -> Result<PlainTable, Error> {
+    let mut reader = ::csv::Reader::from_reader(reader);
+    let (schema, identifier_position, attribute_positions) =
+        import_schema(reader.headers()?, identifier_column, attribute_columns)?;
+    let mut table = PlainTable::new(name.to_string(), schema);
+
+    for record in reader.records() {
+        let record = record?;
+        let field = |position: usize| {
+            record
+                .get(position)
+                .map(str::to_string)
+                .ok_or(Error::CorruptedData)
+        };
+        let values = attribute_positions
+            .iter()
+            .map(|&position| field(position).map(Value::Text))
+            .collect::<Result<Vec<_>, Error>>()?;
+        table.push_row(field(identifier_position)?, values)?;
+    }
+
+    Ok(table)
+}
+
+/// Build the schema of an import from a CSV header row and find the
+/// positions of the identifier column and the attribute columns.
+fn import_schema(
+    headers: &::csv::StringRecord,
+    identifier_column: &str,
+    attribute_columns: &[&str],
+) -> Result<(Schema, usize, Vec<usize>), Error> {
+    let position = |column: &str| {
+        headers
+            .iter()
+            .position(|header| header == column)
+            .ok_or(Error::InvalidInput)
+    };
+
+    let identifier_position = position(identifier_column)?;
+    let attribute_positions = attribute_columns
+        .iter()
+        .map(|column| position(column))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let schema = Schema::new(
+        identifier_column.to_string(),
+        attribute_columns
+            .iter()
+            .map(|column| Column {
+                name: column.to_string(),
+                column_type: ColumnType::Text,
+            })
+            .collect(),
+    )?;
+    Ok((schema, identifier_position, attribute_positions))
+}
+
+/// Read identifiable data from CSV data with a header row, one row at a
+/// time.
+///
+/// In contrast to [`read_identifiable_table`], rows are not collected in
+/// memory. The values of a row are output in the order of
+/// `attribute_columns`, with the column header as attribute name.
+///
+/// Inputs:
+/// - `reader`: The CSV data
+/// - `identifier_column`: The header of the column holding identifiers
+/// - `attribute_columns`: The headers of the columns to import
+///
+/// Output:
+/// A stream of identifiable data, which ends with an error if a row can
+/// not be read.
+///
+/// Raises:
+/// - `InvalidInput`: If one of the given columns is missing from the header
+///   or given twice.
+/// - `CorruptedData`: If the header is not well-formed CSV.
+/// - `StorageError`: If reading the header fails.
+pub fn read_identifiable_entries(
+    reader: impl io::Read,
+    identifier_column: &str,
+    attribute_columns: &[&str],
+) -> Result<impl Iterator<Item = Result<IdentifiableData, Error>>, Error> {
+    let mut reader = ::csv::Reader::from_reader(reader);
+    let (schema, identifier_position, attribute_positions) =
+        import_schema(reader.headers()?, identifier_column, attribute_columns)?;
+
+    let read_row = move |record: Result<::csv::StringRecord, ::csv::Error>| {
+        let record = record?;
+        let field = |position: usize| record.get(position).ok_or(Error::CorruptedData);
+        let handle = field(identifier_position)?;
+        attribute_positions
+            .iter()
+            .zip(schema.columns())
+            .map(|(&position, column)| {
+                Ok(IdentifiableData {
+                    handle: handle.to_string(),
+                    data_value: DataValue {
+                        value: Value::Text(field(position)?.to_string()).encode(),
+                        attribute_name: column.name.clone(),
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()
+    };
+
+    let mut failed = false;
+    Ok(reader
+        .into_records()
+        .map_while(move |record| {
+            if failed {
+                return None;
+            }
+            let row = read_row(record);
+            failed = row.is_err();
+            Some(row)
+        })
+        .flat_map(|row| match row {
+            Ok(entries) => entries.into_iter().map(Ok).collect::<Vec<_>>(),
+            Err(error) => vec![Err(error)],
+        }))
+}
+
+/// Read a table of identifiable data from CSV data with a header row.
+///
+/// This is [`read_plain_table`], followed by conversion to the
+/// per-attribute representation used as input to a split conversion.
+pub fn read_identifiable_table(
+    reader: impl io::Read,
+    name: &str,
+    identifier_column: &str,
+    attribute_columns: &[&str],
+) -> Result<Table<IdentifiableData>, Error> {
+    Ok(
+        read_plain_table(reader, name, identifier_column, attribute_columns)?
+            .to_identifiable_table(),
+    )
+}
+
+/// Read a table of identifiable data from a CSV file, as in
+/// [`read_identifiable_table`]. The table is named after the file.
+pub fn read_identifiable_table_from_path(
+    path: impl AsRef<Path>,
+    identifier_column: &str,
+    attribute_columns: &[&str],
+) -> Result<Table<IdentifiableData>, Error> {
+    let path = path.as_ref();
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    read_identifiable_table(
+        File::open(path)?,
+        &name,
+        identifier_column,
+        attribute_columns,
+    )
+}
+
+/// Write a table of pseudonymized data as CSV.
+///
+/// Both single column tables held by a data lake and joined tables held by a
+/// processor are written with one row per pseudonym, in order of first
+/// occurrence in the table, and one column per attribute, in order of first
+/// occurrence in the table.
+///
+/// If some value of an attribute is not printable UTF-8 text, e.g. because
+/// it is an encoded integer or a byte string, all values of the attribute
+/// are written in the given `encoding`, and the column header is the
+/// attribute name followed by `:hex` or `:base64`, respectively.
+///
+/// Empty values are written as empty cells, the same as missing values, see
+/// the [module documentation](self).
+///
+/// Inputs:
+/// - `writer`: The destination of the CSV data
+/// - `table`: A pseudonymized table
+/// - `pseudonym_column`: The header of the pseudonym column
+/// - `encoding`: The encoding used for pseudonyms and binary values
+///
+/// Raises:
+/// - `InvalidInput`: If a pseudonym has more than one value for an
+///   attribute, or the name of an attribute written as text ends in `:hex`
+///   or `:base64`.
+/// - `StorageError`: If writing the data fails.
+pub fn write_pseudonymized_table(
+    writer: impl io::Write,
+    table: &Table<PseudonymizedData>,
+    pseudonym_column: &str,
+    encoding: PseudonymEncoding,
+) -> Result<(), Error> {
+    let mut attributes: Vec<&str> = Vec::new();
+    let mut row_indices = BTreeMap::new();
+    let mut rows: Vec<(FinalizedPseudonym, BTreeMap<usize, &[u8]>)> = Vec::new();
+
+    for entry in table.data() {
+        let attribute = &entry.data_value.attribute_name;
+        let column = match attributes.iter().position(|a| a == attribute) {
+            Some(column) => column,
+            None => {
+                attributes.push(attribute);
+                attributes.len() - 1
+            }
+        };
+        let row = *row_indices.entry(entry.handle).or_insert_with(|| {
+            rows.push((entry.handle, BTreeMap::new()));
+            rows.len() - 1
+        });
+        if rows[row]
+            .1
+            .insert(column, &entry.data_value.value)
+            .is_some()
+        {
+            return Err(Error::InvalidInput);
+        }
+    }
+
+    let mut binary = vec![false; attributes.len()];
+    for (_, values) in &rows {
+        for (&column, value) in values {
+            binary[column] |= !is_text(value);
+        }
+    }
+    let mut headers = Vec::with_capacity(attributes.len());
+    for (attribute, &binary) in attributes.iter().zip(&binary) {
+        if binary {
+            headers.push(format!("{attribute}{}", encoding.header_suffix()));
+        } else if PseudonymEncoding::ALL
+            .iter()
+            .any(|encoding| attribute.ends_with(encoding.header_suffix()))
+        {
+            return Err(Error::InvalidInput);
+        } else {
+            headers.push(attribute.to_string());
+        }
+    }
+
+    let mut writer = ::csv::Writer::from_writer(writer);
+    writer.write_record(
+        std::iter::once(pseudonym_column).chain(headers.iter().map(String::as_str)),
+    )?;
+    for (pseudonym, values) in rows {
+        let pseudonym = encoding.encode(&pseudonym);
+        let values = (0..attributes.len()).map(|column| match values.get(&column) {
+            None => String::new(),
+            Some(value) if binary[column] => encoding.encode_bytes(value),
+            // Checked by `is_text` above.
+            Some(value) => String::from_utf8_lossy(value).into_owned(),
+        });
+        writer.write_record(std::iter::once(pseudonym).chain(values))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write a table of pseudonymized data to a CSV file, as in
+/// [`write_pseudonymized_table`].
+pub fn write_pseudonymized_table_to_path(
+    path: impl AsRef<Path>,
+    table: &Table<PseudonymizedData>,
+    pseudonym_column: &str,
+    encoding: PseudonymEncoding,
+) -> Result<(), Error> {
+    write_pseudonymized_table(File::create(path)?, table, pseudonym_column, encoding)
+}
+
+/// Read a table of pseudonymized data from CSV data written by
+/// [`write_pseudonymized_table`].
+///
+/// Every column but the pseudonym column is read as an attribute, with the
+/// column header as attribute name. Columns whose header ends in `:hex` or
+/// `:base64` are decoded accordingly, and the suffix is removed from the
+/// attribute name. Empty cells are skipped, since they mark a missing value
+/// on export. Empty values written by [`write_pseudonymized_table`] are
+/// therefore not read back.
+///
+/// Inputs:
+/// - `reader`: The CSV data
+/// - `name`: The name of the resulting table
+/// - `pseudonym_column`: The header of the pseudonym column
+/// - `encoding`: The encoding used for pseudonyms
+///
+/// Output:
+/// A pseudonymized table with one entry per non-empty cell.
+///
+/// Raises:
+/// - `InvalidInput`: If the pseudonym column is missing from the header.
+/// - `CorruptedData`: If the data is not well-formed CSV or a pseudonym or
+///   binary value cannot be decoded.
+/// - `StorageError`: If reading the data fails.
+pub fn read_pseudonymized_table(
+    reader: impl io::Read,
+    name: &str,
+    pseudonym_column: &str,
+    encoding: PseudonymEncoding,
+) -> Result<Table<PseudonymizedData>, Error> {
+    let mut reader = ::csv::Reader::from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let pseudonym_position = headers
+        .iter()
+        .position(|header| header == pseudonym_column)
+        .ok_or(Error::InvalidInput)?;
+    let columns: Vec<(&str, Option<PseudonymEncoding>)> = headers
+        .iter()
+        .map(|header| {
+            PseudonymEncoding::ALL
+                .iter()
+                .find_map(|encoding| {
+                    header
+                        .strip_suffix(encoding.header_suffix())
+                        .map(|attribute_name| (attribute_name, Some(*encoding)))
+                })
+                .unwrap_or((header, None))
+        })
+        .collect();
+
+    let mut data = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let handle =
+            encoding.decode(record.get(pseudonym_position).ok_or(Error::CorruptedData)?)?;
+        for (position, (attribute_name, value_encoding)) in columns.iter().enumerate() {
+            let value = record.get(position).ok_or(Error::CorruptedData)?;
+            if position == pseudonym_position || value.is_empty() {
+                continue;
+            }
+            let value = match value_encoding {
+                Some(value_encoding) => value_encoding.decode_bytes(value)?,
+                None => value.as_bytes().to_vec(),
+            };
+            data.push(PseudonymizedData {
+                handle,
+                data_value: DataValue {
+                    value,
+                    attribute_name: attribute_name.to_string(),
+                },
+            });
+        }
+    }
+
+    Ok(Table::new(name.to_string(), data))
+}
+
+/// Read a table of pseudonymized data from a CSV file, as in
+/// [`read_pseudonymized_table`]. The table is named after the file.
+pub fn read_pseudonymized_table_from_path(
+    path: impl AsRef<Path>,
+    pseudonym_column: &str,
+    encoding: PseudonymEncoding,
+) -> Result<Table<PseudonymizedData>, Error> {
+    let path = path.as_ref();
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    read_pseudonymized_table(File::open(path)?, &name, pseudonym_column, encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_types::DataValue;
+
+    use super::*;
+
+    const INPUT: &str = "\
+Identity (uid),Address,Notes,Favorite Color
+Alice,\"1 Main St, Springfield\",ignored,Blue
+Bob,2 Main St,ignored,Red
+";
+
+    #[test]
+    fn test_read_identifiable_table() {
+        let table = read_identifiable_table(
+            INPUT.as_bytes(),
+            "Example",
+            "Identity (uid)",
+            &["Favorite Color", "Address"],
+        )
+        .unwrap();
+
+        assert_eq!(table.identifier(), "Example");
+        let entries = table
+            .data()
+            .iter()
+            .map(|entry| {
+                (
+                    entry.handle.as_str(),
+                    entry.data_value.attribute_name.as_str(),
+                    entry.data_value.value.as_slice(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                ("Alice", "Favorite Color", b"Blue".as_slice()),
+                ("Bob", "Favorite Color", b"Red"),
+                ("Alice", "Address", b"1 Main St, Springfield"),
+                ("Bob", "Address", b"2 Main St"),
+            ]
+        );
+
+        assert_eq!(
+            read_identifiable_table(INPUT.as_bytes(), "Example", "uid", &["Address"]).unwrap_err(),
+            Error::InvalidInput
+        );
+        assert_eq!(
+            read_identifiable_table(
+                INPUT.as_bytes(),
+                "Example",
+                "Identity (uid)",
+                &["Address", "Address"]
+            )
+            .unwrap_err(),
+            Error::InvalidInput
+        );
+        assert_eq!(
+            read_identifiable_table(
+                "uid,Address\nAlice\n".as_bytes(),
+                "Example",
+                "uid",
+                &["Address"]
+            )
+            .unwrap_err(),
+            Error::CorruptedData
+        );
+    }
+
+    #[test]
+    fn test_read_identifiable_entries() {
+        let table = read_identifiable_table(
+            INPUT.as_bytes(),
+            "Example",
+            "Identity (uid)",
+            &["Favorite Color", "Address"],
+        )
+        .unwrap();
+        let mut entries = read_identifiable_entries(
+            INPUT.as_bytes(),
+            "Identity (uid)",
+            &["Favorite Color", "Address"],
+        )
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        let mut expected = table.data().to_vec();
+        entries.sort();
+        expected.sort();
+        assert_eq!(entries, expected);
+
+        assert_eq!(
+            read_identifiable_entries(INPUT.as_bytes(), "uid", &["Address"]).err(),
+            Some(Error::InvalidInput)
+        );
+        let entries = read_identifiable_entries(
+            "uid,Address\nAlice,1 Main St\nBob\nCarol,3 Main St\n".as_bytes(),
+            "uid",
+            &["Address"],
+        )
+        .unwrap()
+        .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1], Err(Error::CorruptedData));
+    }
+
+    #[test]
+    fn test_write_pseudonymized_table() {
+        let entry = |handle: u8, attribute_name: &str, value: &str| PseudonymizedData {
+            handle: FinalizedPseudonym {
+                epoch: 7,
+                value: [handle; 64],
+            },
+            data_value: DataValue {
+                value: value.as_bytes().to_vec(),
+                attribute_name: attribute_name.into(),
+            },
+        };
+        let table = Table::new(
+            "Join".into(),
+            vec![
+                entry(0xab, "Address", "1 Main St, Springfield"),
+                entry(0x01, "Address", "2 Main St"),
+                entry(0xab, "Favorite Color", "Blue"),
+            ],
+        );
+
+        let mut output = Vec::new();
+        write_pseudonymized_table(&mut output, &table, "Pseudonym", PseudonymEncoding::Hex)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "Pseudonym,Address,Favorite Color\n7:{},\"1 Main St, Springfield\",Blue\n7:{},2 Main St,\n",
+                "ab".repeat(64),
+                "01".repeat(64)
+            )
+        );
+
+        let mut output = Vec::new();
+        write_pseudonymized_table(&mut output, &table, "Pseudonym", PseudonymEncoding::Base64)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let first_pseudonym = output.lines().nth(1).unwrap().split(',').next().unwrap();
+        let (epoch, value) = first_pseudonym.split_once(':').unwrap();
+        assert_eq!(epoch, "7");
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD
+                .decode(value)
+                .unwrap(),
+            vec![0xab; 64]
+        );
+
+        let duplicate = Table::new(
+            "Join".into(),
+            vec![entry(1, "Address", "a"), entry(1, "Address", "b")],
+        );
+        assert_eq!(
+            write_pseudonymized_table(Vec::new(), &duplicate, "Pseudonym", PseudonymEncoding::Hex),
+            Err(Error::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_read_pseudonymized_table() {
+        let entry = |epoch: u32, handle: u8, attribute_name: &str, value: &str| PseudonymizedData {
+            handle: FinalizedPseudonym {
+                epoch,
+                value: [handle; 64],
+            },
+            data_value: DataValue {
+                value: value.as_bytes().to_vec(),
+                attribute_name: attribute_name.into(),
+            },
+        };
+        let table = Table::new(
+            "Join".into(),
+            vec![
+                entry(0, 0xab, "Address", "1 Main St, Springfield"),
+                entry(1, 0x01, "Address", "2 Main St"),
+                entry(0, 0xab, "Favorite Color", "Blue"),
+            ],
+        );
+
+        // Exported tables are read back including the pseudonyms' epochs.
+        for encoding in [PseudonymEncoding::Hex, PseudonymEncoding::Base64] {
+            let mut output = Vec::new();
+            write_pseudonymized_table(&mut output, &table, "Pseudonym", encoding).unwrap();
+            let read =
+                read_pseudonymized_table(output.as_slice(), "Join", "Pseudonym", encoding).unwrap();
+            let mut entries = read.data().to_vec();
+            let mut expected = table.data().to_vec();
+            entries.sort();
+            expected.sort();
+            assert_eq!(entries, expected);
+        }
+
+        // Empty values cannot be told apart from missing values and are
+        // dropped.
+        let with_empty = Table::new(
+            "Join".into(),
+            vec![
+                entry(0, 0xab, "Address", "1 Main St, Springfield"),
+                entry(0, 0xab, "Favorite Color", ""),
+            ],
+        );
+        let mut output = Vec::new();
+        write_pseudonymized_table(
+            &mut output,
+            &with_empty,
+            "Pseudonym",
+            PseudonymEncoding::Hex,
+        )
+        .unwrap();
+        let read = read_pseudonymized_table(
+            output.as_slice(),
+            "Join",
+            "Pseudonym",
+            PseudonymEncoding::Hex,
+        )
+        .unwrap();
+        assert_eq!(read.data(), &with_empty.data()[..1]);
+
+        let read = |input: &str| {
+            read_pseudonymized_table(
+                input.as_bytes(),
+                "Join",
+                "Pseudonym",
+                PseudonymEncoding::Hex,
+            )
+        };
+        assert_eq!(
+            read("Address\n1 Main St\n").unwrap_err(),
+            Error::InvalidInput
+        );
+        for pseudonym in [
+            "ab".repeat(64),
+            format!("x:{}", "ab".repeat(64)),
+            format!("0:{}", "ab".repeat(63)),
+            format!("0:{}", "xy".repeat(64)),
+        ] {
+            assert_eq!(
+                read(&format!("Pseudonym,Address\n{pseudonym},a\n")).unwrap_err(),
+                Error::CorruptedData
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_binary_values() {
+        let schema = Schema::new(
+            "Pseudonym".into(),
+            vec![
+                Column {
+                    name: "Address".into(),
+                    column_type: ColumnType::Text,
+                },
+                Column {
+                    name: "Age".into(),
+                    column_type: ColumnType::Integer,
+                },
+                Column {
+                    name: "Key".into(),
+                    column_type: ColumnType::Bytes,
+                },
+            ],
+        )
+        .unwrap();
+        let mut plain_table = PlainTable::new("Join".into(), schema.clone());
+        for (handle, age, key) in [(0xab, 42, vec![0xff, 0x00]), (0x01, -1, b"key".to_vec())] {
+            plain_table
+                .push_row(
+                    FinalizedPseudonym {
+                        epoch: 0,
+                        value: [handle; 64],
+                    },
+                    vec![
+                        Value::Text("1 Main St".into()),
+                        Value::Integer(age),
+                        Value::Bytes(key),
+                    ],
+                )
+                .unwrap();
+        }
+        let table = plain_table.to_joined_table();
+
+        let mut output = Vec::new();
+        write_pseudonymized_table(&mut output, &table, "Pseudonym", PseudonymEncoding::Hex)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines();
+        assert_eq!(lines.next().unwrap(), "Pseudonym,Address,Age:hex,Key:hex");
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("0:{},1 Main St,000000000000002a,ff00", "ab".repeat(64))
+        );
+        // A column is encoded as a whole, even if some values are text.
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("0:{},1 Main St,ffffffffffffffff,6b6579", "01".repeat(64))
+        );
+
+        for encoding in [PseudonymEncoding::Hex, PseudonymEncoding::Base64] {
+            let mut output = Vec::new();
+            write_pseudonymized_table(&mut output, &table, "Pseudonym", encoding).unwrap();
+            let read =
+                read_pseudonymized_table(output.as_slice(), "Join", "Pseudonym", encoding).unwrap();
+            assert_eq!(
+                PlainTable::from_joined_table(schema.clone(), &read).unwrap(),
+                plain_table
+            );
+        }
+
+        // Text attributes must not be mistaken for encoded ones on import.
+        let text = Table::new(
+            "Join".into(),
+            vec![PseudonymizedData {
+                handle: FinalizedPseudonym {
+                    epoch: 0,
+                    value: [1; 64],
+                },
+                data_value: DataValue {
+                    value: b"text".to_vec(),
+                    attribute_name: "Notes:base64".into(),
+                },
+            }],
+        );
+        assert_eq!(
+            write_pseudonymized_table(Vec::new(), &text, "Pseudonym", PseudonymEncoding::Hex),
+            Err(Error::InvalidInput)
+        );
+    }
+}
@@ -5,18 +5,33 @@
 //! - blinding identifiable and pseudonymous data
 //! - pseudonymizing blinded identifiable data
 //! - converting blinded pseudonymous data
+//! - updating blinded pseudonymous data to a new converter master secret
 //! - finalizing blinded pseudonymous data
 
 use hacspec_lib::Randomness;
 use oprf::coprf::{
-    coprf_online::{blind, blind_convert, blind_evaluate, prepare_blind_convert},
-    coprf_setup::{derive_key, BlindingPublicKey, CoPRFEvaluatorContext},
+    coprf_online::{blind, prepare_blind_convert},
+    coprf_setup::{derive_blinding_key_share, derive_key, BlindingPublicKey},
+    coprf_threshold::{
+        blind_convert_partial_precomputed_verifiable, combine_conversion_public_keys,
+        combine_conversions, combine_evaluations, combine_public_keys, verify_partial_conversion,
+        verify_partial_evaluation,
+    },
+    coprf_verifiable::{
+        blind_convert_precomputed_verifiable, blind_convert_verifiable, blind_evaluate_verifiable,
+        verify_conversion, verify_evaluation,
+    },
 };
+use p256::P256Point;
 
 use crate::{
     data_types::*,
     error::Error,
-    setup::{StoreContext, StoreEncryptionKey},
+    setup::{
+        ConverterBackend, ConverterContext, ConverterPublicKeys, StoreContext, StoreEncryptionKey,
+        UpdateScalars, UpdateToken,
+    },
+    threshold::collect_quorum,
 };
 
 pub(crate) mod data_encryption;
@@ -28,7 +43,7 @@ const PSEUDONYMIZATION_CONTEXT: &[u8] = b"CoPRF-Context-Pseudonymization";
 /// generation.
 ///
 /// Inputs:
-/// - `bpk`: Receiver's blinding public key
+/// - `bpk`: Joint blinding public key of receiver and converter
 /// - `ek`: Receiver's public encryption key
 /// - `datum`: Identifiable data
 /// - `randomness`: Random bytes
@@ -67,7 +82,7 @@ pub fn blind_identifiable_datum(
 /// Inputs:
 /// - `store_context`: The data store's long term private state including the pseudonym
 ///   hardening keys
-/// - `bpk`: Receiver's blinding public key
+/// - `bpk`: Joint blinding public key of receiver and converter
 /// - `ek`: Receiver's public encryption key
 /// - `datum`: Pseudonymized data
 /// - `randomness`: Random bytes
@@ -97,13 +112,14 @@ pub fn blind_pseudonymized_datum(
     Ok(BlindedPseudonymizedData {
         blinded_handle,
         encrypted_data_value,
+        conversion_proof: None,
     })
 }
 
 /// Obliviously pseudonymmize a blinded identifiable datum.
 ///
 /// Inputs:
-/// - `coprf_context`: The converter's CoPRF evaluation context
+/// - `converter_context`: The converter's long term private state
 /// - `bpk`: The receiver's blinding public key
 /// - `ek`: The receiver's public encryption key
 /// - `datum`: A blinded datum output by [`blind_identifiable_datum`]
@@ -112,88 +128,362 @@ pub fn blind_pseudonymized_datum(
 /// Output:
 /// [Blinded pseudonymized data](BlindedPseudonymizedData) such that the
 ///  datum's blinded handle has been obliviously evaluated to a pseudonym and
-///  the datum's value has been level-2 encrypted towards the receiver.
+///  the datum's value has been level-2 encrypted towards the receiver. The
+///  result carries a proof that the pseudonym was computed using the key of
+///  the datum's attribute. For a threshold converter, the first `t`
+///  evaluators that return a valid partial result each contribute it
+///  together with its proof. Failing evaluators are skipped.
+///
+/// Raises:
+/// - `InvalidInput`: If a threshold converter holds fewer than `t`
+///   evaluators.
+/// - The error of the last failing evaluator, if fewer than `t` evaluators
+///   return a valid partial result.
 pub fn pseudonymize_blinded_datum(
-    coprf_context: &CoPRFEvaluatorContext,
+    converter_context: &ConverterContext,
     bpk: &BlindingPublicKey,
     ek: &StoreEncryptionKey,
     datum: &BlindedIdentifiableData,
     randomness: &mut Randomness,
 ) -> Result<BlindedPseudonymizedData, Error> {
-    let key = derive_key(
-        coprf_context,
-        datum.encrypted_data_value.attribute_name.as_bytes(),
-    )?;
+    let attribute = datum.encrypted_data_value.attribute_name.as_bytes();
 
     // Obliviously generate raw pseudonym.
-    let blinded_handle = BlindedPseudonymizedHandle(blind_evaluate(
-        key,
-        *bpk,
-        datum.blinded_handle.0,
-        randomness,
-    )?);
+    let (blinded_handle, conversion_proof) = match &converter_context.backend {
+        ConverterBackend::Single(coprf_context) => {
+            let key = derive_key(coprf_context, attribute)?;
+            let (blinded_handle, proof) = blind_evaluate_verifiable(
+                key,
+                derive_blinding_key_share(coprf_context)?,
+                *bpk,
+                datum.blinded_handle.0,
+                randomness,
+            )?;
+            (
+                blinded_handle,
+                ConversionProof::Pseudonymization {
+                    blinded_input: datum.blinded_handle.0,
+                    proof,
+                },
+            )
+        }
+        ConverterBackend::Threshold(evaluators) => {
+            let quorum = evaluators[0].threshold() as usize;
+            let partials = collect_quorum(evaluators, quorum, |evaluator| {
+                evaluator.evaluate(attribute, *bpk, datum.blinded_handle.0, randomness)
+            })?;
+            let outputs = partials.iter().map(|(p, _)| *p).collect::<Vec<_>>();
+            (
+                combine_evaluations(&outputs)?,
+                ConversionProof::ThresholdPseudonymization {
+                    blinded_input: datum.blinded_handle.0,
+                    partials,
+                },
+            )
+        }
+    };
 
     // Rerandomize encrypted data value towards receiver.
     let encrypted_data_value =
         data_encryption::rerandomize_encryption(&datum.encrypted_data_value, ek, randomness)?;
 
     Ok(BlindedPseudonymizedData {
-        blinded_handle,
+        blinded_handle: BlindedPseudonymizedHandle(blinded_handle),
         encrypted_data_value,
+        conversion_proof: Some(conversion_proof),
     })
 }
 
 /// Obliviously convert a blinded pseudonymous datum to a given target pseudonym key.
 ///
 /// Inputs:
-/// - `coprf_context`: The Converters CoPRF evaluation context
+/// - `converter_context`: The converter's long term private state
 /// - `bpk`: The receiver's blinding public key
 /// - `ek`: The receiver's public encryption key
 /// - `conversion_target`: Target pseudonym key identifier
 /// - `randomness`: Random bytes
 ///
 /// Output:
-/// [Blinded pseudonymized data](BlindedPseudonymizedData)such that the
+/// [Blinded pseudonymized data](BlindedPseudonymizedData) such that the
 /// datum's pseudonymous handle is converted to the target pseudonym key and
-/// the datum's value is level-2 encrypted towards the receiver.
+/// the datum's value is level-2 encrypted towards the receiver. The result
+/// carries a proof that the pseudonym was converted from the key of the
+/// datum's attribute to the target key. For a threshold converter, the
+/// first `2t - 1` evaluators that return a valid partial result each
+/// contribute it together with its proof. Failing evaluators are skipped.
+///
+/// Raises:
+/// - `InvalidInput`: If a threshold converter holds fewer than `2t - 1`
+///   evaluators.
+/// - The error of the last failing evaluator, if fewer than `2t - 1`
+///   evaluators return a valid partial result.
 pub fn convert_blinded_datum(
-    coprf_context: &CoPRFEvaluatorContext,
+    converter_context: &ConverterContext,
     bpk: &BlindingPublicKey,
     ek: &StoreEncryptionKey,
     conversion_target: &[u8],
     datum: &BlindedPseudonymizedData,
     randomness: &mut Randomness,
 ) -> Result<BlindedPseudonymizedData, Error> {
-    // Re-derive original pseudonymization key.
-    let key_from = derive_key(
-        coprf_context,
-        datum.encrypted_data_value.attribute_name.as_bytes(),
-    )?;
+    let attribute = datum.encrypted_data_value.attribute_name.as_bytes();
 
-    // Derive target key.
-    let key_to = derive_key(coprf_context, conversion_target)?;
+    // Obliviously convert pseudonym.
+    let (blinded_handle, conversion_proof) = match &converter_context.backend {
+        ConverterBackend::Single(coprf_context) => {
+            // Re-derive original pseudonymization key.
+            let key_from = derive_key(coprf_context, attribute)?;
+
+            // Derive target key.
+            let key_to = derive_key(coprf_context, conversion_target)?;
+
+            let (blinded_handle, proof) = blind_convert_verifiable(
+                *bpk,
+                key_from,
+                key_to,
+                derive_blinding_key_share(coprf_context)?,
+                datum.blinded_handle.0,
+                randomness,
+            )?;
+            (
+                blinded_handle,
+                ConversionProof::Conversion {
+                    blinded_input: datum.blinded_handle.0,
+                    target_key: p256::p256_point_mul_base(key_to)?,
+                    proof,
+                },
+            )
+        }
+        ConverterBackend::Threshold(evaluators) => {
+            let quorum = evaluators[0].conversion_threshold() as usize;
+            if evaluators.len() < quorum {
+                return Err(Error::InvalidInput);
+            }
+            let public_key_from =
+                converter_context.attribute_key(&datum.encrypted_data_value.attribute_name)?;
+            let partials = collect_quorum(evaluators, quorum, |evaluator| {
+                evaluator.convert(
+                    public_key_from,
+                    attribute,
+                    conversion_target,
+                    *bpk,
+                    datum.blinded_handle.0,
+                    randomness,
+                )
+            })?;
+            let outputs = partials.iter().map(|(p, _)| *p).collect::<Vec<_>>();
+            (
+                combine_conversions(&outputs)?,
+                ConversionProof::ThresholdConversion {
+                    blinded_input: datum.blinded_handle.0,
+                    partials,
+                },
+            )
+        }
+    };
 
+    // Rerandomize encrypted data value towards receiver.
+    let encrypted_data_value =
+        data_encryption::rerandomize_encryption(&datum.encrypted_data_value, ek, randomness)?;
+
+    Ok(BlindedPseudonymizedData {
+        blinded_handle: BlindedPseudonymizedHandle(blinded_handle),
+        encrypted_data_value,
+        conversion_proof: Some(conversion_proof),
+    })
+}
+
+/// Obliviously update a blinded pseudonymous datum to the pseudonymization
+/// key of its attribute under a new converter master secret.
+///
+/// Inputs:
+/// - `token`: The update token for the datum's attribute
+/// - `bpk`: The receiver's blinding public key
+/// - `ek`: The receiver's public encryption key
+/// - `datum`: Blinded pseudonymous datum, blinded towards the joint blinding
+///   key of the receiver and the converter before the rotation
+/// - `randomness`: Random bytes
+///
+/// Output:
+/// [Blinded pseudonymized data](BlindedPseudonymizedData) such that the
+/// datum's pseudonymous handle is converted to the new pseudonymization key
+/// and the datum's value is level-2 encrypted towards the receiver. The
+/// result carries a proof that the pseudonym was converted from the old to
+/// the new key of the datum's attribute. For a token of a threshold
+/// converter, each evaluator of the quorum contributes a partial result
+/// and proof.
+pub fn update_blinded_datum(
+    token: &UpdateToken,
+    bpk: &BlindingPublicKey,
+    ek: &StoreEncryptionKey,
+    datum: &BlindedPseudonymizedData,
+    randomness: &mut Randomness,
+) -> Result<BlindedPseudonymizedData, Error> {
     // Obliviously convert pseudonym.
-    let blinded_handle = BlindedPseudonymizedHandle(blind_convert(
-        *bpk,
-        key_from,
-        key_to,
-        datum.blinded_handle.0,
-        randomness,
-    )?);
+    let (blinded_handle, conversion_proof) = match &token.scalars {
+        UpdateScalars::Single(scalars) => {
+            let (blinded_handle, proof) = blind_convert_precomputed_verifiable(
+                scalars,
+                *bpk,
+                datum.blinded_handle.0,
+                randomness,
+            )?;
+            (
+                blinded_handle,
+                ConversionProof::Conversion {
+                    blinded_input: datum.blinded_handle.0,
+                    target_key: p256::p256_point_mul(scalars.scalar, scalars.public_key_from)?,
+                    proof,
+                },
+            )
+        }
+        UpdateScalars::Threshold(scalars) => {
+            let partials = scalars
+                .iter()
+                .map(|scalars| {
+                    blind_convert_partial_precomputed_verifiable(
+                        scalars,
+                        *bpk,
+                        datum.blinded_handle.0,
+                        randomness,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let outputs = partials.iter().map(|(p, _)| *p).collect::<Vec<_>>();
+            (
+                combine_conversions(&outputs)?,
+                ConversionProof::ThresholdConversion {
+                    blinded_input: datum.blinded_handle.0,
+                    partials,
+                },
+            )
+        }
+    };
 
     // Rerandomize encrypted data value towards receiver.
     let encrypted_data_value =
         data_encryption::rerandomize_encryption(&datum.encrypted_data_value, ek, randomness)?;
 
     Ok(BlindedPseudonymizedData {
-        blinded_handle,
+        blinded_handle: BlindedPseudonymizedHandle(blinded_handle),
         encrypted_data_value,
+        conversion_proof: Some(conversion_proof),
     })
 }
 
+/// Verify the converter's proof for a blinded pseudonymous datum.
+///
+/// Inputs:
+/// - `store_context`: The receiving data store's long term private state
+/// - `converter_keys`: The converter's public keys
+/// - `datum`: blinded pseudonymous datum output by [`convert_blinded_datum`] or
+///   [`pseudonymize_blinded_datum`]
+///
+/// Output:
+/// The public key of the target key if the datum was output by
+/// [`convert_blinded_datum`], `None` otherwise.
+///
+/// Raises:
+/// - `VerificationError`: If the datum carries no proof, the converter's
+///   public key for the datum's attribute is unknown, or the proof does not
+///   verify. For a threshold converter, each partial proof must verify and
+///   the partial results must combine to the datum's blinded handle under
+///   the converter's public key for the datum's attribute.
+pub fn verify_blinded_datum(
+    store_context: &StoreContext,
+    converter_keys: &ConverterPublicKeys,
+    datum: &BlindedPseudonymizedData,
+) -> Result<Option<P256Point>, Error> {
+    let attribute_key = converter_keys
+        .attribute_key(&datum.encrypted_data_value.attribute_name)
+        .ok_or(Error::VerificationError)?;
+    let blinding_key_share = converter_keys.blinding_key_share;
+    let bpk = store_context.blinding_public_key();
+
+    match &datum.conversion_proof {
+        Some(ConversionProof::Pseudonymization {
+            blinded_input,
+            proof,
+        }) => {
+            verify_evaluation(
+                attribute_key,
+                blinding_key_share,
+                bpk,
+                *blinded_input,
+                datum.blinded_handle.0,
+                proof,
+            )?;
+            Ok(None)
+        }
+        Some(ConversionProof::Conversion {
+            blinded_input,
+            target_key,
+            proof,
+        }) => {
+            verify_conversion(
+                attribute_key,
+                *target_key,
+                blinding_key_share,
+                bpk,
+                *blinded_input,
+                datum.blinded_handle.0,
+                proof,
+            )?;
+            Ok(Some(*target_key))
+        }
+        Some(ConversionProof::ThresholdPseudonymization {
+            blinded_input,
+            partials,
+        }) => {
+            for (partial, proof) in partials {
+                verify_partial_evaluation(blinding_key_share, bpk, *blinded_input, partial, proof)?;
+            }
+            let public_keys = partials
+                .iter()
+                .map(|(partial, proof)| (partial.index, proof.public_key))
+                .collect::<Vec<_>>();
+            let outputs = partials.iter().map(|(p, _)| *p).collect::<Vec<_>>();
+            if combine_public_keys(&public_keys).map_err(|_| Error::VerificationError)?
+                != attribute_key
+                || combine_evaluations(&outputs).map_err(|_| Error::VerificationError)?
+                    != datum.blinded_handle.0
+            {
+                return Err(Error::VerificationError);
+            }
+            Ok(None)
+        }
+        Some(ConversionProof::ThresholdConversion {
+            blinded_input,
+            partials,
+        }) => {
+            for (partial, proof) in partials {
+                verify_partial_conversion(
+                    attribute_key,
+                    blinding_key_share,
+                    bpk,
+                    *blinded_input,
+                    partial,
+                    proof,
+                )?;
+            }
+            let outputs = partials.iter().map(|(p, _)| *p).collect::<Vec<_>>();
+            if combine_conversions(&outputs).map_err(|_| Error::VerificationError)?
+                != datum.blinded_handle.0
+            {
+                return Err(Error::VerificationError);
+            }
+            let target_key =
+                combine_conversion_public_keys(partials).map_err(|_| Error::VerificationError)?;
+            Ok(Some(target_key))
+        }
+        None => Err(Error::VerificationError),
+    }
+}
+
 /// Finalize a blinded pseudonymous datum for storage or analysis.
 ///
+/// The converter's proof for the datum must have been checked using
+/// [`verify_blinded_datum`] before.
+///
 /// Inputs:
 /// - `store_context`: The data store's long term private state including the
 ///   receiver's coPRF unblinding key, private decryption key, as well as
@@ -205,6 +495,10 @@ pub fn convert_blinded_datum(
 /// [Pseudonymized data](PseudonymizedData) such that the datum's pseudonymous
 /// handle has been unblinded and hardened and the datum's value has been
 /// decrypted.
+///
+/// Raises:
+/// - `CorruptedData`: If the datum's value does not decrypt, in particular
+///   if its encryption is not bound to the datum's attribute name.
 pub fn finalize_blinded_datum(
     store_context: &StoreContext,
     datum: &BlindedPseudonymizedData,
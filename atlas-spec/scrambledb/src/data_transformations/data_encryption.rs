@@ -1,35 +1,29 @@
 #[cfg(feature = "double-hpke")]
 pub(crate) mod double_hpke;
 
-#[cfg(not(feature = "double-hpke"))]
 pub(crate) mod elgamal;
 
-use crate::data_types::{DataValue, EncryptedDataValue};
+use crate::data_types::{DataValue, EncryptedDataValue, EncryptedValue};
 use crate::error::Error;
-use crate::setup::{StoreContext, StoreEncryptionKey};
+use crate::serialization::put_byte_string;
+use crate::setup::{StoreContext, StoreDecryptionKey, StoreEncryptionKey};
 use hacspec_lib::Randomness;
 
-/// Encrypt a data value towards a data store.
-///
-/// Inputs:
-/// - `data`: The data value to encrypt.
-/// - `ek`: The receiver's public encryption key.
-/// - `randomness`: Random bytes
+/// The associated data every encryption layer of a data value is bound to.
 ///
-/// Output:
-/// A new [EncryptedDataValue], the encryption of `data`.
-#[cfg(feature = "double-hpke")]
-pub(crate) fn encrypt_data_value(
-    data: &DataValue,
-    ek: &StoreEncryptionKey,
-    randomness: &mut Randomness,
-) -> Result<EncryptedDataValue, Error> {
-    let encrypted_data_value = double_hpke::hpke_seal_level_1(data, &ek.0, randomness)?;
-    Ok(encrypted_data_value)
+/// Only the attribute name is bound. Table identifiers change when tables
+/// are converted or merged, so they cannot be fixed at encryption time.
+fn associated_data(attribute_name: &str) -> Vec<u8> {
+    let mut aad = Vec::new();
+    put_byte_string(&mut aad, attribute_name.as_bytes());
+    aad
 }
 
 /// Encrypt a data value towards a data store.
 ///
+/// The data value is encrypted using the encryption scheme of the
+/// receiver's encryption key, bound to the data value's attribute name.
+///
 /// Inputs:
 /// - `data`: The data value to encrypt.
 /// - `ek`: The receiver's public encryption key.
@@ -37,35 +31,33 @@ pub(crate) fn encrypt_data_value(
 ///
 /// Output:
 /// A new [EncryptedDataValue], the encryption of `data`.
-#[cfg(not(feature = "double-hpke"))]
 pub(crate) fn encrypt_data_value(
     data: &DataValue,
     ek: &StoreEncryptionKey,
     randomness: &mut Randomness,
 ) -> Result<EncryptedDataValue, Error> {
-    let encrypted_data_value = elgamal::encrypt(data, ek, randomness)?;
-    Ok(encrypted_data_value)
+    let aad = associated_data(&data.attribute_name);
+    let value = match ek {
+        StoreEncryptionKey::ElGamal(ek) => {
+            EncryptedValue::ElGamal(elgamal::encrypt(&data.value, &aad, *ek, randomness)?)
+        }
+        #[cfg(feature = "double-hpke")]
+        StoreEncryptionKey::DoubleHpke(ek) => EncryptedValue::DoubleHpke {
+            value: double_hpke::hpke_seal_level_1(&data.value, &aad, ek, randomness)?,
+            encryption_level: 1,
+        },
+    };
+    Ok(EncryptedDataValue {
+        value,
+        attribute_name: data.attribute_name.clone(),
+    })
 }
 
 /// Rerandomize the encryption of an encrypted data value.
 ///
-/// Inputs:
-/// - `data`: The encrypted data value.
-/// - `ek`: The receiver's public encryption key.
-/// - `randomness`: Random bytes
-///
-/// Output:
-/// A new, rerandomized [EncryptedDataValue].
-#[cfg(feature = "double-hpke")]
-pub(crate) fn rerandomize_encryption(
-    data: &EncryptedDataValue,
-    ek: &StoreEncryptionKey,
-    randomness: &mut Randomness,
-) -> Result<EncryptedDataValue, Error> {
-    double_hpke::hpke_seal_level_2(data, &ek.0, randomness)
-}
-
-/// Rerandomize the encryption of an encrypted data value.
+/// For ElGamal encryption, the ciphertexts are rerandomized. For double
+/// HPKE encryption, a level-1 encryption is encrypted a second time, bound
+/// to the attribute name the value is presented under.
 ///
 /// Inputs:
 /// - `data`: The encrypted data value.
@@ -74,43 +66,85 @@ pub(crate) fn rerandomize_encryption(
 ///
 /// Output:
 /// A new, rerandomized [EncryptedDataValue].
-#[cfg(not(feature = "double-hpke"))]
+///
+/// Raises:
+/// - `InvalidInput`: If the data value was not encrypted using the scheme
+///   of the receiver's encryption key, or is a double HPKE encryption that
+///   is not at level 1.
 pub(crate) fn rerandomize_encryption(
     data: &EncryptedDataValue,
     ek: &StoreEncryptionKey,
     randomness: &mut Randomness,
 ) -> Result<EncryptedDataValue, Error> {
-    elgamal::rerandomize(data, ek, randomness)
+    let value = match (&data.value, ek) {
+        (EncryptedValue::ElGamal(ciphertexts), StoreEncryptionKey::ElGamal(ek)) => {
+            EncryptedValue::ElGamal(elgamal::rerandomize(ciphertexts, *ek, randomness)?)
+        }
+        #[cfg(feature = "double-hpke")]
+        (
+            EncryptedValue::DoubleHpke {
+                value,
+                encryption_level: 1,
+            },
+            StoreEncryptionKey::DoubleHpke(ek),
+        ) => EncryptedValue::DoubleHpke {
+            value: double_hpke::hpke_seal_level_2(
+                value,
+                &associated_data(&data.attribute_name),
+                ek,
+                randomness,
+            )?,
+            encryption_level: 2,
+        },
+        #[allow(unreachable_patterns)]
+        _ => return Err(Error::InvalidInput),
+    };
+    Ok(EncryptedDataValue {
+        value,
+        attribute_name: data.attribute_name.clone(),
+    })
 }
 
 /// Decrypt an encrypted data value.
 ///
 /// Inputs:
 /// - `data`: The value to decrypt.
-/// - `store_context`: The data store's long term private state, including in particular its decryption key.
+/// - `store_context`: The data store's long term private state, including in particular its decryption keys.
 ///
 /// Output:
 /// The decrypted [DataValue] or an [Error] on decryption failure.
-#[cfg(feature = "double-hpke")]
-pub(crate) fn decrypt_data_value(
-    data: &EncryptedDataValue,
-    store_context: &StoreContext,
-) -> Result<DataValue, Error> {
-    double_hpke::hpke_open_level_2(data, &store_context.dk.0)
-}
-
-/// Decrypt an encrypted data value.
 ///
-/// Inputs:
-/// - `data`: The value to decrypt.
-/// - `store_context`: The data store's long term private state, including in particular its decryption key.
-///
-/// Output:
-/// The decrypted [DataValue] or an [Error] on decryption failure.
-#[cfg(not(feature = "double-hpke"))]
+/// Raises:
+/// - `InvalidInput`: If the data store holds no decryption key for the
+///   value's encryption scheme, or the value is a double HPKE encryption
+///   that is not at level 2.
+/// - `CorruptedData`: If decryption fails, in particular if the value's
+///   encryption is not bound to its attribute name.
 pub(crate) fn decrypt_data_value(
     data: &EncryptedDataValue,
     store_context: &StoreContext,
 ) -> Result<DataValue, Error> {
-    elgamal::decrypt(data, &store_context.dk)
+    let dk = store_context
+        .decryption_key(data.scheme())
+        .ok_or(Error::InvalidInput)?;
+    let aad = associated_data(&data.attribute_name);
+    let value = match (&data.value, dk) {
+        (EncryptedValue::ElGamal(ciphertexts), StoreDecryptionKey::ElGamal(dk)) => {
+            elgamal::decrypt(ciphertexts, &aad, *dk)?
+        }
+        #[cfg(feature = "double-hpke")]
+        (
+            EncryptedValue::DoubleHpke {
+                value,
+                encryption_level: 2,
+            },
+            StoreDecryptionKey::DoubleHpke(dk),
+        ) => double_hpke::hpke_open_level_2(value, &aad, dk)?,
+        #[allow(unreachable_patterns)]
+        _ => return Err(Error::InvalidInput),
+    };
+    Ok(DataValue {
+        value,
+        attribute_name: data.attribute_name.clone(),
+    })
 }
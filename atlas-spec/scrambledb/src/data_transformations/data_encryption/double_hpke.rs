@@ -9,7 +9,8 @@
 //! level-2 encryption of the data value.
 //!
 //! Only level-2 encrypted data values can be decrypted, and only if both
-//! encryptions were performed towards the same receiver.
+//! encryptions were performed towards the same receiver and under the same
+//! associated data, e.g. the data value's attribute name.
 
 use libcrux::hpke::kem::Nsk;
 
@@ -17,8 +18,6 @@ use libcrux::hpke::{HpkeOpen, HpkeSeal};
 
 use crate::SerializedHPKE;
 
-use crate::data_types::{DataValue, EncryptedDataValue};
-
 use libcrux::hpke::HPKEConfig;
 
 use crate::error::Error;
@@ -34,133 +33,106 @@ const HPKE_LEVEL_2_INFO: &[u8] = b"Hpke-Level-2";
 /// Level-1 encrypt a plain text data value.
 ///
 /// Inputs:
-/// - `data_value`: A plain text data value
+/// - `value`: The byte string encoding a plain text data value
+/// - `aad`: The associated data the encryption is bound to
 /// - `ek`: The receivers public encryption key
 /// - `randomness`: Random bytes
 ///
 /// Output:
-/// A level-1 encrypted data value.
+/// A level-1 encryption of the data value.
 ///
 /// Raises:
 /// - `CorruptedData`: If the internal encryption fails.
-///
-/// Panics:
-/// - on insufficient randomness
+/// - `RandomnessError`: If not enough randomness was provided.
 pub(crate) fn hpke_seal_level_1(
-    data_value: &DataValue,
+    value: &[u8],
+    aad: &[u8],
     ek: &[u8],
     randomness: &mut Randomness,
-) -> Result<EncryptedDataValue, Error> {
-    let HPKEConfig(_, kem, _, _) = crate::HPKE_CONF;
-    let encrypted_data_value = EncryptedDataValue {
-        attribute_name: data_value.attribute_name.clone(),
-        value: SerializedHPKE::from_hpke_ct(&HpkeSeal(
-            crate::HPKE_CONF,
-            ek,
-            HPKE_LEVEL_1_INFO,
-            b"",
-            &data_value.value,
-            None,
-            None,
-            None,
-            randomness.bytes(Nsk(kem)).unwrap().to_vec(),
-        )?)
-        .to_bytes(),
-        encryption_level: 1u8,
-    };
-    Ok(encrypted_data_value)
+) -> Result<Vec<u8>, Error> {
+    hpke_seal(value, aad, ek, HPKE_LEVEL_1_INFO, randomness)
 }
 
 /// Level-2 encrypt a level-1 encrypted data value.
 ///
 /// Inputs:
-/// - `data_value`: A level-1 encrypted data value
+/// - `value`: A level-1 encryption of a data value
+/// - `aad`: The associated data the encryption is bound to
 /// - `ek`: The receivers public encryption key
 /// - `randomness`: Random bytes
 ///
 /// Output:
-/// A level-2 encrypted data value.
+/// A level-2 encryption of the data value.
 ///
 /// Raises:
-/// - `InvalidInput`: If the input data value is not level-1 encrypted.
 /// - `CorruptedData`: If the internal encryption fails.
-///
-/// Panics:
-/// - on insufficient randomness
+/// - `RandomnessError`: If not enough randomness was provided.
 pub(crate) fn hpke_seal_level_2(
-    data_value: &EncryptedDataValue,
+    value: &[u8],
+    aad: &[u8],
     ek: &[u8],
     randomness: &mut Randomness,
-) -> Result<EncryptedDataValue, Error> {
-    if data_value.encryption_level != 1u8 {
-        return Err(Error::InvalidInput);
-    }
+) -> Result<Vec<u8>, Error> {
+    hpke_seal(value, aad, ek, HPKE_LEVEL_2_INFO, randomness)
+}
 
+fn hpke_seal(
+    value: &[u8],
+    aad: &[u8],
+    ek: &[u8],
+    info: &[u8],
+    randomness: &mut Randomness,
+) -> Result<Vec<u8>, Error> {
     let HPKEConfig(_, kem, _, _) = crate::HPKE_CONF;
-    let data_value = EncryptedDataValue {
-        attribute_name: data_value.attribute_name.clone(),
-        value: SerializedHPKE::from_hpke_ct(&HpkeSeal(
-            crate::HPKE_CONF,
-            ek,
-            HPKE_LEVEL_2_INFO,
-            b"",
-            &data_value.value,
-            None,
-            None,
-            None,
-            randomness.bytes(Nsk(kem)).unwrap().to_vec(),
-        )?)
-        .to_bytes(),
-        encryption_level: 2u8,
-    };
-    Ok(data_value)
+    Ok(SerializedHPKE::from_hpke_ct(&HpkeSeal(
+        crate::HPKE_CONF,
+        ek,
+        info,
+        aad,
+        value,
+        None,
+        None,
+        None,
+        randomness.bytes(Nsk(kem))?.to_vec(),
+    )?)
+    .to_bytes())
 }
 
 /// Decrypt a level-2 encrypted data value.
 ///
 /// Inputs:
-/// - `data_value`: A Level-2 encrypted data value
+/// - `value`: A Level-2 encryption of a data value
+/// - `aad`: The associated data both encryptions are expected to be bound to
 /// - `sk`: The receiver's decryption key
 ///
 /// Outputs:
-/// A plain text data value.
+/// The byte string encoding the plain text data value.
 ///
 /// Raises:
-/// - `InvalidInput`: If the input data value is not level-2 encrypted.
 /// - `CorruptedData`: If the internal decryption fails, e.g. because of
-///   inconsistent level-1 and level-2 receivers.
-pub(crate) fn hpke_open_level_2(
-    data_value: &EncryptedDataValue,
-    sk: &[u8],
-) -> Result<DataValue, Error> {
-    if data_value.encryption_level != 2u8 {
-        return Err(Error::InvalidInput);
-    }
-
-    let outer_encryption = SerializedHPKE::from_bytes(&data_value.value).to_hpke_ct();
+///   inconsistent level-1 and level-2 receivers, or because either
+///   encryption is not bound to `aad`.
+pub(crate) fn hpke_open_level_2(value: &[u8], aad: &[u8], sk: &[u8]) -> Result<Vec<u8>, Error> {
+    let outer_encryption = SerializedHPKE::from_bytes(value).to_hpke_ct();
     let inner_encryption = SerializedHPKE::from_bytes(&HpkeOpen(
         crate::HPKE_CONF,
         &outer_encryption,
         sk,
         HPKE_LEVEL_2_INFO,
-        b"",
+        aad,
         None,
         None,
         None,
     )?)
     .to_hpke_ct();
-    let data_value = DataValue {
-        attribute_name: data_value.attribute_name.clone(),
-        value: HpkeOpen(
-            crate::HPKE_CONF,
-            &inner_encryption,
-            sk,
-            HPKE_LEVEL_1_INFO,
-            b"",
-            None,
-            None,
-            None,
-        )?,
-    };
-    Ok(data_value)
+    Ok(HpkeOpen(
+        crate::HPKE_CONF,
+        &inner_encryption,
+        sk,
+        HPKE_LEVEL_1_INFO,
+        aad,
+        None,
+        None,
+        None,
+    )?)
 }
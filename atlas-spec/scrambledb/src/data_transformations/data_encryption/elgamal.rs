@@ -1,84 +1,204 @@
+//! Data values are encrypted as a sequence of ElGamal ciphertexts, where
+//! each plaintext is a curve point embedding up to [`BYTES_PER_POINT`]
+//! bytes of the data value in its x-coordinate:
+//!
+//! ``` text
+//! x = 0x00 || len || chunk || 0x00 ... 0x00 || counter
+//! ```
+//!
+//! Here, `chunk` is a piece of the data value of length `len`, padded with
+//! zero bytes, and `counter` is the smallest byte such that `x` is the
+//! x-coordinate of a point on the curve. Since about half of all field
+//! elements are valid x-coordinates, a valid point is found after about two
+//! tries on average. Decoding a decrypted point only requires reading off
+//! its x-coordinate.
+//!
+//! ElGamal encryption offers no associated data, and its ciphertexts can be
+//! rerandomized, reordered or exchanged individually. To bind a data value
+//! to its associated data, e.g. its attribute name, the encrypted byte
+//! string is the data value followed by a binding tag:
+//!
+//! ``` text
+//! tag = SHA256("ScrambleDB-ElGamalBinding" || aad || value)[..16]
+//! ```
+//!
+//! Decryption recomputes the tag from the associated data given by the
+//! receiver and rejects the data value if it does not match. Moving an
+//! encrypted data value to other associated data, or tampering with its
+//! ciphertexts, therefore requires knowing the data value.
+
+use elgamal::Ciphertext;
 use hacspec_lib::hacspec_helper::NatMod;
 use hacspec_lib::Randomness;
-use p256::{P256Point, P256Scalar};
+use p256::{P256FieldElement, P256Point, P256Scalar};
 
-use crate::data_types::{DataValue, EncryptedDataValue};
 use crate::error::Error;
-use crate::setup::{StoreDecryptionKey, StoreEncryptionKey};
 
-fn encode_byte(byte: &u8) -> P256Point {
-    let mut scalar_encoding = P256Scalar::one();
-    for _i in 0..*byte {
-        scalar_encoding = scalar_encoding.fadd(P256Scalar::one());
+/// The number of data bytes embedded in a single curve point.
+const BYTES_PER_POINT: usize = 29;
+
+/// The length of the tag binding a data value to its associated data.
+const BINDING_TAG_BYTES: usize = 16;
+
+/// Domain separator for binding tags.
+const DST_BINDING: &[u8] = b"ScrambleDB-ElGamalBinding";
+
+/// Embed up to [`BYTES_PER_POINT`] bytes in a curve point.
+fn encode_chunk(chunk: &[u8]) -> Result<P256Point, Error> {
+    debug_assert!(chunk.len() <= BYTES_PER_POINT);
+
+    let mut x_bytes = [0u8; 32];
+    x_bytes[1] = chunk.len() as u8;
+    x_bytes[2..2 + chunk.len()].copy_from_slice(chunk);
+    for counter in 0..=u8::MAX {
+        x_bytes[31] = counter;
+        let x = P256FieldElement::from_be_bytes(&x_bytes);
+        let candidate = P256Point::NonInf((x, p256::p256_calculate_w(x)));
+        if p256::p256_validate_public_key(candidate) {
+            return Ok(candidate);
+        }
     }
-    p256::p256_point_mul_base(scalar_encoding).unwrap()
+
+    // Happens with probability 2^-256.
+    Err(Error::InvalidInput)
 }
 
-fn decode_point(point: P256Point) -> u8 {
-    let mut byte_candidate = 0u8;
-    let mut candidate_encoding = P256Scalar::one();
-    for _i in 0..256 {
-        let candidate_point = p256::p256_point_mul_base(candidate_encoding).unwrap();
-        if candidate_point == point {
-            break;
-        }
-        candidate_encoding = candidate_encoding.fadd(P256Scalar::one());
-        byte_candidate += 1;
+/// Recover the bytes embedded in a curve point.
+fn decode_point(point: P256Point) -> Result<Vec<u8>, Error> {
+    let x_bytes = point.x()?.to_be_bytes();
+    let len = x_bytes[1] as usize;
+    if x_bytes[0] != 0
+        || len > BYTES_PER_POINT
+        || x_bytes[2 + len..31].iter().any(|byte| *byte != 0)
+    {
+        return Err(Error::CorruptedData);
     }
-    byte_candidate
+    Ok(x_bytes[2..2 + len].to_vec())
+}
+
+/// The tag binding `value` to the associated data `aad`.
+fn binding_tag(aad: &[u8], value: &[u8]) -> [u8; BINDING_TAG_BYTES] {
+    let mut input = DST_BINDING.to_vec();
+    input.extend_from_slice(aad);
+    input.extend_from_slice(value);
+    let mut tag = [0u8; BINDING_TAG_BYTES];
+    tag.copy_from_slice(&sha256::hash(&input)[..BINDING_TAG_BYTES]);
+    tag
 }
 
+/// Encrypt a byte string, bound to the associated data `aad`, as a
+/// sequence of ElGamal ciphertexts.
 pub(crate) fn encrypt(
-    data: &DataValue,
-    ek: &StoreEncryptionKey,
+    value: &[u8],
+    aad: &[u8],
+    ek: P256Point,
     randomness: &mut Randomness,
-) -> Result<EncryptedDataValue, Error> {
-    let encoded_data = data.value.iter().map(encode_byte);
-    let encrypted_data: Vec<(P256Point, P256Point)> = encoded_data
-        .map(|p| elgamal::encrypt(ek.0, p, randomness).unwrap())
-        .collect();
-    let encryted_data_value = EncryptedDataValue {
-        attribute_name: data.attribute_name.clone(),
-        value: encrypted_data,
-    };
-    Ok(encryted_data_value)
+) -> Result<Vec<Ciphertext>, Error> {
+    let mut bound = value.to_vec();
+    bound.extend_from_slice(&binding_tag(aad, value));
+    bound
+        .chunks(BYTES_PER_POINT)
+        .map(|chunk| Ok(elgamal::encrypt(ek, encode_chunk(chunk)?, randomness)?))
+        .collect()
 }
 
+/// Decrypt a sequence of ElGamal ciphertexts to a byte string.
+///
+/// Raises:
+/// - `CorruptedData`: If a ciphertext does not decrypt to an encoded chunk,
+///   or the byte string is not bound to the associated data `aad`.
 pub(crate) fn decrypt(
-    data: &EncryptedDataValue,
-    dk: &StoreDecryptionKey,
-) -> Result<DataValue, Error> {
-    let decrypted_data = data
-        .value
-        .iter()
-        .map(|c| elgamal::decrypt(dk.0, *c).unwrap());
-    let decoded_data = decrypted_data.map(decode_point).collect();
-    Ok(DataValue {
-        attribute_name: data.attribute_name.clone(),
-        value: decoded_data,
-    })
+    ciphertexts: &[Ciphertext],
+    aad: &[u8],
+    dk: P256Scalar,
+) -> Result<Vec<u8>, Error> {
+    let mut value = Vec::new();
+    for ciphertext in ciphertexts {
+        let point = elgamal::decrypt(dk, *ciphertext)?;
+        value.extend_from_slice(&decode_point(point)?);
+    }
+    let tag_start = value
+        .len()
+        .checked_sub(BINDING_TAG_BYTES)
+        .ok_or(Error::CorruptedData)?;
+    let tag = value.split_off(tag_start);
+    if tag != binding_tag(aad, &value) {
+        return Err(Error::CorruptedData);
+    }
+    Ok(value)
 }
 
+/// Rerandomize a sequence of ElGamal ciphertexts.
 pub(crate) fn rerandomize(
-    data: &EncryptedDataValue,
-    ek: &StoreEncryptionKey,
+    ciphertexts: &[Ciphertext],
+    ek: P256Point,
     randomness: &mut Randomness,
-) -> Result<EncryptedDataValue, Error> {
-    let rerandomized_data = data
-        .value
+) -> Result<Vec<Ciphertext>, Error> {
+    ciphertexts
         .iter()
-        .map(|c| elgamal::rerandomize(ek.0, *c, randomness).unwrap())
-        .collect();
-    Ok(EncryptedDataValue {
-        attribute_name: data.attribute_name.clone(),
-        value: rerandomized_data,
-    })
+        .map(|c| Ok(elgamal::rerandomize(ek, *c, randomness)?))
+        .collect()
 }
 
-#[test]
-fn encode_decode() {
-    assert_eq!(1u8, decode_point(encode_byte(&1u8)));
-    assert_eq!(17u8, decode_point(encode_byte(&17u8)));
-    assert_eq!(0u8, decode_point(encode_byte(&0u8)));
-    assert_eq!(u8::MAX, decode_point(encode_byte(&u8::MAX)));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        for chunk in [
+            &[][..],
+            b"a",
+            &[0u8; BYTES_PER_POINT],
+            &[0xff; BYTES_PER_POINT],
+        ] {
+            assert_eq!(decode_point(encode_chunk(chunk).unwrap()).unwrap(), chunk);
+        }
+
+        // Points that don't follow the encoding are rejected.
+        let generator = p256::p256_point_mul_base(p256::P256Scalar::one()).unwrap();
+        assert_eq!(decode_point(generator), Err(Error::CorruptedData));
+        assert_eq!(
+            decode_point(P256Point::AtInfinity),
+            Err(Error::CorruptedData)
+        );
+    }
+
+    #[test]
+    fn encrypt_decrypt() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let (dk, ek) = elgamal::generate_keys(&mut randomness).unwrap();
+
+        for len in [0, 1, BYTES_PER_POINT, BYTES_PER_POINT + 1, 100] {
+            let value: Vec<u8> = (0..len as u8).collect();
+            let encrypted = encrypt(&value, b"Address", ek, &mut randomness).unwrap();
+            assert_eq!(
+                encrypted.len(),
+                (len + BINDING_TAG_BYTES).div_ceil(BYTES_PER_POINT)
+            );
+            let rerandomized = rerandomize(&encrypted, ek, &mut randomness).unwrap();
+            assert_ne!(encrypted, rerandomized);
+            assert_eq!(decrypt(&rerandomized, b"Address", dk).unwrap(), value);
+
+            // Values are bound to their associated data.
+            assert_eq!(
+                decrypt(&rerandomized, b"Date of Birth", dk),
+                Err(Error::CorruptedData)
+            );
+        }
+
+        // Tampering with individual ciphertexts is detected.
+        let value: Vec<u8> = (0..100).collect();
+        let mut encrypted = encrypt(&value, b"Address", ek, &mut randomness).unwrap();
+        encrypted.swap(0, 1);
+        assert_eq!(
+            decrypt(&encrypted, b"Address", dk),
+            Err(Error::CorruptedData)
+        );
+        encrypted.truncate(2);
+        assert_eq!(
+            decrypt(&encrypted, b"Address", dk),
+            Err(Error::CorruptedData)
+        );
+    }
 }
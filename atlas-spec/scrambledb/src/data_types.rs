@@ -6,15 +6,32 @@
 //! values may be in plain text or encrypted and always carry with them the
 //! name of the attribute they belong to in plain text.
 
-use oprf::coprf::coprf_online::{BlindInput, BlindOutput};
-#[cfg(not(feature = "double-hpke"))]
+use oprf::coprf::{
+    coprf_online::{BlindInput, BlindOutput},
+    coprf_threshold::{PartialConversion, PartialEvaluation, PartialProof},
+    coprf_verifiable::Proof,
+};
 use p256::P256Point;
 
 /// A type for finalized pseudonyms, i.e. those which have been hardened for
 /// storage by applying a PRP.
+///
+/// Each finalized pseudonym records the epoch of the data store's PRP key
+/// that produced it, so that the PRP key can be rotated without losing
+/// access to existing pseudonyms.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(test, derive(Hash))]
-pub struct FinalizedPseudonym(pub(crate) [u8; 64]);
+pub struct FinalizedPseudonym {
+    pub(crate) epoch: u32,
+    pub(crate) value: [u8; 64],
+}
+
+impl FinalizedPseudonym {
+    /// The epoch of the PRP key this pseudonym was finalized under.
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+}
 /// A type for blinded identifiable handles.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct BlindedIdentifiableHandle(pub(crate) BlindInput);
@@ -31,28 +48,56 @@ pub struct DataValue {
     pub(crate) attribute_name: String,
 }
 
-/// An encrypted data value.
-#[cfg(feature = "double-hpke")]
+/// The public key encryption scheme used for encrypting data values.
+///
+/// ElGamal encryption is always available. Double HPKE encryption is
+/// available if the crate is built with the `double-hpke` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EncryptionScheme {
+    /// Rerandomizable ElGamal encryption of data values embedded in curve
+    /// points.
+    ElGamal,
+    /// Double HPKE encryption, where the converter adds a second layer of
+    /// encryption instead of rerandomizing.
+    #[cfg(feature = "double-hpke")]
+    DoubleHpke,
+}
+
+/// The scheme specific ciphertext of an encrypted data value.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct EncryptedDataValue {
-    /// A byte string encoding the encrypted data value.
-    pub(crate) value: Vec<u8>,
-    /// The name of the attribute the value belongs to.
-    pub(crate) attribute_name: String,
-    /// The encryption level, as understood in terms of [crate::data_transformations::double_hpke].
-    pub(crate) encryption_level: u8,
+pub enum EncryptedValue {
+    /// A vector of ElGamal ciphertexts encoding the encrypted data value.
+    ElGamal(Vec<(P256Point, P256Point)>),
+    /// A double HPKE ciphertext.
+    #[cfg(feature = "double-hpke")]
+    DoubleHpke {
+        /// A byte string encoding the encrypted data value.
+        value: Vec<u8>,
+        /// The encryption level, i.e. the number of HPKE layers.
+        encryption_level: u8,
+    },
 }
 
 /// An encrypted data value.
-#[cfg(not(feature = "double-hpke"))]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct EncryptedDataValue {
-    /// A vector of ElGamal ciphertexts encoding the encrypted data value.
-    pub(crate) value: Vec<(P256Point, P256Point)>,
+    /// The ciphertext, tagged with the encryption scheme.
+    pub(crate) value: EncryptedValue,
     /// The name of the attribute the value belongs to.
     pub(crate) attribute_name: String,
 }
 
+impl EncryptedDataValue {
+    /// The encryption scheme this value was encrypted with.
+    pub fn scheme(&self) -> EncryptionScheme {
+        match self.value {
+            EncryptedValue::ElGamal(_) => EncryptionScheme::ElGamal,
+            #[cfg(feature = "double-hpke")]
+            EncryptedValue::DoubleHpke { .. } => EncryptionScheme::DoubleHpke,
+        }
+    }
+}
+
 /// An identifiable piece of data.
 ///
 /// `PartialOrd` derive:
@@ -77,6 +122,47 @@ pub struct BlindedIdentifiableData {
     pub(crate) encrypted_data_value: EncryptedDataValue,
 }
 
+/// The converter's proof that a blinded pseudonymous handle was computed
+/// correctly from the blinded handle it received.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConversionProof {
+    /// The handle was pseudonymized using the key of the datum's attribute.
+    Pseudonymization {
+        /// The blinded identifiable handle received by the converter.
+        blinded_input: BlindInput,
+        /// The proof of correct evaluation.
+        proof: Proof,
+    },
+    /// The handle was converted from the key of the datum's attribute to a
+    /// target key.
+    Conversion {
+        /// The blinded pseudonymous handle received by the converter.
+        blinded_input: BlindInput,
+        /// The public key of the target key.
+        target_key: P256Point,
+        /// The proof of correct conversion.
+        proof: Proof,
+    },
+    /// The handle was pseudonymized by a quorum of threshold evaluators
+    /// using their shares of the key of the datum's attribute.
+    ThresholdPseudonymization {
+        /// The blinded identifiable handle received by the converter.
+        blinded_input: BlindInput,
+        /// The evaluators' partial results and proofs, which combine to the
+        /// blinded handle.
+        partials: Vec<(PartialEvaluation, PartialProof)>,
+    },
+    /// The handle was converted by a quorum of threshold evaluators from
+    /// the key of the datum's attribute to a target key.
+    ThresholdConversion {
+        /// The blinded pseudonymous handle received by the converter.
+        blinded_input: BlindInput,
+        /// The evaluators' partial results and proofs, which combine to the
+        /// blinded handle and the public key of the target key.
+        partials: Vec<(PartialConversion, PartialProof)>,
+    },
+}
+
 /// The blinded version of a pseudonymized piece of data.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct BlindedPseudonymizedData {
@@ -84,6 +170,9 @@ pub struct BlindedPseudonymizedData {
     pub(crate) blinded_handle: BlindedPseudonymizedHandle,
     /// An encrypted data value.
     pub(crate) encrypted_data_value: EncryptedDataValue,
+    /// The converter's proof for the blinded handle, if it was output by
+    /// the converter.
+    pub(crate) conversion_proof: Option<ConversionProof>,
 }
 
 /// A pseudonymized piece of data.
@@ -0,0 +1,373 @@
+//! # Erasure of Data Subjects
+//!
+//! When a data subject requests the deletion of its data, the data source
+//! only knows the subject's plain identifier, while the data lake only
+//! knows the subject's pseudonym per attribute. The pseudonyms to be erased
+//! are computed obliviously, in the same way as during a split conversion:
+//!
+//! 1. The data source blinds the identifier once for each of the requested
+//!    attributes using [`blind_erasure_request`].
+//! 2. The converter obliviously pseudonymizes the blinded request using
+//!    [`pseudonymize_blinded_table`](crate::split::pseudonymize_blinded_table),
+//!    exactly as it would any other blinded table.
+//! 3. The data lake verifies the converter's proofs and finalizes the
+//!    request using [`finalize_erasure_request`], resulting in the
+//!    subject's pseudonym per requested attribute.
+//! 4. The data lake removes all matching entries from its tables using
+//!    [`erase_from_table`], or from its persistent store using
+//!    [`LakeStore::erase`](crate::lake_store::LakeStore::erase).
+//!
+//! Neither the converter nor the data lake learn the identifier. The data
+//! lake learns which of its entries belonged to the same data subject,
+//! which is inherent to deleting them.
+//!
+//! The converter sees a blinded table under an identifier chosen by the
+//! data source, holding one entry per requested attribute and row, with
+//! encrypted placeholder values of a length chosen by the data source.
+//! Besides the data subject's row, the request holds the given number of
+//! dummy rows with random identifiers, whose pseudonyms match no stored
+//! entry. Using the table identifier, value length and number of rows of
+//! its split conversions, see [`ErasureCover`], the data source can make an
+//! erasure request look like a split conversion. An erasure request without
+//! dummy rows can be told apart from split conversions of larger tables by
+//! its size.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use hacspec_lib::Randomness;
+use oprf::coprf::coprf_setup::BlindingPublicKey;
+
+use crate::{
+    data_types::{
+        BlindedIdentifiableData, BlindedPseudonymizedData, DataValue, FinalizedPseudonym,
+        IdentifiableData, PseudonymizedData,
+    },
+    error::Error,
+    finalize::finalize_blinded_table,
+    setup::{ConverterPublicKeys, StoreContext, StoreEncryptionKey},
+    split::blind_orthonymous_table,
+    table::Table,
+};
+
+/// The length of the random identifiers of dummy rows, in bytes.
+const DUMMY_IDENTIFIER_BYTES: usize = 16;
+
+/// The shape of an erasure request as seen by the converter, see the
+/// [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErasureCover {
+    /// The identifier of the blinded table, e.g. the one used for split
+    /// conversions.
+    pub table_identifier: String,
+    /// The length of the placeholder values, e.g. the length of the data
+    /// source's values.
+    pub value_len: usize,
+    /// The number of dummy rows with random identifiers added to the
+    /// request, e.g. to match the number of rows of the data source's
+    /// split conversions.
+    pub dummy_rows: usize,
+}
+
+/// ## Blinding Erasure Requests
+///
+/// Prepare the erasure of a data subject by blinding its identifier once
+/// for each of the requested attributes, adding the dummy rows of the
+/// `cover` and shuffling the result. The request carries zero-filled
+/// placeholder values of the length given by the `cover`.
+///
+/// Inputs:
+/// - `converter_keys`: The converter's public keys
+/// - `ek_lake`: The data lake's public encryption key
+/// - `bpk_lake`: The data lake's public blinding key
+/// - `identifier`: The plain identifier of the data subject
+/// - `attributes`: The attributes to erase the data subject's data from
+/// - `cover`: The shape of the request
+/// - `randomness`: Random bytes
+///
+/// Output:
+/// A table of blinded identifiable data.
+///
+/// Raises:
+/// - `InvalidInput`: If no attributes are given or an attribute is given
+///   twice.
+pub fn blind_erasure_request(
+    converter_keys: &ConverterPublicKeys,
+    ek_lake: &StoreEncryptionKey,
+    bpk_lake: BlindingPublicKey,
+    identifier: &str,
+    attributes: &[&str],
+    cover: &ErasureCover,
+    randomness: &mut Randomness,
+) -> Result<Table<BlindedIdentifiableData>, Error> {
+    if attributes.is_empty()
+        || attributes
+            .iter()
+            .enumerate()
+            .any(|(i, attribute)| attributes[..i].contains(attribute))
+    {
+        return Err(Error::InvalidInput);
+    }
+
+    let mut identifiers = vec![identifier.to_string()];
+    for _ in 0..cover.dummy_rows {
+        identifiers.push(
+            randomness
+                .bytes(DUMMY_IDENTIFIER_BYTES)?
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        );
+    }
+    let request = identifiers
+        .iter()
+        .flat_map(|identifier| {
+            attributes.iter().map(|attribute| IdentifiableData {
+                handle: identifier.clone(),
+                data_value: DataValue {
+                    value: vec![0; cover.value_len],
+                    attribute_name: attribute.to_string(),
+                },
+            })
+        })
+        .collect();
+
+    blind_orthonymous_table(
+        converter_keys,
+        ek_lake,
+        bpk_lake,
+        Table::new(cover.table_identifier.clone(), request),
+        randomness,
+    )
+}
+
+/// ## Finalization of Erasure Requests
+///
+/// Finalize an erasure request pseudonymized by the converter by verifying
+/// the converter's proofs and finalizing the resulting pseudonyms.
+///
+/// Inputs:
+/// - `lake_context`: The data lake's pseudonymization context
+/// - `converter_keys`: The converter's public keys
+/// - `table`: The pseudonymized erasure request
+/// - `randomness`: Random bytes
+///
+/// Output:
+/// The pseudonyms to erase per requested attribute, i.e. the data
+/// subject's and those of the dummy rows. The latter match no stored entry.
+///
+/// Raises:
+/// - `VerificationError`: If a proof of the converter does not verify.
+/// - `InvalidInput`: If the request is empty.
+pub fn finalize_erasure_request(
+    lake_context: &StoreContext,
+    converter_keys: &ConverterPublicKeys,
+    table: Table<BlindedPseudonymizedData>,
+    randomness: &mut Randomness,
+) -> Result<BTreeMap<String, BTreeSet<FinalizedPseudonym>>, Error> {
+    let finalized = finalize_blinded_table(lake_context, converter_keys, table, randomness)?;
+
+    let mut pseudonyms = BTreeMap::<_, BTreeSet<_>>::new();
+    for entry in finalized.into_data() {
+        pseudonyms
+            .entry(entry.data_value.attribute_name)
+            .or_default()
+            .insert(entry.handle);
+    }
+    if pseudonyms.is_empty() {
+        return Err(Error::InvalidInput);
+    }
+
+    Ok(pseudonyms)
+}
+
+/// ## Erasing Pseudonymous Tables
+///
+/// Remove all entries of a data subject from a table of pseudonymous data
+/// values. Entries finalized under a previous PRP key of the data lake are
+/// compared after moving them to the current key.
+///
+/// Inputs:
+/// - `lake_context`: The data lake's pseudonymization context
+/// - `pseudonyms`: The pseudonyms to erase per attribute, as output by
+///   [`finalize_erasure_request`]
+/// - `table`: A table of pseudonymous data values
+///
+/// Output:
+/// The table without the data subject's entries.
+///
+/// Raises:
+/// - `InvalidInput`: If the data lake holds no PRP key for the epoch of an
+///   entry.
+pub fn erase_from_table(
+    lake_context: &StoreContext,
+    pseudonyms: &BTreeMap<String, BTreeSet<FinalizedPseudonym>>,
+    table: Table<PseudonymizedData>,
+) -> Result<Table<PseudonymizedData>, Error> {
+    let identifier = table.identifier().to_string();
+    let mut remaining = Vec::with_capacity(table.data().len());
+    for entry in table.into_data() {
+        let erased = match pseudonyms.get(&entry.data_value.attribute_name) {
+            Some(pseudonyms) => pseudonyms.contains(&lake_context.rekey_pseudonym(entry.handle)?),
+            None => false,
+        };
+        if !erased {
+            remaining.push(entry);
+        }
+    }
+
+    Ok(Table::new(identifier, remaining))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        serialization::WireFormat,
+        setup::ConverterContext,
+        split::pseudonymize_blinded_table,
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_erasure() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let mut lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let plain_table = generate_plain_table();
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            plain_table.clone(),
+            &mut randomness,
+        )
+        .unwrap();
+        let split_entries: Vec<(String, usize)> = blind_table
+            .data()
+            .iter()
+            .map(|entry| {
+                (
+                    entry.encrypted_data_value.attribute_name.clone(),
+                    entry.to_bytes().len(),
+                )
+            })
+            .collect();
+        let converted_table = pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let lake_table = finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            converted_table,
+            &mut randomness,
+        )
+        .unwrap();
+
+        // Erasure also covers entries finalized under a previous PRP key.
+        lake_context.rotate_prp_key(&mut randomness).unwrap();
+
+        assert_eq!(
+            blind_erasure_request(
+                &converter_keys,
+                &lake_ek,
+                lake_bpk,
+                "Alice",
+                &["Address", "Address"],
+                &ErasureCover::default(),
+                &mut randomness,
+            )
+            .unwrap_err(),
+            Error::InvalidInput
+        );
+
+        let cover = ErasureCover {
+            table_identifier: plain_table.identifier().into(),
+            value_len: b"TestData1".len(),
+            dummy_rows: 1,
+        };
+        let request = blind_erasure_request(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            "Alice",
+            &["Address", "Favorite Color"],
+            &cover,
+            &mut randomness,
+        )
+        .unwrap();
+
+        // The request looks like the split conversion of the same table,
+        // which has two rows.
+        assert_eq!(request.identifier(), plain_table.identifier());
+        assert_eq!(request.data().len(), 2 * 2);
+        for entry in request.data() {
+            assert!(split_entries.contains(&(
+                entry.encrypted_data_value.attribute_name.clone(),
+                entry.to_bytes().len()
+            )));
+        }
+        let pseudonymized_request = pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            request,
+            &mut randomness,
+        )
+        .unwrap();
+
+        // The converter's proofs are verified.
+        let other_converter = ConverterContext::setup(&mut randomness).unwrap();
+        assert_eq!(
+            finalize_erasure_request(
+                &lake_context,
+                &converter_public_keys(&other_converter),
+                pseudonymized_request.clone(),
+                &mut randomness,
+            )
+            .unwrap_err(),
+            Error::VerificationError
+        );
+
+        let pseudonyms = finalize_erasure_request(
+            &lake_context,
+            &converter_keys,
+            pseudonymized_request,
+            &mut randomness,
+        )
+        .unwrap();
+        assert_eq!(pseudonyms.len(), 2);
+        assert!(pseudonyms.values().all(|pseudonyms| pseudonyms.len() == 2));
+
+        let erased_table = erase_from_table(&lake_context, &pseudonyms, lake_table).unwrap();
+
+        let is_erased = |entry: &IdentifiableData| {
+            entry.handle == "Alice"
+                && (entry.data_value.attribute_name == "Address"
+                    || entry.data_value.attribute_name == "Favorite Color")
+        };
+        let mut expected: Vec<DataValue> = plain_table
+            .data()
+            .iter()
+            .filter(|entry| !is_erased(entry))
+            .map(|entry| entry.data_value.clone())
+            .collect();
+        let mut remaining: Vec<DataValue> = erased_table
+            .data()
+            .iter()
+            .map(|entry| entry.data_value.clone())
+            .collect();
+        expected.sort();
+        remaining.sort();
+        assert_eq!(remaining, expected);
+        assert!(expected.len() < plain_table.data().len());
+    }
+}
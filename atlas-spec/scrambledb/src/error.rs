@@ -3,6 +3,11 @@ pub enum Error {
     RandomnessError,
     CorruptedData,
     InvalidInput,
+    CommunicationError,
+    VerificationError,
+    PolicyViolation,
+    StorageError,
+    InternalError,
 }
 
 impl From<oprf::Error> for Error {
@@ -14,6 +19,8 @@ impl From<oprf::Error> for Error {
             oprf::Error::HashToCurveError => Self::CorruptedData,
             oprf::Error::ElgamalError => Self::RandomnessError,
             oprf::Error::RandomnessError => Self::RandomnessError,
+            oprf::Error::InvalidProofError => Self::VerificationError,
+            oprf::Error::InverseError => Self::CorruptedData,
         }
     }
 }
@@ -25,10 +32,21 @@ impl From<libcrux::hpke::errors::HpkeError> for Error {
     }
 }
 
+#[cfg(feature = "csv")]
+impl From<csv::Error> for Error {
+    fn from(value: csv::Error) -> Self {
+        match value.kind() {
+            csv::ErrorKind::Io(_) => Self::StorageError,
+            _ => Self::CorruptedData,
+        }
+    }
+}
+
 impl From<hacspec_lib::Error> for Error {
     fn from(value: hacspec_lib::Error) -> Self {
         match value {
             hacspec_lib::Error::InsufficientRandomness => Self::RandomnessError,
+            hacspec_lib::Error::EntropyUnavailable => Self::RandomnessError,
         }
     }
 }
@@ -58,3 +76,11 @@ impl From<elgamal::Error> for Error {
         }
     }
 }
+
+// I/O errors of local files. Failures of connections to other parties are
+// mapped to `CommunicationError` where they occur.
+impl From<std::io::Error> for Error {
+    fn from(_value: std::io::Error) -> Self {
+        Self::StorageError
+    }
+}
@@ -0,0 +1,829 @@
+//! # Threshold Evaluator Service
+//!
+//! A [threshold converter](crate::threshold) whose evaluators are run by
+//! different parties collects their partial results over the wire. Each
+//! evaluator is run as a separate service holding its
+//! [`ThresholdEvaluatorContext`], and the converter talks to it using
+//! [`EvaluatorClient`], which implements [`ThresholdEvaluator`]. Messages
+//! are framed as for the [converter service](crate::converter_service):
+//!
+//! ``` text
+//! EvaluatorRequest = VERSION || TAG_EVALUATOR_REQUEST || kind || arguments
+//! EvaluatorResponse = VERSION || TAG_EVALUATOR_RESPONSE || status || kind || result
+//! ```
+//!
+//! An info request (`kind = EVALUATOR_INFO`) has no arguments and is
+//! answered with the evaluator's `u32` index, threshold and number of
+//! evaluators, followed by the evaluators' common blinding key share. A
+//! public key request (`kind = EVALUATOR_PUBLIC_KEY_SHARE`) carries an
+//! attribute name and is answered with the public key of the evaluator's
+//! share of the attribute's pseudonymization key.
+//!
+//! An evaluation request (`kind = EVALUATOR_EVALUATE`) carries the
+//! attribute name, the receiver's blinding public key and the blinded
+//! input, a conversion request (`kind = EVALUATOR_CONVERT`) additionally
+//! starts with the public key of the attribute's pseudonymization key and
+//! carries the conversion target after the attribute name. They are
+//! answered with the evaluator's partial result and partial proof, encoded
+//! as in [threshold conversion proofs](crate::serialization).
+//!
+//! The response carries a result if `status` is `STATUS_OK`, otherwise
+//! `status` encodes the error that occurred, as for the converter service,
+//! and no result is included. The converter checks every partial result
+//! against its proof before combining it.
+//!
+//! An evaluator answers every request it receives, so it must only accept
+//! connections from the converter, e.g. on a private network or an
+//! authenticated channel.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    sync::Mutex,
+};
+
+use hacspec_lib::Randomness;
+use oprf::coprf::{
+    coprf_online::{BlindInput, BlindOutput},
+    coprf_setup::BlindingPublicKey,
+    coprf_threshold::{
+        verify_partial_conversion, verify_partial_evaluation, PartialConversion, PartialEvaluation,
+        PartialProof, PartyIndex, ThresholdEvaluatorContext,
+    },
+};
+use p256::P256Point;
+
+use crate::{
+    converter_service::{error_status, read_message, status_error, write_message, STATUS_OK},
+    error::Error,
+    serialization::{
+        put_byte_string, put_ciphertext, put_partial_proof, put_point, put_scalar, put_u32, Reader,
+        WireFormat,
+    },
+    threshold::ThresholdEvaluator,
+};
+
+const EVALUATOR_INFO: u8 = 1;
+const EVALUATOR_PUBLIC_KEY_SHARE: u8 = 2;
+const EVALUATOR_EVALUATE: u8 = 3;
+const EVALUATOR_CONVERT: u8 = 4;
+
+/// A request to a threshold evaluator.
+pub enum EvaluatorRequest {
+    /// Retrieve the evaluator's parameters and blinding key share.
+    Info,
+    /// Retrieve the public key of the evaluator's share of an attribute's
+    /// pseudonymization key.
+    PublicKeyShare {
+        /// The attribute name
+        attribute: String,
+    },
+    /// Partially evaluate a blinded input as part of a split conversion.
+    Evaluate {
+        /// The attribute name
+        attribute: Vec<u8>,
+        /// The receiver's public blinding key
+        bpk: BlindingPublicKey,
+        /// The blinded input
+        blinded_input: BlindInput,
+    },
+    /// Partially convert a blinded PRF output as part of a join conversion.
+    Convert {
+        /// The public key of the attribute's pseudonymization key
+        public_key_from: P256Point,
+        /// The attribute name
+        attribute: Vec<u8>,
+        /// The conversion target
+        conversion_target: Vec<u8>,
+        /// The receiver's public blinding key
+        bpk: BlindingPublicKey,
+        /// The blinded PRF output
+        blinded_input: BlindOutput,
+    },
+}
+
+/// A threshold evaluator's result for an [`EvaluatorRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvaluatorReply {
+    /// The evaluator's parameters and blinding key share.
+    Info {
+        /// The index of the evaluator
+        index: PartyIndex,
+        /// The number of evaluators required for blind evaluation
+        threshold: u32,
+        /// The total number of evaluators
+        parties: u32,
+        /// The public part of the evaluators' common blinding key share
+        blinding_key_share: BlindingPublicKey,
+    },
+    /// The public key of the evaluator's key share.
+    PublicKeyShare(P256Point),
+    /// The evaluator's partial evaluation result and proof.
+    Evaluation(PartialEvaluation, PartialProof),
+    /// The evaluator's partial conversion result and proof.
+    Conversion(PartialConversion, PartialProof),
+}
+
+/// A threshold evaluator's response to an [`EvaluatorRequest`].
+pub type EvaluatorResponse = Result<EvaluatorReply, Error>;
+
+impl WireFormat for EvaluatorRequest {
+    const TAG: u8 = 0x22;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        match self {
+            EvaluatorRequest::Info => out.push(EVALUATOR_INFO),
+            EvaluatorRequest::PublicKeyShare { attribute } => {
+                out.push(EVALUATOR_PUBLIC_KEY_SHARE);
+                put_byte_string(out, attribute.as_bytes());
+            }
+            EvaluatorRequest::Evaluate {
+                attribute,
+                bpk,
+                blinded_input,
+            } => {
+                out.push(EVALUATOR_EVALUATE);
+                put_byte_string(out, attribute);
+                put_point(out, bpk);
+                put_ciphertext(out, blinded_input);
+            }
+            EvaluatorRequest::Convert {
+                public_key_from,
+                attribute,
+                conversion_target,
+                bpk,
+                blinded_input,
+            } => {
+                out.push(EVALUATOR_CONVERT);
+                put_point(out, public_key_from);
+                put_byte_string(out, attribute);
+                put_byte_string(out, conversion_target);
+                put_point(out, bpk);
+                put_ciphertext(out, blinded_input);
+            }
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.u8()? {
+            EVALUATOR_INFO => Ok(EvaluatorRequest::Info),
+            EVALUATOR_PUBLIC_KEY_SHARE => Ok(EvaluatorRequest::PublicKeyShare {
+                attribute: reader.string()?,
+            }),
+            EVALUATOR_EVALUATE => Ok(EvaluatorRequest::Evaluate {
+                attribute: reader.byte_string()?.to_vec(),
+                bpk: reader.point()?,
+                blinded_input: reader.ciphertext()?,
+            }),
+            EVALUATOR_CONVERT => Ok(EvaluatorRequest::Convert {
+                public_key_from: reader.point()?,
+                attribute: reader.byte_string()?.to_vec(),
+                conversion_target: reader.byte_string()?.to_vec(),
+                bpk: reader.point()?,
+                blinded_input: reader.ciphertext()?,
+            }),
+            _ => Err(Error::CorruptedData),
+        }
+    }
+}
+
+impl WireFormat for EvaluatorResponse {
+    const TAG: u8 = 0x23;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        match self {
+            Ok(reply) => {
+                out.push(STATUS_OK);
+                match reply {
+                    EvaluatorReply::Info {
+                        index,
+                        threshold,
+                        parties,
+                        blinding_key_share,
+                    } => {
+                        out.push(EVALUATOR_INFO);
+                        put_u32(out, *index);
+                        put_u32(out, *threshold);
+                        put_u32(out, *parties);
+                        put_point(out, blinding_key_share);
+                    }
+                    EvaluatorReply::PublicKeyShare(public_key) => {
+                        out.push(EVALUATOR_PUBLIC_KEY_SHARE);
+                        put_point(out, public_key);
+                    }
+                    EvaluatorReply::Evaluation(partial, proof) => {
+                        out.push(EVALUATOR_EVALUATE);
+                        put_u32(out, partial.index);
+                        put_ciphertext(out, &partial.output);
+                        put_partial_proof(out, proof);
+                    }
+                    EvaluatorReply::Conversion(partial, proof) => {
+                        out.push(EVALUATOR_CONVERT);
+                        put_u32(out, partial.index);
+                        put_scalar(out, &partial.mask_share);
+                        put_ciphertext(out, &partial.output);
+                        put_partial_proof(out, proof);
+                    }
+                }
+            }
+            Err(error) => out.push(error_status(error)),
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.u8()? {
+            STATUS_OK => (),
+            status => return Ok(Err(status_error(status)?)),
+        }
+        match reader.u8()? {
+            EVALUATOR_INFO => Ok(Ok(EvaluatorReply::Info {
+                index: reader.u32()?,
+                threshold: reader.u32()?,
+                parties: reader.u32()?,
+                blinding_key_share: reader.point()?,
+            })),
+            EVALUATOR_PUBLIC_KEY_SHARE => Ok(Ok(EvaluatorReply::PublicKeyShare(reader.point()?))),
+            EVALUATOR_EVALUATE => {
+                let partial = PartialEvaluation {
+                    index: reader.u32()?,
+                    output: reader.ciphertext()?,
+                };
+                Ok(Ok(EvaluatorReply::Evaluation(
+                    partial,
+                    reader.partial_proof()?,
+                )))
+            }
+            EVALUATOR_CONVERT => {
+                let partial = PartialConversion {
+                    index: reader.u32()?,
+                    mask_share: reader.scalar()?,
+                    output: reader.ciphertext()?,
+                };
+                Ok(Ok(EvaluatorReply::Conversion(
+                    partial,
+                    reader.partial_proof()?,
+                )))
+            }
+            _ => Err(Error::CorruptedData),
+        }
+    }
+}
+
+/// The threshold evaluator service, answering requests using the
+/// evaluator's context.
+pub struct EvaluatorService {
+    context: ThresholdEvaluatorContext,
+}
+
+impl EvaluatorService {
+    /// Start an evaluator service using the given evaluator context.
+    pub fn new(context: ThresholdEvaluatorContext) -> Self {
+        Self { context }
+    }
+
+    /// Handle a single request.
+    ///
+    /// Inputs:
+    /// - `request`: A request for the evaluator's parameters, a public key
+    ///   share or a partial result
+    /// - `randomness`: Random bytes
+    ///
+    /// Output:
+    /// The requested result or the error that occurred computing it.
+    pub fn handle(
+        &self,
+        request: EvaluatorRequest,
+        randomness: &mut Randomness,
+    ) -> EvaluatorResponse {
+        let context = &self.context;
+        match request {
+            EvaluatorRequest::Info => Ok(EvaluatorReply::Info {
+                index: context.index(),
+                threshold: context.threshold(),
+                parties: context.parties(),
+                blinding_key_share: context.blinding_key_share()?,
+            }),
+            EvaluatorRequest::PublicKeyShare { attribute } => Ok(EvaluatorReply::PublicKeyShare(
+                context.public_key_share(&attribute)?,
+            )),
+            EvaluatorRequest::Evaluate {
+                attribute,
+                bpk,
+                blinded_input,
+            } => {
+                let (partial, proof) =
+                    context.evaluate(&attribute, bpk, blinded_input, randomness)?;
+                Ok(EvaluatorReply::Evaluation(partial, proof))
+            }
+            EvaluatorRequest::Convert {
+                public_key_from,
+                attribute,
+                conversion_target,
+                bpk,
+                blinded_input,
+            } => {
+                let (partial, proof) = context.convert(
+                    public_key_from,
+                    &attribute,
+                    &conversion_target,
+                    bpk,
+                    blinded_input,
+                    randomness,
+                )?;
+                Ok(EvaluatorReply::Conversion(partial, proof))
+            }
+        }
+    }
+
+    /// Answer requests on a connection until it is closed by the converter.
+    ///
+    /// Inputs:
+    /// - `stream`: A connection to the converter
+    /// - `randomness`: Provides fresh randomness for each received request
+    ///
+    /// Raises:
+    /// - `CommunicationError`: If reading from or writing to the connection
+    ///   fails.
+    /// - `CorruptedData`: If a message exceeds
+    ///   [`MAX_MESSAGE_BYTES`](crate::converter_service::MAX_MESSAGE_BYTES).
+    pub fn serve<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        mut randomness: impl FnMut(&EvaluatorRequest) -> Randomness,
+    ) -> Result<(), Error> {
+        while let Some(message) = read_message(stream)? {
+            let response = EvaluatorRequest::from_bytes(&message).and_then(|request| {
+                let mut randomness = randomness(&request);
+                self.handle(request, &mut randomness)
+            });
+            write_message(stream, &response.to_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// A client for a threshold evaluator service, used by the converter to
+/// collect the evaluator's partial results.
+///
+/// The client opens connections to the evaluator on demand, such that
+/// partial results for several entries can be requested concurrently, and
+/// keeps them open for further requests.
+pub struct EvaluatorClient<S> {
+    connect: Box<dyn Fn() -> Result<S, Error> + Send + Sync>,
+    connections: Mutex<Vec<S>>,
+    index: PartyIndex,
+    threshold: u32,
+    parties: u32,
+    blinding_key_share: BlindingPublicKey,
+    public_key_shares: Mutex<BTreeMap<String, P256Point>>,
+}
+
+impl<S: Read + Write + Send> EvaluatorClient<S> {
+    /// Connect to an evaluator service and retrieve its parameters.
+    ///
+    /// Inputs:
+    /// - `connect`: Establishes a new connection to the evaluator service
+    ///
+    /// Raises:
+    /// - `CommunicationError`: If connecting to the evaluator fails.
+    /// - `CorruptedData`: If the evaluator's response is malformed.
+    pub fn connect(
+        connect: impl Fn() -> Result<S, Error> + Send + Sync + 'static,
+    ) -> Result<Self, Error> {
+        let mut stream = connect()?;
+        let EvaluatorReply::Info {
+            index,
+            threshold,
+            parties,
+            blinding_key_share,
+        } = exchange(&mut stream, &EvaluatorRequest::Info)??
+        else {
+            return Err(Error::CorruptedData);
+        };
+        if threshold == 0 || index == 0 || index > parties || threshold > parties {
+            return Err(Error::CorruptedData);
+        }
+        Ok(Self {
+            connect: Box::new(connect),
+            connections: Mutex::new(vec![stream]),
+            index,
+            threshold,
+            parties,
+            blinding_key_share,
+            public_key_shares: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Send a request to the evaluator and wait for the response.
+    ///
+    /// Raises:
+    /// - `CommunicationError`: If connecting to the evaluator, or reading
+    ///   from or writing to the connection fails. The connection is closed
+    ///   in this case.
+    /// - `CorruptedData`: If the evaluator's response is malformed.
+    /// - Any error reported by the evaluator.
+    pub fn request(&self, request: &EvaluatorRequest) -> EvaluatorResponse {
+        let pooled = self
+            .connections
+            .lock()
+            .map_err(|_| Error::InternalError)?
+            .pop();
+        let mut stream = match pooled {
+            Some(stream) => stream,
+            None => (self.connect)()?,
+        };
+        let response = exchange(&mut stream, request)?;
+        self.connections
+            .lock()
+            .map_err(|_| Error::InternalError)?
+            .push(stream);
+        response
+    }
+}
+
+/// Send a request on a connection and wait for the response.
+///
+/// Raises:
+/// - `CommunicationError`: If reading from or writing to the connection
+///   fails.
+/// - `CorruptedData`: If the response is malformed.
+fn exchange(
+    stream: &mut (impl Read + Write),
+    request: &EvaluatorRequest,
+) -> Result<EvaluatorResponse, Error> {
+    write_message(stream, &request.to_bytes())?;
+    let response = read_message(stream)?.ok_or(Error::CommunicationError)?;
+    EvaluatorResponse::from_bytes(&response)
+}
+
+impl<S: Read + Write + Send> ThresholdEvaluator for EvaluatorClient<S> {
+    fn index(&self) -> PartyIndex {
+        self.index
+    }
+
+    fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    fn parties(&self) -> u32 {
+        self.parties
+    }
+
+    fn blinding_key_share(&self) -> Result<BlindingPublicKey, Error> {
+        Ok(self.blinding_key_share)
+    }
+
+    fn public_key_share(&self, attribute: &str) -> Result<P256Point, Error> {
+        let cached = self
+            .public_key_shares
+            .lock()
+            .map_err(|_| Error::InternalError)?
+            .get(attribute)
+            .copied();
+        if let Some(public_key) = cached {
+            return Ok(public_key);
+        }
+        let EvaluatorReply::PublicKeyShare(public_key) =
+            self.request(&EvaluatorRequest::PublicKeyShare {
+                attribute: attribute.to_string(),
+            })?
+        else {
+            return Err(Error::CorruptedData);
+        };
+        self.public_key_shares
+            .lock()
+            .map_err(|_| Error::InternalError)?
+            .insert(attribute.to_string(), public_key);
+        Ok(public_key)
+    }
+
+    /// Request the evaluator's partial evaluation result. The evaluator
+    /// draws its own randomness.
+    ///
+    /// Raises:
+    /// - `VerificationError`: If the partial result is not the evaluator's,
+    ///   was not computed using the evaluator's key share of the attribute,
+    ///   see [`ThresholdEvaluator::public_key_share`], or its proof does not
+    ///   verify.
+    fn evaluate(
+        &self,
+        attribute: &[u8],
+        bpk: BlindingPublicKey,
+        blinded_input: BlindInput,
+        _randomness: &mut Randomness,
+    ) -> Result<(PartialEvaluation, PartialProof), Error> {
+        let public_key_share = self
+            .public_key_share(std::str::from_utf8(attribute).map_err(|_| Error::InvalidInput)?)?;
+        let EvaluatorReply::Evaluation(partial, proof) =
+            self.request(&EvaluatorRequest::Evaluate {
+                attribute: attribute.to_vec(),
+                bpk,
+                blinded_input,
+            })?
+        else {
+            return Err(Error::CorruptedData);
+        };
+        if partial.index != self.index || proof.public_key != public_key_share {
+            return Err(Error::VerificationError);
+        }
+        verify_partial_evaluation(
+            self.blinding_key_share,
+            bpk,
+            blinded_input,
+            &partial,
+            &proof,
+        )?;
+        Ok((partial, proof))
+    }
+
+    /// Request the evaluator's partial conversion result. The evaluator
+    /// draws its own randomness.
+    ///
+    /// Unlike for evaluation, the public key `T_i = d_i * K_from` of the
+    /// proof depends on the evaluator's share of a fresh mask, so it cannot
+    /// be compared to a known key of the evaluator. A partial result using
+    /// wrong shares is only detected once the receiver verifies the
+    /// combined proof, see
+    /// [`combine_conversion_public_keys`](oprf::coprf::coprf_threshold::combine_conversion_public_keys).
+    ///
+    /// Raises:
+    /// - `VerificationError`: If the partial result is not the evaluator's
+    ///   or its proof does not verify.
+    fn convert(
+        &self,
+        public_key_from: P256Point,
+        attribute: &[u8],
+        conversion_target: &[u8],
+        bpk: BlindingPublicKey,
+        blinded_input: BlindOutput,
+        _randomness: &mut Randomness,
+    ) -> Result<(PartialConversion, PartialProof), Error> {
+        let EvaluatorReply::Conversion(partial, proof) =
+            self.request(&EvaluatorRequest::Convert {
+                public_key_from,
+                attribute: attribute.to_vec(),
+                conversion_target: conversion_target.to_vec(),
+                bpk,
+                blinded_input,
+            })?
+        else {
+            return Err(Error::CorruptedData);
+        };
+        if partial.index != self.index {
+            return Err(Error::VerificationError);
+        }
+        verify_partial_conversion(
+            public_key_from,
+            self.blinding_key_share,
+            bpk,
+            blinded_input,
+            &partial,
+            &proof,
+        )?;
+        Ok((partial, proof))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        net::{TcpListener, TcpStream},
+        sync::atomic::{AtomicBool, Ordering},
+        sync::Arc,
+        thread,
+    };
+
+    use oprf::coprf::{coprf_setup::CoPRFEvaluatorContext, coprf_threshold::deal};
+
+    use crate::{
+        data_types::BlindedPseudonymizedData,
+        finalize::finalize_blinded_table,
+        join::{blind_pseudonymous_table, convert_blinded_table},
+        setup::{ConverterContext, StoreContext},
+        split::{blind_orthonymous_table, pseudonymize_blinded_table},
+        table::Table,
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    use super::*;
+
+    /// Run an evaluator service on a local port, answering every
+    /// connection in its own thread.
+    fn spawn_evaluator(context: ThresholdEvaluatorContext) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let service = Arc::new(EvaluatorService::new(context));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let service = Arc::clone(&service);
+                let mut stream = stream.unwrap();
+                thread::spawn(move || {
+                    service
+                        .serve(&mut stream, |_| Randomness::from_entropy().unwrap())
+                        .unwrap()
+                });
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn test_evaluator_service() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let clients = deal(&dealer, 2, 3)
+            .unwrap()
+            .into_iter()
+            .map(|evaluator| {
+                let address = spawn_evaluator(evaluator);
+                EvaluatorClient::connect(move || {
+                    TcpStream::connect(&address).map_err(|_| Error::CommunicationError)
+                })
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let remote_context = ConverterContext::threshold(clients).unwrap();
+        let local_context = ConverterContext::threshold(deal(&dealer, 2, 3).unwrap()).unwrap();
+
+        // The remote evaluators have the same keys as local ones.
+        let converter_keys = converter_public_keys(&remote_context);
+        assert_eq!(converter_keys, converter_public_keys(&local_context));
+
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+
+        // The combined partial results verify and yield the same pseudonyms
+        // as the local evaluators'.
+        let pseudonyms = |table: &Table<BlindedPseudonymizedData>| {
+            table
+                .data()
+                .iter()
+                .map(|entry| {
+                    lake_context
+                        .finalize_pseudonym(entry.blinded_handle)
+                        .unwrap()
+                })
+                .collect::<HashSet<_>>()
+        };
+        let remote_result = pseudonymize_blinded_table(
+            &remote_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table.clone(),
+            &mut randomness,
+        )
+        .unwrap();
+        let local_result = pseudonymize_blinded_table(
+            &local_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            &mut randomness,
+        )
+        .unwrap();
+        assert_eq!(pseudonyms(&remote_result), pseudonyms(&local_result));
+        let lake_table = finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            remote_result,
+            &mut randomness,
+        )
+        .unwrap();
+
+        // Join conversions are combined from `2t - 1` remote evaluators.
+        let processor_context = StoreContext::setup(&mut randomness).unwrap();
+        let (ek_processor, bpk_processor) = processor_context.public_keys();
+        let blind_tables = blind_pseudonymous_table(
+            &lake_context,
+            &converter_keys,
+            bpk_processor,
+            &ek_processor,
+            lake_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let converted = convert_blinded_table(
+            &remote_context,
+            bpk_processor,
+            &ek_processor,
+            blind_tables,
+            &mut randomness,
+        )
+        .unwrap();
+        let joined = finalize_blinded_table(
+            &processor_context,
+            &converter_keys,
+            converted,
+            &mut randomness,
+        )
+        .unwrap();
+        let handles = joined
+            .data()
+            .iter()
+            .map(|entry| entry.handle)
+            .collect::<HashSet<_>>();
+        assert_eq!(handles.len(), 2);
+
+        // Remote evaluators cannot derive update tokens.
+        assert!(matches!(
+            remote_context.update_token(&local_context, "Address"),
+            Err(Error::InvalidInput)
+        ));
+    }
+
+    #[test]
+    fn test_evaluator_failover() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let local_context = ConverterContext::threshold(deal(&dealer, 2, 4).unwrap()).unwrap();
+        let converter_keys = converter_public_keys(&local_context);
+        let mut evaluators = deal(&dealer, 2, 4).unwrap().into_iter();
+
+        // The first evaluator becomes unreachable after connecting.
+        let reachable = Arc::new(AtomicBool::new(true));
+        let address = spawn_evaluator(evaluators.next().unwrap());
+        let unreachable = EvaluatorClient::connect({
+            let reachable = Arc::clone(&reachable);
+            move || {
+                if !reachable.load(Ordering::SeqCst) {
+                    return Err(Error::CommunicationError);
+                }
+                TcpStream::connect(&address).map_err(|_| Error::CommunicationError)
+            }
+        })
+        .unwrap();
+        reachable.store(false, Ordering::SeqCst);
+        unreachable.connections.lock().unwrap().clear();
+
+        // The second evaluator evaluates using its shares of a deal for five
+        // parties, while its known key shares are those of the honest
+        // evaluator.
+        let honest = evaluators.next().unwrap();
+        let address = spawn_evaluator(deal(&dealer, 2, 5).unwrap().remove(1));
+        let mut cheating = EvaluatorClient::connect(move || {
+            TcpStream::connect(&address).map_err(|_| Error::CommunicationError)
+        })
+        .unwrap();
+        cheating.parties = 4;
+
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+        for entry in blind_table.data() {
+            let attribute = &entry.encrypted_data_value.attribute_name;
+            cheating.public_key_shares.lock().unwrap().insert(
+                attribute.clone(),
+                ThresholdEvaluator::public_key_share(&honest, attribute).unwrap(),
+            );
+        }
+
+        let mut clients = vec![unreachable, cheating];
+        clients.extend(evaluators.map(|evaluator| {
+            let address = spawn_evaluator(evaluator);
+            EvaluatorClient::connect(move || {
+                TcpStream::connect(&address).map_err(|_| Error::CommunicationError)
+            })
+            .unwrap()
+        }));
+        let remote_context = ConverterContext::threshold(clients).unwrap();
+
+        // The failing evaluators are skipped and the remaining quorum yields
+        // the same pseudonyms as the local evaluators.
+        let pseudonyms = |context: &ConverterContext, randomness: &mut Randomness| {
+            let table = pseudonymize_blinded_table(
+                context,
+                lake_bpk,
+                &lake_ek,
+                blind_table.clone(),
+                randomness,
+            )
+            .unwrap();
+            finalize_blinded_table(&lake_context, &converter_keys, table, randomness)
+                .unwrap()
+                .data()
+                .iter()
+                .map(|entry| entry.handle)
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(
+            pseudonyms(&remote_context, &mut randomness),
+            pseudonyms(&local_context, &mut randomness)
+        );
+    }
+}
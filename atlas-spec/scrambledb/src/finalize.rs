@@ -1,8 +1,10 @@
+use hacspec_lib::Randomness;
+
 use crate::{
-    data_transformations::finalize_blinded_datum,
+    data_transformations::{finalize_blinded_datum, verify_blinded_datum},
     data_types::{BlindedPseudonymizedData, PseudonymizedData},
     error::Error,
-    setup::StoreContext,
+    setup::{ConverterPublicKeys, StoreContext},
     table::Table,
 };
 
@@ -12,26 +14,178 @@ use crate::{
 /// i.e. whether they are long term storage pseudonyms at the Data Lake or
 /// join pseudonyms at a Data Processor.
 ///
-/// Finalize a table of blinded pseudonymized data values by applying the
-/// finalization operation on each entry and shuffling the result:
+/// Finalize a table of blinded pseudonymized data values by verifying the
+/// converter's proofs, applying the finalization operation on each entry and
+/// shuffling the result.
+///
+/// All entries of the table must have been produced by the same operation,
+/// i.e. either pseudonymized using the keys of their attributes, or
+/// converted to the same target key. This prevents the converter from
+/// linking or separating entries by using different keys.
 ///
 /// Inputs:
 /// - `store_context`: The data store's pseudonymization context
+/// - `converter_keys`: The converter's public keys
 /// - `table`: A table of blinded pseudonymized data values
+/// - `randomness`: Random bytes
 ///
 /// Output:
 /// A table of pseudonymized data values.
+///
+/// Raises:
+/// - `VerificationError`: If a proof of the converter does not verify or the
+///   entries were not all produced by the same operation.
 pub fn finalize_blinded_table(
     store_context: &StoreContext,
+    converter_keys: &ConverterPublicKeys,
     table: Table<BlindedPseudonymizedData>,
+    randomness: &mut Randomness,
 ) -> Result<Table<PseudonymizedData>, Error> {
-    let mut pseudonymized_data = table
-        .data()
-        .iter()
-        .map(|entry| finalize_blinded_datum(store_context, entry))
-        .collect::<Result<Vec<PseudonymizedData>, Error>>()?;
+    let mut target_keys = table
+        .try_map(|entry| verify_blinded_datum(store_context, converter_keys, entry))?
+        .into_iter();
+    if let Some(target_key) = target_keys.next() {
+        if target_keys.any(|key| key != target_key) {
+            return Err(Error::VerificationError);
+        }
+    }
+
+    let pseudonymized_data = table.try_map(|entry| finalize_blinded_datum(store_context, entry))?;
+
+    let mut pseudonymized_table = Table::new(table.identifier().into(), pseudonymized_data);
+    pseudonymized_table.shuffle(randomness)?;
+
+    Ok(pseudonymized_table)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        setup::ConverterContext,
+        split::{blind_orthonymous_table, pseudonymize_blinded_table},
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_finalize_rejects_unverified_data() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+        let converted_table = pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let finalize = |converter_keys: &ConverterPublicKeys,
+                        data: Vec<BlindedPseudonymizedData>,
+                        randomness: &mut Randomness| {
+            finalize_blinded_table(
+                &lake_context,
+                converter_keys,
+                Table::new("Test".into(), data),
+                randomness,
+            )
+        };
+
+        // Proofs verify against the converter's public keys only.
+        let other_converter = ConverterContext::setup(&mut randomness).unwrap();
+        assert_eq!(
+            finalize(
+                &converter_public_keys(&other_converter),
+                converted_table.data().to_vec(),
+                &mut randomness
+            )
+            .unwrap_err(),
+            Error::VerificationError
+        );
+
+        // Evaluation with a wrong key is detected.
+        let mut data = converted_table.data().to_vec();
+        let (c0, c1) = data[0].blinded_handle.0;
+        data[0].blinded_handle.0 = (c0, -c1);
+        assert_eq!(
+            finalize(&converter_keys, data, &mut randomness).unwrap_err(),
+            Error::VerificationError
+        );
+
+        // Data without proof is rejected.
+        let mut data = converted_table.data().to_vec();
+        data[0].conversion_proof = None;
+        assert_eq!(
+            finalize(&converter_keys, data, &mut randomness).unwrap_err(),
+            Error::VerificationError
+        );
+
+        assert!(finalize(
+            &converter_keys,
+            converted_table.data().to_vec(),
+            &mut randomness
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_finalize_rejects_relabeled_data() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
 
-    pseudonymized_data.sort();
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
 
-    Ok(Table::new(table.identifier().into(), pseudonymized_data))
+        // Moving a value to another attribute before pseudonymization yields
+        // valid proofs, but the value's encryption is bound to its original
+        // attribute name.
+        let mut data = blind_table.data().to_vec();
+        let attribute_name = &mut data[0].encrypted_data_value.attribute_name;
+        *attribute_name = if attribute_name == "Address" {
+            "Date of Birth".into()
+        } else {
+            "Address".into()
+        };
+        let converted_table = pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            Table::new(blind_table.identifier().into(), data),
+            &mut randomness,
+        )
+        .unwrap();
+        assert_eq!(
+            finalize_blinded_table(
+                &lake_context,
+                &converter_keys,
+                converted_table,
+                &mut randomness
+            )
+            .unwrap_err(),
+            Error::CorruptedData
+        );
+    }
 }
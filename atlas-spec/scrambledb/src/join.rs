@@ -1,4 +1,11 @@
 //! # Pseudonym Conversion
+//!
+//! A join may combine columns held by several data lakes, as long as they
+//! received their data from the same converter. Each lake blinds its own
+//! selection towards the same processor using
+//! [`blind_pseudonymous_table`], and the converter converts all parts
+//! together using [`convert_blinded_tables`], such that the processor
+//! receives a single consistently join-pseudonymized table.
 use hacspec_lib::Randomness;
 use oprf::coprf::coprf_setup::BlindingPublicKey;
 
@@ -6,7 +13,7 @@ use crate::{
     data_transformations::{blind_pseudonymized_datum, convert_blinded_datum},
     data_types::{BlindedPseudonymizedData, PseudonymizedData},
     error::Error,
-    setup::{ConverterContext, StoreContext, StoreEncryptionKey},
+    setup::{ConverterContext, ConverterPublicKeys, StoreContext, StoreEncryptionKey},
     table::Table,
     SECPAR_BYTES,
 };
@@ -18,8 +25,9 @@ use crate::{
 ///
 /// Inputs:
 /// - `store_context`: The data store's pseudonymization context
-/// - `ek_receiver`: The receiver's public encryption key
+/// - `converter_keys`: The converter's public keys
 /// - `bpk_receiver`: The receiver's public blinding key
+/// - `ek_receiver`: The receiver's public encryption key
 /// - `pseudonymized_table`: A table of pseudonymous data values
 /// - `randomness`: Random bytes
 ///
@@ -27,24 +35,22 @@ use crate::{
 /// A table of blinded pseudonymous data values.
 pub fn blind_pseudonymous_table(
     store_context: &StoreContext,
+    converter_keys: &ConverterPublicKeys,
     bpk_receiver: BlindingPublicKey,
     ek_receiver: &StoreEncryptionKey,
     pseudonymized_table: Table<PseudonymizedData>,
     randomness: &mut Randomness,
 ) -> Result<Table<BlindedPseudonymizedData>, Error> {
-    let mut blinded_data = pseudonymized_table
-        .data()
-        .iter()
-        .map(|entry| {
-            blind_pseudonymized_datum(store_context, &bpk_receiver, ek_receiver, entry, randomness)
-        })
-        .collect::<Result<Vec<BlindedPseudonymizedData>, Error>>()?;
-
-    blinded_data.sort();
-    Ok(Table::new(
-        pseudonymized_table.identifier().into(),
-        blinded_data,
-    ))
+    let bpk = converter_keys.blinding_key(bpk_receiver)?;
+    let blinded_data =
+        pseudonymized_table.try_map_randomized(randomness, |entry, randomness| {
+            blind_pseudonymized_datum(store_context, &bpk, ek_receiver, entry, randomness)
+        })?;
+
+    let mut blinded_table = Table::new(pseudonymized_table.identifier().into(), blinded_data);
+    blinded_table.shuffle(randomness)?;
+
+    Ok(blinded_table)
 }
 
 pub fn join_identifier(identifier: String) -> String {
@@ -75,46 +81,102 @@ pub fn convert_blinded_table(
     ek_receiver: &StoreEncryptionKey,
     table: Table<BlindedPseudonymizedData>,
     randomness: &mut Randomness,
+) -> Result<Table<BlindedPseudonymizedData>, Error> {
+    convert_blinded_tables(
+        converter_context,
+        bpk_receiver,
+        ek_receiver,
+        vec![table],
+        randomness,
+    )
+}
+
+/// ## Multi-Lake Oblivious Conversion
+///
+/// Obliviously convert several tables of blinded pseudonymous data values,
+/// e.g. blinded by different data lakes towards the same receiver, to fresh
+/// join-pseudonyms. All entries are converted to the same target key, so
+/// the pseudonyms of a data subject agree across all parts, and the result
+/// is shuffled as a single table.
+///
+/// Inputs:
+/// - `converter_context`: The Converter's coPRF conversion context
+/// - `bpk_receiver`: The receiver's public blinding key
+/// - `ek_receiver`: The receiver's public encryption key
+/// - `parts`: Tables of blinded pseudonymous data values
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A table of consistently join-pseudonymized data values, identified by
+/// the identifiers of all parts.
+///
+/// Raises:
+/// - `InvalidInput`: If no parts are given.
+pub fn convert_blinded_tables(
+    converter_context: &ConverterContext,
+    bpk_receiver: BlindingPublicKey,
+    ek_receiver: &StoreEncryptionKey,
+    parts: Vec<Table<BlindedPseudonymizedData>>,
+    randomness: &mut Randomness,
 ) -> Result<Table<BlindedPseudonymizedData>, Error> {
     let conversion_target = randomness.bytes(SECPAR_BYTES)?.to_owned();
-    let mut converted_data = table
-        .data()
-        .iter()
-        .map(|entry| {
-            convert_blinded_datum(
-                &converter_context.coprf_context,
-                &bpk_receiver,
-                ek_receiver,
-                &conversion_target,
-                entry,
-                randomness,
-            )
-        })
-        .collect::<Result<Vec<BlindedPseudonymizedData>, Error>>()?;
+    convert_blinded_tables_to(
+        converter_context,
+        &conversion_target,
+        bpk_receiver,
+        ek_receiver,
+        parts,
+        randomness,
+    )
+}
+
+/// Convert several tables of blinded pseudonymous data values to the given
+/// conversion target and shuffle the result as a single table.
+pub(crate) fn convert_blinded_tables_to(
+    converter_context: &ConverterContext,
+    conversion_target: &[u8],
+    bpk_receiver: BlindingPublicKey,
+    ek_receiver: &StoreEncryptionKey,
+    parts: Vec<Table<BlindedPseudonymizedData>>,
+    randomness: &mut Randomness,
+) -> Result<Table<BlindedPseudonymizedData>, Error> {
+    let table = Table::merge(parts)?;
+    let converted_data = table.try_map_randomized(randomness, |entry, randomness| {
+        convert_blinded_datum(
+            converter_context,
+            &bpk_receiver,
+            ek_receiver,
+            conversion_target,
+            entry,
+            randomness,
+        )
+    })?;
 
-    converted_data.sort();
+    let mut converted_table = Table::new(table.identifier().into(), converted_data);
+    converted_table.shuffle(randomness)?;
 
-    Ok(Table::new(table.identifier().into(), converted_data))
+    Ok(converted_table)
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
 
-    use crate::{setup::StoreContext, test_util::generate_plain_table};
+    use oprf::coprf::{coprf_setup::CoPRFEvaluatorContext, coprf_threshold::deal};
+
+    use crate::{
+        setup::StoreContext,
+        test_util::{converter_public_keys, generate_plain_table},
+    };
 
     use super::*;
 
     #[test]
     fn test_join_full() {
-        use rand::prelude::*;
-
-        let mut rng = rand::thread_rng();
-        let mut randomness = [0u8; 1000000];
-        rng.fill_bytes(&mut randomness);
-        let mut randomness = Randomness::new(randomness.to_vec());
+        let mut randomness = Randomness::from_entropy().unwrap();
 
         let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
         let lake_context = StoreContext::setup(&mut randomness).unwrap();
 
         // == Generate Plain Table ==
@@ -124,6 +186,7 @@ mod tests {
 
         // == Blind Table for Pseudonymization ==
         let blind_table = crate::split::blind_orthonymous_table(
+            &converter_keys,
             &lake_ek,
             lake_bpk,
             plain_table.clone(),
@@ -142,8 +205,13 @@ mod tests {
         .unwrap();
 
         // == Unblinded Pseudonymized Table ==
-        let lake_tables =
-            crate::finalize::finalize_blinded_table(&lake_context, converted_tables).unwrap();
+        let lake_tables = crate::finalize::finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            converted_tables,
+            &mut randomness,
+        )
+        .unwrap();
 
         let mut pseudonym_set = HashSet::new();
 
@@ -175,6 +243,7 @@ mod tests {
         let (ek_processor, bpk_processor) = processor_context.public_keys();
         let blind_tables = crate::join::blind_pseudonymous_table(
             &lake_context,
+            &converter_keys,
             bpk_processor,
             &ek_processor,
             join_table,
@@ -183,6 +252,16 @@ mod tests {
         .unwrap();
 
         let converted_join_tables = crate::join::convert_blinded_table(
+            &converter_context,
+            bpk_processor,
+            &ek_processor,
+            blind_tables.clone(),
+            &mut randomness,
+        )
+        .unwrap();
+
+        // Entries converted to different target keys are rejected.
+        let other_join_tables = crate::join::convert_blinded_table(
             &converter_context,
             bpk_processor,
             &ek_processor,
@@ -190,10 +269,26 @@ mod tests {
             &mut randomness,
         )
         .unwrap();
+        let mut mixed = converted_join_tables.data().to_vec();
+        mixed[0] = other_join_tables.data()[0].clone();
+        assert_eq!(
+            crate::finalize::finalize_blinded_table(
+                &processor_context,
+                &converter_keys,
+                Table::new("Join".into(), mixed),
+                &mut randomness,
+            )
+            .unwrap_err(),
+            Error::VerificationError
+        );
 
-        let joined_tables =
-            crate::finalize::finalize_blinded_table(&processor_context, converted_join_tables)
-                .unwrap();
+        let joined_tables = crate::finalize::finalize_blinded_table(
+            &processor_context,
+            &converter_keys,
+            converted_join_tables,
+            &mut randomness,
+        )
+        .unwrap();
 
         for entry in joined_tables.data() {
             let mut lake_pseudonyms = pseudonym_set.clone();
@@ -214,4 +309,206 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_join_multi_lake() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let processor_context = StoreContext::setup(&mut randomness).unwrap();
+        let (ek_processor, bpk_processor) = processor_context.public_keys();
+
+        // Each lake receives one attribute and blinds it for the processor.
+        let mut parts = Vec::new();
+        for attribute in ["Address", "Date of Birth"] {
+            let lake_context = StoreContext::setup(&mut randomness).unwrap();
+            let (lake_ek, lake_bpk) = lake_context.public_keys();
+            let plain_table = generate_plain_table();
+            let plain_table = Table::new(
+                attribute.into(),
+                plain_table
+                    .data()
+                    .iter()
+                    .filter(|entry| entry.data_value.attribute_name == attribute)
+                    .cloned()
+                    .collect(),
+            );
+            let blind_table = crate::split::blind_orthonymous_table(
+                &converter_keys,
+                &lake_ek,
+                lake_bpk,
+                plain_table,
+                &mut randomness,
+            )
+            .unwrap();
+            let converted_table = crate::split::pseudonymize_blinded_table(
+                &converter_context,
+                lake_bpk,
+                &lake_ek,
+                blind_table,
+                &mut randomness,
+            )
+            .unwrap();
+            let lake_table = crate::finalize::finalize_blinded_table(
+                &lake_context,
+                &converter_keys,
+                converted_table,
+                &mut randomness,
+            )
+            .unwrap();
+            parts.push(
+                blind_pseudonymous_table(
+                    &lake_context,
+                    &converter_keys,
+                    bpk_processor,
+                    &ek_processor,
+                    lake_table,
+                    &mut randomness,
+                )
+                .unwrap(),
+            );
+        }
+
+        let converted_table = convert_blinded_tables(
+            &converter_context,
+            bpk_processor,
+            &ek_processor,
+            parts,
+            &mut randomness,
+        )
+        .unwrap();
+        assert_eq!(converted_table.identifier(), "Address+Date of Birth");
+        let joined_table = crate::finalize::finalize_blinded_table(
+            &processor_context,
+            &converter_keys,
+            converted_table,
+            &mut randomness,
+        )
+        .unwrap();
+
+        // The entries of a data subject are joined across lakes.
+        let pseudonym = |value: &[u8]| {
+            joined_table
+                .data()
+                .iter()
+                .find(|entry| entry.data_value.value == value)
+                .unwrap()
+                .handle
+        };
+        assert_eq!(pseudonym(b"TestData1"), pseudonym(b"TestData3"));
+        assert_eq!(pseudonym(b"TestData2"), pseudonym(b"TestData4"));
+        assert_ne!(pseudonym(b"TestData1"), pseudonym(b"TestData2"));
+
+        assert_eq!(
+            convert_blinded_tables(
+                &converter_context,
+                bpk_processor,
+                &ek_processor,
+                Vec::new(),
+                &mut randomness,
+            )
+            .unwrap_err(),
+            Error::InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_join_threshold() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let converter_context = ConverterContext::threshold(deal(&dealer, 2, 3).unwrap()).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let blind_table = crate::split::blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+        let converted_tables = crate::split::pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let lake_tables = crate::finalize::finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            converted_tables,
+            &mut randomness,
+        )
+        .unwrap();
+        let join_table = Table::new(
+            "Join".into(),
+            lake_tables
+                .data()
+                .iter()
+                .filter(|entry| {
+                    entry.data_value.attribute_name == "Address"
+                        || entry.data_value.attribute_name == "Date of Birth"
+                })
+                .cloned()
+                .collect(),
+        );
+
+        let processor_context = StoreContext::setup(&mut randomness).unwrap();
+        let (ek_processor, bpk_processor) = processor_context.public_keys();
+        let blind_tables = crate::join::blind_pseudonymous_table(
+            &lake_context,
+            &converter_keys,
+            bpk_processor,
+            &ek_processor,
+            join_table,
+            &mut randomness,
+        )
+        .unwrap();
+
+        // A quorum of `t` evaluators is not enough for conversion.
+        let mut evaluators = deal(&dealer, 2, 3).unwrap();
+        evaluators.truncate(2);
+        let quorum = ConverterContext::threshold(evaluators).unwrap();
+        assert_eq!(
+            crate::join::convert_blinded_table(
+                &quorum,
+                bpk_processor,
+                &ek_processor,
+                blind_tables.clone(),
+                &mut randomness,
+            )
+            .unwrap_err(),
+            Error::InvalidInput
+        );
+
+        let converted_join_tables = crate::join::convert_blinded_table(
+            &converter_context,
+            bpk_processor,
+            &ek_processor,
+            blind_tables,
+            &mut randomness,
+        )
+        .unwrap();
+        let joined_tables = crate::finalize::finalize_blinded_table(
+            &processor_context,
+            &converter_keys,
+            converted_join_tables,
+            &mut randomness,
+        )
+        .unwrap();
+
+        // Both attributes of a data subject are joined under one pseudonym.
+        let pseudonyms = joined_tables
+            .data()
+            .iter()
+            .map(|entry| entry.handle)
+            .collect::<HashSet<_>>();
+        assert_eq!(pseudonyms.len(), 2);
+    }
 }
@@ -0,0 +1,243 @@
+//! # Key Material Export
+//!
+//! Data stores and the converter hold long term secrets which have to
+//! survive restarts. This module defines the container format for
+//! exported key material, optionally protected under a password.
+//!
+//! ``` text
+//! export = VERSION || TAG || protection || public || secret
+//! ```
+//!
+//! where `VERSION` is the
+//! [storage format version](crate::serialization::STORAGE_FORMAT_VERSION)
+//! and `public` and `secret` are length prefixed byte strings. If
+//! `protection` is `PROTECTION_PASSWORD`, the `secret` byte string is
+//!
+//! ``` text
+//! salt || iterations || nonce || ChaCha20Poly1305(k, nonce, aad, secret)
+//! ```
+//!
+//! with `k = PBKDF2-HMAC-SHA256(password, salt, iterations)` and
+//! `aad = VERSION || TAG || protection || public`, such that the public
+//! key material cannot be swapped out either.
+//!
+//! The iteration count is read from the export, so imports only accept
+//! counts between [`PBKDF2_ITERATIONS`] and [`MAX_PBKDF2_ITERATIONS`]. A
+//! modified export can thus neither weaken the key derivation nor stall
+//! the import.
+
+use hacspec_chacha20poly1305::{chacha20_poly1305_decrypt, chacha20_poly1305_encrypt};
+use hacspec_lib::Randomness;
+
+use crate::{
+    error::Error,
+    serialization::{put_byte_string, put_u32, Reader, STORAGE_FORMAT_VERSION},
+};
+
+/// The secret key material is stored in plain text.
+const PROTECTION_NONE: u8 = 0;
+/// The secret key material is encrypted under a password derived key.
+const PROTECTION_PASSWORD: u8 = 1;
+
+/// Number of PBKDF2 iterations used for wrapping new exports, and the
+/// minimum accepted on import.
+pub(crate) const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Maximum number of PBKDF2 iterations accepted on import.
+pub(crate) const MAX_PBKDF2_ITERATIONS: u32 = 10 * PBKDF2_ITERATIONS;
+
+const SALT_BYTES: usize = 16;
+const NONCE_BYTES: usize = 12;
+const TAG_BYTES: usize = 16;
+
+/// Seal exported key material, encrypting the secret part if a password is
+/// given.
+///
+/// Inputs:
+/// - `tag`: The wire format tag of the exported context
+/// - `public`: Public key material, stored in the clear
+/// - `secret`: Secret key material
+/// - `password`: An optional password and randomness for salt and nonce
+///   generation
+/// - `iterations`: The PBKDF2 iteration count
+///
+/// Output:
+/// The exported key material.
+pub(crate) fn seal(
+    tag: u8,
+    public: &[u8],
+    secret: &[u8],
+    password: Option<(&[u8], &mut Randomness)>,
+    iterations: u32,
+) -> Result<Vec<u8>, Error> {
+    let mut out = vec![STORAGE_FORMAT_VERSION, tag];
+
+    match password {
+        None => {
+            out.push(PROTECTION_NONE);
+            put_byte_string(&mut out, public);
+            put_byte_string(&mut out, secret);
+        }
+        Some((password, randomness)) => {
+            out.push(PROTECTION_PASSWORD);
+            put_byte_string(&mut out, public);
+
+            let salt = randomness.bytes(SALT_BYTES)?.to_vec();
+            let nonce: [u8; NONCE_BYTES] = randomness.bytes(NONCE_BYTES)?.try_into()?;
+            let key = derive_wrapping_key(password, &salt, iterations)?;
+            let (ciphertext, aead_tag) = chacha20_poly1305_encrypt(key, nonce, &out, secret);
+
+            let mut wrapped = salt;
+            put_u32(&mut wrapped, iterations);
+            wrapped.extend_from_slice(&nonce);
+            wrapped.extend_from_slice(&ciphertext);
+            wrapped.extend_from_slice(&aead_tag);
+            put_byte_string(&mut out, &wrapped);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Open exported key material.
+///
+/// Inputs:
+/// - `tag`: The expected wire format tag of the exported context
+/// - `bytes`: The exported key material
+/// - `password`: The password, if the export is password protected
+///
+/// Output:
+/// The public and secret key material.
+///
+/// Raises:
+/// - `InvalidInput`: If the export is password protected, but no password
+///   was given.
+/// - `CorruptedData`: If the export is malformed or cannot be decrypted
+///   under the given password.
+pub(crate) fn open(
+    tag: u8,
+    bytes: &[u8],
+    password: Option<&[u8]>,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let mut reader = Reader::new(bytes);
+    if reader.u8()? != STORAGE_FORMAT_VERSION || reader.u8()? != tag {
+        return Err(Error::CorruptedData);
+    }
+    let protection = reader.u8()?;
+    let public = reader.byte_string()?.to_vec();
+    let secret = reader.byte_string()?;
+    reader.finish()?;
+
+    match protection {
+        PROTECTION_NONE => Ok((public, secret.to_vec())),
+        PROTECTION_PASSWORD => {
+            let password = password.ok_or(Error::InvalidInput)?;
+            let aad = &bytes[..bytes.len() - secret.len() - 4];
+
+            let mut reader = Reader::new(secret);
+            let salt = reader.take(SALT_BYTES)?;
+            let iterations = reader.u32()?;
+            let nonce: [u8; NONCE_BYTES] = reader.take(NONCE_BYTES)?.try_into()?;
+            let rest = reader.take(secret.len() - SALT_BYTES - 4 - NONCE_BYTES)?;
+            if rest.len() < TAG_BYTES {
+                return Err(Error::CorruptedData);
+            }
+            let (ciphertext, aead_tag) = rest.split_at(rest.len() - TAG_BYTES);
+
+            let key = derive_wrapping_key(password, salt, iterations)?;
+            let secret =
+                chacha20_poly1305_decrypt(key, nonce, aad, ciphertext, aead_tag.try_into()?)
+                    .map_err(|_| Error::CorruptedData)?;
+            Ok((public, secret))
+        }
+        _ => Err(Error::CorruptedData),
+    }
+}
+
+fn derive_wrapping_key(password: &[u8], salt: &[u8], iterations: u32) -> Result<[u8; 32], Error> {
+    if !(PBKDF2_ITERATIONS..=MAX_PBKDF2_ITERATIONS).contains(&iterations) {
+        return Err(Error::CorruptedData);
+    }
+    Ok(hmac::pbkdf2(password, salt, iterations, 32)
+        .try_into()
+        .unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let mut randomness = Randomness::new(vec![0xab; 28]);
+
+        let plain = seal(0x42, b"public", b"secret", None, 1).unwrap();
+        assert_eq!(
+            open(0x42, &plain, None).unwrap(),
+            (b"public".to_vec(), b"secret".to_vec())
+        );
+
+        let wrapped = seal(
+            0x42,
+            b"public",
+            b"secret",
+            Some((b"password", &mut randomness)),
+            PBKDF2_ITERATIONS,
+        )
+        .unwrap();
+        assert_eq!(
+            open(0x42, &wrapped, Some(b"password")).unwrap(),
+            (b"public".to_vec(), b"secret".to_vec())
+        );
+
+        assert_eq!(open(0x42, &wrapped, None), Err(Error::InvalidInput));
+        assert_eq!(
+            open(0x42, &wrapped, Some(b"wrong password")),
+            Err(Error::CorruptedData)
+        );
+        assert_eq!(
+            open(0x43, &wrapped, Some(b"password")),
+            Err(Error::CorruptedData)
+        );
+
+        // The public key material is authenticated as well.
+        let mut swapped = wrapped.clone();
+        swapped[7] ^= 1;
+        assert_eq!(
+            open(0x42, &swapped, Some(b"password")),
+            Err(Error::CorruptedData)
+        );
+        for len in 0..wrapped.len() {
+            assert!(open(0x42, &wrapped[..len], Some(b"password")).is_err());
+        }
+    }
+
+    #[test]
+    fn test_open_bounds_iterations() {
+        let mut randomness = Randomness::new(vec![0xab; 28]);
+        let wrapped = seal(
+            0x42,
+            b"public",
+            b"secret",
+            Some((b"password", &mut randomness)),
+            PBKDF2_ITERATIONS,
+        )
+        .unwrap();
+
+        // The iteration count follows the header, the public key material,
+        // the length of the secret key material and the salt.
+        let offset = 3 + 4 + 6 + 4 + SALT_BYTES;
+        assert_eq!(
+            wrapped[offset..offset + 4],
+            PBKDF2_ITERATIONS.to_be_bytes()
+        );
+        for iterations in [0, PBKDF2_ITERATIONS - 1, MAX_PBKDF2_ITERATIONS + 1, u32::MAX] {
+            let mut modified = wrapped.clone();
+            modified[offset..offset + 4].copy_from_slice(&iterations.to_be_bytes());
+            assert_eq!(
+                open(0x42, &modified, Some(b"password")),
+                Err(Error::CorruptedData)
+            );
+        }
+    }
+}
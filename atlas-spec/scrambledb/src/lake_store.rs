@@ -0,0 +1,1010 @@
+//! # Persistent Data Lake Storage
+//!
+//! A data lake holds the pseudonymized columns output by split conversions
+//! over a long time. The [`LakeStore`] keeps them in an embedded,
+//! append-only, file-backed column store, keyed by attribute name and
+//! [`FinalizedPseudonym`].
+//!
+//! The store consists of a single log file. It starts with a header
+//! identifying the file and its
+//! [storage format version](crate::serialization::STORAGE_FORMAT_VERSION),
+//! followed by one record per ingested batch of entries or other update of
+//! the store:
+//!
+//! ``` text
+//! file   = MAGIC || VERSION || record*
+//! record = body_len: u32 || body || SHA256(body)
+//! body   = count: u32 || (entry_len: u32 || entry)*
+//! ```
+//!
+//! Each entry is either the [wire format](crate::serialization) encoding of
+//! a stored [`PseudonymizedData`], or an operation on the entries stored
+//! before it, encoded with the same header:
+//!
+//! ``` text
+//! remove = VERSION || TAG_REMOVE || attribute || pseudonym
+//! move   = VERSION || TAG_MOVE || attribute || pseudonym || pseudonym
+//! ```
+//!
+//! A removal drops all data values of a pseudonym from the column of an
+//! attribute, a move assigns them to another pseudonym. Records are only
+//! ever appended and made durable before an update returns, so an update
+//! is either applied completely or not at all: When the store is opened, a
+//! trailing record that was not written completely, e.g. because of a
+//! crash, is discarded. Any other inconsistency of the log results in
+//! [`Error::CorruptedData`].
+//!
+//! On opening, the log is replayed one record at a time to rebuild an index
+//! mapping each attribute and pseudonym to the position of its entries in
+//! the log. Data values themselves are read from the log on demand.
+//!
+//! ## Erasure and Key Rotation
+//!
+//! [`LakeStore::erase`] removes the entries of a data subject, as
+//! identified by an [erasure request](crate::erasure), by appending
+//! removals. The removed data values remain in the log file until
+//! [`LakeStore::compact`] rewrites it with only the entries still stored.
+//!
+//! After the data lake moved to a new PRP key using
+//! [`StoreContext::rotate_prp_key`], [`LakeStore::rekey`] moves all stored
+//! pseudonyms to the new key by appending moves, such that entries are
+//! looked up and selected by their pseudonym under the current key. The
+//! previous key can be retired afterwards.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    data_types::{DataValue, FinalizedPseudonym, PseudonymizedData},
+    error::Error,
+    serialization::{put_byte_string, put_u32, Reader, WireFormat, STORAGE_FORMAT_VERSION},
+    setup::StoreContext,
+    table::Table,
+};
+
+/// The magic bytes at the start of a lake store file.
+const MAGIC: &[u8; 8] = b"SDB-LAKE";
+
+/// The length of the file header.
+const HEADER_LEN: u64 = MAGIC.len() as u64 + 1;
+
+/// The length of a record checksum.
+const CHECKSUM_LEN: usize = sha256::HASH_SIZE;
+
+/// Tag of the removal of a pseudonym's data values.
+const TAG_REMOVE: u8 = 0x0e;
+
+/// Tag of the move of a pseudonym's data values to another pseudonym.
+const TAG_MOVE: u8 = 0x0f;
+
+/// The maximum number of entries per record written during compaction.
+const ENTRIES_PER_RECORD: usize = 1 << 14;
+
+/// The position of an encoded entry in the log.
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    offset: u64,
+    len: u32,
+}
+
+/// An update of the store, recorded as an entry of the log.
+enum Operation {
+    /// Store a data value.
+    Insert(PseudonymizedData),
+    /// Remove all data values of a pseudonym from the column of an
+    /// attribute.
+    Remove {
+        attribute: String,
+        pseudonym: FinalizedPseudonym,
+    },
+    /// Assign all data values of a pseudonym in the column of an attribute
+    /// to another pseudonym.
+    Move {
+        attribute: String,
+        from: FinalizedPseudonym,
+        to: FinalizedPseudonym,
+    },
+}
+
+impl Operation {
+    fn encode(&self) -> Vec<u8> {
+        let (tag, attribute, pseudonyms) = match self {
+            Operation::Insert(entry) => return entry.to_bytes(),
+            Operation::Remove {
+                attribute,
+                pseudonym,
+            } => (TAG_REMOVE, attribute, vec![pseudonym]),
+            Operation::Move {
+                attribute,
+                from,
+                to,
+            } => (TAG_MOVE, attribute, vec![from, to]),
+        };
+        let mut out = vec![STORAGE_FORMAT_VERSION, tag];
+        put_byte_string(&mut out, attribute.as_bytes());
+        for pseudonym in pseudonyms {
+            pseudonym.encode_body(&mut out);
+        }
+        out
+    }
+
+    fn decode(encoded: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(encoded);
+        if reader.u8()? != STORAGE_FORMAT_VERSION {
+            return Err(Error::CorruptedData);
+        }
+        let operation = match reader.u8()? {
+            TAG_REMOVE => Operation::Remove {
+                attribute: reader.string()?,
+                pseudonym: FinalizedPseudonym::decode_body(&mut reader)?,
+            },
+            TAG_MOVE => Operation::Move {
+                attribute: reader.string()?,
+                from: FinalizedPseudonym::decode_body(&mut reader)?,
+                to: FinalizedPseudonym::decode_body(&mut reader)?,
+            },
+            _ => return Ok(Operation::Insert(PseudonymizedData::from_bytes(encoded)?)),
+        };
+        reader.finish()?;
+        Ok(operation)
+    }
+}
+
+/// An append-only, file-backed store of pseudonymized data values.
+pub struct LakeStore {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// The length of the valid part of the log.
+    len: u64,
+    index: BTreeMap<String, BTreeMap<FinalizedPseudonym, Vec<Location>>>,
+    entries: usize,
+}
+
+impl LakeStore {
+    /// Open the lake store at the given path, creating it if it does not
+    /// exist.
+    ///
+    /// A trailing update that was not written completely is discarded.
+    ///
+    /// Raises:
+    /// - `CorruptedData`: If the file is not a lake store or its contents are
+    ///   inconsistent.
+    /// - `StorageError`: If accessing the file fails.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut header = MAGIC.to_vec();
+        header.push(STORAGE_FORMAT_VERSION);
+        let existing = read_up_to(&mut file, HEADER_LEN)?;
+        if existing.len() < header.len() && header.starts_with(&existing) {
+            // The store is new or its creation was interrupted.
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&header)?;
+            file.sync_all()?;
+        } else if existing != header {
+            return Err(Error::CorruptedData);
+        }
+
+        let mut store = Self {
+            path,
+            file: Mutex::new(file),
+            len: HEADER_LEN,
+            index: BTreeMap::new(),
+            entries: 0,
+        };
+        store.replay(file_len)?;
+        if store.len < file_len {
+            let file = store
+                .file
+                .get_mut()
+                .map_err(|_| Error::InternalError)?;
+            file.set_len(store.len)?;
+            file.sync_all()?;
+        }
+
+        Ok(store)
+    }
+
+    /// Rebuild the index by reading the log one record at a time, stopping
+    /// before a trailing incomplete record.
+    fn replay(&mut self, file_len: u64) -> Result<(), Error> {
+        let file = self.file.get_mut().map_err(|_| Error::InternalError)?;
+        let mut reader = BufReader::new(file.try_clone()?);
+        reader.seek(SeekFrom::Start(HEADER_LEN))?;
+
+        let mut offset = HEADER_LEN;
+        loop {
+            let length = read_up_to(&mut reader, 4)?;
+            if length.len() < 4 {
+                break;
+            }
+            let body_len = u32::from_be_bytes(length.as_slice().try_into()?) as u64;
+            let record_len = 4 + body_len + CHECKSUM_LEN as u64;
+            let body = read_up_to(&mut reader, body_len)?;
+            let checksum = read_up_to(&mut reader, CHECKSUM_LEN as u64)?;
+            if body.len() as u64 != body_len || checksum.len() != CHECKSUM_LEN {
+                break;
+            }
+            if sha256::hash(&body) != checksum[..] {
+                if offset + record_len == file_len {
+                    break;
+                }
+                return Err(Error::CorruptedData);
+            }
+
+            for (location, operation) in decode_body(&body, offset + 4)? {
+                self.apply(location, operation)?;
+            }
+            offset += record_len;
+        }
+        self.len = offset;
+        Ok(())
+    }
+
+    /// Apply an operation stored at `location` to the index.
+    ///
+    /// Raises:
+    /// - `CorruptedData`: If the operation refers to a pseudonym that is not
+    ///   stored.
+    fn apply(&mut self, location: Location, operation: Operation) -> Result<(), Error> {
+        match operation {
+            Operation::Insert(entry) => {
+                self.index
+                    .entry(entry.data_value.attribute_name)
+                    .or_default()
+                    .entry(entry.handle)
+                    .or_default()
+                    .push(location);
+                self.entries += 1;
+            }
+            Operation::Remove {
+                attribute,
+                pseudonym,
+            } => {
+                let column = self.index.get_mut(&attribute).ok_or(Error::CorruptedData)?;
+                let removed = column.remove(&pseudonym).ok_or(Error::CorruptedData)?;
+                if column.is_empty() {
+                    self.index.remove(&attribute);
+                }
+                self.entries -= removed.len();
+            }
+            Operation::Move {
+                attribute,
+                from,
+                to,
+            } => {
+                let column = self.index.get_mut(&attribute).ok_or(Error::CorruptedData)?;
+                let moved = column.remove(&from).ok_or(Error::CorruptedData)?;
+                let locations = column.entry(to).or_default();
+                locations.extend(moved);
+                locations.sort_by_key(|location| location.offset);
+            }
+        }
+        Ok(())
+    }
+
+    /// Append a record of operations to the log and apply them once it is
+    /// durable.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the operations are too large to be stored as one
+    ///   record.
+    /// - `StorageError`: If writing the file fails. The store is left
+    ///   unchanged.
+    fn append(&mut self, operations: Vec<Operation>) -> Result<(), Error> {
+        let mut body = Vec::new();
+        put_u32(&mut body, operations.len() as u32);
+        let mut locations = Vec::with_capacity(operations.len());
+        for operation in &operations {
+            let encoded = operation.encode();
+            let len = u32::try_from(encoded.len()).map_err(|_| Error::InvalidInput)?;
+            put_u32(&mut body, len);
+            locations.push(Location {
+                offset: self.len + 4 + body.len() as u64,
+                len,
+            });
+            body.extend_from_slice(&encoded);
+        }
+
+        let mut record = Vec::with_capacity(4 + body.len() + CHECKSUM_LEN);
+        put_u32(
+            &mut record,
+            u32::try_from(body.len()).map_err(|_| Error::InvalidInput)?,
+        );
+        record.extend_from_slice(&body);
+        record.extend_from_slice(&sha256::hash(&body));
+
+        let file = self.file.get_mut().map_err(|_| Error::InternalError)?;
+        let written = file
+            .seek(SeekFrom::Start(self.len))
+            .and_then(|_| file.write_all(&record))
+            .and_then(|_| file.sync_data());
+        if let Err(e) = written {
+            // Best effort to drop the partial record right away, it is
+            // discarded on the next opening otherwise.
+            let _ = file.set_len(self.len);
+            return Err(e.into());
+        }
+
+        self.len += record.len() as u64;
+        for (location, operation) in locations.into_iter().zip(operations) {
+            self.apply(location, operation)?;
+        }
+        Ok(())
+    }
+
+    /// ## Ingestion
+    ///
+    /// Append a batch of pseudonymized data values, e.g. the result of a
+    /// split conversion, to the store. The batch is durable once this
+    /// returns.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the batch is too large to be stored as one
+    ///   record.
+    /// - `StorageError`: If writing the file fails. The store is left without
+    ///   any entries of the batch.
+    pub fn ingest(&mut self, table: &Table<PseudonymizedData>) -> Result<(), Error> {
+        if table.data().is_empty() {
+            return Ok(());
+        }
+        self.append(
+            table
+                .data()
+                .iter()
+                .cloned()
+                .map(Operation::Insert)
+                .collect(),
+        )
+    }
+
+    /// The number of stored entries.
+    pub fn len(&self) -> usize {
+        self.entries
+    }
+
+    /// Whether the store holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// The attributes the store holds entries for, in lexicographical
+    /// order.
+    pub fn attributes(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(|attribute| attribute.as_str())
+    }
+
+    /// ## Lookup
+    ///
+    /// Look up the data values stored for a pseudonym in the column of an
+    /// attribute, in the order they were ingested.
+    ///
+    /// Pseudonyms are compared as they are, i.e. entries finalized under a
+    /// previous PRP key of the data lake are only found under their current
+    /// pseudonym once the store has been [rekeyed](LakeStore::rekey).
+    ///
+    /// Raises:
+    /// - `CorruptedData`: If a stored entry cannot be decoded.
+    /// - `StorageError`: If reading the file fails.
+    pub fn lookup(
+        &self,
+        attribute: &str,
+        pseudonym: &FinalizedPseudonym,
+    ) -> Result<Vec<DataValue>, Error> {
+        let locations = match self
+            .index
+            .get(attribute)
+            .and_then(|column| column.get(pseudonym))
+        {
+            Some(locations) => locations,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut file = self.file.lock().map_err(|_| Error::InternalError)?;
+        locations
+            .iter()
+            .map(|location| Ok(read_entry(&mut file, *location)?.data_value))
+            .collect()
+    }
+
+    /// ## Column Selection
+    ///
+    /// Read the columns of the given attributes into a table, e.g. to
+    /// prepare them for join conversion using
+    /// [`authorized_blind_pseudonymous_table`](crate::policy::authorized_blind_pseudonymous_table),
+    /// or its streaming variant
+    /// [`authorized_blind_pseudonymous_stream`](crate::policy::authorized_blind_pseudonymous_stream),
+    /// which enforce the data lake's join policy.
+    ///
+    /// Inputs:
+    /// - `identifier`: The identifier of the resulting table
+    /// - `attributes`: The attributes to select
+    ///
+    /// Output:
+    /// A table holding all entries of the selected attributes, under their
+    /// current pseudonyms.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the store holds no column for an attribute.
+    /// - `CorruptedData`: If a stored entry cannot be decoded.
+    /// - `StorageError`: If reading the file fails.
+    pub fn select(
+        &self,
+        identifier: &str,
+        attributes: &[&str],
+    ) -> Result<Table<PseudonymizedData>, Error> {
+        let columns = attributes
+            .iter()
+            .map(|attribute| self.index.get(*attribute).ok_or(Error::InvalidInput))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut file = self.file.lock().map_err(|_| Error::InternalError)?;
+        let mut data = Vec::new();
+        for column in columns {
+            for (pseudonym, locations) in column {
+                for location in locations {
+                    let mut entry = read_entry(&mut file, *location)?;
+                    entry.handle = *pseudonym;
+                    data.push(entry);
+                }
+            }
+        }
+
+        Ok(Table::new(identifier.into(), data))
+    }
+
+    /// ## Erasure
+    ///
+    /// Remove all entries of a data subject from the store, as
+    /// [`erase_from_table`](crate::erasure::erase_from_table) does for
+    /// tables. Stored pseudonyms of the current epoch are looked up in the
+    /// index, while those finalized under a previous PRP key of the data
+    /// lake are compared after moving them to the current key. Erasure thus
+    /// only scans the pseudonyms which are not yet
+    /// [rekeyed](LakeStore::rekey). The removal is durable once this returns, but the removed data values
+    /// remain in the log file until it is [compacted](LakeStore::compact).
+    ///
+    /// Inputs:
+    /// - `lake_context`: The data lake's pseudonymization context
+    /// - `pseudonyms`: The pseudonyms to erase per attribute, as output by
+    ///   [`finalize_erasure_request`](crate::erasure::finalize_erasure_request)
+    ///
+    /// Output:
+    /// The number of removed entries.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the data lake holds no PRP key for the epoch of a
+    ///   stored pseudonym.
+    /// - `StorageError`: If writing the file fails. The store is left
+    ///   unchanged.
+    pub fn erase(
+        &mut self,
+        lake_context: &StoreContext,
+        pseudonyms: &BTreeMap<String, BTreeSet<FinalizedPseudonym>>,
+    ) -> Result<usize, Error> {
+        // Pseudonyms are ordered by epoch first, so the stored pseudonyms of
+        // the current epoch lie between these bounds.
+        let epoch = lake_context.prp_epoch();
+        let first_current = FinalizedPseudonym {
+            epoch,
+            value: [0; 64],
+        };
+        let last_current = FinalizedPseudonym {
+            epoch,
+            value: [0xff; 64],
+        };
+
+        let mut operations = Vec::new();
+        let mut removed = 0;
+        for (attribute, pseudonyms) in pseudonyms {
+            let Some(column) = self.index.get(attribute) else {
+                continue;
+            };
+            let mut erased = BTreeSet::new();
+            for pseudonym in pseudonyms {
+                if column.contains_key(pseudonym) {
+                    erased.insert(*pseudonym);
+                }
+            }
+            // Only pseudonyms of previous epochs are moved to the current
+            // key for comparison.
+            let previous = column
+                .range(..first_current)
+                .chain(column.range((Bound::Excluded(last_current), Bound::Unbounded)));
+            for (stored, _) in previous {
+                if pseudonyms.contains(&lake_context.rekey_pseudonym(*stored)?) {
+                    erased.insert(*stored);
+                }
+            }
+            for pseudonym in erased {
+                removed += column[&pseudonym].len();
+                operations.push(Operation::Remove {
+                    attribute: attribute.clone(),
+                    pseudonym,
+                });
+            }
+        }
+
+        if !operations.is_empty() {
+            self.append(operations)?;
+        }
+        Ok(removed)
+    }
+
+    /// ## Rekeying
+    ///
+    /// Move all stored pseudonyms finalized under a previous PRP key of the
+    /// data lake to its current key, as
+    /// [`rekey_table`](crate::rotation::rekey_table) does for tables. The
+    /// move is durable once this returns, after which the previous keys can
+    /// be retired using [`StoreContext::retire_prp_key`].
+    ///
+    /// Output:
+    /// The number of moved pseudonyms.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the data lake holds no PRP key for the epoch of a
+    ///   stored pseudonym, or there are too many pseudonyms to move them in
+    ///   one record.
+    /// - `StorageError`: If writing the file fails. The store is left
+    ///   unchanged.
+    pub fn rekey(&mut self, lake_context: &StoreContext) -> Result<usize, Error> {
+        let epoch = lake_context.prp_epoch();
+        let mut operations = Vec::new();
+        for (attribute, column) in &self.index {
+            for from in column.keys().filter(|pseudonym| pseudonym.epoch != epoch) {
+                operations.push(Operation::Move {
+                    attribute: attribute.clone(),
+                    from: *from,
+                    to: lake_context.rekey_pseudonym(*from)?,
+                });
+            }
+        }
+
+        let moved = operations.len();
+        if moved > 0 {
+            self.append(operations)?;
+        }
+        Ok(moved)
+    }
+
+    /// ## Compaction
+    ///
+    /// Rewrite the log with only the entries still stored, each under its
+    /// current pseudonym, such that removed data values are deleted from
+    /// the file. The compacted log is written to a temporary file next to
+    /// the store, which then replaces the log in a single rename, so the
+    /// store holds either the previous or the compacted log after a crash.
+    ///
+    /// Raises:
+    /// - `CorruptedData`: If a stored entry cannot be decoded.
+    /// - `StorageError`: If reading or writing a file fails. The store is
+    ///   left unchanged.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".compact");
+        let temp_path = PathBuf::from(temp_path);
+        match fs::remove_file(&temp_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+
+        let mut compacted = Self::open(&temp_path)?;
+        let mut batch = Vec::with_capacity(ENTRIES_PER_RECORD);
+        {
+            let mut file = self.file.lock().map_err(|_| Error::InternalError)?;
+            for (pseudonym, locations) in self.index.values().flatten() {
+                for location in locations {
+                    let mut entry = read_entry(&mut file, *location)?;
+                    entry.handle = *pseudonym;
+                    batch.push(Operation::Insert(entry));
+                    if batch.len() == ENTRIES_PER_RECORD {
+                        compacted.append(std::mem::take(&mut batch))?;
+                    }
+                }
+            }
+        }
+        if !batch.is_empty() {
+            compacted.append(batch)?;
+        }
+
+        fs::rename(&temp_path, &self.path)?;
+        #[cfg(unix)]
+        File::open(match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        })?
+        .sync_all()?;
+
+        compacted.path = std::mem::take(&mut self.path);
+        *self = compacted;
+        Ok(())
+    }
+}
+
+/// Read up to `len` bytes, fewer only if the end of the input is reached.
+fn read_up_to(reader: &mut impl Read, len: u64) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Decode the operations of a record body starting at `offset` in the log.
+fn decode_body(body: &[u8], offset: u64) -> Result<Vec<(Location, Operation)>, Error> {
+    let mut reader = Reader::new(body);
+    let count = reader.count(4)?;
+    let mut position = offset + 4;
+    let mut operations = Vec::with_capacity(count);
+    for _ in 0..count {
+        let encoded = reader.byte_string()?;
+        let location = Location {
+            offset: position + 4,
+            len: encoded.len() as u32,
+        };
+        operations.push((location, Operation::decode(encoded)?));
+        position += 4 + encoded.len() as u64;
+    }
+    reader.finish()?;
+    Ok(operations)
+}
+
+fn read_entry(file: &mut File, location: Location) -> Result<PseudonymizedData, Error> {
+    let mut encoded = vec![0; location.len as usize];
+    file.seek(SeekFrom::Start(location.offset))?;
+    file.read_exact(&mut encoded)?;
+    PseudonymizedData::from_bytes(&encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use hacspec_lib::Randomness;
+
+    use super::*;
+
+    /// A lake store file that is removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new() -> Self {
+            let mut randomness = Randomness::from_entropy().unwrap();
+            Self(std::env::temp_dir().join(format!(
+                "scrambledb-lake-{}",
+                hex::encode(randomness.bytes(16).unwrap())
+            )))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn entry(handle: u8, attribute_name: &str, value: &[u8]) -> PseudonymizedData {
+        PseudonymizedData {
+            handle: FinalizedPseudonym {
+                epoch: 0,
+                value: [handle; 64],
+            },
+            data_value: DataValue {
+                value: value.to_vec(),
+                attribute_name: attribute_name.into(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_lake_store() {
+        let path = TempFile::new();
+        let first_batch = Table::new(
+            "Lake".into(),
+            vec![
+                entry(1, "Address", b"1 Main St"),
+                entry(2, "Address", b"2 Main St"),
+                entry(3, "Date of Birth", b"1970-01-01"),
+            ],
+        );
+        let second_batch = Table::new(
+            "Lake".into(),
+            vec![
+                entry(4, "Favorite Color", b"Blue"),
+                entry(1, "Address", b""),
+            ],
+        );
+
+        // Failures of the file are reported as storage errors.
+        assert_eq!(
+            LakeStore::open(std::env::temp_dir()).err(),
+            Some(Error::StorageError)
+        );
+
+        let mut store = LakeStore::open(&path.0).unwrap();
+        assert!(store.is_empty());
+        store.ingest(&first_batch).unwrap();
+        store.ingest(&second_batch).unwrap();
+        drop(store);
+
+        let mut store = LakeStore::open(&path.0).unwrap();
+        assert_eq!(store.len(), 5);
+        assert_eq!(
+            store.attributes().collect::<Vec<_>>(),
+            ["Address", "Date of Birth", "Favorite Color"]
+        );
+        assert_eq!(
+            store
+                .lookup("Address", &entry(1, "Address", b"").handle)
+                .unwrap(),
+            [
+                first_batch.data()[0].data_value.clone(),
+                second_batch.data()[1].data_value.clone()
+            ]
+        );
+        assert!(store
+            .lookup("Date of Birth", &entry(1, "Address", b"").handle)
+            .unwrap()
+            .is_empty());
+
+        let mut selected = store
+            .select("Join", &["Address", "Favorite Color"])
+            .unwrap()
+            .data()
+            .to_vec();
+        let mut expected = vec![
+            first_batch.data()[0].clone(),
+            first_batch.data()[1].clone(),
+            second_batch.data()[0].clone(),
+            second_batch.data()[1].clone(),
+        ];
+        selected.sort();
+        expected.sort();
+        assert_eq!(selected, expected);
+        assert_eq!(
+            store.select("Join", &["Name"]).unwrap_err(),
+            Error::InvalidInput
+        );
+
+        store
+            .ingest(&Table::new("Lake".into(), vec![entry(5, "Name", b"x")]))
+            .unwrap();
+        assert_eq!(store.len(), 6);
+    }
+
+    #[test]
+    fn test_lake_store_recovery() {
+        let path = TempFile::new();
+        let batch = Table::new(
+            "Lake".into(),
+            vec![entry(1, "Address", b"1 Main St"), entry(2, "Address", b"")],
+        );
+
+        let mut store = LakeStore::open(&path.0).unwrap();
+        store.ingest(&batch).unwrap();
+        store.ingest(&batch).unwrap();
+        drop(store);
+        let complete = std::fs::read(&path.0).unwrap();
+        let first_record = (complete.len() - HEADER_LEN as usize) / 2;
+
+        // A partially written batch is discarded, including a torn length.
+        for torn_len in [1, 3, first_record - 1] {
+            std::fs::write(&path.0, &complete[..complete.len() - torn_len]).unwrap();
+            let mut store = LakeStore::open(&path.0).unwrap();
+            assert_eq!(store.len(), 2);
+            store.ingest(&batch).unwrap();
+            drop(store);
+            assert_eq!(LakeStore::open(&path.0).unwrap().len(), 4);
+        }
+
+        // A complete trailing batch with a wrong checksum is discarded.
+        let mut corrupted = complete.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        std::fs::write(&path.0, &corrupted).unwrap();
+        assert_eq!(LakeStore::open(&path.0).unwrap().len(), 2);
+
+        // Corruption before the end of the log is detected.
+        let mut corrupted = complete.clone();
+        corrupted[HEADER_LEN as usize + 10] ^= 1;
+        std::fs::write(&path.0, &corrupted).unwrap();
+        assert_eq!(LakeStore::open(&path.0).err(), Some(Error::CorruptedData));
+
+        // An interrupted creation is completed.
+        std::fs::write(&path.0, b"SDB-L").unwrap();
+        assert!(LakeStore::open(&path.0).unwrap().is_empty());
+        assert_eq!(std::fs::read(&path.0).unwrap().len(), HEADER_LEN as usize);
+
+        std::fs::write(&path.0, b"SDB-LAKX").unwrap();
+        assert_eq!(LakeStore::open(&path.0).err(), Some(Error::CorruptedData));
+    }
+
+    #[test]
+    fn test_lake_store_erasure_and_rotation() {
+        use crate::{
+            erasure::{blind_erasure_request, finalize_erasure_request, ErasureCover},
+            finalize::finalize_blinded_table,
+            rotation::rekey_table,
+            setup::ConverterContext,
+            split::{blind_orthonymous_table, pseudonymize_blinded_table},
+            test_util::{converter_public_keys, generate_plain_table},
+        };
+
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let mut lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let plain_table = generate_plain_table();
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            plain_table.clone(),
+            &mut randomness,
+        )
+        .unwrap();
+        let converted_table = pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let lake_table = finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            converted_table,
+            &mut randomness,
+        )
+        .unwrap();
+
+        let path = TempFile::new();
+        let mut store = LakeStore::open(&path.0).unwrap();
+        store.ingest(&lake_table).unwrap();
+
+        // After rotating the PRP key, the store is moved to the new key.
+        let previous_epoch = lake_context.prp_epoch();
+        lake_context.rotate_prp_key(&mut randomness).unwrap();
+        let rekeyed_table = rekey_table(&lake_context, lake_table.clone()).unwrap();
+        assert_eq!(store.rekey(&lake_context).unwrap(), lake_table.data().len());
+        assert_eq!(store.rekey(&lake_context).unwrap(), 0);
+        lake_context.retire_prp_key(previous_epoch).unwrap();
+        drop(store);
+
+        let mut store = LakeStore::open(&path.0).unwrap();
+        for entry in rekeyed_table.data() {
+            assert!(store
+                .lookup(&entry.data_value.attribute_name, &entry.handle)
+                .unwrap()
+                .contains(&entry.data_value));
+        }
+        let attributes = store.attributes().map(String::from).collect::<Vec<_>>();
+        let attributes = attributes.iter().map(String::as_str).collect::<Vec<_>>();
+        let mut selected = store.select("Join", &attributes).unwrap().data().to_vec();
+        let mut expected = rekeyed_table.data().to_vec();
+        selected.sort();
+        expected.sort();
+        assert_eq!(selected, expected);
+
+        let erasure_request = |lake_context: &StoreContext,
+                               identifier: &str,
+                               attribute: &str,
+                               randomness: &mut Randomness| {
+            let request = blind_erasure_request(
+                &converter_keys,
+                &lake_ek,
+                lake_bpk,
+                identifier,
+                &[attribute],
+                &ErasureCover {
+                    table_identifier: "Table".into(),
+                    value_len: 16,
+                    dummy_rows: 1,
+                },
+                randomness,
+            )
+            .unwrap();
+            let pseudonymized_request = pseudonymize_blinded_table(
+                &converter_context,
+                lake_bpk,
+                &lake_ek,
+                request,
+                randomness,
+            )
+            .unwrap();
+            finalize_erasure_request(
+                lake_context,
+                &converter_keys,
+                pseudonymized_request,
+                randomness,
+            )
+            .unwrap()
+        };
+        let subject_values = |identifier: &str, attribute: &str| {
+            plain_table
+                .data()
+                .iter()
+                .filter(|entry| {
+                    entry.handle == identifier && entry.data_value.attribute_name == attribute
+                })
+                .map(|entry| entry.data_value.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Erasure removes the data subject's entries durably.
+        let pseudonyms = erasure_request(&lake_context, "Alice", "Address", &mut randomness);
+        let erased = subject_values("Alice", "Address");
+        assert!(!erased.is_empty());
+        assert_eq!(
+            store.erase(&lake_context, &pseudonyms).unwrap(),
+            erased.len()
+        );
+        assert_eq!(store.erase(&lake_context, &pseudonyms).unwrap(), 0);
+        for pseudonym in &pseudonyms["Address"] {
+            assert_eq!(store.lookup("Address", pseudonym).unwrap(), Vec::new());
+        }
+        drop(store);
+
+        let mut store = LakeStore::open(&path.0).unwrap();
+        let remaining = plain_table.data().len() - erased.len();
+        assert_eq!(store.len(), remaining);
+
+        // Compaction deletes erased data values from the file.
+        let contains = |needle: &[u8]| {
+            std::fs::read(&path.0)
+                .unwrap()
+                .windows(needle.len())
+                .any(|window| window == needle)
+        };
+        assert!(erased.iter().all(|value| contains(&value.value)));
+        store.compact().unwrap();
+        assert!(!erased.iter().any(|value| contains(&value.value)));
+        assert_eq!(store.len(), remaining);
+        store
+            .ingest(&Table::new("Lake".into(), vec![entry(5, "Name", b"x")]))
+            .unwrap();
+        drop(store);
+
+        let store = LakeStore::open(&path.0).unwrap();
+        assert_eq!(store.len(), remaining + 1);
+        let mut selected = store.select("Join", &attributes).unwrap().data().to_vec();
+        let mut expected = rekeyed_table
+            .data()
+            .iter()
+            .filter(|entry| !erased.contains(&entry.data_value))
+            .cloned()
+            .collect::<Vec<_>>();
+        selected.sort();
+        expected.sort();
+        assert_eq!(selected, expected);
+        drop(store);
+
+        // Entries which are not yet moved to the current PRP key are erased
+        // as well.
+        let mut store = LakeStore::open(&path.0).unwrap();
+        lake_context.rotate_prp_key(&mut randomness).unwrap();
+        let pseudonyms = erasure_request(&lake_context, "Bob", "Address", &mut randomness);
+        let erased = subject_values("Bob", "Address");
+        assert!(!erased.is_empty());
+        assert_eq!(
+            store.erase(&lake_context, &pseudonyms).unwrap(),
+            erased.len()
+        );
+        assert_eq!(store.len(), remaining + 1 - erased.len());
+    }
+}
@@ -74,6 +74,9 @@
 //! The contained pseudonyms are fresh for each join and are
 //! non-transitive, i.e. it is not possible to further join two
 //! join-results based on the join pseudonym.
+//! Processors that need to add columns to an earlier join result, e.g. for
+//! longitudinal studies, can opt in to a [join session](session), within
+//! which join pseudonyms stay consistent.
 //!
 //! Since the result of this conversion is a joined table, we refer to the
 //! operation as a _join conversion_.
@@ -270,11 +273,25 @@ impl SerializedHPKE {
 
 pub mod table;
 pub mod setup;
+pub mod threshold;
 pub mod split;
 pub mod join;
 pub mod finalize;
+pub mod rotation;
+pub mod erasure;
+pub mod policy;
+pub mod session;
+pub mod audit;
 pub mod data_transformations;
 pub mod data_types;
+pub mod serialization;
+pub mod key_wrap;
+pub mod converter_service;
+pub mod evaluator_service;
+pub mod stream;
+pub mod lake_store;
+#[cfg(feature = "csv")]
+pub mod csv;
 
 pub mod error;
 
@@ -0,0 +1,512 @@
+//! # Join Authorization
+//!
+//! Without further restrictions, any data processor can have any
+//! combination of pseudonymized columns joined by handing its blinding
+//! public key to the data lake. A [`JoinPolicy`] declares which
+//! processors may request joins over which sets of attributes.
+//!
+//! Processors are identified by their blinding public key. The converted
+//! pseudonyms are blinded towards this key, so only the holder of the
+//! corresponding private key can link the entries of a join.
+//!
+//! The policy is enforced twice:
+//! - at the data lake before blinding the requested columns, using
+//!   [`authorized_blind_pseudonymous_table`] or, for streamed columns,
+//!   [`authorized_blind_pseudonymous_stream`], and
+//! - at the converter before converting them, using
+//!   [`authorized_convert_blinded_table`],
+//!   [`authorized_convert_blinded_tables`] or a
+//!   [converter service](crate::converter_service::ConverterService) set
+//!   up with the policy.
+//!
+//! Requests that are not covered by the policy are rejected with
+//! [`Error::PolicyViolation`].
+//!
+//! Policies can be stored in the [wire format](crate::serialization), e.g.
+//! to configure a standalone converter service.
+
+use std::collections::BTreeSet;
+
+use hacspec_lib::Randomness;
+use oprf::coprf::coprf_setup::BlindingPublicKey;
+
+use crate::{
+    data_types::{BlindedPseudonymizedData, PseudonymizedData},
+    error::Error,
+    join::{blind_pseudonymous_table, convert_blinded_tables},
+    serialization::{
+        put_byte_string, put_point, put_u32, Reader, WireFormat, POINT_BYTES,
+        STORAGE_FORMAT_VERSION,
+    },
+    setup::{ConverterContext, ConverterPublicKeys, StoreContext, StoreEncryptionKey},
+    stream::{blind_pseudonymous_stream, ShuffledStream, StreamConfig},
+    table::Table,
+};
+
+/// Permission for a processor to join any subset of a set of attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoinGrant {
+    /// The processor's public blinding key
+    pub processor: BlindingPublicKey,
+    /// The attributes the processor may join
+    pub attributes: BTreeSet<String>,
+}
+
+/// A set of join grants. Everything that is not granted is denied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JoinPolicy {
+    grants: Vec<JoinGrant>,
+}
+
+impl JoinPolicy {
+    /// Create a policy from a list of grants.
+    pub fn new(grants: Vec<JoinGrant>) -> Self {
+        Self { grants }
+    }
+
+    /// Grant a processor the join of any subset of the given attributes.
+    pub fn allow(mut self, processor: BlindingPublicKey, attributes: &[&str]) -> Self {
+        self.grants.push(JoinGrant {
+            processor,
+            attributes: attributes.iter().map(|a| a.to_string()).collect(),
+        });
+        self
+    }
+
+    /// The grants of this policy.
+    pub fn grants(&self) -> &[JoinGrant] {
+        &self.grants
+    }
+
+    /// Check that a processor may join the given attributes, i.e. that a
+    /// single grant of the processor covers all of them.
+    ///
+    /// Raises:
+    /// - `PolicyViolation`: If no grant covers the attributes.
+    pub fn authorize<'a>(
+        &self,
+        processor: &BlindingPublicKey,
+        attributes: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), Error> {
+        let attributes: BTreeSet<&str> = attributes.into_iter().collect();
+        if self.grants.iter().any(|grant| {
+            grant.processor == *processor
+                && attributes
+                    .iter()
+                    .all(|attribute| grant.attributes.contains(*attribute))
+        }) {
+            Ok(())
+        } else {
+            Err(Error::PolicyViolation)
+        }
+    }
+}
+
+impl WireFormat for JoinPolicy {
+    const TAG: u8 = 0x12;
+    const VERSION: u8 = STORAGE_FORMAT_VERSION;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_u32(out, self.grants.len() as u32);
+        for grant in &self.grants {
+            put_point(out, &grant.processor);
+            put_u32(out, grant.attributes.len() as u32);
+            for attribute in &grant.attributes {
+                put_byte_string(out, attribute.as_bytes());
+            }
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        let count = reader.count(POINT_BYTES + 4)?;
+        let mut grants = Vec::with_capacity(count);
+        for _ in 0..count {
+            let processor = reader.point()?;
+            let attribute_count = reader.count(4)?;
+            let mut attributes = BTreeSet::new();
+            for _ in 0..attribute_count {
+                if !attributes.insert(reader.string()?) {
+                    return Err(Error::CorruptedData);
+                }
+            }
+            grants.push(JoinGrant {
+                processor,
+                attributes,
+            });
+        }
+        Ok(Self { grants })
+    }
+}
+
+/// ## Authorized Blinding of Pseudonymous Tables
+///
+/// Check that the processor may join the attributes of a table of
+/// pseudonymous data values, then blind it as in
+/// [`blind_pseudonymous_table`].
+///
+/// Inputs:
+/// - `policy`: The data lake's join policy
+/// - `store_context`: The data store's pseudonymization context
+/// - `converter_keys`: The converter's public keys
+/// - `bpk_processor`: The processor's public blinding key
+/// - `ek_processor`: The processor's public encryption key
+/// - `table`: A table of pseudonymous data values
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A table of blinded pseudonymous data values.
+///
+/// Raises:
+/// - `PolicyViolation`: If the policy does not allow the processor to join
+///   the attributes of the table.
+pub fn authorized_blind_pseudonymous_table(
+    policy: &JoinPolicy,
+    store_context: &StoreContext,
+    converter_keys: &ConverterPublicKeys,
+    bpk_processor: BlindingPublicKey,
+    ek_processor: &StoreEncryptionKey,
+    table: Table<PseudonymizedData>,
+    randomness: &mut Randomness,
+) -> Result<Table<BlindedPseudonymizedData>, Error> {
+    policy.authorize(
+        &bpk_processor,
+        table
+            .data()
+            .iter()
+            .map(|entry| entry.data_value.attribute_name.as_str()),
+    )?;
+    blind_pseudonymous_table(
+        store_context,
+        converter_keys,
+        bpk_processor,
+        ek_processor,
+        table,
+        randomness,
+    )
+}
+
+/// ## Authorized Blinding of Pseudonymous Streams
+///
+/// Streaming variant of [`authorized_blind_pseudonymous_table`]. Since the
+/// attributes of a stream are only known once it is read, each entry is
+/// checked before it is blinded: The policy must allow the processor to
+/// join its attribute together with those of all previous entries.
+///
+/// Inputs:
+/// - `policy`: The data lake's join policy
+/// - `store_context`: The data store's pseudonymization context
+/// - `converter_keys`: The converter's public keys
+/// - `bpk_processor`: The processor's public blinding key
+/// - `ek_processor`: The processor's public encryption key
+/// - `entries`: A stream of pseudonymous data values
+/// - `config`: Resource limits of the transformation
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A stream of blinded pseudonymous data values, in random order.
+///
+/// Raises:
+/// - `PolicyViolation`: If the policy does not allow the processor to join
+///   the attributes of the stream.
+/// - Any error of [`blind_pseudonymous_stream`].
+#[allow(clippy::too_many_arguments)]
+pub fn authorized_blind_pseudonymous_stream(
+    policy: &JoinPolicy,
+    store_context: &StoreContext,
+    converter_keys: &ConverterPublicKeys,
+    bpk_processor: BlindingPublicKey,
+    ek_processor: &StoreEncryptionKey,
+    entries: impl IntoIterator<Item = Result<PseudonymizedData, Error>>,
+    config: &StreamConfig,
+    randomness: &mut Randomness,
+) -> Result<ShuffledStream<BlindedPseudonymizedData>, Error> {
+    let mut attributes = BTreeSet::new();
+    let authorized = entries.into_iter().map(|entry| {
+        let entry = entry?;
+        if !attributes.contains(&entry.data_value.attribute_name) {
+            attributes.insert(entry.data_value.attribute_name.clone());
+            policy.authorize(&bpk_processor, attributes.iter().map(String::as_str))?;
+        }
+        Ok(entry)
+    });
+    blind_pseudonymous_stream(
+        store_context,
+        converter_keys,
+        bpk_processor,
+        ek_processor,
+        authorized,
+        config,
+        randomness,
+    )
+}
+
+/// ## Authorized Oblivious Conversion
+///
+/// Check that the processor may join the attributes of a table of blinded
+/// pseudonymous data values, then convert it as in
+/// [`convert_blinded_table`](crate::join::convert_blinded_table).
+///
+/// Inputs:
+/// - `policy`: The converter's join policy
+/// - `converter_context`: The Converter's coPRF conversion context
+/// - `bpk_processor`: The processor's public blinding key
+/// - `ek_processor`: The processor's public encryption key
+/// - `table`: A table of blinded pseudonymous data values
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A table of consistently join-pseudonymized data values.
+///
+/// Raises:
+/// - `PolicyViolation`: If the policy does not allow the processor to join
+///   the attributes of the table.
+pub fn authorized_convert_blinded_table(
+    policy: &JoinPolicy,
+    converter_context: &ConverterContext,
+    bpk_processor: BlindingPublicKey,
+    ek_processor: &StoreEncryptionKey,
+    table: Table<BlindedPseudonymizedData>,
+    randomness: &mut Randomness,
+) -> Result<Table<BlindedPseudonymizedData>, Error> {
+    authorized_convert_blinded_tables(
+        policy,
+        converter_context,
+        bpk_processor,
+        ek_processor,
+        vec![table],
+        randomness,
+    )
+}
+
+/// ## Authorized Multi-Lake Oblivious Conversion
+///
+/// Check that the processor may join the attributes of all parts of a
+/// multi-lake join, then convert them as in [`convert_blinded_tables`]. A
+/// single grant must cover the attributes of all parts.
+///
+/// Inputs:
+/// - `policy`: The converter's join policy
+/// - `converter_context`: The Converter's coPRF conversion context
+/// - `bpk_processor`: The processor's public blinding key
+/// - `ek_processor`: The processor's public encryption key
+/// - `parts`: Tables of blinded pseudonymous data values
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A table of consistently join-pseudonymized data values.
+///
+/// Raises:
+/// - `PolicyViolation`: If the policy does not allow the processor to join
+///   the attributes of all parts.
+pub fn authorized_convert_blinded_tables(
+    policy: &JoinPolicy,
+    converter_context: &ConverterContext,
+    bpk_processor: BlindingPublicKey,
+    ek_processor: &StoreEncryptionKey,
+    parts: Vec<Table<BlindedPseudonymizedData>>,
+    randomness: &mut Randomness,
+) -> Result<Table<BlindedPseudonymizedData>, Error> {
+    policy.authorize(
+        &bpk_processor,
+        parts
+            .iter()
+            .flat_map(|part| part.data())
+            .map(|entry| entry.encrypted_data_value.attribute_name.as_str()),
+    )?;
+    convert_blinded_tables(
+        converter_context,
+        bpk_processor,
+        ek_processor,
+        parts,
+        randomness,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        finalize::finalize_blinded_table,
+        split::{blind_orthonymous_table, pseudonymize_blinded_table},
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_join_policy() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+        let processor_context = StoreContext::setup(&mut randomness).unwrap();
+        let (processor_ek, processor_bpk) = processor_context.public_keys();
+        let other_processor = StoreContext::setup(&mut randomness).unwrap();
+        let (other_ek, other_bpk) = other_processor.public_keys();
+
+        let policy = JoinPolicy::default()
+            .allow(processor_bpk, &["Address", "Favorite Color"])
+            .allow(processor_bpk, &["Date of Birth"]);
+
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+        let converted_table = pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let lake_table = finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            converted_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let columns = |attributes: &[&str]| {
+            Table::new(
+                "Join".into(),
+                lake_table
+                    .data()
+                    .iter()
+                    .filter(|entry| attributes.contains(&entry.data_value.attribute_name.as_str()))
+                    .cloned()
+                    .collect(),
+            )
+        };
+
+        // Attributes must be covered by a single grant of the processor.
+        for (bpk, ek, attributes) in [
+            (
+                processor_bpk,
+                &processor_ek,
+                &["Address", "Date of Birth"][..],
+            ),
+            (other_bpk, &other_ek, &["Address"][..]),
+        ] {
+            assert_eq!(
+                authorized_blind_pseudonymous_table(
+                    &policy,
+                    &lake_context,
+                    &converter_keys,
+                    bpk,
+                    ek,
+                    columns(attributes),
+                    &mut randomness,
+                )
+                .unwrap_err(),
+                Error::PolicyViolation
+            );
+            assert_eq!(
+                authorized_blind_pseudonymous_stream(
+                    &policy,
+                    &lake_context,
+                    &converter_keys,
+                    bpk,
+                    ek,
+                    columns(attributes).into_data().into_iter().map(Ok),
+                    &StreamConfig::default(),
+                    &mut randomness,
+                )
+                .err(),
+                Some(Error::PolicyViolation)
+            );
+        }
+        let streamed = authorized_blind_pseudonymous_stream(
+            &policy,
+            &lake_context,
+            &converter_keys,
+            processor_bpk,
+            &processor_ek,
+            columns(&["Address", "Favorite Color"])
+                .into_data()
+                .into_iter()
+                .map(Ok),
+            &StreamConfig::default(),
+            &mut randomness,
+        )
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(
+            streamed.len(),
+            columns(&["Address", "Favorite Color"]).data().len()
+        );
+
+        let blinded_table = authorized_blind_pseudonymous_table(
+            &policy,
+            &lake_context,
+            &converter_keys,
+            processor_bpk,
+            &processor_ek,
+            columns(&["Address", "Favorite Color"]),
+            &mut randomness,
+        )
+        .unwrap();
+
+        // The converter enforces its own policy.
+        assert_eq!(
+            authorized_convert_blinded_table(
+                &JoinPolicy::default().allow(processor_bpk, &["Address"]),
+                &converter_context,
+                processor_bpk,
+                &processor_ek,
+                blinded_table.clone(),
+                &mut randomness,
+            )
+            .unwrap_err(),
+            Error::PolicyViolation
+        );
+        let joined_table = authorized_convert_blinded_table(
+            &policy,
+            &converter_context,
+            processor_bpk,
+            &processor_ek,
+            blinded_table,
+            &mut randomness,
+        )
+        .unwrap();
+        assert!(finalize_blinded_table(
+            &processor_context,
+            &converter_keys,
+            joined_table,
+            &mut randomness
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_join_policy_encoding() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let (_, processor_bpk) = StoreContext::setup(&mut randomness).unwrap().public_keys();
+        let (_, other_bpk) = StoreContext::setup(&mut randomness).unwrap().public_keys();
+
+        let policy = JoinPolicy::default()
+            .allow(processor_bpk, &["Address", "Favorite Color"])
+            .allow(other_bpk, &[])
+            .allow(processor_bpk, &["Date of Birth"]);
+        let bytes = policy.to_bytes();
+        assert_eq!(JoinPolicy::from_bytes(&bytes).unwrap(), policy);
+        for len in 0..bytes.len() {
+            assert!(JoinPolicy::from_bytes(&bytes[..len]).is_err());
+        }
+
+        // Attributes are listed at most once per grant.
+        let mut bytes = JoinPolicy::default()
+            .allow(processor_bpk, &["Address", "Addresx"])
+            .to_bytes();
+        *bytes.last_mut().unwrap() = b's';
+        assert_eq!(
+            JoinPolicy::from_bytes(&bytes).unwrap_err(),
+            Error::CorruptedData
+        );
+    }
+}
@@ -0,0 +1,533 @@
+//! # Converter Key Rotation
+//!
+//! All pseudonymization keys are derived from the converter's master
+//! secret. If the master secret is compromised, it is replaced by setting
+//! up a fresh converter context. Since pseudonyms held by data stores were
+//! derived from the old master secret, they are then moved to the new one
+//! without re-ingesting the source data and without revealing the
+//! underlying identifiers to anyone:
+//!
+//! 1. The data store blinds its table of pseudonymous data towards itself
+//!    and the old converter context using
+//!    [`blind_pseudonymous_table`](crate::join::blind_pseudonymous_table)
+//!    and the old converter public keys.
+//! 2. The converter derives the [update token](crate::setup::UpdateToken)
+//!    of every attribute using [`ConverterContext::update_token`](crate::setup::ConverterContext::update_token), after
+//!    which the old converter context can be destroyed. It then obliviously
+//!    updates the blinded pseudonyms using [`update_blinded_table`], which
+//!    applies the update token of each entry's attribute.
+//! 3. The data store verifies the converter's proofs and finalizes the
+//!    result using [`finalize_updated_table`].
+//!
+//! Afterwards, the data store holds the same pseudonyms as if its data had
+//! been pseudonymized using the new converter context in the first place,
+//! and uses the new converter public keys from then on. The same steps
+//! migrate pseudonyms from a converter holding the master secret to a
+//! [threshold converter](crate::setup::ConverterContext::threshold).
+//!
+//! # Data Store PRP Key Rotation
+//!
+//! Independently, a data store can replace the PRP key its pseudonyms are
+//! hardened with. This does not involve the converter:
+//!
+//! 1. The data store adds a PRP key for a new epoch using
+//!    [`StoreContext::rotate_prp_key`]. Pseudonyms finalized from then on
+//!    are finalized under the new key, while pseudonyms of previous epochs
+//!    can still be recovered.
+//! 2. The data store moves each of its tables to the new key using
+//!    [`rekey_table`].
+//! 3. Once all tables have been moved, the data store removes the previous
+//!    key using [`StoreContext::retire_prp_key`].
+
+use std::collections::BTreeMap;
+
+use hacspec_lib::Randomness;
+use oprf::coprf::coprf_setup::BlindingPublicKey;
+
+use crate::{
+    data_transformations::{finalize_blinded_datum, update_blinded_datum, verify_blinded_datum},
+    data_types::{BlindedPseudonymizedData, PseudonymizedData},
+    error::Error,
+    setup::{ConverterPublicKeys, StoreContext, StoreEncryptionKey, UpdateToken},
+    table::Table,
+};
+
+/// ## Oblivious Pseudonym Update
+///
+/// Obliviously update a table of blinded pseudonymous data values from the
+/// pseudonymization keys derived from one converter master secret to those
+/// derived from another and shuffle the result.
+///
+/// Inputs:
+/// - `tokens`: The update tokens, by attribute name, see
+///   [`ConverterContext::update_token`](crate::setup::ConverterContext::update_token)
+/// - `bpk_receiver`: The receiver's public blinding key
+/// - `ek_receiver`: The receiver's public encryption key
+/// - `table`: A table of blinded pseudonymous data values, blinded towards
+///   the converter context before the rotation
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A table of blinded pseudonymous data values under the new keys.
+///
+/// Raises:
+/// - `InvalidInput`: If there is no update token for the attribute of an
+///   entry.
+pub fn update_blinded_table(
+    tokens: &BTreeMap<String, UpdateToken>,
+    bpk_receiver: BlindingPublicKey,
+    ek_receiver: &StoreEncryptionKey,
+    table: Table<BlindedPseudonymizedData>,
+    randomness: &mut Randomness,
+) -> Result<Table<BlindedPseudonymizedData>, Error> {
+    let updated_data = table.try_map_randomized(randomness, |entry, randomness| {
+        update_blinded_datum(
+            tokens
+                .get(&entry.encrypted_data_value.attribute_name)
+                .ok_or(Error::InvalidInput)?,
+            &bpk_receiver,
+            ek_receiver,
+            entry,
+            randomness,
+        )
+    })?;
+
+    let mut updated_table = Table::new(table.identifier().into(), updated_data);
+    updated_table.shuffle(randomness)?;
+
+    Ok(updated_table)
+}
+
+/// ## Finalization of Updated Tables
+///
+/// Finalize a table of blinded pseudonymous data values output by
+/// [`update_blinded_table`] by verifying the converter's proofs, applying
+/// the finalization operation on each entry and shuffling the result.
+///
+/// Every entry must have been converted from the key of its attribute
+/// under the old converter public keys to the key of its attribute under
+/// the new converter public keys.
+///
+/// Inputs:
+/// - `store_context`: The data store's pseudonymization context
+/// - `converter_keys_from`: The converter's public keys before the rotation
+/// - `converter_keys_to`: The converter's public keys after the rotation
+/// - `table`: A table of updated blinded pseudonymous data values
+/// - `randomness`: Random bytes
+///
+/// Output:
+/// A table of pseudonymized data values under the new keys.
+///
+/// Raises:
+/// - `VerificationError`: If a proof of the converter does not verify or an
+///   entry was not converted to the new key of its attribute.
+pub fn finalize_updated_table(
+    store_context: &StoreContext,
+    converter_keys_from: &ConverterPublicKeys,
+    converter_keys_to: &ConverterPublicKeys,
+    table: Table<BlindedPseudonymizedData>,
+    randomness: &mut Randomness,
+) -> Result<Table<PseudonymizedData>, Error> {
+    table.try_map(|entry| {
+        let target_key = verify_blinded_datum(store_context, converter_keys_from, entry)?;
+        let expected_key =
+            converter_keys_to.attribute_key(&entry.encrypted_data_value.attribute_name);
+        if target_key.is_none() || target_key != expected_key {
+            return Err(Error::VerificationError);
+        }
+        Ok(())
+    })?;
+
+    let pseudonymized_data = table.try_map(|entry| finalize_blinded_datum(store_context, entry))?;
+
+    let mut pseudonymized_table = Table::new(table.identifier().into(), pseudonymized_data);
+    pseudonymized_table.shuffle(randomness)?;
+
+    Ok(pseudonymized_table)
+}
+
+/// ## Rekeying Pseudonymous Tables
+///
+/// Move a table of pseudonymous data values to the data store's current PRP
+/// key by recovering the raw pseudonym of each entry finalized under a
+/// previous key and finalizing it under the current key. Entries already
+/// finalized under the current key are left unchanged.
+///
+/// Inputs:
+/// - `store_context`: The data store's pseudonymization context
+/// - `table`: A table of pseudonymous data values
+///
+/// Output:
+/// A table of pseudonymous data values finalized under the current PRP key.
+///
+/// Raises:
+/// - `InvalidInput`: If the data store holds no PRP key for the epoch of an
+///   entry.
+pub fn rekey_table(
+    store_context: &StoreContext,
+    table: Table<PseudonymizedData>,
+) -> Result<Table<PseudonymizedData>, Error> {
+    let rekeyed_data = table.try_map(|entry| {
+        Ok(PseudonymizedData {
+            handle: store_context.rekey_pseudonym(entry.handle)?,
+            data_value: entry.data_value.clone(),
+        })
+    })?;
+
+    Ok(Table::new(table.identifier().into(), rekeyed_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use oprf::coprf::{coprf_setup::CoPRFEvaluatorContext, coprf_threshold::deal};
+
+    use crate::{
+        finalize::finalize_blinded_table,
+        join::{blind_pseudonymous_table, convert_blinded_table},
+        setup::ConverterContext,
+        split::{blind_orthonymous_table, pseudonymize_blinded_table},
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    use super::*;
+
+    fn split(
+        converter_context: &ConverterContext,
+        lake_context: &StoreContext,
+        randomness: &mut Randomness,
+    ) -> Table<PseudonymizedData> {
+        let converter_keys = converter_public_keys(converter_context);
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            randomness,
+        )
+        .unwrap();
+        let converted_table = pseudonymize_blinded_table(
+            converter_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            randomness,
+        )
+        .unwrap();
+        finalize_blinded_table(lake_context, &converter_keys, converted_table, randomness).unwrap()
+    }
+
+    /// The update tokens of all attributes of a table.
+    fn update_tokens(
+        from: &ConverterContext,
+        to: &ConverterContext,
+        table: &Table<BlindedPseudonymizedData>,
+    ) -> BTreeMap<String, UpdateToken> {
+        table
+            .data()
+            .iter()
+            .map(|entry| {
+                let attribute = entry.encrypted_data_value.attribute_name.clone();
+                let token = from.update_token(to, &attribute).unwrap();
+                (attribute, token)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rotation() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let old_converter = ConverterContext::setup(&mut randomness).unwrap();
+        let old_keys = converter_public_keys(&old_converter);
+        let new_converter = ConverterContext::setup(&mut randomness).unwrap();
+        let new_keys = converter_public_keys(&new_converter);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let old_table = split(&old_converter, &lake_context, &mut randomness);
+        let blinded_table = blind_pseudonymous_table(
+            &lake_context,
+            &old_keys,
+            lake_bpk,
+            &lake_ek,
+            old_table.clone(),
+            &mut randomness,
+        )
+        .unwrap();
+        let tokens = update_tokens(&old_converter, &new_converter, &blinded_table);
+
+        // A conversion is not accepted as an update. The old converter
+        // context is no longer needed afterwards.
+        let conversion = convert_blinded_table(
+            &old_converter,
+            lake_bpk,
+            &lake_ek,
+            blind_pseudonymous_table(
+                &lake_context,
+                &old_keys,
+                lake_bpk,
+                &lake_ek,
+                old_table.clone(),
+                &mut randomness,
+            )
+            .unwrap(),
+            &mut randomness,
+        )
+        .unwrap();
+        drop(old_converter);
+        assert_eq!(
+            finalize_updated_table(
+                &lake_context,
+                &old_keys,
+                &new_keys,
+                conversion,
+                &mut randomness
+            )
+            .unwrap_err(),
+            Error::VerificationError
+        );
+
+        // Attributes without an update token are rejected.
+        assert_eq!(
+            update_blinded_table(
+                &BTreeMap::new(),
+                lake_bpk,
+                &lake_ek,
+                blinded_table.clone(),
+                &mut randomness
+            )
+            .unwrap_err(),
+            Error::InvalidInput
+        );
+
+        let updated_table =
+            update_blinded_table(&tokens, lake_bpk, &lake_ek, blinded_table, &mut randomness)
+                .unwrap();
+
+        // Updated entries are only accepted under the new keys.
+        assert_eq!(
+            finalize_updated_table(
+                &lake_context,
+                &old_keys,
+                &old_keys,
+                updated_table.clone(),
+                &mut randomness
+            )
+            .unwrap_err(),
+            Error::VerificationError
+        );
+
+        // The updated pseudonyms are those of a split using the new keys.
+        let mut updated = finalize_updated_table(
+            &lake_context,
+            &old_keys,
+            &new_keys,
+            updated_table,
+            &mut randomness,
+        )
+        .unwrap()
+        .data()
+        .to_vec();
+        let mut expected = split(&new_converter, &lake_context, &mut randomness)
+            .data()
+            .to_vec();
+        updated.sort();
+        expected.sort();
+        assert_eq!(updated, expected);
+
+        let mut old = old_table.data().to_vec();
+        old.sort();
+        assert_ne!(updated, old);
+    }
+
+    #[test]
+    fn test_threshold_rotation() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let old_dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let old_converter = ConverterContext::threshold(deal(&old_dealer, 2, 3).unwrap()).unwrap();
+        let old_keys = converter_public_keys(&old_converter);
+        let new_dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let new_converter = ConverterContext::threshold(deal(&new_dealer, 2, 3).unwrap()).unwrap();
+        let new_keys = converter_public_keys(&new_converter);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        // Both converters must be backed by the same evaluators, and a
+        // quorum of `2t - 1` of them is required.
+        let single_converter = ConverterContext::setup(&mut randomness).unwrap();
+        assert_eq!(
+            old_converter
+                .update_token(&single_converter, "Address")
+                .err(),
+            Some(Error::InvalidInput)
+        );
+        let mut evaluators = deal(&old_dealer, 2, 3).unwrap();
+        evaluators.truncate(2);
+        assert_eq!(
+            ConverterContext::threshold(evaluators)
+                .unwrap()
+                .update_token(&new_converter, "Address")
+                .err(),
+            Some(Error::InvalidInput)
+        );
+        let mut evaluators = deal(&new_dealer, 2, 3).unwrap();
+        evaluators.remove(1);
+        assert_eq!(
+            old_converter
+                .update_token(&ConverterContext::threshold(evaluators).unwrap(), "Address")
+                .err(),
+            Some(Error::InvalidInput)
+        );
+
+        let old_table = split(&old_converter, &lake_context, &mut randomness);
+        let blinded_table = blind_pseudonymous_table(
+            &lake_context,
+            &old_keys,
+            lake_bpk,
+            &lake_ek,
+            old_table.clone(),
+            &mut randomness,
+        )
+        .unwrap();
+        let tokens = update_tokens(&old_converter, &new_converter, &blinded_table);
+        drop(old_converter);
+        let updated_table =
+            update_blinded_table(&tokens, lake_bpk, &lake_ek, blinded_table, &mut randomness)
+                .unwrap();
+
+        // The updated pseudonyms are those of a split using the new keys.
+        let mut updated = finalize_updated_table(
+            &lake_context,
+            &old_keys,
+            &new_keys,
+            updated_table,
+            &mut randomness,
+        )
+        .unwrap()
+        .data()
+        .to_vec();
+        let mut expected = split(&new_converter, &lake_context, &mut randomness)
+            .data()
+            .to_vec();
+        updated.sort();
+        expected.sort();
+        assert_eq!(updated, expected);
+
+        let mut old = old_table.data().to_vec();
+        old.sort();
+        assert_ne!(updated, old);
+    }
+
+    #[test]
+    fn test_threshold_migration() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let old_converter = ConverterContext::setup(&mut randomness).unwrap();
+        let old_keys = converter_public_keys(&old_converter);
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let new_converter = ConverterContext::threshold(deal(&dealer, 2, 3).unwrap()).unwrap();
+        let new_keys = converter_public_keys(&new_converter);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let old_table = split(&old_converter, &lake_context, &mut randomness);
+        let blinded_table = blind_pseudonymous_table(
+            &lake_context,
+            &old_keys,
+            lake_bpk,
+            &lake_ek,
+            old_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let tokens = update_tokens(&old_converter, &new_converter, &blinded_table);
+        let updated_table =
+            update_blinded_table(&tokens, lake_bpk, &lake_ek, blinded_table, &mut randomness)
+                .unwrap();
+
+        // The migrated pseudonyms are those of a split by the evaluators.
+        let mut updated = finalize_updated_table(
+            &lake_context,
+            &old_keys,
+            &new_keys,
+            updated_table,
+            &mut randomness,
+        )
+        .unwrap()
+        .data()
+        .to_vec();
+        let mut expected = split(&new_converter, &lake_context, &mut randomness)
+            .data()
+            .to_vec();
+        updated.sort();
+        expected.sort();
+        assert_eq!(updated, expected);
+    }
+
+    #[test]
+    fn test_rekey_table() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let mut lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let old_table = split(&converter_context, &lake_context, &mut randomness);
+        let old_epoch = lake_context.prp_epoch();
+        let new_epoch = lake_context.rotate_prp_key(&mut randomness).unwrap();
+        assert_ne!(old_epoch, new_epoch);
+
+        // Pseudonyms of the previous epoch are still readable.
+        for entry in old_table.data() {
+            assert_eq!(entry.handle.epoch(), old_epoch);
+            assert!(lake_context.recover_raw_pseudonym(entry.handle).is_ok());
+        }
+
+        let rekeyed_table = rekey_table(&lake_context, old_table.clone()).unwrap();
+        for (old, rekeyed) in old_table.data().iter().zip(rekeyed_table.data()) {
+            assert_eq!(rekeyed.handle.epoch(), new_epoch);
+            assert_ne!(rekeyed.handle, old.handle);
+            assert_eq!(rekeyed.data_value, old.data_value);
+            assert_eq!(
+                lake_context.recover_raw_pseudonym(rekeyed.handle).unwrap(),
+                lake_context.recover_raw_pseudonym(old.handle).unwrap()
+            );
+        }
+
+        // The rekeyed pseudonyms are those of a split under the new key.
+        let mut rekeyed = rekeyed_table.data().to_vec();
+        let mut expected = split(&converter_context, &lake_context, &mut randomness)
+            .data()
+            .to_vec();
+        rekeyed.sort();
+        expected.sort();
+        assert_eq!(rekeyed, expected);
+
+        // Rekeyed tables can be joined as before.
+        assert!(blind_pseudonymous_table(
+            &lake_context,
+            &converter_keys,
+            lake_bpk,
+            &lake_ek,
+            rekeyed_table.clone(),
+            &mut randomness,
+        )
+        .is_ok());
+
+        // After retiring the previous key, only rekeyed tables are readable.
+        lake_context.retire_prp_key(old_epoch).unwrap();
+        assert_eq!(
+            rekey_table(&lake_context, old_table).unwrap_err(),
+            Error::InvalidInput
+        );
+        assert_eq!(
+            rekey_table(&lake_context, rekeyed_table.clone())
+                .unwrap()
+                .data(),
+            rekeyed_table.data()
+        );
+        assert_eq!(
+            lake_context.retire_prp_key(new_epoch),
+            Err(Error::InvalidInput)
+        );
+    }
+}
@@ -0,0 +1,986 @@
+//! # Wire Format
+//!
+//! The parties of `ScrambleDB` exchange tables of blinded and encrypted data.
+//! This module defines a canonical, versioned byte encoding for every type
+//! that crosses a party boundary, as well as `serde` implementations based on
+//! it.
+//!
+//! Every top-level encoding starts with a two byte header consisting of the
+//! encoding version and a tag identifying the encoded type:
+//!
+//! ``` text
+//! encoding = VERSION || TAG || body
+//! ```
+//!
+//! Bodies are composed of the following primitives:
+//! - `u8`, `u32` and `u64` integers, the latter two in big-endian byte order,
+//! - byte strings and UTF-8 strings, prefixed by their length as a `u32`,
+//! - P256 scalars as 32 big-endian bytes,
+//! - P256 points in compressed SEC1 encoding of 33 bytes,
+//! - ElGamal ciphertexts as two consecutive compressed points,
+//! - encryption schemes as a single byte, `SCHEME_ELGAMAL` or
+//!   `SCHEME_DOUBLE_HPKE`.
+//!
+//! Finalized pseudonyms are encoded as the `u32` epoch of the PRP key that
+//! produced them, followed by the 64 byte PRP output.
+//!
+//! Encrypted data values and encryption keys start with the encryption
+//! scheme they belong to, followed by the scheme specific encoding. Every
+//! encryption layer of a data value is bound to its attribute name, encoded
+//! as a byte string, so attribute names cannot be changed in transit.
+//!
+//! Blinded pseudonymized data ends with the converter's proof, if any,
+//! preceded by a byte `PROOF_NONE`, `PROOF_PSEUDONYMIZATION`,
+//! `PROOF_CONVERSION`, `PROOF_THRESHOLD_PSEUDONYMIZATION` or
+//! `PROOF_THRESHOLD_CONVERSION`. Threshold proofs consist of the blinded
+//! input, followed by the `u32` count of partial results. Each partial
+//! result is encoded as the evaluator's `u32` index, its mask share for
+//! conversions, its output ciphertext, the public key of its key share and
+//! its proof.
+//!
+//! Identifiable and pseudonymized data in plain text never cross a party
+//! boundary, but are encoded in the same format when a party spills them to
+//! local storage, e.g. during an external shuffle.
+//!
+//! Encodings persisted by a single party, i.e. key exports, lake stores,
+//! audit logs, join sessions and join policies, as well as the plain text
+//! data they hold, carry [`STORAGE_FORMAT_VERSION`] instead of
+//! [`WIRE_FORMAT_VERSION`]. The two versions are bumped independently, so a
+//! change to the values exchanged between parties does not invalidate
+//! persisted data.
+//!
+//! Decoding validates all lengths and that all points are canonically encoded
+//! points on the curve. Any violation results in [`Error::CorruptedData`].
+//!
+//! In human-readable `serde` formats, such as JSON, values are represented as
+//! the hex string of their canonical encoding, otherwise as a byte string.
+
+use std::collections::BTreeMap;
+
+use elgamal::Ciphertext;
+use hacspec_lib::hacspec_helper::NatMod;
+use oprf::coprf::{
+    coprf_threshold::{PartialConversion, PartialEvaluation, PartialProof},
+    coprf_verifiable::Proof,
+};
+use p256::{P256Point, P256Scalar};
+
+use crate::{
+    data_types::{
+        BlindedIdentifiableData, BlindedIdentifiableHandle, BlindedPseudonymizedData,
+        BlindedPseudonymizedHandle, ConversionProof, DataValue, EncryptedDataValue, EncryptedValue,
+        EncryptionScheme, FinalizedPseudonym, IdentifiableData, PseudonymizedData,
+    },
+    error::Error,
+    setup::{ConverterPublicKeys, StoreEncryptionKey},
+    table::Table,
+};
+
+/// The current version of the wire format.
+pub const WIRE_FORMAT_VERSION: u8 = 1;
+
+/// The current version of the storage format of persisted encodings.
+pub const STORAGE_FORMAT_VERSION: u8 = 1;
+
+/// The length of a P256 scalar encoding.
+const SCALAR_BYTES: usize = 32;
+
+/// The length of a compressed P256 point encoding.
+pub(crate) const POINT_BYTES: usize = 33;
+
+/// The length of a proof of correct blind evaluation or conversion.
+const PROOF_BYTES: usize = POINT_BYTES + 4 * SCALAR_BYTES;
+
+/// The length of a partial evaluation together with its proof.
+const PARTIAL_EVALUATION_BYTES: usize = 4 + 3 * POINT_BYTES + PROOF_BYTES;
+
+/// Encoding of [`EncryptionScheme::ElGamal`].
+const SCHEME_ELGAMAL: u8 = 1;
+
+/// Encoding of `EncryptionScheme::DoubleHpke`.
+#[cfg(feature = "double-hpke")]
+const SCHEME_DOUBLE_HPKE: u8 = 2;
+
+/// Marks blinded pseudonymized data without a converter's proof.
+const PROOF_NONE: u8 = 0;
+/// Marks a [`ConversionProof::Pseudonymization`].
+const PROOF_PSEUDONYMIZATION: u8 = 1;
+/// Marks a [`ConversionProof::Conversion`].
+const PROOF_CONVERSION: u8 = 2;
+/// Marks a [`ConversionProof::ThresholdPseudonymization`].
+const PROOF_THRESHOLD_PSEUDONYMIZATION: u8 = 3;
+/// Marks a [`ConversionProof::ThresholdConversion`].
+const PROOF_THRESHOLD_CONVERSION: u8 = 4;
+
+/// Tag bit marking the encoding of a table of values.
+const TAG_TABLE: u8 = 0x80;
+
+/// A type with a canonical encoding in the `ScrambleDB` wire format.
+pub trait WireFormat: Sized {
+    /// The tag identifying the encoded type.
+    const TAG: u8;
+
+    /// The version of the format the type is encoded in.
+    const VERSION: u8 = WIRE_FORMAT_VERSION;
+
+    /// Append the encoding of `self` without header to `out`.
+    fn encode_body(&self, out: &mut Vec<u8>);
+
+    /// Decode a value from the body of an encoding.
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error>;
+
+    /// Encode `self` including the version header.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![Self::VERSION, Self::TAG];
+        self.encode_body(&mut out);
+        out
+    }
+
+    /// Decode a value from an encoding including the version header.
+    ///
+    /// Raises:
+    /// - `CorruptedData`: If the version or type tag do not match, the
+    ///   encoding is malformed, or trailing bytes remain.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        if reader.u8()? != Self::VERSION || reader.u8()? != Self::TAG {
+            return Err(Error::CorruptedData);
+        }
+        let value = Self::decode_body(&mut reader)?;
+        reader.finish()?;
+        Ok(value)
+    }
+}
+
+/// A cursor over an encoding that checks all reads against the remaining
+/// input.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Start reading `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Read exactly `len` bytes.
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.bytes.len() {
+            return Err(Error::CorruptedData);
+        }
+        let (out, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(out)
+    }
+
+    /// Read a single byte.
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Read a big-endian `u32`.
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    /// Read a big-endian `u64`.
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into()?))
+    }
+
+    /// Read a length prefixed byte string.
+    pub fn byte_string(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// Read a length prefixed UTF-8 string.
+    pub fn string(&mut self) -> Result<String, Error> {
+        let bytes = self.byte_string()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::CorruptedData)
+    }
+
+    /// Read the element count of a sequence whose elements take up at least
+    /// `min_element_len` bytes each.
+    pub fn count(&mut self, min_element_len: usize) -> Result<usize, Error> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(min_element_len) > self.bytes.len() {
+            return Err(Error::CorruptedData);
+        }
+        Ok(count)
+    }
+
+    /// Read a canonically encoded P256 scalar.
+    pub fn scalar(&mut self) -> Result<P256Scalar, Error> {
+        let bytes = self.take(SCALAR_BYTES)?;
+        let scalar = P256Scalar::from_be_bytes(bytes);
+        if scalar.to_be_bytes() != bytes {
+            return Err(Error::CorruptedData);
+        }
+        Ok(scalar)
+    }
+
+    /// Read and validate a compressed P256 point.
+    pub fn point(&mut self) -> Result<P256Point, Error> {
+        let bytes: [u8; POINT_BYTES] = self.take(POINT_BYTES)?.try_into()?;
+        let point = p256::deserialize_point(bytes)?;
+        // The decompression does not check that the point is on the curve,
+        // and it reduces the x-coordinate, so we also require the
+        // re-encoding to be identical.
+        if !p256::p256_validate_public_key(point) || p256::serialize_point(&point) != bytes {
+            return Err(Error::CorruptedData);
+        }
+        Ok(point)
+    }
+
+    /// Read an ElGamal ciphertext.
+    pub fn ciphertext(&mut self) -> Result<Ciphertext, Error> {
+        Ok((self.point()?, self.point()?))
+    }
+
+    /// Read a proof of correct blind evaluation or conversion.
+    pub fn proof(&mut self) -> Result<Proof, Error> {
+        Ok(Proof {
+            key_share: self.point()?,
+            challenge: self.scalar()?,
+            responses: [self.scalar()?, self.scalar()?, self.scalar()?],
+        })
+    }
+
+    /// Read the public key and proof of an evaluator's partial result.
+    pub fn partial_proof(&mut self) -> Result<PartialProof, Error> {
+        Ok(PartialProof {
+            public_key: self.point()?,
+            proof: self.proof()?,
+        })
+    }
+
+    /// Read an encryption scheme.
+    ///
+    /// Schemes that are not supported by this build are rejected as
+    /// corrupted data.
+    pub fn scheme(&mut self) -> Result<EncryptionScheme, Error> {
+        match self.u8()? {
+            SCHEME_ELGAMAL => Ok(EncryptionScheme::ElGamal),
+            #[cfg(feature = "double-hpke")]
+            SCHEME_DOUBLE_HPKE => Ok(EncryptionScheme::DoubleHpke),
+            _ => Err(Error::CorruptedData),
+        }
+    }
+
+    /// Ensure that the entire input has been consumed.
+    pub fn finish(self) -> Result<(), Error> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::CorruptedData)
+        }
+    }
+}
+
+/// Append a big-endian `u32` to `out`.
+pub fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Append a big-endian `u64` to `out`.
+pub fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Append a length prefixed byte string to `out`.
+pub fn put_byte_string(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_u32(out, bytes.len() as u32);
+    out.extend_from_slice(bytes);
+}
+
+/// Append a P256 scalar to `out`.
+pub fn put_scalar(out: &mut Vec<u8>, scalar: &P256Scalar) {
+    out.extend_from_slice(&scalar.to_be_bytes());
+}
+
+/// Append a compressed P256 point to `out`.
+pub fn put_point(out: &mut Vec<u8>, point: &P256Point) {
+    out.extend_from_slice(&p256::serialize_point(point));
+}
+
+/// Append an ElGamal ciphertext to `out`.
+pub fn put_ciphertext(out: &mut Vec<u8>, ciphertext: &Ciphertext) {
+    put_point(out, &ciphertext.0);
+    put_point(out, &ciphertext.1);
+}
+
+/// Append a proof of correct blind evaluation or conversion to `out`.
+pub fn put_proof(out: &mut Vec<u8>, proof: &Proof) {
+    put_point(out, &proof.key_share);
+    put_scalar(out, &proof.challenge);
+    for response in &proof.responses {
+        put_scalar(out, response);
+    }
+}
+
+/// Append the public key and proof of an evaluator's partial result to
+/// `out`.
+pub fn put_partial_proof(out: &mut Vec<u8>, proof: &PartialProof) {
+    put_point(out, &proof.public_key);
+    put_proof(out, &proof.proof);
+}
+
+/// Append an encryption scheme to `out`.
+pub fn put_scheme(out: &mut Vec<u8>, scheme: EncryptionScheme) {
+    out.push(match scheme {
+        EncryptionScheme::ElGamal => SCHEME_ELGAMAL,
+        #[cfg(feature = "double-hpke")]
+        EncryptionScheme::DoubleHpke => SCHEME_DOUBLE_HPKE,
+    });
+}
+
+impl WireFormat for FinalizedPseudonym {
+    const TAG: u8 = 0x01;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_u32(out, self.epoch);
+        out.extend_from_slice(&self.value);
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(FinalizedPseudonym {
+            epoch: reader.u32()?,
+            value: reader.take(64)?.try_into()?,
+        })
+    }
+}
+
+impl WireFormat for BlindedIdentifiableHandle {
+    const TAG: u8 = 0x02;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_ciphertext(out, &self.0);
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(BlindedIdentifiableHandle(reader.ciphertext()?))
+    }
+}
+
+impl WireFormat for BlindedPseudonymizedHandle {
+    const TAG: u8 = 0x03;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_ciphertext(out, &self.0);
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(BlindedPseudonymizedHandle(reader.ciphertext()?))
+    }
+}
+
+impl WireFormat for EncryptedDataValue {
+    const TAG: u8 = 0x04;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_byte_string(out, self.attribute_name.as_bytes());
+        put_scheme(out, self.scheme());
+        match &self.value {
+            EncryptedValue::ElGamal(ciphertexts) => {
+                put_u32(out, ciphertexts.len() as u32);
+                for ciphertext in ciphertexts {
+                    put_ciphertext(out, ciphertext);
+                }
+            }
+            #[cfg(feature = "double-hpke")]
+            EncryptedValue::DoubleHpke {
+                value,
+                encryption_level,
+            } => {
+                out.push(*encryption_level);
+                put_byte_string(out, value);
+            }
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        let attribute_name = reader.string()?;
+        let value = match reader.scheme()? {
+            EncryptionScheme::ElGamal => {
+                let count = reader.count(2 * POINT_BYTES)?;
+                EncryptedValue::ElGamal((0..count).map(|_| reader.ciphertext()).collect::<Result<
+                    Vec<Ciphertext>,
+                    Error,
+                >>(
+                )?)
+            }
+            #[cfg(feature = "double-hpke")]
+            EncryptionScheme::DoubleHpke => {
+                let encryption_level = reader.u8()?;
+                if encryption_level != 1 && encryption_level != 2 {
+                    return Err(Error::CorruptedData);
+                }
+                EncryptedValue::DoubleHpke {
+                    value: reader.byte_string()?.to_vec(),
+                    encryption_level,
+                }
+            }
+        };
+        Ok(EncryptedDataValue {
+            value,
+            attribute_name,
+        })
+    }
+}
+
+impl WireFormat for BlindedIdentifiableData {
+    const TAG: u8 = 0x05;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        self.blinded_handle.encode_body(out);
+        self.encrypted_data_value.encode_body(out);
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(BlindedIdentifiableData {
+            blinded_handle: BlindedIdentifiableHandle::decode_body(reader)?,
+            encrypted_data_value: EncryptedDataValue::decode_body(reader)?,
+        })
+    }
+}
+
+impl WireFormat for BlindedPseudonymizedData {
+    const TAG: u8 = 0x06;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        self.blinded_handle.encode_body(out);
+        self.encrypted_data_value.encode_body(out);
+        match &self.conversion_proof {
+            None => out.push(PROOF_NONE),
+            Some(ConversionProof::Pseudonymization {
+                blinded_input,
+                proof,
+            }) => {
+                out.push(PROOF_PSEUDONYMIZATION);
+                put_ciphertext(out, blinded_input);
+                put_proof(out, proof);
+            }
+            Some(ConversionProof::Conversion {
+                blinded_input,
+                target_key,
+                proof,
+            }) => {
+                out.push(PROOF_CONVERSION);
+                put_ciphertext(out, blinded_input);
+                put_point(out, target_key);
+                put_proof(out, proof);
+            }
+            Some(ConversionProof::ThresholdPseudonymization {
+                blinded_input,
+                partials,
+            }) => {
+                out.push(PROOF_THRESHOLD_PSEUDONYMIZATION);
+                put_ciphertext(out, blinded_input);
+                put_u32(out, partials.len() as u32);
+                for (partial, proof) in partials {
+                    put_u32(out, partial.index);
+                    put_ciphertext(out, &partial.output);
+                    put_partial_proof(out, proof);
+                }
+            }
+            Some(ConversionProof::ThresholdConversion {
+                blinded_input,
+                partials,
+            }) => {
+                out.push(PROOF_THRESHOLD_CONVERSION);
+                put_ciphertext(out, blinded_input);
+                put_u32(out, partials.len() as u32);
+                for (partial, proof) in partials {
+                    put_u32(out, partial.index);
+                    put_scalar(out, &partial.mask_share);
+                    put_ciphertext(out, &partial.output);
+                    put_partial_proof(out, proof);
+                }
+            }
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        let blinded_handle = BlindedPseudonymizedHandle::decode_body(reader)?;
+        let encrypted_data_value = EncryptedDataValue::decode_body(reader)?;
+        let conversion_proof = match reader.u8()? {
+            PROOF_NONE => None,
+            PROOF_PSEUDONYMIZATION => Some(ConversionProof::Pseudonymization {
+                blinded_input: reader.ciphertext()?,
+                proof: reader.proof()?,
+            }),
+            PROOF_CONVERSION => Some(ConversionProof::Conversion {
+                blinded_input: reader.ciphertext()?,
+                target_key: reader.point()?,
+                proof: reader.proof()?,
+            }),
+            PROOF_THRESHOLD_PSEUDONYMIZATION => {
+                let blinded_input = reader.ciphertext()?;
+                let count = reader.count(PARTIAL_EVALUATION_BYTES)?;
+                let mut partials = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let partial = PartialEvaluation {
+                        index: reader.u32()?,
+                        output: reader.ciphertext()?,
+                    };
+                    partials.push((partial, reader.partial_proof()?));
+                }
+                Some(ConversionProof::ThresholdPseudonymization {
+                    blinded_input,
+                    partials,
+                })
+            }
+            PROOF_THRESHOLD_CONVERSION => {
+                let blinded_input = reader.ciphertext()?;
+                let count = reader.count(PARTIAL_EVALUATION_BYTES + SCALAR_BYTES)?;
+                let mut partials = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let partial = PartialConversion {
+                        index: reader.u32()?,
+                        mask_share: reader.scalar()?,
+                        output: reader.ciphertext()?,
+                    };
+                    partials.push((partial, reader.partial_proof()?));
+                }
+                Some(ConversionProof::ThresholdConversion {
+                    blinded_input,
+                    partials,
+                })
+            }
+            _ => return Err(Error::CorruptedData),
+        };
+        Ok(BlindedPseudonymizedData {
+            blinded_handle,
+            encrypted_data_value,
+            conversion_proof,
+        })
+    }
+}
+
+impl WireFormat for StoreEncryptionKey {
+    const TAG: u8 = 0x07;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_scheme(out, self.scheme());
+        match self {
+            StoreEncryptionKey::ElGamal(ek) => put_point(out, ek),
+            #[cfg(feature = "double-hpke")]
+            StoreEncryptionKey::DoubleHpke(ek) => put_byte_string(out, ek),
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.scheme()? {
+            EncryptionScheme::ElGamal => Ok(StoreEncryptionKey::ElGamal(reader.point()?)),
+            #[cfg(feature = "double-hpke")]
+            EncryptionScheme::DoubleHpke => {
+                let libcrux::hpke::HPKEConfig(_, kem, _, _) = crate::HPKE_CONF;
+                let ek = reader.byte_string()?;
+                if ek.len() != libcrux::hpke::kem::Npk(kem) {
+                    return Err(Error::CorruptedData);
+                }
+                Ok(StoreEncryptionKey::DoubleHpke(ek.to_vec()))
+            }
+        }
+    }
+}
+
+impl WireFormat for ConverterPublicKeys {
+    const TAG: u8 = 0x09;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_point(out, &self.blinding_key_share);
+        put_u32(out, self.attribute_keys.len() as u32);
+        for (attribute, key) in &self.attribute_keys {
+            put_byte_string(out, attribute.as_bytes());
+            put_point(out, key);
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        let blinding_key_share = reader.point()?;
+        let count = reader.count(4 + POINT_BYTES)?;
+        let mut attribute_keys = BTreeMap::new();
+        for _ in 0..count {
+            let attribute = reader.string()?;
+            // Attributes are encoded in strictly ascending order.
+            if attribute_keys
+                .last_key_value()
+                .is_some_and(|(last, _)| *last >= attribute)
+            {
+                return Err(Error::CorruptedData);
+            }
+            attribute_keys.insert(attribute, reader.point()?);
+        }
+        Ok(ConverterPublicKeys {
+            blinding_key_share,
+            attribute_keys,
+        })
+    }
+}
+
+/// A coPRF blinding public key is a P256 point.
+impl WireFormat for P256Point {
+    const TAG: u8 = 0x08;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_point(out, self);
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        reader.point()
+    }
+}
+
+/// Append a plain text data value to `out`.
+fn put_data_value(out: &mut Vec<u8>, data_value: &DataValue) {
+    put_byte_string(out, data_value.attribute_name.as_bytes());
+    put_byte_string(out, &data_value.value);
+}
+
+impl Reader<'_> {
+    /// Read a plain text data value.
+    fn data_value(&mut self) -> Result<DataValue, Error> {
+        Ok(DataValue {
+            attribute_name: self.string()?,
+            value: self.byte_string()?.to_vec(),
+        })
+    }
+}
+
+impl WireFormat for IdentifiableData {
+    const TAG: u8 = 0x0a;
+    const VERSION: u8 = STORAGE_FORMAT_VERSION;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_byte_string(out, self.handle.as_bytes());
+        put_data_value(out, &self.data_value);
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(IdentifiableData {
+            handle: reader.string()?,
+            data_value: reader.data_value()?,
+        })
+    }
+}
+
+impl WireFormat for PseudonymizedData {
+    const TAG: u8 = 0x0b;
+    const VERSION: u8 = STORAGE_FORMAT_VERSION;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        self.handle.encode_body(out);
+        put_data_value(out, &self.data_value);
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(PseudonymizedData {
+            handle: FinalizedPseudonym::decode_body(reader)?,
+            data_value: reader.data_value()?,
+        })
+    }
+}
+
+impl<T: WireFormat> WireFormat for Table<T> {
+    const TAG: u8 = TAG_TABLE | T::TAG;
+    const VERSION: u8 = T::VERSION;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_byte_string(out, self.identifier().as_bytes());
+        put_u32(out, self.data().len() as u32);
+        for entry in self.data() {
+            entry.encode_body(out);
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        let identifier = reader.string()?;
+        let count = reader.count(1)?;
+        let data = (0..count)
+            .map(|_| T::decode_body(reader))
+            .collect::<Result<Vec<T>, Error>>()?;
+        Ok(Table::new(identifier, data))
+    }
+}
+
+/// `serde` support for types implementing [`WireFormat`].
+mod serde_impls {
+    use std::marker::PhantomData;
+
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    fn serialize<T: WireFormat, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let bytes = value.to_bytes();
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(bytes))
+        } else {
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+
+    fn deserialize<'de, T: WireFormat, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(WireFormatVisitor(PhantomData))
+        } else {
+            deserializer.deserialize_bytes(WireFormatVisitor(PhantomData))
+        }
+    }
+
+    struct WireFormatVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: WireFormat> de::Visitor<'de> for WireFormatVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a ScrambleDB wire format encoding")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+            let bytes = hex::decode(v).map_err(E::custom)?;
+            self.visit_bytes(&bytes)
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<T, E> {
+            T::from_bytes(v).map_err(|e| E::custom(format!("{e:?}")))
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+            let mut bytes = Vec::new();
+            while let Some(byte) = seq.next_element::<u8>()? {
+                bytes.push(byte);
+            }
+            self.visit_bytes(&bytes)
+        }
+    }
+
+    macro_rules! impl_serde {
+        ($($ty:ty),*) => {
+            $(
+                impl Serialize for $ty {
+                    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                        serialize(self, serializer)
+                    }
+                }
+
+                impl<'de> Deserialize<'de> for $ty {
+                    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                        deserialize(deserializer)
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_serde!(
+        FinalizedPseudonym,
+        BlindedIdentifiableHandle,
+        BlindedPseudonymizedHandle,
+        EncryptedDataValue,
+        BlindedIdentifiableData,
+        BlindedPseudonymizedData,
+        StoreEncryptionKey,
+        ConverterPublicKeys
+    );
+
+    impl<T: WireFormat> Serialize for Table<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(self, serializer)
+        }
+    }
+
+    impl<'de, T: WireFormat> Deserialize<'de> for Table<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserialize(deserializer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hacspec_lib::Randomness;
+
+    use crate::{
+        setup::{ConverterContext, StoreContext},
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    use super::*;
+
+    fn generate_randomness() -> Randomness {
+        Randomness::from_entropy().unwrap()
+    }
+
+    #[test]
+    fn test_wire_format_split() {
+        let mut randomness = generate_randomness();
+
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        // The source only receives the lake's encoded public keys.
+        let lake_ek = StoreEncryptionKey::from_bytes(&lake_ek.to_bytes()).unwrap();
+        let lake_bpk = P256Point::from_bytes(&lake_bpk.to_bytes()).unwrap();
+        let encoded_keys = converter_keys.to_bytes();
+        let converter_keys = ConverterPublicKeys::from_bytes(&encoded_keys).unwrap();
+        assert_eq!(encoded_keys, converter_keys.to_bytes());
+
+        let blind_table = crate::split::blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+
+        let encoded = blind_table.to_bytes();
+        let blind_table = Table::<BlindedIdentifiableData>::from_bytes(&encoded).unwrap();
+        assert_eq!(encoded, blind_table.to_bytes());
+
+        let converted_table = crate::split::pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            &mut randomness,
+        )
+        .unwrap();
+
+        let json = serde_json::to_string(&converted_table).unwrap();
+        let decoded: Table<BlindedPseudonymizedData> = serde_json::from_str(&json).unwrap();
+        assert_eq!(converted_table.data(), decoded.data());
+
+        let lake_table = crate::finalize::finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            decoded,
+            &mut randomness,
+        )
+        .unwrap();
+        for entry in lake_table.data() {
+            assert_eq!(
+                entry.handle,
+                FinalizedPseudonym::from_bytes(&entry.handle.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_wire_format_rejects_corrupted_data() {
+        let mut randomness = generate_randomness();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let blind_table = crate::split::blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+        let encoded = blind_table.to_bytes();
+
+        // Wrong version
+        let mut corrupted = encoded.clone();
+        corrupted[0] = WIRE_FORMAT_VERSION + 1;
+        assert_eq!(
+            Table::<BlindedIdentifiableData>::from_bytes(&corrupted).unwrap_err(),
+            Error::CorruptedData
+        );
+
+        // Wrong type
+        assert_eq!(
+            Table::<BlindedPseudonymizedData>::from_bytes(&encoded).unwrap_err(),
+            Error::CorruptedData
+        );
+
+        // Truncated and trailing data
+        assert_eq!(
+            Table::<BlindedIdentifiableData>::from_bytes(&encoded[..encoded.len() - 1])
+                .unwrap_err(),
+            Error::CorruptedData
+        );
+        let mut extended = encoded.clone();
+        extended.push(0);
+        assert_eq!(
+            Table::<BlindedIdentifiableData>::from_bytes(&extended).unwrap_err(),
+            Error::CorruptedData
+        );
+
+        // Invalid point
+        let mut encoded_point = lake_bpk.to_bytes();
+        encoded_point[2] = 0x04;
+        assert_eq!(
+            P256Point::from_bytes(&encoded_point).unwrap_err(),
+            Error::CorruptedData
+        );
+        let mut encoded_point = lake_bpk.to_bytes();
+        encoded_point[3..].copy_from_slice(&[0xff; 32]);
+        assert_eq!(
+            P256Point::from_bytes(&encoded_point).unwrap_err(),
+            Error::CorruptedData
+        );
+
+        // Oversized length prefix
+        let mut oversized = encoded;
+        oversized[2..6].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            Table::<BlindedIdentifiableData>::from_bytes(&oversized).unwrap_err(),
+            Error::CorruptedData
+        );
+
+        assert!(serde_json::from_str::<FinalizedPseudonym>("\"00\"").is_err());
+    }
+
+    #[test]
+    fn test_storage_format() {
+        // A pseudonymized entry as persisted in lake stores.
+        let mut persisted = vec![STORAGE_FORMAT_VERSION, PseudonymizedData::TAG];
+        persisted.extend_from_slice(&1u32.to_be_bytes());
+        persisted.extend_from_slice(&[7u8; 64]);
+        persisted.extend_from_slice(&7u32.to_be_bytes());
+        persisted.extend_from_slice(b"Address");
+        persisted.extend_from_slice(&9u32.to_be_bytes());
+        persisted.extend_from_slice(b"TestData1");
+        let entry = PseudonymizedData {
+            handle: FinalizedPseudonym {
+                epoch: 1,
+                value: [7u8; 64],
+            },
+            data_value: DataValue {
+                value: b"TestData1".to_vec(),
+                attribute_name: "Address".into(),
+            },
+        };
+        assert_eq!(PseudonymizedData::from_bytes(&persisted).unwrap(), entry);
+        assert_eq!(entry.to_bytes(), persisted);
+
+        // Key exports are versioned by the storage format, while keys
+        // exchanged with other parties follow the wire format.
+        let mut randomness = generate_randomness();
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        assert_eq!(
+            lake_context.export(None).unwrap()[0],
+            STORAGE_FORMAT_VERSION
+        );
+        assert_eq!(
+            lake_context.public_keys().0.to_bytes()[0],
+            WIRE_FORMAT_VERSION
+        );
+    }
+}
@@ -0,0 +1,720 @@
+//! # Join Sessions
+//!
+//! By default, every join conversion uses a fresh random conversion
+//! target, so the pseudonyms of two joins can not be linked, even if they
+//! were requested by the same processor. Longitudinal studies, however,
+//! need to add columns to an earlier join result. For this, the converter
+//! can open a [`JoinSession`] for one processor and one study, which
+//! expires at a given time.
+//!
+//! All join conversions within a session use the same conversion target,
+//! which the converter derives from the session identifier, the
+//! processor's blinding public key and the study:
+//!
+//! ``` text
+//! conversion_target = "ScrambleDB-JoinSession" || id || bpk_processor || study
+//! ```
+//!
+//! The pseudonyms of a data subject are thus consistent across all joins of
+//! a session, while joins of different sessions, or without a session,
+//! remain non-transitive. Only the processor a session was opened for can
+//! use it, and only until it expires.
+//!
+//! Since the joins of a session can be linked, a session records the
+//! attributes joined in it so far. Under a [`JoinPolicy`], every join of a
+//! session is checked against the union of these and the newly requested
+//! attributes, so that a session links no more attributes than a single
+//! join could.
+//!
+//! The converter keeps its open sessions in [`JoinSessions`], which can be
+//! persisted in the [wire format](crate::serialization) across restarts,
+//! e.g. using [`JoinSessions::save`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    path::Path,
+};
+
+use hacspec_lib::Randomness;
+use oprf::coprf::coprf_setup::BlindingPublicKey;
+
+use crate::{
+    data_types::BlindedPseudonymizedData,
+    error::Error,
+    join::convert_blinded_tables_to,
+    policy::JoinPolicy,
+    serialization::{
+        put_byte_string, put_point, put_u32, put_u64, Reader, WireFormat, STORAGE_FORMAT_VERSION,
+    },
+    setup::{ConverterContext, StoreEncryptionKey},
+    table::Table,
+    SECPAR_BYTES,
+};
+
+/// Domain separator for session conversion targets.
+const DST_SESSION: &[u8] = b"ScrambleDB-JoinSession";
+
+/// The identifier of a join session.
+pub type JoinSessionId = [u8; SECPAR_BYTES];
+
+/// A join session of one processor for one study.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JoinSession {
+    /// The processor's public blinding key
+    pub processor: BlindingPublicKey,
+    /// The study the session belongs to
+    pub study: String,
+    /// The time at which the session expires, in seconds since the Unix
+    /// epoch
+    pub expires_at: u64,
+    /// The attributes joined in the session so far
+    pub attributes: BTreeSet<String>,
+}
+
+/// The join sessions opened by a converter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JoinSessions {
+    sessions: BTreeMap<JoinSessionId, JoinSession>,
+}
+
+impl JoinSessions {
+    /// Open a session for a processor and a study, which expires at the
+    /// given time.
+    ///
+    /// Output:
+    /// The identifier of the new session, to be handed to the processor.
+    ///
+    /// Raises:
+    /// - `RandomnessError`: If not enough randomness was provided.
+    pub fn open(
+        &mut self,
+        processor: BlindingPublicKey,
+        study: &str,
+        expires_at: u64,
+        randomness: &mut Randomness,
+    ) -> Result<JoinSessionId, Error> {
+        let id: JoinSessionId = randomness.bytes(SECPAR_BYTES)?.try_into()?;
+        self.sessions.insert(
+            id,
+            JoinSession {
+                processor,
+                study: study.to_string(),
+                expires_at,
+                attributes: BTreeSet::new(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Close a session. Later joins can no longer be linked to the joins
+    /// of the session.
+    pub fn close(&mut self, id: &JoinSessionId) -> Option<JoinSession> {
+        self.sessions.remove(id)
+    }
+
+    /// Close all sessions that have expired at time `now`.
+    pub fn purge_expired(&mut self, now: u64) {
+        self.sessions.retain(|_, session| session.expires_at > now);
+    }
+
+    /// Look up an open session.
+    pub fn get(&self, id: &JoinSessionId) -> Option<&JoinSession> {
+        self.sessions.get(id)
+    }
+
+    /// The conversion target of a session for a processor at time `now`.
+    ///
+    /// Raises:
+    /// - `PolicyViolation`: If the session is unknown, was opened for a
+    ///   different processor, or has expired.
+    pub fn conversion_target(
+        &self,
+        id: &JoinSessionId,
+        processor: &BlindingPublicKey,
+        now: u64,
+    ) -> Result<Vec<u8>, Error> {
+        let session = self.get(id).ok_or(Error::PolicyViolation)?;
+        if session.processor != *processor || session.expires_at <= now {
+            return Err(Error::PolicyViolation);
+        }
+
+        let mut conversion_target = DST_SESSION.to_vec();
+        conversion_target.extend_from_slice(id);
+        put_point(&mut conversion_target, &session.processor);
+        put_byte_string(&mut conversion_target, session.study.as_bytes());
+        Ok(conversion_target)
+    }
+
+    /// Check that a processor may join the given attributes in a session
+    /// at time `now`, without recording the join, see
+    /// [`JoinSessions::join`].
+    ///
+    /// Output:
+    /// The conversion target of the session.
+    ///
+    /// Raises:
+    /// - `PolicyViolation`: If the session is unknown, was opened for a
+    ///   different processor, or has expired, or the policy does not allow
+    ///   the processor to join all attributes of the session.
+    pub fn authorize_join<'a>(
+        &self,
+        id: &JoinSessionId,
+        processor: &BlindingPublicKey,
+        attributes: impl IntoIterator<Item = &'a str>,
+        policy: Option<&JoinPolicy>,
+        now: u64,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .joined_attributes(id, processor, attributes, policy, now)?
+            .0)
+    }
+
+    /// Record a join of the given attributes in a session for a processor
+    /// at time `now`.
+    ///
+    /// If a policy is given, a single grant of the processor has to cover
+    /// the given attributes together with all attributes joined in the
+    /// session before. Rejected joins are not recorded.
+    ///
+    /// Output:
+    /// The conversion target of the session.
+    ///
+    /// Raises:
+    /// - `PolicyViolation`: If the session is unknown, was opened for a
+    ///   different processor, or has expired, or the policy does not allow
+    ///   the processor to join all attributes of the session.
+    pub fn join<'a>(
+        &mut self,
+        id: &JoinSessionId,
+        processor: &BlindingPublicKey,
+        attributes: impl IntoIterator<Item = &'a str>,
+        policy: Option<&JoinPolicy>,
+        now: u64,
+    ) -> Result<Vec<u8>, Error> {
+        let (conversion_target, joined) =
+            self.joined_attributes(id, processor, attributes, policy, now)?;
+        let session = self.sessions.get_mut(id).ok_or(Error::PolicyViolation)?;
+        session.attributes = joined;
+        Ok(conversion_target)
+    }
+
+    /// The conversion target of a session and the union of the given
+    /// attributes with those joined in the session before, if the policy
+    /// allows the processor to join them.
+    fn joined_attributes<'a>(
+        &self,
+        id: &JoinSessionId,
+        processor: &BlindingPublicKey,
+        attributes: impl IntoIterator<Item = &'a str>,
+        policy: Option<&JoinPolicy>,
+        now: u64,
+    ) -> Result<(Vec<u8>, BTreeSet<String>), Error> {
+        let conversion_target = self.conversion_target(id, processor, now)?;
+        let session = self.get(id).ok_or(Error::PolicyViolation)?;
+        let mut joined = session.attributes.clone();
+        joined.extend(attributes.into_iter().map(str::to_string));
+        if let Some(policy) = policy {
+            policy.authorize(processor, joined.iter().map(String::as_str))?;
+        }
+        Ok((conversion_target, joined))
+    }
+
+    /// Read join sessions persisted using [`JoinSessions::save`]. If there
+    /// is no file at `path`, there are no sessions.
+    ///
+    /// Raises:
+    /// - `StorageError`: If reading the file fails.
+    /// - `CorruptedData`: If the file does not hold join sessions.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        match fs::read(path) {
+            Ok(bytes) => Self::from_bytes(&bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Persist the join sessions to the file at `path`. The sessions are
+    /// written to a temporary file next to it, which then replaces the file
+    /// in a single rename, so the file holds either the previous or the new
+    /// sessions after a crash.
+    ///
+    /// Raises:
+    /// - `StorageError`: If writing the file fails. The file is left
+    ///   unchanged.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, self.to_bytes())?;
+        File::open(&temp_path)?.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        #[cfg(unix)]
+        File::open(match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        })?
+        .sync_all()?;
+        Ok(())
+    }
+}
+
+impl WireFormat for JoinSessions {
+    const TAG: u8 = 0x0d;
+    const VERSION: u8 = STORAGE_FORMAT_VERSION;
+
+    fn encode_body(&self, out: &mut Vec<u8>) {
+        put_u32(out, self.sessions.len() as u32);
+        for (id, session) in &self.sessions {
+            out.extend_from_slice(id);
+            put_point(out, &session.processor);
+            put_byte_string(out, session.study.as_bytes());
+            put_u64(out, session.expires_at);
+            put_u32(out, session.attributes.len() as u32);
+            for attribute in &session.attributes {
+                put_byte_string(out, attribute.as_bytes());
+            }
+        }
+    }
+
+    fn decode_body(reader: &mut Reader) -> Result<Self, Error> {
+        let count = reader.count(SECPAR_BYTES)?;
+        let mut sessions = BTreeMap::new();
+        for _ in 0..count {
+            let id = reader.take(SECPAR_BYTES)?.try_into()?;
+            let processor = reader.point()?;
+            let study = reader.string()?;
+            let expires_at = reader.u64()?;
+            let attribute_count = reader.count(4)?;
+            let mut attributes = BTreeSet::new();
+            for _ in 0..attribute_count {
+                if !attributes.insert(reader.string()?) {
+                    return Err(Error::CorruptedData);
+                }
+            }
+            let session = JoinSession {
+                processor,
+                study,
+                expires_at,
+                attributes,
+            };
+            if sessions.insert(id, session).is_some() {
+                return Err(Error::CorruptedData);
+            }
+        }
+        Ok(Self { sessions })
+    }
+}
+
+/// ## Session Oblivious Conversion
+///
+/// Obliviously convert tables of blinded pseudonymous data values to the
+/// join-pseudonyms of a join session, as in
+/// [`convert_blinded_tables`](crate::join::convert_blinded_tables), but
+/// using the session's conversion target instead of a fresh one. The
+/// attributes of the parts are recorded in the session once the conversion
+/// succeeded, see [`JoinSessions::join`].
+///
+/// Inputs:
+/// - `sessions`: The converter's open join sessions
+/// - `session`: The identifier of the join session
+/// - `policy`: The converter's join policy, if any
+/// - `converter_context`: The Converter's coPRF conversion context
+/// - `bpk_processor`: The processor's public blinding key
+/// - `ek_processor`: The processor's public encryption key
+/// - `parts`: Tables of blinded pseudonymous data values
+/// - `now`: The current time in seconds since the Unix epoch
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A table of join-pseudonymized data values, consistent with all other
+/// joins of the session.
+///
+/// Raises:
+/// - `PolicyViolation`: If the session is unknown, was opened for a
+///   different processor, or has expired, or the policy does not allow the
+///   processor to join all attributes of the session.
+/// - `InvalidInput`: If no parts are given.
+#[allow(clippy::too_many_arguments)]
+pub fn convert_blinded_tables_in_session(
+    sessions: &mut JoinSessions,
+    session: &JoinSessionId,
+    policy: Option<&JoinPolicy>,
+    converter_context: &ConverterContext,
+    bpk_processor: BlindingPublicKey,
+    ek_processor: &StoreEncryptionKey,
+    parts: Vec<Table<BlindedPseudonymizedData>>,
+    now: u64,
+    randomness: &mut Randomness,
+) -> Result<Table<BlindedPseudonymizedData>, Error> {
+    let attributes = parts
+        .iter()
+        .flat_map(|part| part.data())
+        .map(|entry| entry.encrypted_data_value.attribute_name.clone())
+        .collect::<BTreeSet<_>>();
+    let conversion_target = sessions.authorize_join(
+        session,
+        &bpk_processor,
+        attributes.iter().map(String::as_str),
+        policy,
+        now,
+    )?;
+    let converted = convert_blinded_tables_to(
+        converter_context,
+        &conversion_target,
+        bpk_processor,
+        ek_processor,
+        parts,
+        randomness,
+    )?;
+    sessions.join(
+        session,
+        &bpk_processor,
+        attributes.iter().map(String::as_str),
+        policy,
+        now,
+    )?;
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use oprf::coprf::{coprf_setup::CoPRFEvaluatorContext, coprf_threshold::deal};
+
+    use crate::{
+        data_types::{FinalizedPseudonym, PseudonymizedData},
+        finalize::finalize_blinded_table,
+        join::{blind_pseudonymous_table, convert_blinded_table},
+        setup::StoreContext,
+        split::{blind_orthonymous_table, pseudonymize_blinded_table},
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    use super::*;
+
+    /// The pseudonyms of a joined table, by data value.
+    fn pseudonyms(table: &Table<PseudonymizedData>) -> BTreeMap<Vec<u8>, FinalizedPseudonym> {
+        table
+            .data()
+            .iter()
+            .map(|entry| (entry.data_value.value.clone(), entry.handle))
+            .collect()
+    }
+
+    #[test]
+    fn test_join_session() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+        let converted_table = pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let lake_table = finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            converted_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let column = |attribute: &str| {
+            Table::new(
+                attribute.into(),
+                lake_table
+                    .data()
+                    .iter()
+                    .filter(|entry| entry.data_value.attribute_name == attribute)
+                    .cloned()
+                    .collect(),
+            )
+        };
+
+        let processor_context = StoreContext::setup(&mut randomness).unwrap();
+        let (ek_processor, bpk_processor) = processor_context.public_keys();
+        let mut sessions = JoinSessions::default();
+        let session = sessions
+            .open(bpk_processor, "Cohort", 100, &mut randomness)
+            .unwrap();
+
+        let join = |sessions: &mut JoinSessions,
+                    attribute: &str,
+                    session: Option<&JoinSessionId>,
+                    now: u64,
+                    randomness: &mut Randomness| {
+            let blinded_table = blind_pseudonymous_table(
+                &lake_context,
+                &converter_keys,
+                bpk_processor,
+                &ek_processor,
+                column(attribute),
+                randomness,
+            )?;
+            let converted_table = match session {
+                Some(session) => convert_blinded_tables_in_session(
+                    sessions,
+                    session,
+                    None,
+                    &converter_context,
+                    bpk_processor,
+                    &ek_processor,
+                    vec![blinded_table],
+                    now,
+                    randomness,
+                )?,
+                None => convert_blinded_table(
+                    &converter_context,
+                    bpk_processor,
+                    &ek_processor,
+                    blinded_table,
+                    randomness,
+                )?,
+            };
+            finalize_blinded_table(
+                &processor_context,
+                &converter_keys,
+                converted_table,
+                randomness,
+            )
+            .map(|table| pseudonyms(&table))
+        };
+
+        // Joins within a session are consistent.
+        let address = join(
+            &mut sessions,
+            "Address",
+            Some(&session),
+            10,
+            &mut randomness,
+        )
+        .unwrap();
+        let birth = join(
+            &mut sessions,
+            "Date of Birth",
+            Some(&session),
+            20,
+            &mut randomness,
+        )
+        .unwrap();
+        assert_eq!(
+            address[&b"TestData1".to_vec()],
+            birth[&b"TestData3".to_vec()]
+        );
+        assert_eq!(
+            address[&b"TestData2".to_vec()],
+            birth[&b"TestData4".to_vec()]
+        );
+
+        // Joins outside of the session, or in another session, are not.
+        let fresh = join(&mut sessions, "Date of Birth", None, 20, &mut randomness).unwrap();
+        assert_ne!(
+            address[&b"TestData1".to_vec()],
+            fresh[&b"TestData3".to_vec()]
+        );
+        let other = sessions
+            .open(bpk_processor, "Cohort", 100, &mut randomness)
+            .unwrap();
+        let join_other = |sessions: &mut JoinSessions, randomness: &mut Randomness| {
+            let blinded_table = blind_pseudonymous_table(
+                &lake_context,
+                &converter_keys,
+                bpk_processor,
+                &ek_processor,
+                column("Date of Birth"),
+                randomness,
+            )
+            .unwrap();
+            let converted_table = convert_blinded_tables_in_session(
+                sessions,
+                &other,
+                None,
+                &converter_context,
+                bpk_processor,
+                &ek_processor,
+                vec![blinded_table],
+                20,
+                randomness,
+            )
+            .unwrap();
+            pseudonyms(
+                &finalize_blinded_table(
+                    &processor_context,
+                    &converter_keys,
+                    converted_table,
+                    randomness,
+                )
+                .unwrap(),
+            )
+        };
+        assert_ne!(
+            address[&b"TestData1".to_vec()],
+            join_other(&mut sessions, &mut randomness)[&b"TestData3".to_vec()]
+        );
+
+        // Sessions are scoped to their processor and expire.
+        assert_eq!(
+            sessions.conversion_target(&session, &lake_bpk, 10),
+            Err(Error::PolicyViolation)
+        );
+        assert_eq!(
+            sessions.conversion_target(&session, &bpk_processor, 100),
+            Err(Error::PolicyViolation)
+        );
+        assert_eq!(
+            sessions.conversion_target(&[0; SECPAR_BYTES], &bpk_processor, 10),
+            Err(Error::PolicyViolation)
+        );
+
+        // Sessions survive a restart, including their joined attributes.
+        assert_eq!(
+            sessions.get(&session).unwrap().attributes,
+            BTreeSet::from(["Address".to_string(), "Date of Birth".to_string()])
+        );
+        let restored = JoinSessions::from_bytes(&sessions.to_bytes()).unwrap();
+        assert_eq!(restored, sessions);
+        let path = std::env::temp_dir().join(format!(
+            "scrambledb-sessions-{}",
+            hex::encode(randomness.bytes(16).unwrap())
+        ));
+        assert_eq!(JoinSessions::load(&path).unwrap(), JoinSessions::default());
+        sessions.save(&path).unwrap();
+        assert_eq!(JoinSessions::load(&path).unwrap(), sessions);
+        std::fs::remove_file(&path).unwrap();
+        sessions.purge_expired(100);
+        assert_eq!(sessions, JoinSessions::default());
+    }
+
+    #[test]
+    fn test_join_session_policy() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let (_, processor) = StoreContext::setup(&mut randomness).unwrap().public_keys();
+        let policy = JoinPolicy::default()
+            .allow(processor, &["Address", "Date of Birth"])
+            .allow(processor, &["Favorite Color", "Income"]);
+        let mut sessions = JoinSessions::default();
+        let session = sessions
+            .open(processor, "Cohort", 100, &mut randomness)
+            .unwrap();
+
+        // Each join of a session is covered by a grant, but together they
+        // would link attributes of both grants.
+        assert!(sessions
+            .join(&session, &processor, ["Address"], Some(&policy), 10)
+            .is_ok());
+        assert_eq!(
+            sessions.join(&session, &processor, ["Favorite Color"], Some(&policy), 20),
+            Err(Error::PolicyViolation)
+        );
+        assert!(sessions
+            .join(&session, &processor, ["Date of Birth"], Some(&policy), 30)
+            .is_ok());
+        assert_eq!(
+            sessions.join(&session, &processor, ["Income"], Some(&policy), 40),
+            Err(Error::PolicyViolation)
+        );
+        assert_eq!(
+            sessions.get(&session).unwrap().attributes,
+            BTreeSet::from(["Address".to_string(), "Date of Birth".to_string()])
+        );
+
+        // The other grant can still be used in another session.
+        let other = sessions
+            .open(processor, "Cohort", 100, &mut randomness)
+            .unwrap();
+        assert!(sessions
+            .join(
+                &other,
+                &processor,
+                ["Favorite Color", "Income"],
+                Some(&policy),
+                10
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_join_session_failed_conversion() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        // A quorum of `t` evaluators pseudonymizes, but cannot convert.
+        let mut evaluators = deal(&dealer, 2, 3).unwrap();
+        evaluators.truncate(2);
+        let converter_context = ConverterContext::threshold(evaluators).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+        let blind_table = blind_orthonymous_table(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            generate_plain_table(),
+            &mut randomness,
+        )
+        .unwrap();
+        let converted_table = pseudonymize_blinded_table(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blind_table,
+            &mut randomness,
+        )
+        .unwrap();
+        let lake_table = finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            converted_table,
+            &mut randomness,
+        )
+        .unwrap();
+
+        let processor_context = StoreContext::setup(&mut randomness).unwrap();
+        let (ek_processor, bpk_processor) = processor_context.public_keys();
+        let mut sessions = JoinSessions::default();
+        let session = sessions
+            .open(bpk_processor, "Cohort", 100, &mut randomness)
+            .unwrap();
+        let blinded_table = blind_pseudonymous_table(
+            &lake_context,
+            &converter_keys,
+            bpk_processor,
+            &ek_processor,
+            lake_table,
+            &mut randomness,
+        )
+        .unwrap();
+
+        // The authorized join fails in conversion and is not recorded.
+        assert_eq!(
+            convert_blinded_tables_in_session(
+                &mut sessions,
+                &session,
+                None,
+                &converter_context,
+                bpk_processor,
+                &ek_processor,
+                vec![blinded_table],
+                10,
+                &mut randomness,
+            )
+            .err(),
+            Some(Error::InvalidInput)
+        );
+        assert!(sessions.get(&session).unwrap().attributes.is_empty());
+    }
+}
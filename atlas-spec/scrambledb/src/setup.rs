@@ -1,49 +1,136 @@
 //! # Setup
+use std::collections::BTreeMap;
+
 use hacspec_lib::Randomness;
 #[cfg(feature = "double-hpke")]
+use hacspec_lib::hacspec_helper::NatMod;
+#[cfg(feature = "double-hpke")]
 use libcrux::hpke::{
     kem::{GenerateKeyPair, Nsk},
     HPKEConfig,
 };
 use oprf::coprf::{
     coprf_online,
-    coprf_setup::{BlindingPublicKey, CoPRFEvaluatorContext, CoPRFReceiverContext},
+    coprf_setup::{
+        derive_blinding_key_share, derive_key, derive_public_key, joint_blinding_key,
+        BlindingPublicKey, CoPRFEvaluatorContext, CoPRFReceiverContext,
+    },
+    coprf_threshold::{
+        combine_public_keys, reconstruct_key, update_scalars, PartialConversionScalars,
+    },
+    coprf_verifiable::{conversion_scalars, ConversionScalars},
 };
 
+#[cfg(feature = "double-hpke")]
+use p256::P256Scalar;
 use p256::P256Point;
 
 use crate::{
-    data_types::{BlindedPseudonymizedHandle, FinalizedPseudonym},
+    data_types::{BlindedPseudonymizedHandle, EncryptionScheme, FinalizedPseudonym},
     error::Error,
+    key_wrap,
+    serialization::{
+        put_byte_string, put_point, put_scalar, put_scheme, put_u32, Reader, WireFormat,
+    },
+    threshold::{collect_quorum, ThresholdEvaluator},
 };
 
+/// Wire format tag of exported converter key material.
+const TAG_CONVERTER_KEYS: u8 = 0x10;
+/// Wire format tag of exported data store key material.
+const TAG_STORE_KEYS: u8 = 0x11;
+
 pub struct ConverterContext {
-    pub(crate) coprf_context: CoPRFEvaluatorContext,
+    pub(crate) backend: ConverterBackend,
 }
 
-/// A data store's private decryption key.
-#[cfg(feature = "double-hpke")]
-pub struct StoreDecryptionKey(pub(crate) Vec<u8>);
+/// The coPRF evaluation backend of a converter.
+pub(crate) enum ConverterBackend {
+    /// A single coPRF evaluator holding the master secret.
+    Single(CoPRFEvaluatorContext),
+    /// A quorum of threshold coPRF evaluators, each holding shares of the
+    /// pseudonymization keys.
+    Threshold(Vec<Box<dyn ThresholdEvaluator>>),
+}
+
+/// The converter's public keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConverterPublicKeys {
+    /// The converter's share of the joint blinding keys.
+    pub(crate) blinding_key_share: BlindingPublicKey,
+    /// The public keys of the pseudonymization keys, by attribute name.
+    pub(crate) attribute_keys: BTreeMap<String, P256Point>,
+}
+
+/// A pseudonym update token, which moves pseudonyms of a single attribute
+/// from the pseudonymization key derived from one converter master secret
+/// to the one derived from another.
+///
+/// An update token holds neither pseudonymization key, only their quotient
+/// and what is needed to prove the update, so that the converter context
+/// before the rotation can be destroyed once all tokens are derived.
+/// Update tokens are still secret to the converter.
+pub struct UpdateToken {
+    /// The scalars converting from the attribute's key before the rotation
+    /// to the one after it, using the blinding key share before the
+    /// rotation.
+    pub(crate) scalars: UpdateScalars,
+}
+
+/// The scalars of an update token, by converter backend.
+pub(crate) enum UpdateScalars {
+    /// The conversion scalars of a single converter.
+    Single(ConversionScalars),
+    /// The partial conversion scalars of a quorum of `2t - 1` threshold
+    /// evaluators.
+    Threshold(Vec<PartialConversionScalars>),
+}
 
 /// A data store's private decryption key.
-#[cfg(not(feature = "double-hpke"))]
-pub struct StoreDecryptionKey(pub(crate) p256::P256Scalar);
+pub enum StoreDecryptionKey {
+    ElGamal(p256::P256Scalar),
+    #[cfg(feature = "double-hpke")]
+    DoubleHpke(Vec<u8>),
+}
 
 /// A data store's public encryption key.
 #[derive(Clone)]
-#[cfg(feature = "double-hpke")]
-pub struct StoreEncryptionKey(pub(crate) Vec<u8>);
+pub enum StoreEncryptionKey {
+    ElGamal(p256::P256Point),
+    #[cfg(feature = "double-hpke")]
+    DoubleHpke(Vec<u8>),
+}
 
-/// A data store's public encryption key.
-#[derive(Clone)]
-#[cfg(not(feature = "double-hpke"))]
-pub struct StoreEncryptionKey(pub(crate) p256::P256Point);
+impl StoreEncryptionKey {
+    /// The encryption scheme of this key.
+    pub fn scheme(&self) -> EncryptionScheme {
+        match self {
+            StoreEncryptionKey::ElGamal(_) => EncryptionScheme::ElGamal,
+            #[cfg(feature = "double-hpke")]
+            StoreEncryptionKey::DoubleHpke(_) => EncryptionScheme::DoubleHpke,
+        }
+    }
+}
+
+impl StoreDecryptionKey {
+    /// The encryption scheme of this key.
+    pub fn scheme(&self) -> EncryptionScheme {
+        match self {
+            StoreDecryptionKey::ElGamal(_) => EncryptionScheme::ElGamal,
+            #[cfg(feature = "double-hpke")]
+            StoreDecryptionKey::DoubleHpke(_) => EncryptionScheme::DoubleHpke,
+        }
+    }
+}
 
 pub struct StoreContext {
     coprf_receiver_context: CoPRFReceiverContext,
-    pub(crate) dk: StoreDecryptionKey,
-    ek: StoreEncryptionKey,
-    k_prp: [u8; 32],
+    /// One encryption key pair per supported encryption scheme. The first
+    /// key pair is the one new data is encrypted towards.
+    encryption_keys: Vec<(StoreDecryptionKey, StoreEncryptionKey)>,
+    /// The PRP keys together with their epochs. The first key is the one new
+    /// pseudonyms are finalized under.
+    prp_keys: Vec<(u32, [u8; 32])>,
 }
 
 pub type LakeContext = StoreContext;
@@ -65,9 +152,280 @@ impl ConverterContext {
     /// ```
     pub fn setup(randomness: &mut Randomness) -> Result<Self, Error> {
         Ok(ConverterContext {
-            coprf_context: CoPRFEvaluatorContext::new(randomness)?,
+            backend: ConverterBackend::Single(CoPRFEvaluatorContext::new(randomness)?),
+        })
+    }
+
+    /// ## Threshold Converter Setup
+    /// Instead of holding the master secret, a converter can combine the
+    /// partial results of a quorum of threshold coPRF evaluators, see
+    /// [`coprf_threshold`](oprf::coprf::coprf_threshold). Any `t` of the
+    /// evaluators can pseudonymize, while converting requires `2t - 1` of
+    /// them.
+    ///
+    /// The evaluators are either held in-process, as their
+    /// [contexts](oprf::coprf::coprf_threshold::ThresholdEvaluatorContext),
+    /// or run by different parties, in which case the converter collects
+    /// their partial results over the wire, see
+    /// [`evaluator_service`](crate::evaluator_service). Pseudonyms are
+    /// computed under the evaluators' joint keys, which differ from the keys
+    /// of a single evaluator, even if dealt from its master secret. Existing
+    /// pseudonyms are migrated to the joint keys using update tokens, see
+    /// [`ConverterContext::update_token`]. The evaluators can be set up by a dealer or,
+    /// without anyone knowing all of their keys, by [distributed key
+    /// generation](oprf::coprf::coprf_dkg).
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If fewer than `t` evaluators are given, or the
+    ///   evaluators do not belong to the same group.
+    pub fn threshold<E: ThresholdEvaluator + 'static>(evaluators: Vec<E>) -> Result<Self, Error> {
+        let first = evaluators.first().ok_or(Error::InvalidInput)?;
+        let blinding_key_share = first.blinding_key_share()?;
+        for (position, evaluator) in evaluators.iter().enumerate() {
+            if evaluator.threshold() != first.threshold()
+                || evaluator.parties() != first.parties()
+                || evaluator.blinding_key_share()? != blinding_key_share
+                || evaluators[..position]
+                    .iter()
+                    .any(|other| other.index() == evaluator.index())
+            {
+                return Err(Error::InvalidInput);
+            }
+        }
+        if evaluators.len() < first.threshold() as usize {
+            return Err(Error::InvalidInput);
+        }
+        Ok(ConverterContext {
+            backend: ConverterBackend::Threshold(
+                evaluators
+                    .into_iter()
+                    .map(|evaluator| Box::new(evaluator) as Box<dyn ThresholdEvaluator>)
+                    .collect(),
+            ),
+        })
+    }
+
+    /// The converter's single coPRF evaluator.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the converter is backed by threshold evaluators.
+    fn single(&self) -> Result<&CoPRFEvaluatorContext, Error> {
+        match &self.backend {
+            ConverterBackend::Single(coprf_context) => Ok(coprf_context),
+            ConverterBackend::Threshold(_) => Err(Error::InvalidInput),
+        }
+    }
+
+    /// ## Converter Key Export
+    /// The converter's coPRF master secret can be exported for persistent
+    /// storage and later restored using [`ConverterContext::import`].
+    ///
+    /// If a password is given, the master secret is encrypted under a key
+    /// derived from the password, as described in
+    /// [`key_wrap`](crate::key_wrap).
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the converter is backed by threshold evaluators,
+    ///   which hold no master secret.
+    pub fn export(&self, password: Option<(&[u8], &mut Randomness)>) -> Result<Vec<u8>, Error> {
+        key_wrap::seal(
+            TAG_CONVERTER_KEYS,
+            &[],
+            self.single()?.master_secret(),
+            password,
+            key_wrap::PBKDF2_ITERATIONS,
+        )
+    }
+
+    /// ## Converter Key Import
+    /// Restore a converter context from key material output by
+    /// [`ConverterContext::export`].
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the export is password protected, but no
+    ///   password was given.
+    /// - `CorruptedData`: If the export is malformed or the password is wrong.
+    pub fn import(bytes: &[u8], password: Option<&[u8]>) -> Result<Self, Error> {
+        let (_, msk) = key_wrap::open(TAG_CONVERTER_KEYS, bytes, password)?;
+        Ok(ConverterContext {
+            backend: ConverterBackend::Single(
+                CoPRFEvaluatorContext::from_master_secret(&msk)
+                    .map_err(|_| Error::CorruptedData)?,
+            ),
+        })
+    }
+
+    /// ## Converter Public Keys
+    /// The converter publishes its blinding key share, as well as the
+    /// public keys of the pseudonymization keys of the given attributes.
+    /// Data sources and data stores need the former for blinding, data
+    /// stores need the latter to verify the converter's proofs.
+    ///
+    /// A converter backed by threshold evaluators interpolates the public
+    /// keys from the public keys of the evaluators' key shares.
+    pub fn public_keys(&self, attributes: &[&str]) -> Result<ConverterPublicKeys, Error> {
+        let attribute_keys = attributes
+            .iter()
+            .map(|attribute| Ok((attribute.to_string(), self.attribute_key(attribute)?)))
+            .collect::<Result<BTreeMap<_, _>, Error>>()?;
+        Ok(ConverterPublicKeys {
+            blinding_key_share: self.blinding_key_share()?,
+            attribute_keys,
         })
     }
+
+    /// The converter's share of the joint blinding keys.
+    pub(crate) fn blinding_key_share(&self) -> Result<BlindingPublicKey, Error> {
+        match &self.backend {
+            ConverterBackend::Single(coprf_context) => Ok(coprf_context.blinding_key_share()?),
+            ConverterBackend::Threshold(evaluators) => Ok(evaluators[0].blinding_key_share()?),
+        }
+    }
+
+    /// The public key of the pseudonymization key of an attribute.
+    pub(crate) fn attribute_key(&self, attribute: &str) -> Result<P256Point, Error> {
+        match &self.backend {
+            ConverterBackend::Single(coprf_context) => {
+                Ok(derive_public_key(coprf_context, attribute.as_bytes())?)
+            }
+            ConverterBackend::Threshold(evaluators) => {
+                let quorum = evaluators[0].threshold() as usize;
+                let shares = collect_quorum(evaluators, quorum, |evaluator| {
+                    Ok((evaluator.index(), evaluator.public_key_share(attribute)?))
+                })?;
+                Ok(combine_public_keys(&shares)?)
+            }
+        }
+    }
+
+    /// ## Converter Master Secret Rotation
+    /// The converter's master secret is rotated by setting up a fresh
+    /// converter context. Pseudonyms held by data stores are then moved to
+    /// the new context by oblivious conversion using update tokens, see
+    /// [`rotation`](crate::rotation).
+    ///
+    /// The update token of an attribute consists of the scalar
+    /// `delta = k_to / k_from`, where `k_from` is the attribute's key
+    /// derived from this context's master secret and `k_to` is the one
+    /// derived from the master secret of `to`, together with the scalars
+    /// needed to prove the update, see [`conversion_scalars`].
+    ///
+    /// ``` text
+    /// Inputs:
+    ///     context: ConverterContext
+    ///     to: ConverterContext
+    ///     attribute: String
+    ///
+    /// Output:
+    ///     token: UpdateToken
+    ///
+    /// fn update_token(context, to, attribute):
+    ///     let k_from = coPRF.derive_key(context.coprf_context, attribute);
+    ///     let k_to = coPRF.derive_key(to.coprf_context, attribute);
+    ///     let x = coPRF.derive_blinding_key_share(context.coprf_context);
+    ///     let delta = k_to / k_from;
+    ///     return (k_from * G, delta, delta * x, x * G)
+    /// ```
+    ///
+    /// A converter backed by threshold evaluators holds no complete
+    /// pseudonymization keys. Instead, each of the first `2t - 1`
+    /// evaluators contributes its scalars for partial conversion from its
+    /// share of `k_from` to its share of `k_to`, which requires that the
+    /// evaluators of `to` are those of this context, under the same
+    /// indices, see [`update_scalars`]. Since this needs the evaluators' key
+    /// shares under both contexts, the evaluators must be held in-process.
+    ///
+    /// A converter moving from a single master secret to threshold
+    /// evaluators, e.g. dealt from the same master secret, migrates the
+    /// existing pseudonyms using update tokens from this context to the
+    /// threshold context `to`, since the evaluators' keys differ from the
+    /// keys derived from the master secret. The token is derived as above,
+    /// with `k_to` reconstructed from the key shares of `t` evaluators of
+    /// `to`, see [`reconstruct_key`]. This reveals the evaluators' keys, so
+    /// the migration must be done by the dealer while it still holds the
+    /// evaluators' contexts in-process, before handing them out.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If this converter is backed by threshold
+    ///   evaluators but `to` is not, or `to` holds fewer than `t`
+    ///   evaluators in-process when this converter is not. If both are
+    ///   backed by threshold evaluators: if this context holds fewer than
+    ///   `2t - 1` evaluators, an evaluator of this context is missing from
+    ///   `to` or has a different threshold there, or an evaluator is not
+    ///   held in-process.
+    pub fn update_token(
+        &self,
+        to: &ConverterContext,
+        attribute: &str,
+    ) -> Result<UpdateToken, Error> {
+        let scalars = match (&self.backend, &to.backend) {
+            (ConverterBackend::Single(from), ConverterBackend::Single(to)) => {
+                UpdateScalars::Single(conversion_scalars(
+                    derive_key(from, attribute.as_bytes())?,
+                    derive_key(to, attribute.as_bytes())?,
+                    derive_blinding_key_share(from)?,
+                )?)
+            }
+            (ConverterBackend::Single(from), ConverterBackend::Threshold(to)) => {
+                let quorum = to
+                    .iter()
+                    .filter_map(|evaluator| evaluator.context())
+                    .take(to[0].threshold() as usize)
+                    .collect::<Vec<_>>();
+                UpdateScalars::Single(conversion_scalars(
+                    derive_key(from, attribute.as_bytes())?,
+                    reconstruct_key(&quorum, attribute.as_bytes())
+                        .map_err(|_| Error::InvalidInput)?,
+                    derive_blinding_key_share(from)?,
+                )?)
+            }
+            (ConverterBackend::Threshold(from), ConverterBackend::Threshold(to)) => {
+                let quorum = from[0].conversion_threshold() as usize;
+                if from.len() < quorum {
+                    return Err(Error::InvalidInput);
+                }
+                let public_key_from = self.attribute_key(attribute)?;
+                let scalars = from[..quorum]
+                    .iter()
+                    .map(|from| {
+                        let from = from.context().ok_or(Error::InvalidInput)?;
+                        let to = to
+                            .iter()
+                            .filter_map(|to| to.context())
+                            .find(|to| to.index() == from.index())
+                            .filter(|to| to.threshold() == from.threshold())
+                            .ok_or(Error::InvalidInput)?;
+                        Ok(update_scalars(
+                            from,
+                            to,
+                            public_key_from,
+                            attribute.as_bytes(),
+                        )?)
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                UpdateScalars::Threshold(scalars)
+            }
+            _ => return Err(Error::InvalidInput),
+        };
+        Ok(UpdateToken { scalars })
+    }
+}
+
+impl ConverterPublicKeys {
+    /// The joint blinding key of the converter and the receiver with the
+    /// given blinding public key, towards which handles are blinded.
+    pub fn blinding_key(
+        &self,
+        bpk_receiver: BlindingPublicKey,
+    ) -> Result<BlindingPublicKey, Error> {
+        Ok(joint_blinding_key(bpk_receiver, self.blinding_key_share)?)
+    }
+
+    /// The public key of the pseudonymization key of an attribute, if
+    /// known.
+    pub fn attribute_key(&self, attribute: &str) -> Option<P256Point> {
+        self.attribute_keys.get(attribute).copied()
+    }
 }
 
 impl StoreContext {
@@ -98,21 +456,121 @@ impl StoreContext {
     ///       k_prp
     ///     }
     /// ```
+    ///
+    /// The RPKE used by [`StoreContext::setup`] is ElGamal encryption.
     pub fn setup(randomness: &mut Randomness) -> Result<Self, Error> {
+        Self::setup_with_scheme(EncryptionScheme::ElGamal, randomness)
+    }
+
+    /// Set up a data store using the given encryption scheme as RPKE.
+    pub fn setup_with_scheme(
+        scheme: EncryptionScheme,
+        randomness: &mut Randomness,
+    ) -> Result<Self, Error> {
         let receiver_context = CoPRFReceiverContext::new(randomness);
 
-        let (dk, ek) = generate_store_keys(randomness)?;
+        let (dk, ek) = generate_store_keys(scheme, randomness)?;
 
         let k_prp = randomness.bytes(32)?.try_into()?;
 
         Ok(Self {
             coprf_receiver_context: receiver_context,
-            dk,
-            ek,
-            k_prp,
+            encryption_keys: vec![(dk, ek)],
+            prp_keys: vec![(0, k_prp)],
         })
     }
 
+    /// Add an encryption key pair for another encryption scheme, e.g. when
+    /// migrating from one scheme to the other.
+    ///
+    /// The new key pair is used for all data sent to the data store from
+    /// now on, i.e. [`StoreContext::public_keys`] returns the new
+    /// encryption key. Data encrypted towards the previous keys can still
+    /// be decrypted.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the data store already holds keys for the given
+    ///   scheme.
+    pub fn add_encryption_scheme(
+        &mut self,
+        scheme: EncryptionScheme,
+        randomness: &mut Randomness,
+    ) -> Result<(), Error> {
+        if self.encryption_key(scheme).is_some() {
+            return Err(Error::InvalidInput);
+        }
+        let key_pair = generate_store_keys(scheme, randomness)?;
+        self.encryption_keys.insert(0, key_pair);
+        Ok(())
+    }
+
+    /// The epoch of the PRP key new pseudonyms are finalized under.
+    pub fn prp_epoch(&self) -> u32 {
+        self.prp_keys[0].0
+    }
+
+    /// Add a fresh PRP key for the next epoch and return the new epoch.
+    ///
+    /// All pseudonyms finalized from now on are finalized under the new
+    /// key. Pseudonyms finalized under previous keys can still be recovered
+    /// and are moved to the new key using
+    /// [`rekey_table`](crate::rotation::rekey_table), or
+    /// [`LakeStore::rekey`](crate::lake_store::LakeStore::rekey) for a
+    /// persistent store. Once all pseudonyms
+    /// have been moved, the previous keys are removed using
+    /// [`StoreContext::retire_prp_key`].
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the epochs are exhausted.
+    pub fn rotate_prp_key(&mut self, randomness: &mut Randomness) -> Result<u32, Error> {
+        let epoch = self.prp_epoch().checked_add(1).ok_or(Error::InvalidInput)?;
+        let k_prp = randomness.bytes(32)?.try_into()?;
+        self.prp_keys.insert(0, (epoch, k_prp));
+        Ok(epoch)
+    }
+
+    /// Remove the PRP key of a previous epoch. Pseudonyms finalized under
+    /// this key can no longer be recovered afterwards.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the data store holds no key for the epoch or the
+    ///   epoch is the current one.
+    pub fn retire_prp_key(&mut self, epoch: u32) -> Result<(), Error> {
+        match self.prp_keys.iter().position(|(e, _)| *e == epoch) {
+            Some(index) if index > 0 => {
+                self.prp_keys.remove(index);
+                Ok(())
+            }
+            _ => Err(Error::InvalidInput),
+        }
+    }
+
+    /// The PRP key of the given epoch, if the data store holds it.
+    fn prp_key(&self, epoch: u32) -> Option<&[u8; 32]> {
+        self.prp_keys
+            .iter()
+            .find(|(e, _)| *e == epoch)
+            .map(|(_, k_prp)| k_prp)
+    }
+
+    /// Get the data store's public encryption key for the given scheme, if
+    /// it supports the scheme.
+    pub fn encryption_key(&self, scheme: EncryptionScheme) -> Option<StoreEncryptionKey> {
+        self.encryption_keys
+            .iter()
+            .find(|(_, ek)| ek.scheme() == scheme)
+            .map(|(_, ek)| ek.clone())
+    }
+
+    /// Get the data store's private decryption key for the given scheme, if
+    /// it supports the scheme.
+    pub(crate) fn decryption_key(&self, scheme: EncryptionScheme) -> Option<&StoreDecryptionKey> {
+        self.encryption_keys
+            .iter()
+            .find(|(dk, _)| dk.scheme() == scheme)
+            .map(|(dk, _)| dk)
+    }
+
     /// Given a store context generated as above, the following methods are
     /// available:
     ///
@@ -130,7 +588,15 @@ impl StoreContext {
     ///     return (ek, bpk);
     /// ```
     pub fn public_keys(&self) -> (StoreEncryptionKey, BlindingPublicKey) {
-        (self.ek.clone(), self.coprf_receiver_context.get_bpk())
+        (
+            self.encryption_keys[0].1.clone(),
+            self.coprf_receiver_context.get_bpk(),
+        )
+    }
+
+    /// The data store's blinding public key.
+    pub(crate) fn blinding_public_key(&self) -> BlindingPublicKey {
+        self.coprf_receiver_context.get_bpk()
     }
 
     /// - Finalize Pseudonym: As part of the finalization of a split or join
@@ -149,16 +615,24 @@ impl StoreContext {
     ///     context.coprf_receiver_context.finalize(blind_pseudonym);
     ///     return PRP.eval(context.k_prp, raw_pseudonym)
     /// ```
+    ///
+    /// The pseudonym is finalized under the PRP key of the current epoch.
     pub fn finalize_pseudonym(
         &self,
         blind_pseudonym: BlindedPseudonymizedHandle,
     ) -> Result<FinalizedPseudonym, Error> {
         let raw_pseudonym =
             coprf_online::finalize(&self.coprf_receiver_context, blind_pseudonym.0)?;
-        Ok(FinalizedPseudonym(prp::prp(
-            raw_pseudonym.raw_bytes(),
-            &self.k_prp,
-        )))
+        Ok(self.harden_pseudonym(raw_pseudonym))
+    }
+
+    /// Apply the PRP key of the current epoch to a raw pseudonym.
+    fn harden_pseudonym(&self, raw_pseudonym: P256Point) -> FinalizedPseudonym {
+        let (epoch, k_prp) = &self.prp_keys[0];
+        FinalizedPseudonym {
+            epoch: *epoch,
+            value: prp::prp(raw_pseudonym.raw_bytes(), k_prp),
+        }
     }
 
     /// - Recover Raw Pseudonym: In preparation of a join conversion, the raw
@@ -177,25 +651,539 @@ impl StoreContext {
     ///   fn recover_raw_pseudonym(context, pseudonym):
     ///       return PRP.invert(context.k_prp, pseudonym)
     ///   ```
+    ///
+    ///   The PRP is inverted using the key of the pseudonym's epoch.
+    ///
+    ///   Raises:
+    ///   - `InvalidInput`: If the data store holds no PRP key for the
+    ///     pseudonym's epoch, e.g. because it has been retired.
     pub fn recover_raw_pseudonym(&self, pseudonym: FinalizedPseudonym) -> Result<P256Point, Error> {
-        P256Point::from_raw_bytes(prp::prp(pseudonym.0, &self.k_prp)).map_err(|e| e.into())
+        let k_prp = self.prp_key(pseudonym.epoch).ok_or(Error::InvalidInput)?;
+        P256Point::from_raw_bytes(prp::prp(pseudonym.value, k_prp)).map_err(|e| e.into())
+    }
+
+    /// - Rekey Pseudonym: Move a pseudonym finalized under a previous PRP
+    ///   key to the PRP key of the current epoch. Pseudonyms of the current
+    ///   epoch are returned unchanged.
+    ///
+    ///   ``` text
+    ///   fn rekey_pseudonym(context, pseudonym):
+    ///       let raw_pseudonym = PRP.invert(context.k_prp_old, pseudonym);
+    ///       return PRP.eval(context.k_prp, raw_pseudonym)
+    ///   ```
+    ///
+    ///   Raises:
+    ///   - `InvalidInput`: If the data store holds no PRP key for the
+    ///     pseudonym's epoch.
+    pub fn rekey_pseudonym(
+        &self,
+        pseudonym: FinalizedPseudonym,
+    ) -> Result<FinalizedPseudonym, Error> {
+        if pseudonym.epoch == self.prp_epoch() {
+            return Ok(pseudonym);
+        }
+        Ok(self.harden_pseudonym(self.recover_raw_pseudonym(pseudonym)?))
+    }
+
+    /// ## Data Store Key Export
+    /// A data store's key material, i.e. the coPRF unblinding key, the
+    /// private decryption keys and the PRP keys of all epochs, can be exported for
+    /// persistent storage and later restored using
+    /// [`StoreContext::import`]. This allows a data store to recover its
+    /// pseudonyms after a restart.
+    ///
+    /// If a password is given, the secret key material is encrypted under a
+    /// key derived from the password, as described in
+    /// [`key_wrap`](crate::key_wrap).
+    pub fn export(&self, password: Option<(&[u8], &mut Randomness)>) -> Result<Vec<u8>, Error> {
+        let mut public = Vec::new();
+        put_u32(&mut public, self.encryption_keys.len() as u32);
+        for (_, ek) in &self.encryption_keys {
+            ek.encode_body(&mut public);
+        }
+        put_point(&mut public, &self.coprf_receiver_context.get_bpk());
+
+        let mut secret = Vec::new();
+        put_scalar(&mut secret, &self.coprf_receiver_context.get_bsk());
+        for (dk, _) in &self.encryption_keys {
+            encode_decryption_key(dk, &mut secret);
+        }
+        put_u32(&mut secret, self.prp_keys.len() as u32);
+        for (epoch, k_prp) in &self.prp_keys {
+            put_u32(&mut secret, *epoch);
+            put_byte_string(&mut secret, k_prp);
+        }
+
+        key_wrap::seal(
+            TAG_STORE_KEYS,
+            &public,
+            &secret,
+            password,
+            key_wrap::PBKDF2_ITERATIONS,
+        )
+    }
+
+    /// ## Data Store Key Import
+    /// Restore a data store context from key material output by
+    /// [`StoreContext::export`].
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the export is password protected, but no
+    ///   password was given.
+    /// - `CorruptedData`: If the export is malformed, the password is wrong,
+    ///   or the public keys do not match the private keys.
+    pub fn import(bytes: &[u8], password: Option<&[u8]>) -> Result<Self, Error> {
+        let (public, secret) = key_wrap::open(TAG_STORE_KEYS, bytes, password)?;
+
+        let mut reader = Reader::new(&public);
+        let count = reader.count(1)?;
+        let encryption_keys = (0..count)
+            .map(|_| StoreEncryptionKey::decode_body(&mut reader))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let bpk = reader.point()?;
+        reader.finish()?;
+
+        let mut reader = Reader::new(&secret);
+        let bsk = reader.scalar()?;
+        let encryption_keys = encryption_keys
+            .into_iter()
+            .map(|ek| Ok((decode_decryption_key(&mut reader)?, ek)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let prp_count = reader.count(1)?;
+        let prp_keys = (0..prp_count)
+            .map(|_| {
+                let epoch = reader.u32()?;
+                let k_prp = reader
+                    .byte_string()?
+                    .try_into()
+                    .map_err(|_| Error::CorruptedData)?;
+                Ok((epoch, k_prp))
+            })
+            .collect::<Result<Vec<(u32, [u8; 32])>, Error>>()?;
+        reader.finish()?;
+
+        let coprf_receiver_context =
+            CoPRFReceiverContext::from_private_key(bsk).map_err(|_| Error::CorruptedData)?;
+        if coprf_receiver_context.get_bpk() != bpk
+            || encryption_keys.is_empty()
+            || prp_keys.is_empty()
+        {
+            return Err(Error::CorruptedData);
+        }
+        for (i, (epoch, _)) in prp_keys.iter().enumerate() {
+            if prp_keys[..i].iter().any(|(other, _)| other == epoch) {
+                return Err(Error::CorruptedData);
+            }
+        }
+        for (i, (dk, ek)) in encryption_keys.iter().enumerate() {
+            if !keys_match(dk, ek)?
+                || encryption_keys[..i]
+                    .iter()
+                    .any(|(_, other)| other.scheme() == ek.scheme())
+            {
+                return Err(Error::CorruptedData);
+            }
+        }
+
+        Ok(Self {
+            coprf_receiver_context,
+            encryption_keys,
+            prp_keys,
+        })
     }
 }
 
-#[cfg(feature = "double-hpke")]
 fn generate_store_keys(
+    scheme: EncryptionScheme,
     randomness: &mut Randomness,
 ) -> Result<(StoreDecryptionKey, StoreEncryptionKey), Error> {
-    let HPKEConfig(_, kem, _, _) = crate::HPKE_CONF;
-    let (hpke_sk, hpke_pk) = GenerateKeyPair(kem, randomness.bytes(Nsk(kem)).unwrap().to_vec())?;
-    Ok((StoreDecryptionKey(hpke_sk), StoreEncryptionKey(hpke_pk)))
+    match scheme {
+        EncryptionScheme::ElGamal => {
+            let (dk, ek) = elgamal::generate_keys(randomness)?;
+            Ok((
+                StoreDecryptionKey::ElGamal(dk),
+                StoreEncryptionKey::ElGamal(ek),
+            ))
+        }
+        #[cfg(feature = "double-hpke")]
+        EncryptionScheme::DoubleHpke => {
+            let HPKEConfig(_, kem, _, _) = crate::HPKE_CONF;
+            let (hpke_sk, hpke_pk) = GenerateKeyPair(kem, randomness.bytes(Nsk(kem))?.to_vec())?;
+            Ok((
+                StoreDecryptionKey::DoubleHpke(hpke_sk),
+                StoreEncryptionKey::DoubleHpke(hpke_pk),
+            ))
+        }
+    }
 }
 
-#[cfg(not(feature = "double-hpke"))]
-fn generate_store_keys(
-    randomness: &mut Randomness,
-) -> Result<(StoreDecryptionKey, StoreEncryptionKey), Error> {
-    let (dk, ek) = elgamal::generate_keys(randomness)?;
+fn encode_decryption_key(dk: &StoreDecryptionKey, out: &mut Vec<u8>) {
+    put_scheme(out, dk.scheme());
+    match dk {
+        StoreDecryptionKey::ElGamal(dk) => put_scalar(out, dk),
+        #[cfg(feature = "double-hpke")]
+        StoreDecryptionKey::DoubleHpke(dk) => put_byte_string(out, dk),
+    }
+}
+
+fn decode_decryption_key(reader: &mut Reader) -> Result<StoreDecryptionKey, Error> {
+    match reader.scheme()? {
+        EncryptionScheme::ElGamal => Ok(StoreDecryptionKey::ElGamal(reader.scalar()?)),
+        #[cfg(feature = "double-hpke")]
+        EncryptionScheme::DoubleHpke => {
+            let HPKEConfig(_, kem, _, _) = crate::HPKE_CONF;
+            let dk = reader.byte_string()?;
+            if dk.len() != Nsk(kem) {
+                return Err(Error::CorruptedData);
+            }
+            Ok(StoreDecryptionKey::DoubleHpke(dk.to_vec()))
+        }
+    }
+}
+
+/// Check that a pair of keys belongs together, by recomputing the public
+/// key from the private key.
+fn keys_match(dk: &StoreDecryptionKey, ek: &StoreEncryptionKey) -> Result<bool, Error> {
+    match (dk, ek) {
+        (StoreDecryptionKey::ElGamal(dk), StoreEncryptionKey::ElGamal(ek)) => {
+            Ok(p256::p256_point_mul_base(*dk)? == *ek)
+        }
+        #[cfg(feature = "double-hpke")]
+        (StoreDecryptionKey::DoubleHpke(dk), StoreEncryptionKey::DoubleHpke(ek)) => {
+            Ok(hpke_public_key(dk)? == *ek)
+        }
+        #[allow(unreachable_patterns)]
+        _ => Ok(false),
+    }
+}
+
+/// Compute the public key of a `DHKEM(P-256, HKDF-SHA256)` private key,
+/// i.e. the uncompressed SEC1 encoding of the private key times the base
+/// point.
+///
+/// Raises:
+/// - `CorruptedData`: If the private key is not a canonical, non-zero
+///   scalar.
+#[cfg(feature = "double-hpke")]
+fn hpke_public_key(dk: &[u8]) -> Result<Vec<u8>, Error> {
+    let scalar = P256Scalar::from_be_bytes(dk);
+    if scalar.to_be_bytes().as_slice() != dk {
+        return Err(Error::CorruptedData);
+    }
+    let point = p256::p256_point_mul_base(scalar)?;
+    let mut pk = vec![0x04];
+    pk.extend_from_slice(&point.x()?.to_be_bytes());
+    pk.extend_from_slice(&point.y()?.to_be_bytes());
+    Ok(pk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate_randomness() -> Randomness {
+        Randomness::from_entropy().unwrap()
+    }
+
+    /// Export the key material of a data store context with the given
+    /// public encryption keys, blinding public key and PRP keys instead of
+    /// its own, to test that import rejects inconsistent key material.
+    fn export_modified(
+        store_context: &StoreContext,
+        encryption_keys: &[StoreEncryptionKey],
+        bpk: BlindingPublicKey,
+        prp_keys: &[(u32, &[u8])],
+    ) -> Vec<u8> {
+        let mut public = Vec::new();
+        put_u32(&mut public, encryption_keys.len() as u32);
+        for ek in encryption_keys {
+            ek.encode_body(&mut public);
+        }
+        put_point(&mut public, &bpk);
+
+        let mut secret = Vec::new();
+        put_scalar(&mut secret, &store_context.coprf_receiver_context.get_bsk());
+        for (dk, _) in &store_context.encryption_keys {
+            encode_decryption_key(dk, &mut secret);
+        }
+        put_u32(&mut secret, prp_keys.len() as u32);
+        for (epoch, k_prp) in prp_keys {
+            put_u32(&mut secret, *epoch);
+            put_byte_string(&mut secret, k_prp);
+        }
+
+        key_wrap::seal(
+            TAG_STORE_KEYS,
+            &public,
+            &secret,
+            None,
+            key_wrap::PBKDF2_ITERATIONS,
+        )
+        .unwrap()
+    }
+
+    /// The public encryption keys of a data store context.
+    fn encryption_keys(store_context: &StoreContext) -> Vec<StoreEncryptionKey> {
+        store_context
+            .encryption_keys
+            .iter()
+            .map(|(_, ek)| ek.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_store_export_import() {
+        let mut randomness = generate_randomness();
+        let store_context = StoreContext::setup(&mut randomness).unwrap();
+        let (ek, bpk) = store_context.public_keys();
+
+        let blind_pseudonym = BlindedPseudonymizedHandle(
+            coprf_online::prepare_blind_convert(
+                bpk,
+                p256::p256_point_mul_base(p256::random_scalar(&mut randomness, b"Test").unwrap())
+                    .unwrap(),
+                &mut randomness,
+            )
+            .unwrap(),
+        );
+        let pseudonym = store_context.finalize_pseudonym(blind_pseudonym).unwrap();
+
+        let exports = [
+            (store_context.export(None).unwrap(), None),
+            (
+                store_context
+                    .export(Some((b"password", &mut randomness)))
+                    .unwrap(),
+                Some(b"password".as_slice()),
+            ),
+        ];
+
+        for (export, password) in exports {
+            let restored = StoreContext::import(&export, password).unwrap();
+            let (restored_ek, restored_bpk) = restored.public_keys();
+            assert_eq!(restored_ek.to_bytes(), ek.to_bytes());
+            assert_eq!(restored_bpk, bpk);
+            assert_eq!(
+                restored.finalize_pseudonym(blind_pseudonym).unwrap(),
+                pseudonym
+            );
+        }
+
+        let export = store_context
+            .export(Some((b"password", &mut randomness)))
+            .unwrap();
+        assert_eq!(
+            StoreContext::import(&export, None).err(),
+            Some(Error::InvalidInput)
+        );
+        assert_eq!(
+            StoreContext::import(&export, Some(b"passwort")).err(),
+            Some(Error::CorruptedData)
+        );
+
+        let encryption_keys = encryption_keys(&store_context);
+        let prp_keys = store_context
+            .prp_keys
+            .iter()
+            .map(|(epoch, k_prp)| (*epoch, k_prp.as_slice()))
+            .collect::<Vec<_>>();
+        let unmodified = export_modified(&store_context, &encryption_keys, bpk, &prp_keys);
+        assert!(StoreContext::import(&unmodified, None).is_ok());
+
+        // Public keys that do not belong to the private keys are rejected.
+        let other_context = StoreContext::setup(&mut randomness).unwrap();
+        let (other_ek, other_bpk) = other_context.public_keys();
+        let mismatched = export_modified(&store_context, &encryption_keys, other_bpk, &prp_keys);
+        assert_eq!(
+            StoreContext::import(&mismatched, None).err(),
+            Some(Error::CorruptedData)
+        );
+        let mismatched = export_modified(&store_context, &[other_ek], bpk, &prp_keys);
+        assert_eq!(
+            StoreContext::import(&mismatched, None).err(),
+            Some(Error::CorruptedData)
+        );
+
+        // A PRP key of the wrong length is rejected.
+        let short_key = export_modified(&store_context, &encryption_keys, bpk, &[(0, &[0; 31])]);
+        assert_eq!(
+            StoreContext::import(&short_key, None).err(),
+            Some(Error::CorruptedData)
+        );
+    }
+
+    #[test]
+    fn test_prp_key_rotation() {
+        let mut randomness = generate_randomness();
+        let mut store_context = StoreContext::setup(&mut randomness).unwrap();
+        let (_, bpk) = store_context.public_keys();
+        let raw_pseudonym =
+            p256::p256_point_mul_base(p256::random_scalar(&mut randomness, b"Test").unwrap())
+                .unwrap();
+        let blind_pseudonym = BlindedPseudonymizedHandle(
+            coprf_online::prepare_blind_convert(bpk, raw_pseudonym, &mut randomness).unwrap(),
+        );
+
+        let old_pseudonym = store_context.finalize_pseudonym(blind_pseudonym).unwrap();
+        let old_epoch = store_context.prp_epoch();
+        assert_eq!(old_pseudonym.epoch(), old_epoch);
+        let new_epoch = store_context.rotate_prp_key(&mut randomness).unwrap();
+        let new_pseudonym = store_context.finalize_pseudonym(blind_pseudonym).unwrap();
+        assert_eq!(new_pseudonym.epoch(), new_epoch);
+        assert_ne!(new_pseudonym, old_pseudonym);
+        assert_eq!(
+            store_context.rekey_pseudonym(old_pseudonym).unwrap(),
+            new_pseudonym
+        );
+
+        // All epochs survive an export.
+        let restored = StoreContext::import(&store_context.export(None).unwrap(), None).unwrap();
+        assert_eq!(restored.prp_epoch(), new_epoch);
+        for pseudonym in [old_pseudonym, new_pseudonym] {
+            assert_eq!(
+                restored.recover_raw_pseudonym(pseudonym).unwrap(),
+                raw_pseudonym
+            );
+        }
+
+        store_context.retire_prp_key(old_epoch).unwrap();
+        assert_eq!(
+            store_context.recover_raw_pseudonym(old_pseudonym),
+            Err(Error::InvalidInput)
+        );
+        assert_eq!(
+            store_context.recover_raw_pseudonym(new_pseudonym).unwrap(),
+            raw_pseudonym
+        );
+        assert_eq!(
+            store_context.retire_prp_key(old_epoch),
+            Err(Error::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_add_encryption_scheme() {
+        let mut randomness = generate_randomness();
+        let mut store_context = StoreContext::setup(&mut randomness).unwrap();
+        assert_eq!(
+            store_context.add_encryption_scheme(EncryptionScheme::ElGamal, &mut randomness),
+            Err(Error::InvalidInput)
+        );
+        assert_eq!(
+            store_context.public_keys().0.scheme(),
+            EncryptionScheme::ElGamal
+        );
+    }
+
+    /// A data store migrating from double HPKE to ElGamal encryption can
+    /// decrypt data encrypted using either scheme.
+    #[cfg(feature = "double-hpke")]
+    #[test]
+    fn test_mixed_encryption_schemes() {
+        use crate::data_transformations::data_encryption::{
+            decrypt_data_value, encrypt_data_value, rerandomize_encryption,
+        };
+        use crate::data_types::DataValue;
+
+        let mut randomness = generate_randomness();
+        let mut store_context =
+            StoreContext::setup_with_scheme(EncryptionScheme::DoubleHpke, &mut randomness).unwrap();
+        let (hpke_ek, _) = store_context.public_keys();
+        store_context
+            .add_encryption_scheme(EncryptionScheme::ElGamal, &mut randomness)
+            .unwrap();
+        let (elgamal_ek, _) = store_context.public_keys();
+        assert_eq!(elgamal_ek.scheme(), EncryptionScheme::ElGamal);
 
-    Ok((StoreDecryptionKey(dk), StoreEncryptionKey(ek)))
+        let data = DataValue {
+            value: b"TestData".to_vec(),
+            attribute_name: "Address".into(),
+        };
+        for ek in [&hpke_ek, &elgamal_ek] {
+            let encrypted = encrypt_data_value(&data, ek, &mut randomness).unwrap();
+            assert_eq!(encrypted.scheme(), ek.scheme());
+            let rerandomized = rerandomize_encryption(&encrypted, ek, &mut randomness).unwrap();
+            assert_eq!(
+                decrypt_data_value(&rerandomized, &store_context).unwrap(),
+                data
+            );
+
+            // Every encryption layer is bound to the attribute name.
+            let mut relabeled = encrypted.clone();
+            relabeled.attribute_name = "Date of Birth".into();
+            let relabeled = rerandomize_encryption(&relabeled, ek, &mut randomness).unwrap();
+            assert_eq!(
+                decrypt_data_value(&relabeled, &store_context),
+                Err(Error::CorruptedData)
+            );
+            let mut relabeled = rerandomized;
+            relabeled.attribute_name = "Date of Birth".into();
+            assert_eq!(
+                decrypt_data_value(&relabeled, &store_context),
+                Err(Error::CorruptedData)
+            );
+
+            let other_ek = if ek.scheme() == EncryptionScheme::ElGamal {
+                &hpke_ek
+            } else {
+                &elgamal_ek
+            };
+            assert_eq!(
+                rerandomize_encryption(&encrypted, other_ek, &mut randomness),
+                Err(Error::InvalidInput)
+            );
+        }
+
+        let restored = StoreContext::import(&store_context.export(None).unwrap(), None).unwrap();
+        assert!(restored
+            .decryption_key(EncryptionScheme::DoubleHpke)
+            .is_some());
+        assert_eq!(restored.public_keys().0.scheme(), EncryptionScheme::ElGamal);
+    }
+
+    #[cfg(feature = "double-hpke")]
+    #[test]
+    fn test_hpke_keys_must_match() {
+        let mut randomness = generate_randomness();
+        let store_context =
+            StoreContext::setup_with_scheme(EncryptionScheme::DoubleHpke, &mut randomness).unwrap();
+        let export = store_context.export(None).unwrap();
+        assert!(StoreContext::import(&export, None).is_ok());
+
+        let other_context =
+            StoreContext::setup_with_scheme(EncryptionScheme::DoubleHpke, &mut randomness).unwrap();
+        let prp_keys = store_context
+            .prp_keys
+            .iter()
+            .map(|(epoch, k_prp)| (*epoch, k_prp.as_slice()))
+            .collect::<Vec<_>>();
+        let mismatched = export_modified(
+            &store_context,
+            &encryption_keys(&other_context),
+            store_context.public_keys().1,
+            &prp_keys,
+        );
+        assert_eq!(
+            StoreContext::import(&mismatched, None).err(),
+            Some(Error::CorruptedData)
+        );
+    }
+
+    #[test]
+    fn test_converter_export_import() {
+        let mut randomness = generate_randomness();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+
+        let export = converter_context.export(None).unwrap();
+        let restored = ConverterContext::import(&export, None).unwrap();
+        assert_eq!(
+            derive_key(restored.single().unwrap(), b"Address").unwrap(),
+            derive_key(converter_context.single().unwrap(), b"Address").unwrap()
+        );
+
+        assert_eq!(
+            StoreContext::import(&export, None).err(),
+            Some(Error::CorruptedData)
+        );
+    }
 }
@@ -6,7 +6,7 @@ use crate::{
     data_transformations::{blind_identifiable_datum, pseudonymize_blinded_datum},
     data_types::{BlindedIdentifiableData, BlindedPseudonymizedData, IdentifiableData},
     error::Error,
-    setup::{ConverterContext, StoreEncryptionKey},
+    setup::{ConverterContext, ConverterPublicKeys, StoreEncryptionKey},
     table::Table,
 };
 
@@ -16,6 +16,7 @@ use crate::{
 /// the blinding operation on each entry and shuffling the result.
 ///
 /// Inputs:
+/// - `converter_keys`: The converter's public keys
 /// - `ek_receiver`: The receiver's public encryption key
 /// - `bpk_receiver`: The receiver's public blinding key
 /// - `table`: A table of identifiable data values
@@ -24,20 +25,21 @@ use crate::{
 /// Outputs:
 /// A table of blinded identifiable data.
 pub fn blind_orthonymous_table(
+    converter_keys: &ConverterPublicKeys,
     ek_receiver: &StoreEncryptionKey,
     bpk_receiver: BlindingPublicKey,
     table: Table<IdentifiableData>,
     randomness: &mut Randomness,
 ) -> Result<Table<BlindedIdentifiableData>, Error> {
-    let mut blinded_table_entries = table
-        .data()
-        .iter()
-        .map(|entry| blind_identifiable_datum(&bpk_receiver, ek_receiver, entry, randomness))
-        .collect::<Result<Vec<BlindedIdentifiableData>, Error>>()?;
+    let bpk = converter_keys.blinding_key(bpk_receiver)?;
+    let blinded_table_entries = table.try_map_randomized(randomness, |entry, randomness| {
+        blind_identifiable_datum(&bpk, ek_receiver, entry, randomness)
+    })?;
 
-    blinded_table_entries.sort();
+    let mut blinded_table = Table::new(table.identifier().into(), blinded_table_entries);
+    blinded_table.shuffle(randomness)?;
 
-    Ok(Table::new(table.identifier().into(), blinded_table_entries))
+    Ok(blinded_table)
 }
 
 /// ## Oblivious Pseudonymization
@@ -62,45 +64,43 @@ pub fn pseudonymize_blinded_table(
     blinded_table: Table<BlindedIdentifiableData>,
     randomness: &mut Randomness,
 ) -> Result<Table<BlindedPseudonymizedData>, Error> {
-    let mut blinded_pseudonymized_entries = blinded_table
-        .data()
-        .iter()
-        .map(|entry| {
+    let blinded_pseudonymized_entries =
+        blinded_table.try_map_randomized(randomness, |entry, randomness| {
             pseudonymize_blinded_datum(
-                &converter_context.coprf_context,
+                converter_context,
                 &bpk_receiver,
                 ek_receiver,
                 entry,
                 randomness,
             )
-        })
-        .collect::<Result<Vec<BlindedPseudonymizedData>, Error>>()?;
-    blinded_pseudonymized_entries.sort();
+        })?;
 
-    Ok(Table::new(
+    let mut pseudonymized_table = Table::new(
         blinded_table.identifier().into(),
         blinded_pseudonymized_entries,
-    ))
+    );
+    pseudonymized_table.shuffle(randomness)?;
+
+    Ok(pseudonymized_table)
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
 
-    use crate::{setup::StoreContext, test_util::generate_plain_table};
+    use crate::{
+        setup::StoreContext,
+        test_util::{converter_public_keys, generate_plain_table},
+    };
 
     use super::*;
 
     #[test]
     fn test_split_full() {
-        use rand::prelude::*;
-
-        let mut rng = rand::thread_rng();
-        let mut randomness = [0u8; 1000000];
-        rng.fill_bytes(&mut randomness);
-        let mut randomness = Randomness::new(randomness.to_vec());
+        let mut randomness = Randomness::from_entropy().unwrap();
 
         let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
         let lake_context = StoreContext::setup(&mut randomness).unwrap();
 
         // == Generate Plain Table ==
@@ -110,6 +110,7 @@ mod tests {
 
         // == Blind Table for Pseudonymization ==
         let blind_table = crate::split::blind_orthonymous_table(
+            &converter_keys,
             &lake_ek,
             lake_bpk,
             plain_table.clone(),
@@ -128,8 +129,13 @@ mod tests {
         .unwrap();
 
         // == Unblinded Pseudonymized Table ==
-        let lake_tables =
-            crate::finalize::finalize_blinded_table(&lake_context, converted_tables).unwrap();
+        let lake_tables = crate::finalize::finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            converted_tables,
+            &mut randomness,
+        )
+        .unwrap();
 
         let mut pseudonym_set = HashSet::new();
         // test that data is preserved
@@ -150,4 +156,41 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_blinding_uses_fresh_randomness() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+
+        // Blinding the same table twice using the same randomness must not
+        // reuse any blinding or encryption randomness.
+        let mut blind = || {
+            blind_orthonymous_table(
+                &converter_keys,
+                &lake_ek,
+                lake_bpk,
+                generate_plain_table(),
+                &mut randomness,
+            )
+            .unwrap()
+        };
+        let first = blind();
+        let second = blind();
+
+        for (first, second) in first
+            .data()
+            .iter()
+            .flat_map(|first| second.data().iter().map(move |second| (first, second)))
+        {
+            assert_ne!(first.blinded_handle, second.blinded_handle);
+            assert_ne!(
+                first.encrypted_data_value.value,
+                second.encrypted_data_value.value
+            );
+        }
+    }
 }
@@ -0,0 +1,807 @@
+//! # Streaming Conversions
+//!
+//! The table transformations of [`split`](crate::split),
+//! [`join`](crate::join) and [`finalize`](crate::finalize) operate on
+//! tables held in memory in their entirety. This module provides variants
+//! of these transformations that process entries as they are read, e.g.
+//! from a CSV file or a converter connection, such that tables larger than
+//! the available memory can be converted.
+//!
+//! A streaming transformation reads its input in chunks of
+//! [`StreamConfig::chunk_entries`] entries. The entries of each chunk are
+//! transformed as in the corresponding table transformation, using a child
+//! of the given randomness, forked with the index of the chunk as label.
+//! Every fork draws fresh randomness, so repeated transformations never
+//! share randomness. The transformed entries are passed to an
+//! [`ExternalShuffle`], which outputs them in uniformly random order once
+//! the input is exhausted.
+//!
+//! ## External Shuffle
+//!
+//! As long as at most [`StreamConfig::memory_entries`] entries have been
+//! pushed to an [`ExternalShuffle`], they are kept in memory and shuffled
+//! using the Fisher-Yates shuffle, as in [`Table::shuffle`]. Once this
+//! limit is exceeded, all entries are scattered over [`SHUFFLE_BUCKETS`]
+//! temporary files, choosing the file for each entry uniformly at random.
+//! When reading the output, the buckets are shuffled one at a time and
+//! output in order, where buckets exceeding the memory limit are shuffled
+//! externally in the same way.
+//!
+//! Since the bucket of each entry is chosen independently and uniformly at
+//! random, and each bucket is permuted uniformly at random, the resulting
+//! permutation of all entries is uniformly random as well.
+//!
+//! Temporary files hold entries in the [wire format](crate::serialization),
+//! each prefixed by its length as a big-endian `u32`. They are created in a
+//! fresh directory below [`StreamConfig::temp_dir`], which is removed once
+//! the output has been consumed or dropped. At a data lake or processor,
+//! finalized entries are written to temporary files in plain text, so on
+//! Unix the directory is only accessible to its owner (mode `0700`) and
+//! the files are only readable and writable by their owner (mode `0600`).
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+
+use hacspec_lib::Randomness;
+use oprf::coprf::coprf_setup::BlindingPublicKey;
+
+use crate::{
+    data_transformations::{
+        blind_identifiable_datum, blind_pseudonymized_datum, convert_blinded_datum,
+        finalize_blinded_datum, pseudonymize_blinded_datum, verify_blinded_datum,
+    },
+    data_types::{
+        BlindedIdentifiableData, BlindedPseudonymizedData, IdentifiableData, PseudonymizedData,
+    },
+    error::Error,
+    serialization::WireFormat,
+    setup::{ConverterContext, ConverterPublicKeys, StoreContext, StoreEncryptionKey},
+    table::{shuffle_entries, uniform_index, Table},
+    SECPAR_BYTES,
+};
+
+/// The number of temporary files an [`ExternalShuffle`] scatters its
+/// entries over once they exceed its memory limit.
+pub const SHUFFLE_BUCKETS: usize = 64;
+
+/// Resource limits of streaming transformations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamConfig {
+    /// The number of entries read and transformed at once.
+    pub chunk_entries: usize,
+    /// The maximum number of entries an [`ExternalShuffle`] keeps in
+    /// memory.
+    pub memory_entries: usize,
+    /// The directory in which temporary files are created.
+    pub temp_dir: PathBuf,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            chunk_entries: 1 << 14,
+            memory_entries: 1 << 20,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+}
+
+/// A temporary directory, which is removed including its contents on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create a fresh directory with a random name below `parent`, which
+    /// is only accessible to its owner.
+    fn new(parent: &std::path::Path, randomness: &mut Randomness) -> Result<Self, Error> {
+        let path = parent.join(format!(
+            "scrambledb-shuffle-{}",
+            hex::encode(randomness.bytes(16)?)
+        ));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder.create(&path)?;
+        Ok(Self(path))
+    }
+
+    /// Create the `index`-th bucket file, which is only readable and
+    /// writable by its owner.
+    fn create_bucket(&self, index: usize) -> Result<File, Error> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        Ok(options.open(self.bucket(index))?)
+    }
+
+    /// The path of the `index`-th bucket file in this directory.
+    fn bucket(&self, index: usize) -> PathBuf {
+        self.0.join(format!("bucket-{index}"))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Entries spilled to bucket files.
+struct Buckets {
+    dir: TempDir,
+    writers: Vec<BufWriter<File>>,
+    counts: Vec<usize>,
+}
+
+impl Buckets {
+    fn new(dir: TempDir) -> Result<Self, Error> {
+        let writers = (0..SHUFFLE_BUCKETS)
+            .map(|index| Ok(BufWriter::new(dir.create_bucket(index)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Self {
+            dir,
+            writers,
+            counts: vec![0; SHUFFLE_BUCKETS],
+        })
+    }
+
+    /// Append an entry to a uniformly random bucket.
+    fn push<T: WireFormat>(&mut self, entry: &T, randomness: &mut Randomness) -> Result<(), Error> {
+        let index = uniform_index(SHUFFLE_BUCKETS, randomness)?;
+        let bytes = entry.to_bytes();
+        let writer = &mut self.writers[index];
+        let length = u32::try_from(bytes.len()).map_err(|_| Error::InvalidInput)?;
+        writer.write_all(&length.to_be_bytes())?;
+        writer.write_all(&bytes)?;
+        self.counts[index] += 1;
+        Ok(())
+    }
+}
+
+/// Read the next length prefixed entry of a bucket file.
+fn read_entry<T: WireFormat>(reader: &mut impl Read) -> Result<T, Error> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    let mut bytes = vec![0u8; u32::from_be_bytes(length) as usize];
+    reader.read_exact(&mut bytes)?;
+    T::from_bytes(&bytes)
+}
+
+/// A uniformly random permutation of a sequence of entries, using a bounded
+/// amount of memory.
+pub struct ExternalShuffle<T> {
+    memory_entries: usize,
+    temp_dir: PathBuf,
+    entries: Vec<T>,
+    buckets: Option<Buckets>,
+    randomness: Randomness,
+}
+
+impl<T: WireFormat> ExternalShuffle<T> {
+    /// Start a new shuffle.
+    ///
+    /// Inputs:
+    /// - `config`: The memory limit and directory for temporary files
+    /// - `randomness`: Random bytes
+    ///
+    /// Raises:
+    /// - `RandomnessError`: If not enough randomness was provided.
+    pub fn new(config: &StreamConfig, randomness: &mut Randomness) -> Result<Self, Error> {
+        Ok(Self::with_randomness(
+            config.memory_entries,
+            config.temp_dir.clone(),
+            Randomness::from_seed(randomness.bytes(32)?.try_into()?),
+        ))
+    }
+
+    fn with_randomness(memory_entries: usize, temp_dir: PathBuf, randomness: Randomness) -> Self {
+        Self {
+            memory_entries: memory_entries.max(1),
+            temp_dir,
+            entries: Vec::new(),
+            buckets: None,
+            randomness,
+        }
+    }
+
+    /// Add an entry to the shuffle.
+    ///
+    /// Raises:
+    /// - `StorageError`: If writing a temporary file fails.
+    pub fn push(&mut self, entry: T) -> Result<(), Error> {
+        if let Some(buckets) = &mut self.buckets {
+            return buckets.push(&entry, &mut self.randomness);
+        }
+
+        self.entries.push(entry);
+        if self.entries.len() > self.memory_entries {
+            let mut buckets = Buckets::new(TempDir::new(&self.temp_dir, &mut self.randomness)?)?;
+            for entry in self.entries.drain(..) {
+                buckets.push(&entry, &mut self.randomness)?;
+            }
+            self.buckets = Some(buckets);
+        }
+        Ok(())
+    }
+
+    /// Finish adding entries and start reading the shuffled output.
+    ///
+    /// Raises:
+    /// - `RandomnessError`: If not enough randomness was provided.
+    /// - `StorageError`: If writing a temporary file fails.
+    pub fn finish(mut self) -> Result<ShuffledStream<T>, Error> {
+        let Some(Buckets {
+            dir,
+            writers,
+            counts,
+        }) = self.buckets
+        else {
+            shuffle_entries(&mut self.entries, &mut self.randomness)?;
+            return Ok(ShuffledStream {
+                entries: self.entries.into_iter(),
+                nested: None,
+                pending: VecDeque::new(),
+                dir: None,
+                memory_entries: self.memory_entries,
+                randomness: self.randomness,
+            });
+        };
+
+        for mut writer in writers {
+            writer.flush()?;
+        }
+        Ok(ShuffledStream {
+            entries: Vec::new().into_iter(),
+            nested: None,
+            pending: counts.into_iter().enumerate().collect(),
+            dir: Some(dir),
+            memory_entries: self.memory_entries,
+            randomness: self.randomness,
+        })
+    }
+}
+
+/// The output of an [`ExternalShuffle`], in uniformly random order.
+///
+/// Reading a temporary file may fail, in which case the error is returned
+/// and the stream ends.
+pub struct ShuffledStream<T> {
+    /// Shuffled entries of the current bucket.
+    entries: std::vec::IntoIter<T>,
+    /// The output of the current bucket, if it is shuffled externally.
+    nested: Option<Box<ShuffledStream<T>>>,
+    /// Index and size of the buckets not yet read.
+    pending: VecDeque<(usize, usize)>,
+    dir: Option<TempDir>,
+    memory_entries: usize,
+    randomness: Randomness,
+}
+
+impl<T: WireFormat> ShuffledStream<T> {
+    /// Read and shuffle the bucket with the given index and size.
+    fn load_bucket(&mut self, index: usize, count: usize) -> Result<(), Error> {
+        let dir = self.dir.as_ref().ok_or(Error::InvalidInput)?;
+        let path = dir.bucket(index);
+        let mut reader = BufReader::new(File::open(&path)?);
+
+        if count <= self.memory_entries {
+            let mut entries = (0..count)
+                .map(|_| read_entry(&mut reader))
+                .collect::<Result<Vec<T>, Error>>()?;
+            shuffle_entries(&mut entries, &mut self.randomness)?;
+            self.entries = entries.into_iter();
+        } else {
+            let mut shuffle = ExternalShuffle::with_randomness(
+                self.memory_entries,
+                dir.0.clone(),
+                self.randomness.fork(&(index as u64).to_be_bytes())?,
+            );
+            for _ in 0..count {
+                shuffle.push(read_entry(&mut reader)?)?;
+            }
+            self.nested = Some(Box::new(shuffle.finish()?));
+        }
+
+        drop(reader);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
+
+impl<T: WireFormat> Iterator for ShuffledStream<T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(nested) = &mut self.nested {
+                match nested.next() {
+                    Some(entry) => return Some(entry),
+                    None => self.nested = None,
+                }
+            }
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+
+            let (index, count) = self.pending.pop_front()?;
+            if let Err(error) = self.load_bucket(index, count) {
+                self.pending.clear();
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+/// Transform a stream of entries chunk by chunk and shuffle the result.
+fn transform_stream<T, U: WireFormat>(
+    entries: impl IntoIterator<Item = Result<T, Error>>,
+    config: &StreamConfig,
+    randomness: &mut Randomness,
+    mut transform: impl FnMut(Table<T>, &mut Randomness) -> Result<Vec<U>, Error>,
+) -> Result<ShuffledStream<U>, Error> {
+    let mut shuffle = ExternalShuffle::new(config, randomness)?;
+    let mut entries = entries.into_iter();
+
+    for index in 0u64.. {
+        let chunk = entries
+            .by_ref()
+            .take(config.chunk_entries.max(1))
+            .collect::<Result<Vec<T>, Error>>()?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let mut chunk_randomness = randomness.fork(&index.to_be_bytes())?;
+        for entry in transform(Table::new(String::new(), chunk), &mut chunk_randomness)? {
+            shuffle.push(entry)?;
+        }
+    }
+
+    shuffle.finish()
+}
+
+/// ## Blinding Orthonymous Streams
+///
+/// Streaming variant of
+/// [`blind_orthonymous_table`](crate::split::blind_orthonymous_table).
+///
+/// Inputs:
+/// - `converter_keys`: The converter's public keys
+/// - `ek_receiver`: The receiver's public encryption key
+/// - `bpk_receiver`: The receiver's public blinding key
+/// - `entries`: A stream of identifiable data values
+/// - `config`: Resource limits of the transformation
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A stream of blinded identifiable data, in random order.
+///
+/// Raises:
+/// - Any error of the input stream.
+/// - `StorageError`: If writing a temporary file fails.
+pub fn blind_orthonymous_stream(
+    converter_keys: &ConverterPublicKeys,
+    ek_receiver: &StoreEncryptionKey,
+    bpk_receiver: BlindingPublicKey,
+    entries: impl IntoIterator<Item = Result<IdentifiableData, Error>>,
+    config: &StreamConfig,
+    randomness: &mut Randomness,
+) -> Result<ShuffledStream<BlindedIdentifiableData>, Error> {
+    let bpk = converter_keys.blinding_key(bpk_receiver)?;
+    transform_stream(entries, config, randomness, |chunk, randomness| {
+        chunk.try_map_randomized(randomness, |entry, randomness| {
+            blind_identifiable_datum(&bpk, ek_receiver, entry, randomness)
+        })
+    })
+}
+
+/// ## Oblivious Pseudonymization of Streams
+///
+/// Streaming variant of
+/// [`pseudonymize_blinded_table`](crate::split::pseudonymize_blinded_table).
+///
+/// Inputs:
+/// - `converter_context`: The Converter's coPRF evaluation context
+/// - `bpk_receiver`: The receiver's public blinding key
+/// - `ek_receiver`: The receiver's public encryption key
+/// - `entries`: A stream of blinded identifiable data values
+/// - `config`: Resource limits of the transformation
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A stream of blinded pseudonymized data, in random order.
+///
+/// Raises:
+/// - Any error of the input stream.
+/// - `StorageError`: If writing a temporary file fails.
+pub fn pseudonymize_blinded_stream(
+    converter_context: &ConverterContext,
+    bpk_receiver: BlindingPublicKey,
+    ek_receiver: &StoreEncryptionKey,
+    entries: impl IntoIterator<Item = Result<BlindedIdentifiableData, Error>>,
+    config: &StreamConfig,
+    randomness: &mut Randomness,
+) -> Result<ShuffledStream<BlindedPseudonymizedData>, Error> {
+    transform_stream(entries, config, randomness, |chunk, randomness| {
+        chunk.try_map_randomized(randomness, |entry, randomness| {
+            pseudonymize_blinded_datum(
+                converter_context,
+                &bpk_receiver,
+                ek_receiver,
+                entry,
+                randomness,
+            )
+        })
+    })
+}
+
+/// ## Blinding Pseudonymous Streams
+///
+/// Streaming variant of
+/// [`blind_pseudonymous_table`](crate::join::blind_pseudonymous_table).
+///
+/// Inputs:
+/// - `store_context`: The data store's pseudonymization context
+/// - `converter_keys`: The converter's public keys
+/// - `bpk_receiver`: The receiver's public blinding key
+/// - `ek_receiver`: The receiver's public encryption key
+/// - `entries`: A stream of pseudonymous data values
+/// - `config`: Resource limits of the transformation
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A stream of blinded pseudonymous data values, in random order.
+///
+/// Raises:
+/// - Any error of the input stream.
+/// - `StorageError`: If writing a temporary file fails.
+pub fn blind_pseudonymous_stream(
+    store_context: &StoreContext,
+    converter_keys: &ConverterPublicKeys,
+    bpk_receiver: BlindingPublicKey,
+    ek_receiver: &StoreEncryptionKey,
+    entries: impl IntoIterator<Item = Result<PseudonymizedData, Error>>,
+    config: &StreamConfig,
+    randomness: &mut Randomness,
+) -> Result<ShuffledStream<BlindedPseudonymizedData>, Error> {
+    let bpk = converter_keys.blinding_key(bpk_receiver)?;
+    transform_stream(entries, config, randomness, |chunk, randomness| {
+        chunk.try_map_randomized(randomness, |entry, randomness| {
+            blind_pseudonymized_datum(store_context, &bpk, ek_receiver, entry, randomness)
+        })
+    })
+}
+
+/// ## Oblivious Conversion of Streams
+///
+/// Streaming variant of
+/// [`convert_blinded_table`](crate::join::convert_blinded_table). All
+/// entries of the stream are converted to the same fresh join-pseudonyms.
+///
+/// Inputs:
+/// - `converter_context`: The Converter's coPRF conversion context
+/// - `bpk_receiver`: The receiver's public blinding key
+/// - `ek_receiver`: The receiver's public encryption key
+/// - `entries`: A stream of blinded pseudonymous data values
+/// - `config`: Resource limits of the transformation
+/// - `randomness`: Random bytes
+///
+/// Outputs:
+/// A stream of consistently join-pseudonymized data values, in random
+/// order.
+///
+/// Raises:
+/// - Any error of the input stream.
+/// - `StorageError`: If writing a temporary file fails.
+pub fn convert_blinded_stream(
+    converter_context: &ConverterContext,
+    bpk_receiver: BlindingPublicKey,
+    ek_receiver: &StoreEncryptionKey,
+    entries: impl IntoIterator<Item = Result<BlindedPseudonymizedData, Error>>,
+    config: &StreamConfig,
+    randomness: &mut Randomness,
+) -> Result<ShuffledStream<BlindedPseudonymizedData>, Error> {
+    let conversion_target = randomness.bytes(SECPAR_BYTES)?.to_owned();
+    transform_stream(entries, config, randomness, |chunk, randomness| {
+        chunk.try_map_randomized(randomness, |entry, randomness| {
+            convert_blinded_datum(
+                converter_context,
+                &bpk_receiver,
+                ek_receiver,
+                &conversion_target,
+                entry,
+                randomness,
+            )
+        })
+    })
+}
+
+/// ## Finalization of Streams
+///
+/// Streaming variant of
+/// [`finalize_blinded_table`](crate::finalize::finalize_blinded_table).
+///
+/// As for tables, all entries of the stream must have been produced by the
+/// same operation. Since entries are checked as they are read, an error is
+/// raised as soon as an entry does not match the ones before it.
+///
+/// Inputs:
+/// - `store_context`: The data store's pseudonymization context
+/// - `converter_keys`: The converter's public keys
+/// - `entries`: A stream of blinded pseudonymized data values
+/// - `config`: Resource limits of the transformation
+/// - `randomness`: Random bytes
+///
+/// Output:
+/// A stream of pseudonymized data values, in random order.
+///
+/// Raises:
+/// - Any error of the input stream.
+/// - `VerificationError`: If a proof of the converter does not verify or the
+///   entries were not all produced by the same operation.
+/// - `StorageError`: If writing a temporary file fails.
+pub fn finalize_blinded_stream(
+    store_context: &StoreContext,
+    converter_keys: &ConverterPublicKeys,
+    entries: impl IntoIterator<Item = Result<BlindedPseudonymizedData, Error>>,
+    config: &StreamConfig,
+    randomness: &mut Randomness,
+) -> Result<ShuffledStream<PseudonymizedData>, Error> {
+    let mut target_key = None;
+    transform_stream(entries, config, randomness, |chunk, _| {
+        for key in
+            chunk.try_map(|entry| verify_blinded_datum(store_context, converter_keys, entry))?
+        {
+            if *target_key.get_or_insert(key) != key {
+                return Err(Error::VerificationError);
+            }
+        }
+        chunk.try_map(|entry| finalize_blinded_datum(store_context, entry))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        finalize::finalize_blinded_table,
+        test_util::{converter_public_keys, generate_plain_table},
+    };
+
+    fn small_config() -> StreamConfig {
+        StreamConfig {
+            chunk_entries: 2,
+            memory_entries: 3,
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+
+    fn pseudonyms(count: u8) -> Vec<crate::data_types::FinalizedPseudonym> {
+        (0..count)
+            .map(|i| crate::data_types::FinalizedPseudonym {
+                epoch: 0,
+                value: [i; 64],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_external_shuffle_permutes() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        // Exceeds the memory limit within single buckets, so that buckets
+        // are shuffled externally in turn.
+        let config = StreamConfig {
+            chunk_entries: 16,
+            memory_entries: 4,
+            temp_dir: std::env::temp_dir(),
+        };
+
+        let mut shuffle = ExternalShuffle::new(&config, &mut randomness).unwrap();
+        for pseudonym in pseudonyms(255) {
+            shuffle.push(pseudonym).unwrap();
+        }
+        let dir = shuffle.buckets.as_ref().unwrap().dir.0.clone();
+        assert!(dir.exists());
+
+        // Spilled entries are only accessible to the owner.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode =
+                |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&dir), 0o700);
+            assert_eq!(mode(&dir.join("bucket-0")), 0o600);
+        }
+
+        let mut output = shuffle
+            .finish()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_ne!(output, pseudonyms(255));
+        output.sort();
+        assert_eq!(output, pseudonyms(255));
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_external_shuffle_uniform() {
+        const TRIALS: usize = 6000;
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let config = StreamConfig {
+            memory_entries: 1,
+            ..small_config()
+        };
+
+        // Each of the 6 orderings should be observed roughly equally often,
+        // although the entries are always spilled to temporary files.
+        let mut counts = HashMap::new();
+        for _ in 0..TRIALS {
+            let mut shuffle = ExternalShuffle::new(&config, &mut randomness).unwrap();
+            for pseudonym in pseudonyms(3) {
+                shuffle.push(pseudonym).unwrap();
+            }
+            let output = shuffle
+                .finish()
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            *counts.entry(output).or_insert(0usize) += 1;
+        }
+
+        assert_eq!(counts.len(), 6);
+        for count in counts.values() {
+            assert!((800..=1200).contains(count), "Biased shuffle: {counts:?}");
+        }
+    }
+
+    #[test]
+    fn test_stream_uses_fresh_randomness() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+        let config = small_config();
+
+        // Blinding the same stream twice using the same randomness must not
+        // reuse any blinding or encryption randomness.
+        let plain_table = generate_plain_table();
+        let mut blind = || {
+            blind_orthonymous_stream(
+                &converter_keys,
+                &lake_ek,
+                lake_bpk,
+                plain_table.data().iter().cloned().map(Ok),
+                &config,
+                &mut randomness,
+            )
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+        };
+        let first = blind();
+        let second = blind();
+        for first in &first {
+            for second in &second {
+                assert_ne!(first.blinded_handle, second.blinded_handle);
+                assert_ne!(
+                    first.encrypted_data_value.value,
+                    second.encrypted_data_value.value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_stream() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let converter_context = ConverterContext::setup(&mut randomness).unwrap();
+        let converter_keys = converter_public_keys(&converter_context);
+        let lake_context = StoreContext::setup(&mut randomness).unwrap();
+        let (lake_ek, lake_bpk) = lake_context.public_keys();
+        let config = small_config();
+
+        let plain_table = generate_plain_table();
+        let blinded = blind_orthonymous_stream(
+            &converter_keys,
+            &lake_ek,
+            lake_bpk,
+            plain_table.data().iter().cloned().map(Ok),
+            &config,
+            &mut randomness,
+        )
+        .unwrap();
+        let pseudonymized = pseudonymize_blinded_stream(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blinded,
+            &config,
+            &mut randomness,
+        )
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+        // Streaming and table finalization agree.
+        let finalized = finalize_blinded_stream(
+            &lake_context,
+            &converter_keys,
+            pseudonymized.iter().cloned().map(Ok),
+            &config,
+            &mut randomness,
+        )
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        let mut expected = finalize_blinded_table(
+            &lake_context,
+            &converter_keys,
+            Table::new("Test".into(), pseudonymized.clone()),
+            &mut randomness,
+        )
+        .unwrap()
+        .data()
+        .to_vec();
+        let mut finalized_sorted = finalized.clone();
+        finalized_sorted.sort();
+        expected.sort();
+        assert_eq!(finalized_sorted, expected);
+
+        // Join conversion of the lake's stream.
+        let blinded = blind_pseudonymous_stream(
+            &lake_context,
+            &converter_keys,
+            lake_bpk,
+            &lake_ek,
+            finalized.into_iter().map(Ok),
+            &config,
+            &mut randomness,
+        )
+        .unwrap();
+        let converted = convert_blinded_stream(
+            &converter_context,
+            lake_bpk,
+            &lake_ek,
+            blinded,
+            &config,
+            &mut randomness,
+        )
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        let joined = finalize_blinded_stream(
+            &lake_context,
+            &converter_keys,
+            converted.iter().cloned().map(Ok),
+            &config,
+            &mut randomness,
+        )
+        .unwrap()
+        .count();
+        assert_eq!(joined, plain_table.data().len());
+
+        // Entries of different operations are rejected.
+        let mixed = pseudonymized.into_iter().chain(converted).map(Ok);
+        assert!(matches!(
+            finalize_blinded_stream(
+                &lake_context,
+                &converter_keys,
+                mixed,
+                &config,
+                &mut randomness
+            ),
+            Err(Error::VerificationError)
+        ));
+    }
+}
@@ -1,6 +1,25 @@
 //! ## Tables and Data Types
 //! The `ScrambleDB` protocol provides conversions between types of data
 //! tables that are differentiated by their structure and contents.
+//!
+//! Internally, the protocol operates on [`Table`]s of single attribute
+//! entries, such as [`IdentifiableData`]. Data sources, however, usually
+//! hold multi-column tables of rows, which are represented as
+//! [`PlainTable`]s. A plain table has a [`Schema`] naming its
+//! identifier column as well as the name and type of each of its data
+//! columns and converts to and from the per-attribute representation, where
+//! the column name becomes the attribute name of each value.
+
+use std::collections::BTreeMap;
+
+use hacspec_lib::Randomness;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{
+    data_types::{DataValue, FinalizedPseudonym, IdentifiableData, PseudonymizedData},
+    error::Error,
+};
 
 #[derive(Debug, Clone)]
 pub struct Table<T> {
@@ -24,6 +43,29 @@ impl<T> Table<T> {
         self.data.as_ref()
     }
 
+    /// Take the table entries.
+    pub(crate) fn into_data(self) -> Vec<T> {
+        self.data
+    }
+
+    /// Concatenate the entries of several tables into one table, identified
+    /// by the identifiers of all parts separated by `+`.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If no parts are given.
+    pub fn merge(parts: Vec<Table<T>>) -> Result<Self, Error> {
+        if parts.is_empty() {
+            return Err(Error::InvalidInput);
+        }
+        let identifier = parts
+            .iter()
+            .map(|part| part.identifier())
+            .collect::<Vec<_>>()
+            .join("+");
+        let data = parts.into_iter().flat_map(Table::into_data).collect();
+        Ok(Self::new(identifier, data))
+    }
+
     /// Sort the table by its handles.
     pub fn sort(&mut self)
     where
@@ -31,4 +73,708 @@ impl<T> Table<T> {
     {
         self.data.sort()
     }
+
+    /// Shuffle the table entries using a uniformly random permutation.
+    ///
+    /// The permutation is sampled using the Fisher-Yates shuffle, where
+    /// the random choice of swap position in each step is made using the
+    /// given randomness. The resulting order of entries is thus independent
+    /// of their original order and of the entries themselves.
+    ///
+    /// Inputs:
+    /// - `randomness`: Random bytes
+    ///
+    /// Raises:
+    /// - `RandomnessError`: If not enough randomness was provided.
+    pub fn shuffle(&mut self, randomness: &mut Randomness) -> Result<(), Error> {
+        shuffle_entries(&mut self.data, randomness)
+    }
+}
+
+/// The number of consecutive entries that share a child randomness
+/// provider in [`Table::try_map_randomized`].
+const ENTRIES_PER_CHUNK: usize = 1024;
+
+impl<T: Sync> Table<T> {
+    /// Apply a fallible transformation to every entry of the table.
+    ///
+    /// With the `parallel` feature, entries are transformed in parallel.
+    pub(crate) fn try_map<U: Send>(
+        &self,
+        f: impl Fn(&T) -> Result<U, Error> + Sync + Send,
+    ) -> Result<Vec<U>, Error> {
+        #[cfg(feature = "parallel")]
+        return self.data.par_iter().map(f).collect();
+        #[cfg(not(feature = "parallel"))]
+        self.data.iter().map(f).collect()
+    }
+
+    /// Apply a fallible, randomized transformation to every entry of the
+    /// table.
+    ///
+    /// The entries are split into chunks of [`ENTRIES_PER_CHUNK`]
+    /// consecutive entries. Each chunk is transformed using its own child
+    /// of `randomness`, forked in order using the index of the chunk as
+    /// label, which its entries consume in order. The randomness used for
+    /// an entry thus only depends on `randomness` and the entry's position
+    /// in the table, and repeated calls on the same `randomness` never
+    /// share randomness.
+    ///
+    /// With the `parallel` feature, chunks are transformed in parallel,
+    /// with the same result as transforming them sequentially.
+    pub(crate) fn try_map_randomized<U: Send>(
+        &self,
+        randomness: &mut Randomness,
+        f: impl Fn(&T, &mut Randomness) -> Result<U, Error> + Sync + Send,
+    ) -> Result<Vec<U>, Error> {
+        let chunks = self
+            .data
+            .chunks(ENTRIES_PER_CHUNK)
+            .enumerate()
+            .map(|(index, chunk)| Ok((chunk, randomness.fork(&(index as u64).to_be_bytes())?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let map_chunk = |(chunk, mut randomness): (&[T], Randomness)| {
+            chunk
+                .iter()
+                .map(|entry| f(entry, &mut randomness))
+                .collect::<Result<Vec<U>, Error>>()
+        };
+
+        #[cfg(feature = "parallel")]
+        let mapped = chunks
+            .into_par_iter()
+            .map(map_chunk)
+            .collect::<Result<Vec<_>, Error>>()?;
+        #[cfg(not(feature = "parallel"))]
+        let mapped = chunks
+            .into_iter()
+            .map(map_chunk)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(mapped.into_iter().flatten().collect())
+    }
+}
+
+/// Shuffle entries in place using the Fisher-Yates shuffle, as in
+/// [`Table::shuffle`].
+pub(crate) fn shuffle_entries<T>(
+    entries: &mut [T],
+    randomness: &mut Randomness,
+) -> Result<(), Error> {
+    for i in (1..entries.len()).rev() {
+        let j = uniform_index(i + 1, randomness)?;
+        entries.swap(i, j);
+    }
+    Ok(())
+}
+
+/// Sample a uniformly random index in `0..bound` by rejection sampling.
+pub(crate) fn uniform_index(bound: usize, randomness: &mut Randomness) -> Result<usize, Error> {
+    let bound = bound as u64;
+    // Largest multiple of `bound` representable as a `u64`, such that all
+    // residues are equally likely among the samples below it.
+    let limit = u64::MAX - (u64::MAX % bound);
+    loop {
+        let sample = u64::from_be_bytes(randomness.bytes(8)?.try_into()?);
+        if sample < limit {
+            return Ok((sample % bound) as usize);
+        }
+    }
+}
+
+/// The type of values in a column of a [`PlainTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    /// UTF-8 text, encoded as its bytes.
+    Text,
+    /// A signed 64-bit integer, encoded as 8 big-endian bytes.
+    Integer,
+    /// An arbitrary byte string.
+    Bytes,
+}
+
+/// A single value in a [`PlainTable`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Value {
+    Text(String),
+    Integer(i64),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// The type of this value.
+    pub fn column_type(&self) -> ColumnType {
+        match self {
+            Value::Text(_) => ColumnType::Text,
+            Value::Integer(_) => ColumnType::Integer,
+            Value::Bytes(_) => ColumnType::Bytes,
+        }
+    }
+
+    /// Encode the value as the byte string of a [`DataValue`].
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Value::Text(text) => text.as_bytes().to_vec(),
+            Value::Integer(integer) => integer.to_be_bytes().to_vec(),
+            Value::Bytes(bytes) => bytes.clone(),
+        }
+    }
+
+    /// Decode a value of the given type from the byte string of a
+    /// [`DataValue`].
+    ///
+    /// Raises:
+    /// - `CorruptedData`: If the bytes are not a valid encoding of a value
+    ///   of the given type.
+    pub fn decode(column_type: ColumnType, bytes: &[u8]) -> Result<Self, Error> {
+        match column_type {
+            ColumnType::Text => String::from_utf8(bytes.to_vec())
+                .map(Value::Text)
+                .map_err(|_| Error::CorruptedData),
+            ColumnType::Integer => bytes
+                .try_into()
+                .map(|bytes| Value::Integer(i64::from_be_bytes(bytes)))
+                .map_err(|_| Error::CorruptedData),
+            ColumnType::Bytes => Ok(Value::Bytes(bytes.to_vec())),
+        }
+    }
+}
+
+/// A named and typed data column of a [`PlainTable`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    /// The column name, used as attribute name of its values.
+    pub name: String,
+    /// The type of values in this column.
+    pub column_type: ColumnType,
+}
+
+/// The schema of a [`PlainTable`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    identifier: String,
+    columns: Vec<Column>,
+}
+
+impl Schema {
+    /// Create a new schema.
+    ///
+    /// Inputs:
+    /// - `identifier`: The name of the identifier column
+    /// - `columns`: The data columns
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If two data columns share a name, or a data column
+    ///   is named like the identifier column.
+    pub fn new(identifier: String, columns: Vec<Column>) -> Result<Self, Error> {
+        for (i, column) in columns.iter().enumerate() {
+            if column.name == identifier || columns[..i].iter().any(|c| c.name == column.name) {
+                return Err(Error::InvalidInput);
+            }
+        }
+        Ok(Self {
+            identifier,
+            columns,
+        })
+    }
+
+    /// Get the name of the identifier column.
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    /// Get the data columns.
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Get the position of the data column with the given name.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+}
+
+/// A row of a [`PlainTable`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row<I> {
+    /// The row's identifier.
+    pub identifier: I,
+    /// The row's values, in the order of the schema's columns.
+    pub values: Vec<Value>,
+}
+
+/// A row oriented table with multiple data columns.
+///
+/// Rows are identified by values of type `I`, which are plain text
+/// identifiers for data held by a source and [`FinalizedPseudonym`]s for
+/// data resulting from a join conversion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlainTable<I = String> {
+    name: String,
+    schema: Schema,
+    rows: Vec<Row<I>>,
+}
+
+impl<I> PlainTable<I> {
+    /// Create a new table without any rows.
+    pub fn new(name: String, schema: Schema) -> Self {
+        Self {
+            name,
+            schema,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Get the name of this table.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the schema of this table.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Get the table rows.
+    pub fn rows(&self) -> &[Row<I>] {
+        &self.rows
+    }
+
+    /// Append a row to the table.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If the values don't match the table's schema.
+    pub fn push_row(&mut self, identifier: I, values: Vec<Value>) -> Result<(), Error> {
+        if values.len() != self.schema.columns.len()
+            || values
+                .iter()
+                .zip(self.schema.columns.iter())
+                .any(|(value, column)| value.column_type() != column.column_type)
+        {
+            return Err(Error::InvalidInput);
+        }
+        self.rows.push(Row { identifier, values });
+        Ok(())
+    }
+
+    /// Collect rows from single attribute entries, in the order in which
+    /// identifiers first appear.
+    fn from_entries<'a>(
+        name: String,
+        schema: Schema,
+        entries: impl Iterator<Item = (I, &'a DataValue)>,
+    ) -> Result<Self, Error>
+    where
+        I: Ord + Clone,
+    {
+        let mut row_indices = BTreeMap::new();
+        let mut rows: Vec<(I, Vec<Option<Value>>)> = Vec::new();
+
+        for (identifier, data_value) in entries {
+            let column = schema
+                .column_index(&data_value.attribute_name)
+                .ok_or(Error::InvalidInput)?;
+            let row = *row_indices.entry(identifier.clone()).or_insert_with(|| {
+                rows.push((identifier, vec![None; schema.columns.len()]));
+                rows.len() - 1
+            });
+
+            let cell = &mut rows[row].1[column];
+            if cell.is_some() {
+                return Err(Error::InvalidInput);
+            }
+            *cell = Some(Value::decode(
+                schema.columns[column].column_type,
+                &data_value.value,
+            )?);
+        }
+
+        let rows = rows
+            .into_iter()
+            .map(|(identifier, values)| {
+                Ok(Row {
+                    identifier,
+                    values: values
+                        .into_iter()
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or(Error::InvalidInput)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self { name, schema, rows })
+    }
+
+    /// Split the rows into single attribute entries, column by column.
+    fn entries(&self) -> impl Iterator<Item = (&I, DataValue)> {
+        self.schema
+            .columns
+            .iter()
+            .enumerate()
+            .flat_map(move |(i, column)| {
+                self.rows.iter().map(move |row| {
+                    (
+                        &row.identifier,
+                        DataValue {
+                            value: row.values[i].encode(),
+                            attribute_name: column.name.clone(),
+                        },
+                    )
+                })
+            })
+    }
+}
+
+impl PlainTable<String> {
+    /// Convert to the per-attribute representation used as input to a
+    /// split conversion.
+    ///
+    /// Each value of the table becomes one entry of the output table, with
+    /// its row's identifier as handle and its column name as attribute
+    /// name.
+    pub fn to_identifiable_table(&self) -> Table<IdentifiableData> {
+        Table::new(
+            self.name.clone(),
+            self.entries()
+                .map(|(handle, data_value)| IdentifiableData {
+                    handle: handle.clone(),
+                    data_value,
+                })
+                .collect(),
+        )
+    }
+
+    /// Rebuild rows from the per-attribute representation.
+    ///
+    /// Inputs:
+    /// - `schema`: The schema of the resulting table
+    /// - `table`: A table of identifiable data
+    ///
+    /// Output:
+    /// A table with one row per handle occurring in the input table.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If an entry's attribute is not part of the schema,
+    ///   or a handle has no or more than one value for some column.
+    /// - `CorruptedData`: If a value is not a valid encoding for the type of
+    ///   its column.
+    pub fn from_identifiable_table(
+        schema: Schema,
+        table: &Table<IdentifiableData>,
+    ) -> Result<Self, Error> {
+        Self::from_entries(
+            table.identifier.clone(),
+            schema,
+            table
+                .data
+                .iter()
+                .map(|entry| (entry.handle.clone(), &entry.data_value)),
+        )
+    }
+}
+
+impl PlainTable<FinalizedPseudonym> {
+    /// Rebuild joined rows from the result of a join conversion.
+    ///
+    /// All values that were converted to the same join pseudonym are
+    /// collected in a single row.
+    ///
+    /// Inputs:
+    /// - `schema`: The schema of the resulting table, where the identifier
+    ///   column holds join pseudonyms
+    /// - `table`: A joined table of pseudonymized data
+    ///
+    /// Output:
+    /// A table with one row per join pseudonym.
+    ///
+    /// Raises:
+    /// - `InvalidInput`: If an entry's attribute is not part of the schema,
+    ///   or a pseudonym has no or more than one value for some column.
+    /// - `CorruptedData`: If a value is not a valid encoding for the type of
+    ///   its column.
+    pub fn from_joined_table(
+        schema: Schema,
+        table: &Table<PseudonymizedData>,
+    ) -> Result<Self, Error> {
+        Self::from_entries(
+            table.identifier.clone(),
+            schema,
+            table
+                .data
+                .iter()
+                .map(|entry| (entry.handle, &entry.data_value)),
+        )
+    }
+
+    /// Convert to the per-attribute representation of a joined table.
+    pub fn to_joined_table(&self) -> Table<PseudonymizedData> {
+        Table::new(
+            self.name.clone(),
+            self.entries()
+                .map(|(handle, data_value)| PseudonymizedData {
+                    handle: *handle,
+                    data_value,
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::data_types::{DataValue, FinalizedPseudonym, PseudonymizedData};
+
+    fn generate_randomness() -> Randomness {
+        Randomness::from_entropy().unwrap()
+    }
+
+    #[test]
+    fn test_try_map_randomized() {
+        let table = Table::new(
+            "Test".into(),
+            (0..2 * ENTRIES_PER_CHUNK as u32 + 10).collect(),
+        );
+        let sample =
+            |entry: &u32, randomness: &mut Randomness| Ok((*entry, randomness.bytes(8)?.to_vec()));
+        let mapped = table
+            .try_map_randomized(&mut Randomness::from_seed([1u8; 32]), sample)
+            .unwrap();
+
+        // Chunks of entries consume the children of the randomness in
+        // order.
+        let mut randomness = Randomness::from_seed([1u8; 32]);
+        let mut expected = Vec::new();
+        for (index, chunk) in table.data().chunks(ENTRIES_PER_CHUNK).enumerate() {
+            let mut chunk_randomness = randomness.fork(&(index as u64).to_be_bytes()).unwrap();
+            for entry in chunk {
+                expected.push(sample(entry, &mut chunk_randomness).unwrap());
+            }
+        }
+        assert_eq!(mapped, expected);
+
+        // Repeated calls on the same randomness do not share randomness.
+        let mut randomness = Randomness::from_seed([1u8; 32]);
+        let first = table.try_map_randomized(&mut randomness, sample).unwrap();
+        let second = table.try_map_randomized(&mut randomness, sample).unwrap();
+        assert!(first
+            .iter()
+            .zip(&second)
+            .all(|((_, first), (_, second))| first != second));
+    }
+
+    #[test]
+    fn test_shuffle_permutes() {
+        let mut randomness = generate_randomness();
+        let mut table = Table::new("Test".into(), (0..100).collect::<Vec<u32>>());
+        table.shuffle(&mut randomness).unwrap();
+
+        let mut entries = table.data().to_vec();
+        entries.sort();
+        assert_eq!(entries, (0..100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_shuffle_independent_of_input_order() {
+        const TRIALS: usize = 6000;
+        let mut randomness = generate_randomness();
+
+        // For every ordering of the input, each of the 6 possible output
+        // orderings should be observed roughly equally often.
+        for input in [[0u8, 1, 2], [2, 1, 0], [1, 0, 2]] {
+            let mut counts = HashMap::new();
+            for _ in 0..TRIALS {
+                let mut table = Table::new("Test".into(), input.to_vec());
+                table.shuffle(&mut randomness).unwrap();
+                *counts.entry(table.data().to_vec()).or_insert(0usize) += 1;
+            }
+
+            assert_eq!(counts.len(), 6);
+            for count in counts.values() {
+                // The expected count is 1000 with a standard deviation of
+                // about 29, so this bound is exceeded with negligible
+                // probability.
+                assert!((800..=1200).contains(count), "Biased shuffle: {counts:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_shuffle_insufficient_randomness() {
+        let mut table = Table::new("Test".into(), (0..100).collect::<Vec<u32>>());
+        assert_eq!(
+            table.shuffle(&mut Randomness::new(vec![0u8; 16])),
+            Err(Error::RandomnessError)
+        );
+    }
+
+    fn example_schema() -> Schema {
+        Schema::new(
+            "Name".into(),
+            ["Address", "Date of Birth", "Favorite Color"]
+                .into_iter()
+                .map(|name| Column {
+                    name: name.into(),
+                    column_type: ColumnType::Text,
+                })
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_plain_table_roundtrip() {
+        let table = crate::test_util::generate_plain_table();
+        let plain_table = PlainTable::from_identifiable_table(example_schema(), &table).unwrap();
+
+        assert_eq!(plain_table.rows().len(), 2);
+        assert_eq!(plain_table.rows()[0].identifier, "Alice");
+        assert_eq!(
+            plain_table.rows()[1].values,
+            vec![
+                Value::Text("TestData2".into()),
+                Value::Text("TestData4".into()),
+                Value::Text("TestData6".into())
+            ]
+        );
+
+        let mut entries = plain_table.to_identifiable_table().data().to_vec();
+        let mut expected = table.data().to_vec();
+        entries.sort();
+        expected.sort();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn test_plain_table_schema_mismatch() {
+        let schema = Schema::new(
+            "Name".into(),
+            vec![
+                Column {
+                    name: "Address".into(),
+                    column_type: ColumnType::Text,
+                },
+                Column {
+                    name: "Age".into(),
+                    column_type: ColumnType::Integer,
+                },
+            ],
+        )
+        .unwrap();
+        let mut plain_table = PlainTable::new("Test".into(), schema.clone());
+        assert_eq!(
+            plain_table.push_row("Alice".into(), vec![Value::Text("1 Main St".into())]),
+            Err(Error::InvalidInput)
+        );
+        assert_eq!(
+            plain_table.push_row(
+                "Alice".into(),
+                vec![Value::Text("1 Main St".into()), Value::Text("42".into())]
+            ),
+            Err(Error::InvalidInput)
+        );
+        plain_table
+            .push_row(
+                "Alice".into(),
+                vec![Value::Text("1 Main St".into()), Value::Integer(-42)],
+            )
+            .unwrap();
+        assert_eq!(
+            PlainTable::from_identifiable_table(
+                schema.clone(),
+                &plain_table.to_identifiable_table()
+            )
+            .unwrap(),
+            plain_table
+        );
+
+        // Incomplete rows and unknown attributes are rejected.
+        let mut entries = plain_table.to_identifiable_table().data().to_vec();
+        entries.pop();
+        assert_eq!(
+            PlainTable::from_identifiable_table(
+                schema.clone(),
+                &Table::new("Test".into(), entries)
+            ),
+            Err(Error::InvalidInput)
+        );
+        assert_eq!(
+            PlainTable::from_identifiable_table(
+                example_schema(),
+                &plain_table.to_identifiable_table()
+            ),
+            Err(Error::InvalidInput)
+        );
+        assert_eq!(
+            Schema::new("Name".into(), vec![schema.columns()[0].clone(); 2]),
+            Err(Error::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_plain_table_from_joined_table() {
+        let entry = |handle: u8, attribute_name: &str, value: &[u8]| PseudonymizedData {
+            handle: FinalizedPseudonym {
+                epoch: 0,
+                value: [handle; 64],
+            },
+            data_value: DataValue {
+                value: value.to_vec(),
+                attribute_name: attribute_name.into(),
+            },
+        };
+        let joined_table = Table::new(
+            "Join".into(),
+            vec![
+                entry(2, "Favorite Color", b"Blue"),
+                entry(1, "Address", b"1 Main St"),
+                entry(1, "Favorite Color", b"Red"),
+                entry(2, "Address", b"2 Main St"),
+            ],
+        );
+        let schema = Schema::new(
+            "Join Pseudonym".into(),
+            vec![
+                Column {
+                    name: "Address".into(),
+                    column_type: ColumnType::Text,
+                },
+                Column {
+                    name: "Favorite Color".into(),
+                    column_type: ColumnType::Bytes,
+                },
+            ],
+        )
+        .unwrap();
+
+        let plain_table = PlainTable::from_joined_table(schema, &joined_table).unwrap();
+        assert_eq!(
+            plain_table.rows(),
+            &[
+                Row {
+                    identifier: FinalizedPseudonym {
+                        epoch: 0,
+                        value: [2; 64],
+                    },
+                    values: vec![
+                        Value::Text("2 Main St".into()),
+                        Value::Bytes(b"Blue".to_vec())
+                    ]
+                },
+                Row {
+                    identifier: FinalizedPseudonym {
+                        epoch: 0,
+                        value: [1; 64],
+                    },
+                    values: vec![
+                        Value::Text("1 Main St".into()),
+                        Value::Bytes(b"Red".to_vec())
+                    ]
+                },
+            ]
+        );
+        assert_eq!(plain_table.to_joined_table().data().len(), 4);
+    }
 }
@@ -1,5 +1,6 @@
 use crate::{
     data_types::{DataValue, IdentifiableData},
+    setup::{ConverterContext, ConverterPublicKeys},
     table::Table,
 };
 
@@ -50,3 +51,10 @@ pub fn generate_plain_table() -> Table<IdentifiableData> {
 
     Table::new(String::from("ExampleTable"), data)
 }
+
+/// The converter's public keys for the attributes of [`generate_plain_table`].
+pub fn converter_public_keys(converter_context: &ConverterContext) -> ConverterPublicKeys {
+    converter_context
+        .public_keys(&["Address", "Date of Birth", "Favorite Color"])
+        .unwrap()
+}
@@ -0,0 +1,325 @@
+//! # Threshold Converters
+//!
+//! A [threshold converter](crate::setup::ConverterContext::threshold) holds
+//! no coPRF master secret. Instead, it combines the partial results of a
+//! quorum of threshold coPRF evaluators, see
+//! [`coprf_threshold`](oprf::coprf::coprf_threshold). Any `t` of the
+//! evaluators can pseudonymize, while converting requires `2t - 1` of them.
+//!
+//! This module defines the interface between the converter and its
+//! evaluators, [`ThresholdEvaluator`]. It is implemented by the evaluators'
+//! contexts, for a converter computing all partial results in-process, and
+//! by the [`EvaluatorClient`](crate::evaluator_service::EvaluatorClient),
+//! for a converter collecting the partial results of evaluators run by
+//! different parties, see [`evaluator_service`](crate::evaluator_service).
+//!
+//! Each evaluator's key material is exported for persistent storage in the
+//! [key export format](crate::key_wrap), with the public part
+//!
+//! ``` text
+//! public = index || threshold || parties
+//! ```
+//!
+//! as `u32`s, and the secret part holding the evaluators' common blinding
+//! key share, followed by the `u32` count of the evaluator's seeds, each
+//! encoded as the `threshold - 1` indices of the evaluators which do not
+//! know it, as `u32`s, and the 32 byte seed.
+
+use std::collections::BTreeMap;
+
+use hacspec_lib::Randomness;
+use oprf::coprf::{
+    coprf_online::{BlindInput, BlindOutput},
+    coprf_setup::BlindingPublicKey,
+    coprf_threshold::{
+        blind_convert_partial_verifiable, blind_evaluate_partial_verifiable,
+        derive_public_key_share, PartialConversion, PartialEvaluation, PartialProof, PartyIndex,
+        ThresholdEvaluatorContext,
+    },
+};
+use p256::P256Point;
+
+use crate::{
+    error::Error,
+    key_wrap,
+    serialization::{put_scalar, put_u32, Reader},
+};
+
+/// Wire format tag of exported threshold evaluator key material.
+const TAG_EVALUATOR_KEYS: u8 = 0x13;
+
+/// A threshold coPRF evaluator, as seen by the converter combining its
+/// partial results.
+pub trait ThresholdEvaluator: Send + Sync {
+    /// The index of the evaluator.
+    fn index(&self) -> PartyIndex;
+
+    /// The number of evaluators required for blind evaluation.
+    fn threshold(&self) -> u32;
+
+    /// The total number of evaluators.
+    fn parties(&self) -> u32;
+
+    /// The number of evaluators required for blind conversion, `2t - 1`.
+    fn conversion_threshold(&self) -> u32 {
+        2 * self.threshold() - 1
+    }
+
+    /// The public part of the evaluators' common blinding key share.
+    fn blinding_key_share(&self) -> Result<BlindingPublicKey, Error>;
+
+    /// The public key `K_i` of the evaluator's share of the
+    /// pseudonymization key of an attribute, see [`derive_public_key_share`].
+    fn public_key_share(&self, attribute: &str) -> Result<P256Point, Error>;
+
+    /// The evaluator's partial result for verifiable blind evaluation of an
+    /// input using the pseudonymization key of an attribute, see
+    /// [`blind_evaluate_partial_verifiable`].
+    fn evaluate(
+        &self,
+        attribute: &[u8],
+        bpk: BlindingPublicKey,
+        blinded_input: BlindInput,
+        randomness: &mut Randomness,
+    ) -> Result<(PartialEvaluation, PartialProof), Error>;
+
+    /// The evaluator's partial result for verifiable blind conversion of a
+    /// PRF output from the pseudonymization key of an attribute, with
+    /// public key `public_key_from`, to the key of the conversion target,
+    /// see [`blind_convert_partial_verifiable`].
+    fn convert(
+        &self,
+        public_key_from: P256Point,
+        attribute: &[u8],
+        conversion_target: &[u8],
+        bpk: BlindingPublicKey,
+        blinded_input: BlindOutput,
+        randomness: &mut Randomness,
+    ) -> Result<(PartialConversion, PartialProof), Error>;
+
+    /// The evaluator's context, if it is held in-process. Only evaluators
+    /// held in-process can derive update tokens, see
+    /// [`ConverterContext::update_token`](crate::setup::ConverterContext::update_token).
+    fn context(&self) -> Option<&ThresholdEvaluatorContext> {
+        None
+    }
+}
+
+impl ThresholdEvaluator for ThresholdEvaluatorContext {
+    fn index(&self) -> PartyIndex {
+        ThresholdEvaluatorContext::index(self)
+    }
+
+    fn threshold(&self) -> u32 {
+        ThresholdEvaluatorContext::threshold(self)
+    }
+
+    fn parties(&self) -> u32 {
+        ThresholdEvaluatorContext::parties(self)
+    }
+
+    fn blinding_key_share(&self) -> Result<BlindingPublicKey, Error> {
+        Ok(ThresholdEvaluatorContext::blinding_key_share(self)?)
+    }
+
+    fn public_key_share(&self, attribute: &str) -> Result<P256Point, Error> {
+        Ok(derive_public_key_share(self, attribute.as_bytes())?)
+    }
+
+    fn evaluate(
+        &self,
+        attribute: &[u8],
+        bpk: BlindingPublicKey,
+        blinded_input: BlindInput,
+        randomness: &mut Randomness,
+    ) -> Result<(PartialEvaluation, PartialProof), Error> {
+        Ok(blind_evaluate_partial_verifiable(
+            self,
+            attribute,
+            bpk,
+            blinded_input,
+            randomness,
+        )?)
+    }
+
+    fn convert(
+        &self,
+        public_key_from: P256Point,
+        attribute: &[u8],
+        conversion_target: &[u8],
+        bpk: BlindingPublicKey,
+        blinded_input: BlindOutput,
+        randomness: &mut Randomness,
+    ) -> Result<(PartialConversion, PartialProof), Error> {
+        Ok(blind_convert_partial_verifiable(
+            self,
+            public_key_from,
+            attribute,
+            conversion_target,
+            bpk,
+            blinded_input,
+            randomness,
+        )?)
+    }
+
+    fn context(&self) -> Option<&ThresholdEvaluatorContext> {
+        Some(self)
+    }
+}
+
+/// Collect the results of a quorum of evaluators, trying the evaluators in
+/// order until `quorum` of them succeeded. Evaluators that fail, e.g.
+/// because they are unreachable or their partial result does not verify,
+/// are skipped, so that any quorum of the evaluators suffices.
+///
+/// Raises:
+/// - `InvalidInput`: If fewer than `quorum` evaluators are given.
+/// - The error of the last failing evaluator, if fewer than `quorum`
+///   evaluators succeed.
+pub(crate) fn collect_quorum<T>(
+    evaluators: &[Box<dyn ThresholdEvaluator>],
+    quorum: usize,
+    mut result: impl FnMut(&dyn ThresholdEvaluator) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+    if evaluators.len() < quorum {
+        return Err(Error::InvalidInput);
+    }
+    let mut results = Vec::with_capacity(quorum);
+    let mut error = Error::InvalidInput;
+    for evaluator in evaluators {
+        if results.len() == quorum {
+            break;
+        }
+        match result(evaluator.as_ref()) {
+            Ok(value) => results.push(value),
+            Err(failure) => error = failure,
+        }
+    }
+    if results.len() < quorum {
+        return Err(error);
+    }
+    Ok(results)
+}
+
+/// ## Evaluator Key Export
+/// A threshold evaluator's key material, i.e. its seeds and the evaluators'
+/// common blinding key share, can be exported for persistent storage and
+/// later restored using [`import_evaluator`].
+///
+/// If a password is given, the secret key material is encrypted under a
+/// key derived from the password, as described in
+/// [`key_wrap`](crate::key_wrap).
+pub fn export_evaluator(
+    evaluator: &ThresholdEvaluatorContext,
+    password: Option<(&[u8], &mut Randomness)>,
+) -> Result<Vec<u8>, Error> {
+    let mut public = Vec::new();
+    put_u32(&mut public, evaluator.index());
+    put_u32(&mut public, evaluator.threshold());
+    put_u32(&mut public, evaluator.parties());
+
+    let mut secret = Vec::new();
+    put_scalar(&mut secret, &evaluator.blinding_private_key());
+    put_u32(&mut secret, evaluator.seeds().len() as u32);
+    for (excluded, seed) in evaluator.seeds() {
+        for index in excluded {
+            put_u32(&mut secret, *index);
+        }
+        secret.extend_from_slice(seed);
+    }
+
+    key_wrap::seal(
+        TAG_EVALUATOR_KEYS,
+        &public,
+        &secret,
+        password,
+        key_wrap::PBKDF2_ITERATIONS,
+    )
+}
+
+/// ## Evaluator Key Import
+/// Restore a threshold evaluator's context from key material output by
+/// [`export_evaluator`].
+///
+/// Raises:
+/// - `InvalidInput`: If the export is password protected, but no
+///   password was given.
+/// - `CorruptedData`: If the export is malformed, the password is wrong,
+///   or the seeds do not belong to the evaluator's index and threshold.
+pub fn import_evaluator(
+    bytes: &[u8],
+    password: Option<&[u8]>,
+) -> Result<ThresholdEvaluatorContext, Error> {
+    let (public, secret) = key_wrap::open(TAG_EVALUATOR_KEYS, bytes, password)?;
+
+    let mut reader = Reader::new(&public);
+    let index = reader.u32()?;
+    let threshold = reader.u32()?;
+    let parties = reader.u32()?;
+    reader.finish()?;
+    if threshold == 0 {
+        return Err(Error::CorruptedData);
+    }
+
+    let mut reader = Reader::new(&secret);
+    let blinding_key_share = reader.scalar()?;
+    let count = reader.count(4 * (threshold as usize - 1) + 32)?;
+    let mut seeds = BTreeMap::new();
+    for _ in 0..count {
+        let excluded = (1..threshold)
+            .map(|_| reader.u32())
+            .collect::<Result<Vec<_>, Error>>()?;
+        seeds.insert(excluded, reader.take(32)?.try_into()?);
+    }
+    reader.finish()?;
+
+    ThresholdEvaluatorContext::from_seeds(index, threshold, parties, seeds, blinding_key_share)
+        .map_err(|_| Error::CorruptedData)
+}
+
+#[cfg(test)]
+mod tests {
+    use oprf::coprf::{coprf_setup::CoPRFEvaluatorContext, coprf_threshold::deal};
+
+    use super::*;
+
+    #[test]
+    fn test_evaluator_export() {
+        let mut randomness = Randomness::from_entropy().unwrap();
+        let dealer = CoPRFEvaluatorContext::new(&mut randomness).unwrap();
+        let evaluators = deal(&dealer, 2, 3).unwrap();
+
+        for evaluator in &evaluators {
+            let restored =
+                import_evaluator(&export_evaluator(evaluator, None).unwrap(), None).unwrap();
+            assert_eq!(restored.index(), evaluator.index());
+            assert_eq!(restored.seeds(), evaluator.seeds());
+            assert_eq!(
+                restored.public_key_share("Address").unwrap(),
+                evaluator.public_key_share("Address").unwrap()
+            );
+        }
+
+        let exported =
+            export_evaluator(&evaluators[0], Some((b"password", &mut randomness))).unwrap();
+        assert_eq!(
+            import_evaluator(&exported, None).err(),
+            Some(Error::InvalidInput)
+        );
+        assert_eq!(
+            import_evaluator(&exported, Some(b"wrong")).err(),
+            Some(Error::CorruptedData)
+        );
+        let restored = import_evaluator(&exported, Some(b"password")).unwrap();
+        assert_eq!(restored.seeds(), evaluators[0].seeds());
+
+        // Seeds that do not belong to the index are rejected.
+        let mut exported = export_evaluator(&evaluators[0], None).unwrap();
+        assert_eq!(exported[7..11], 1u32.to_be_bytes());
+        exported[10] = 2;
+        assert_eq!(
+            import_evaluator(&exported, None).err(),
+            Some(Error::CorruptedData)
+        );
+    }
+}
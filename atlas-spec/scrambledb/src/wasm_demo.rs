@@ -11,6 +11,7 @@ use crate::data_types::BlindedPseudonymizedData;
 use crate::data_types::BlindedPseudonymizedHandle;
 use crate::data_types::DataValue;
 use crate::data_types::EncryptedDataValue;
+use crate::data_types::EncryptedValue;
 use crate::data_types::FinalizedPseudonym;
 use crate::data_types::IdentifiableData;
 use crate::data_types::PseudonymizedData;
@@ -58,20 +59,13 @@ pub fn generate_plain_table(table: serde_json::Value, columns: &Vec<&str>) -> Ta
 }
 
 pub fn run(table: serde_json::Value, source: &str, seed: u64) {
-    use rand::prelude::*;
-
-    let mut rng = rand::thread_rng();
-    let mut seeded_rng = rand::rngs::StdRng::seed_from_u64(seed);
-
     // used for randomized encryption and every other one-way-streets
-    let mut randomness = [0u8; 100000];
-    rng.fill_bytes(&mut randomness);
-    let mut randomness = Randomness::new(randomness.to_vec());
+    let mut randomness = Randomness::from_entropy().unwrap();
     
     // used if randomization across different instances need to be identical. Would be served naturally if it was a true distributed system
-    let mut seeded_randomness = [0u8; 100000];
-    seeded_rng.fill_bytes(&mut seeded_randomness);
-    let mut seeded_randomness = Randomness::new(seeded_randomness.to_vec());
+    let mut seeded_randomness = [0u8; 32];
+    seeded_randomness[..8].copy_from_slice(&seed.to_le_bytes());
+    let mut seeded_randomness = Randomness::from_seed(seeded_randomness);
 
     // Setup contexts
     let converter_context = ConverterContext::setup(&mut seeded_randomness).unwrap();
@@ -93,8 +87,11 @@ pub fn run(table: serde_json::Value, source: &str, seed: u64) {
     let source_context = StoreContext::setup(&mut randomness).unwrap();
     let (ek, bpk) = source_context.public_keys();
 
+    let converter_keys = converter_context.public_keys(&columns).unwrap();
+
     // Split conversion
     let blind_source_table = crate::split::blind_orthonymous_table(
+        &converter_keys,
         &ek,
         bpk,
         source_table.clone(),
@@ -112,7 +109,7 @@ pub fn run(table: serde_json::Value, source: &str, seed: u64) {
     .unwrap();
 
     let finalized_split_tables =
-        crate::finalize::finalize_blinded_table(&source_context, blind_split_tables.clone()).unwrap();
+        crate::finalize::finalize_blinded_table(&source_context, &converter_keys, blind_split_tables.clone(), &mut randomness).unwrap();
 
     // Join conversion
     let join_table_selection = Table::new(
@@ -133,6 +130,7 @@ pub fn run(table: serde_json::Value, source: &str, seed: u64) {
 
     let blind_pre_join_tables = crate::join::blind_pseudonymous_table(
         &source_context,
+        &converter_keys,
         bpk_processor,
         &ek_processor,
         join_table_selection.clone(),
@@ -150,7 +148,7 @@ pub fn run(table: serde_json::Value, source: &str, seed: u64) {
     .unwrap();
 
     let joined_tables =
-        crate::finalize::finalize_blinded_table(&processor_context, blind_joined_tables.clone())
+        crate::finalize::finalize_blinded_table(&processor_context, &converter_keys, blind_joined_tables.clone(), &mut randomness)
             .unwrap();
 
     // == Visualization ==
@@ -324,7 +322,7 @@ fn fill_pseudonymized_column(
 
 impl Display for FinalizedPseudonym {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "NYM({}...)", hex::encode(&self.0[0..5]))
+        write!(f, "NYM({}...)", hex::encode(&self.value[0..5]))
     }
 }
 
@@ -358,10 +356,16 @@ impl Display for DataValue {
 
 impl Display for EncryptedDataValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "ENC({}...)",
-            hex::encode(&self.value[0].0.raw_bytes()[0..5]),
-        )
+        match &self.value {
+            EncryptedValue::ElGamal(ciphertexts) => write!(
+                f,
+                "ENC({}...)",
+                hex::encode(&ciphertexts[0].0.raw_bytes()[0..5]),
+            ),
+            #[cfg(feature = "double-hpke")]
+            EncryptedValue::DoubleHpke { value, .. } => {
+                write!(f, "ENC({}...)", hex::encode(&value[0..5]))
+            }
+        }
     }
 }